@@ -1,79 +1,154 @@
+#[cfg(feature = "gui")]
 use std::fmt;
 
+#[cfg(feature = "gui")]
+use eframe::egui::Vec2;
 use image::RgbaImage;
 use imgref::ImgVec;
 
 use crate::{
-    coords::{CellPos, CellRect, PixelPoint},
+    cell_image::{CellCoordinates, CellImageSize},
+    coords::{self, Anchor, CellPos, CellRect, PixelPoint, PixelRect, SizeInCells},
+    document::DocumentSettings,
+    image_operations::Dithering,
+    metadata::Metadata,
+    selection::Selection,
+    update_area::UpdateArea,
+    vic::{
+        Char, ColorFormat, GlobalColors, PasteMode, PixelColor, Register, SavedChars, VicImage,
+        VicPalette,
+    },
+    Document,
+};
+#[cfg(feature = "gui")]
+use crate::{
     error::{DisallowedAction, Severity},
     mode::Mode,
     tool::ToolType,
-    ui::ViewSettings,
-    update_area::UpdateArea,
-    vic::{Char, ColorFormat, PixelColor, Register},
-    Document,
+    update_area::{PenShape, Symmetry},
+    vic::ViewSettings,
 };
 
+#[cfg(feature = "gui")]
 pub struct Undoable {
-    pub action: DocAction,
-    previous: Option<Document>,
+    /// The action, followed by any actions that have been merged into it.
+    /// Each is stored with what is needed to undo it, if it has been applied and changed the document.
+    steps: Vec<(DocAction, Option<UndoData>)>,
+    /// Merge this into the previous entry in the history, so they are undone together.
+    merge_with_previous: bool,
 }
 
+#[cfg(feature = "gui")]
 impl Undoable {
     pub fn new(action: DocAction) -> Self {
         Self {
-            action,
-            previous: None,
+            steps: vec![(action, None)],
+            merge_with_previous: false,
+        }
+    }
+
+    /// Create an Undoable that is merged with the previous entry in the history when applied.
+    pub fn merged(action: DocAction) -> Self {
+        Self {
+            merge_with_previous: true,
+            ..Self::new(action)
+        }
+    }
+
+    /// Undo the applied steps, last one first.
+    fn undo_steps(&mut self, target: &mut Document) {
+        for (_, undo_data) in self.steps.iter_mut().rev() {
+            if let Some(undo_data) = undo_data.take() {
+                target.restore(undo_data);
+            }
+        }
+    }
+}
+
+/// What is needed to undo a `DocAction`: the parts of the document it may change,
+/// as they were before the action was applied.
+pub enum UndoData {
+    /// The characters of the cells the action may change.
+    Chars(SavedChars),
+    /// The global colors and the characters of the cells the action may change.
+    ColorsAndChars(GlobalColors, SavedChars),
+    Register {
+        index: Register,
+        value: u8,
+    },
+    Palette(VicPalette),
+    StableCharacters(bool),
+    MaxChars(Option<usize>),
+    Metadata(Box<Metadata>),
+    Settings(Box<DocumentSettings>),
+    /// The whole image, for actions that change its size.
+    Image(Box<VicImage>),
+}
+
+impl UndoData {
+    /// Forget the saved characters that the action didn't change,
+    /// given the image after the action was applied.
+    #[cfg(feature = "gui")]
+    fn retain_changes(&mut self, after: &VicImage) {
+        match self {
+            UndoData::Chars(chars) | UndoData::ColorsAndChars(_, chars) => {
+                after.retain_changed_chars(chars)
+            }
+            _ => {}
         }
     }
 }
 
+/// Anything the user can do in the editor.
+#[cfg(feature = "gui")]
 pub enum Action {
     Document(DocAction),
+    /// A document action that continues a stroke started with `Document`,
+    /// e.g. when dragging the mouse to paint.
+    /// The whole stroke becomes a single step in the undo history.
+    ContinueStroke(DocAction),
     Ui(UiAction),
 }
 
 pub enum DocAction {
     /// Change one of the global colors.
-    ChangeRegister {
-        index: Register,
-        value: u8,
-    },
+    ChangeRegister { index: Register, value: u8 },
     /// Paste a true color image into the image
     PasteTrueColor {
         source: RgbaImage,
         target: PixelPoint,
         format: ColorFormat,
+        dithering: Dithering,
+        /// Bit mask of the character colors to use
+        allowed_colors: u8,
+        /// Global colors to set before pasting, if they should be changed
+        global_colors: Option<GlobalColors>,
     },
     /// Change the color of single pixels
-    Plot {
-        area: UpdateArea,
-        color: PixelColor,
-    },
+    Plot { area: UpdateArea, color: PixelColor },
     /// Fill the whole character cell with a color
-    Fill {
-        area: UpdateArea,
-        color: PixelColor,
-    },
+    Fill { area: UpdateArea, color: PixelColor },
+    /// Fill the connected pixels with the same color as the pixels in the area
+    FloodFill { area: UpdateArea, color: PixelColor },
+    /// Replace the colors of the pixels in the area in the whole image
+    GlobalFill { area: UpdateArea, color: PixelColor },
     /// Change the color of the cell
-    CellColor {
-        area: UpdateArea,
-        color: PixelColor,
-    },
+    CellColor { area: UpdateArea, color: PixelColor },
     /// Make the cell high-res
-    MakeHighRes {
-        area: UpdateArea,
-    },
+    MakeHighRes { area: UpdateArea },
     /// Make the cell multicolor
-    MakeMulticolor {
-        area: UpdateArea,
-    },
+    MakeMulticolor { area: UpdateArea },
     /// Replace one color with another.
     ReplaceColor {
         area: UpdateArea,
         to_replace: PixelColor,
         replacement: PixelColor,
     },
+    /// Replace one color with another in the whole image.
+    ReplaceColorEverywhere {
+        to_replace: PixelColor,
+        replacement: PixelColor,
+    },
     /// Swap two colors
     SwapColors {
         area: UpdateArea,
@@ -83,67 +158,626 @@ pub enum DocAction {
     CharBrushPaint {
         pos: CellPos,
         chars: ImgVec<Char>,
+        mode: PasteMode,
     },
+    /// Set the whole image to one color
+    Clear { color: PixelColor },
+    /// Convert cells that the hardware can't show to valid cells
+    FixCells { area: UpdateArea },
+    /// Swap the pixels between background and foreground colors
+    InvertColors { area: UpdateArea },
+    /// Swap background and foreground colors in the whole image
+    InvertImage,
+    /// Mirror the whole image horizontally
+    FlipX,
+    /// Mirror the whole image vertically
+    FlipY,
+    /// Move all cells a number of columns and rows
+    Shift { dx: i32, dy: i32, wrap: bool },
+    /// Rotate the whole image 90 degrees clockwise
+    RotateCw,
+    /// Rotate the whole image 90 degrees counterclockwise
+    RotateCcw,
+    /// Remove everything outside a rectangle
+    Crop { rect: CellRect },
+    /// Change the size of the image without scaling it
+    ResizeCanvas { size: SizeInCells, anchor: Anchor },
+    /// Set the maximum number of unique characters, or None for no limit
+    SetMaxChars(Option<usize>),
+    /// Change which palette to display the image with
+    SetPalette(VicPalette),
+    /// Set whether character numbers are kept when the image is edited
+    SetStableCharacters(bool),
+    /// Change the settings in the Document Settings dialog in one step
+    SetSettings(Box<DocumentSettings>),
+    /// Change the title, author, etc. of the document
+    SetMetadata(Box<Metadata>),
+}
+
+impl DocAction {
+    /// Restrict the pixels this action affects to the selection.
+    /// Fills are converted to actions on the affected pixels, as they would otherwise
+    /// spread outside the selection. Flood fills don't pass unselected pixels.
+    /// Actions on all pixels in the image, like clearing it, only change the selected pixels.
+    /// A character brush only paints the cells that are entirely selected,
+    /// as a cell can't be partly replaced.
+    /// Other actions that don't have an area, like flipping the image, affect the whole image as usual.
+    pub fn restricted_to(self, selection: &Selection, image: &VicImage) -> DocAction {
+        match self {
+            DocAction::Plot { area, color } => DocAction::Plot {
+                area: selection.restrict(area),
+                color,
+            },
+            DocAction::Fill { area, color } => DocAction::Fill {
+                area: selection.restrict(area),
+                color,
+            },
+            DocAction::FloodFill { area, color } => DocAction::Plot {
+                area: image.connected_pixels_within(&area, |p| selection.contains(p)),
+                color,
+            },
+            DocAction::GlobalFill { area, color } => {
+                let seeds = selection.restrict(area);
+                let to_replace = seeds.pixels().find_map(|p| image.pixel_color(p));
+                match to_replace {
+                    Some(to_replace) => DocAction::ReplaceColor {
+                        area: selection.to_area(),
+                        to_replace,
+                        replacement: color,
+                    },
+                    None => DocAction::GlobalFill { area: seeds, color },
+                }
+            }
+            DocAction::CellColor { area, color } => DocAction::CellColor {
+                area: selection.restrict(area),
+                color,
+            },
+            DocAction::MakeHighRes { area } => DocAction::MakeHighRes {
+                area: selection.restrict(area),
+            },
+            DocAction::MakeMulticolor { area } => DocAction::MakeMulticolor {
+                area: selection.restrict(area),
+            },
+            DocAction::ReplaceColor {
+                area,
+                to_replace,
+                replacement,
+            } => DocAction::ReplaceColor {
+                area: selection.restrict(area),
+                to_replace,
+                replacement,
+            },
+            DocAction::SwapColors {
+                area,
+                color_1,
+                color_2,
+            } => DocAction::SwapColors {
+                area: selection.restrict(area),
+                color_1,
+                color_2,
+            },
+            DocAction::FixCells { area } => DocAction::FixCells {
+                area: selection.restrict(area),
+            },
+            DocAction::InvertColors { area } => DocAction::InvertColors {
+                area: selection.restrict(area),
+            },
+            DocAction::ReplaceColorEverywhere {
+                to_replace,
+                replacement,
+            } => DocAction::ReplaceColor {
+                area: selection.to_area(),
+                to_replace,
+                replacement,
+            },
+            DocAction::CharBrushPaint {
+                pos,
+                mut chars,
+                mode,
+            } => {
+                // Paint the cells that are not entirely selected with what they already contain
+                for row in 0..chars.height() {
+                    for column in 0..chars.width() {
+                        let cell = pos + SizeInCells::new(column as i32, row as i32);
+                        let (top_left, bottom_right) =
+                            image.cell_rectangle(&CellRect::new(cell, SizeInCells::new(1, 1)));
+                        let rect = PixelRect::new(top_left, (bottom_right - top_left).to_size());
+                        if selection.contains_rect(&rect) {
+                            continue;
+                        }
+                        if let Some(cell) = coords::within_bounds(cell, image.size_in_cells()) {
+                            chars[(column, row)] = image.char_at(&cell);
+                        }
+                    }
+                }
+                DocAction::CharBrushPaint { pos, chars, mode }
+            }
+            DocAction::Clear { color } => DocAction::Plot {
+                area: selection.to_area(),
+                color,
+            },
+            DocAction::InvertImage => DocAction::InvertColors {
+                area: selection.to_area(),
+            },
+            other => other,
+        }
+    }
+
+    /// Save what is needed to undo this action, given the document before the action is applied.
+    pub fn invert(&self, before: &Document) -> UndoData {
+        let image = &before.image;
+        match self {
+            DocAction::ChangeRegister { index, .. } => UndoData::Register {
+                index: *index,
+                value: image.global_colors()[*index],
+            },
+            DocAction::PasteTrueColor { .. } => {
+                UndoData::ColorsAndChars(image.global_colors().clone(), image.save_all_chars())
+            }
+            DocAction::Plot { area, .. }
+            | DocAction::Fill { area, .. }
+            | DocAction::CellColor { area, .. }
+            | DocAction::MakeHighRes { area }
+            | DocAction::MakeMulticolor { area }
+            | DocAction::ReplaceColor { area, .. }
+            | DocAction::SwapColors { area, .. }
+            | DocAction::FixCells { area }
+            | DocAction::InvertColors { area } => UndoData::Chars(image.save_chars_in_area(area)),
+            DocAction::FloodFill { area, .. } => {
+                UndoData::Chars(image.save_chars_in_area(&image.connected_pixels(area)))
+            }
+            DocAction::CharBrushPaint { pos, chars, .. } => {
+                let rect = CellRect::new(
+                    *pos,
+                    SizeInCells::new(chars.width() as i32, chars.height() as i32),
+                );
+                UndoData::Chars(
+                    image.save_chars_in_rect(&coords::clamp_rect_to_bounds(
+                        rect,
+                        image.size_in_cells(),
+                    )),
+                )
+            }
+            DocAction::GlobalFill { .. }
+            | DocAction::ReplaceColorEverywhere { .. }
+            | DocAction::Clear { .. }
+            | DocAction::InvertImage
+            | DocAction::FlipX
+            | DocAction::FlipY
+            | DocAction::Shift { .. } => UndoData::Chars(image.save_all_chars()),
+            DocAction::RotateCw
+            | DocAction::RotateCcw
+            | DocAction::Crop { .. }
+            | DocAction::ResizeCanvas { .. } => UndoData::Image(Box::new((**image).clone())),
+            DocAction::SetMaxChars(_) => UndoData::MaxChars(before.max_chars),
+            DocAction::SetPalette(_) => UndoData::Palette(image.palette().clone()),
+            DocAction::SetStableCharacters(_) => {
+                UndoData::StableCharacters(image.stable_characters())
+            }
+            DocAction::SetMetadata(_) => UndoData::Metadata(Box::new(before.metadata.clone())),
+            DocAction::SetSettings(_) => UndoData::Settings(Box::new(before.settings())),
+        }
+    }
+
+    /// A name for the action to show to the user, e.g. in the undo history.
+    pub fn label(&self) -> String {
+        match self {
+            DocAction::ChangeRegister { index, .. } => format!(
+                "Change {} Color",
+                match index {
+                    Register::Background => "Background",
+                    Register::Border => "Border",
+                    Register::Aux => "Aux",
+                }
+            ),
+            DocAction::PasteTrueColor { .. } => "Import Image".to_string(),
+            DocAction::Plot { .. } => "Plot".to_string(),
+            DocAction::Fill { .. } => "Fill Cell".to_string(),
+            DocAction::FloodFill { .. } => "Flood Fill".to_string(),
+            DocAction::GlobalFill { .. } => "Global Fill".to_string(),
+            DocAction::CellColor { .. } => "Change Cell Color".to_string(),
+            DocAction::MakeHighRes { .. } => "Make High-Res".to_string(),
+            DocAction::MakeMulticolor { .. } => "Make Multicolor".to_string(),
+            DocAction::ReplaceColor { .. } => "Replace Color".to_string(),
+            DocAction::ReplaceColorEverywhere { .. } => "Replace Color Everywhere".to_string(),
+            DocAction::SwapColors { .. } => "Swap Colors".to_string(),
+            DocAction::CharBrushPaint { .. } => "Paint with Brush".to_string(),
+            DocAction::Clear { .. } => "Clear".to_string(),
+            DocAction::FixCells { .. } => "Fix Invalid Cells".to_string(),
+            DocAction::InvertColors { .. } | DocAction::InvertImage => "Invert Colors".to_string(),
+            DocAction::FlipX => "Flip Horizontally".to_string(),
+            DocAction::FlipY => "Flip Vertically".to_string(),
+            DocAction::Shift { .. } => "Shift".to_string(),
+            DocAction::RotateCw => "Rotate Clockwise".to_string(),
+            DocAction::RotateCcw => "Rotate Counterclockwise".to_string(),
+            DocAction::Crop { .. } => "Crop".to_string(),
+            DocAction::ResizeCanvas { .. } => "Resize Canvas".to_string(),
+            DocAction::SetMaxChars(_) => "Change Character Limit".to_string(),
+            DocAction::SetPalette(_) => "Change Palette".to_string(),
+            DocAction::SetStableCharacters(_) => "Change Character Numbering".to_string(),
+            DocAction::SetSettings(_) => "Change Document Settings".to_string(),
+            DocAction::SetMetadata(_) => "Change Document Info".to_string(),
+        }
+    }
 }
 
 /// An action that changes something in the user interface, not the document. Not undoable.
+#[cfg(feature = "gui")]
 pub enum UiAction {
     Undo,
     Redo,
-    NewDocument(Document),
+    NewDocument(Box<Document>),
+    /// Open a copy of the active document as a new, unsaved document
+    DuplicateDocument,
     CloseEditor(usize),
     SelectTool(ToolType),
     SelectMode(Mode),
-    CreateCharBrush { rect: CellRect },
+    /// Select how the character brush is combined with the image
+    SelectPasteMode(PasteMode),
+    CreateCharBrush {
+        rect: CellRect,
+    },
     ZoomIn,
     ZoomOut,
+    /// Zoom in, keeping the point at this position relative to the center of the view in place.
+    ZoomInAt(Vec2),
+    /// Zoom out, keeping the point at this position relative to the center of the view in place.
+    ZoomOutAt(Vec2),
     SetZoom(f32),
+    /// Zoom so the whole image fits in the view
+    ZoomToFit,
+    /// Make the pen larger or smaller by this many pixels
+    ChangePenSize(i32),
+    SetPenShape(PenShape),
+    SetSymmetry(Symmetry),
     ToggleGrid,
     ToggleRaw,
     ViewSettings(ViewSettings),
     MirrorBrushX,
     MirrorBrushY,
+    /// Rotate the character brush 90 degrees clockwise
+    RotateBrush,
+    /// Ask for a file name and save the character brush
+    SaveBrush,
+    /// Ask for a file name and load a character brush
+    LoadBrush,
+    /// Add the character brush to the brush library
+    AddBrushToLibrary,
+    /// Ask for a directory and add all brushes in it to the brush library
+    ImportBrushDirectory,
+    /// Use a brush from the brush library as the character brush
+    UseLibraryBrush(usize),
+    CopyImage,
+    PasteImage,
+    /// Open the dialog for changing the settings
+    ShowSettingsDialog,
+    /// Open the dialog for changing keyboard shortcuts
+    ShowKeymapDialog,
+    /// Open the dialog for creating a new document
+    ShowNewDocumentDialog,
+    /// Ask for a palette file and use it as the custom palette.
+    LoadPalette,
+    /// Load the active document from its file again, discarding unsaved changes
+    Revert,
+    /// Close all editors without asking, and open a new empty document
+    CloseAll,
+    /// Move the keyboard cursor this many pixels
+    MoveCursor {
+        dx: i32,
+        dy: i32,
+    },
+    /// Paint at the keyboard cursor with the current mode
+    PaintAtCursor,
+    /// Select the primary color, swapping with the secondary color if it is the same
+    SetPrimaryColor(PixelColor),
+    /// Swap the primary and secondary colors
+    SwapPaintColors,
+    /// Restrict painting to some pixels, or remove the restriction
+    SetSelection(Option<Selection>),
 }
 
+#[cfg(feature = "gui")]
 impl undo::Action for Undoable {
     type Target = Document;
     type Output = bool;
     type Error = Box<dyn DisallowedAction>;
 
     fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
-        let previous = target.clone();
-        match target.apply(&self.action) {
-            Ok(true) => {
-                self.previous = Some(previous);
-                Ok(true)
+        let mut changed = false;
+        for i in 0..self.steps.len() {
+            let (action, _) = &self.steps[i];
+            let mut undo_data = action.invert(target);
+            match target.apply(action) {
+                Ok(true) => {
+                    undo_data.retain_changes(&target.image);
+                    self.steps[i].1 = Some(undo_data);
+                    changed = true;
+                }
+                Ok(false) => self.steps[i].1 = None,
+                Err(e) => {
+                    // Don't leave the document half changed,
+                    // as the failing action may have changed some of it before it stopped
+                    target.restore(undo_data);
+                    self.undo_steps(target);
+                    return Err(e);
+                }
             }
-            Ok(false) => Err(Box::new(NoChange)),
-            other => other,
+        }
+        if changed {
+            Ok(true)
+        } else {
+            Err(Box::new(NoChange))
         }
     }
 
     fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
-        match self.previous.take() {
-            Some(previous) => {
-                *target = previous;
-                Ok(true)
-            }
-            None => Ok(false),
+        self.undo_steps(target);
+        Ok(true)
+    }
+
+    fn merge(&mut self, other: &mut Self) -> undo::Merged {
+        // Keep the steps of the whole stroke, so it can be undone and redone as one
+        if other.merge_with_previous {
+            self.steps.append(&mut other.steps);
+            undo::Merged::Yes
+        } else {
+            undo::Merged::No
         }
     }
 }
 
+#[cfg(feature = "gui")]
 #[derive(Debug)]
 struct NoChange;
 
+#[cfg(feature = "gui")]
 impl fmt::Display for NoChange {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "No change")
     }
 }
 
+#[cfg(feature = "gui")]
 impl DisallowedAction for NoChange {
     fn severity(&self) -> crate::error::Severity {
         Severity::Silent
     }
 }
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+    #[cfg(feature = "gui")]
+    use undo::Record;
+
+    use super::DocAction;
+    #[cfg(feature = "gui")]
+    use crate::{
+        actions::Undoable,
+        cell_image::CellImageSize,
+        coords::{CellRect, SizeInCells},
+        vic::Register,
+    };
+    use crate::{
+        coords::{CellPos, PixelPoint, PixelRect},
+        selection::Selection,
+        update_area::UpdateArea,
+        vic::{Char, PasteMode, PixelColor, VicImage},
+        Document,
+    };
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn change_register_is_undoable() {
+        let mut doc = Document::new();
+        let mut history = Record::new();
+        let action = DocAction::ChangeRegister {
+            index: Register::Aux,
+            value: 9,
+        };
+        assert!(history.apply(&mut doc, Undoable::new(action)).unwrap());
+        assert_eq!(doc.image.global_colors().aux, 9);
+        history.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.image.global_colors().aux, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn merged_actions_are_undone_and_redone_together() {
+        let mut doc = Document::new();
+        let mut history = Record::new();
+        let first = DocAction::ChangeRegister {
+            index: Register::Aux,
+            value: 9,
+        };
+        let second = DocAction::ChangeRegister {
+            index: Register::Border,
+            value: 5,
+        };
+        let third = DocAction::ChangeRegister {
+            index: Register::Background,
+            value: 3,
+        };
+        history.apply(&mut doc, Undoable::new(first)).unwrap();
+        history.apply(&mut doc, Undoable::merged(second)).unwrap();
+        history.apply(&mut doc, Undoable::merged(third)).unwrap();
+        assert_eq!(history.len(), 1);
+        history.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.image.global_colors().aux, 2);
+        assert_eq!(doc.image.global_colors().border, 1);
+        assert_eq!(doc.image.global_colors().background, 0);
+
+        // Redo must apply every merged action, not only the first one
+        history.redo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.image.global_colors().aux, 9);
+        assert_eq!(doc.image.global_colors().border, 5);
+        assert_eq!(doc.image.global_colors().background, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn stroke_is_undone_and_redone_as_a_whole() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        let original = doc.image.clone();
+        let mut history = Record::new();
+        let plot = |x| DocAction::Plot {
+            area: UpdateArea::from_pixel(PixelPoint::new(x, 0)),
+            color: PixelColor::CharColor(1),
+        };
+        history.apply(&mut doc, Undoable::new(plot(0))).unwrap();
+        history.apply(&mut doc, Undoable::merged(plot(9))).unwrap();
+        let painted = doc.image.clone();
+        history.undo(&mut doc).unwrap().unwrap();
+        assert!(doc.image.render() == original.render());
+        history.redo(&mut doc).unwrap().unwrap();
+        assert!(doc.image.render() == painted.render());
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn failed_action_leaves_image_unchanged() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        doc.apply(&DocAction::MakeHighRes {
+            area: UpdateArea::from_pixel(PixelPoint::new(8, 0)),
+        })
+        .unwrap();
+        let original = doc.image.render();
+        let mut history = Record::new();
+        // Changes the multicolor cell, then fails on the high-res cell
+        let action = DocAction::ReplaceColorEverywhere {
+            to_replace: PixelColor::Background,
+            replacement: PixelColor::Aux,
+        };
+        assert!(history.apply(&mut doc, Undoable::new(action)).is_err());
+        assert!(doc.image.render() == original);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn crop_is_undoable() {
+        let mut doc = Document::from_image(VicImage::new(3, 2));
+        doc.apply(&DocAction::Plot {
+            area: UpdateArea::from_pixel(PixelPoint::new(20, 10)),
+            color: PixelColor::CharColor(1),
+        })
+        .unwrap();
+        let original = doc.image.render();
+        let mut history = Record::new();
+        let crop = DocAction::Crop {
+            rect: CellRect::new(CellPos::new(1, 1), SizeInCells::new(1, 1)),
+        };
+        history.apply(&mut doc, Undoable::new(crop)).unwrap();
+        assert_eq!(doc.image.size_in_cells(), SizeInCells::new(1, 1));
+        history.undo(&mut doc).unwrap().unwrap();
+        assert!(doc.image.render() == original);
+    }
+
+    #[test]
+    fn flood_fill_stays_within_selection() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        let selection = Selection::from_area(&UpdateArea::rectangle(PixelRect::new(
+            PixelPoint::new(0, 0),
+            euclid::Size2D::new(2, 2),
+        )));
+        let action = DocAction::FloodFill {
+            area: UpdateArea::from_pixel(PixelPoint::new(1, 1)),
+            color: PixelColor::CharColor(1),
+        };
+        doc.apply(&action.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_eq!(color(0, 0), Some(PixelColor::CharColor(1)));
+        assert_eq!(color(1, 1), Some(PixelColor::CharColor(1)));
+        assert_eq!(color(2, 2), Some(PixelColor::Background));
+    }
+
+    #[test]
+    fn flood_fill_does_not_pass_unselected_pixels() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        // Two selected squares with unselected pixels of the same color between them
+        let square = |y| {
+            UpdateArea::rectangle(PixelRect::new(
+                PixelPoint::new(0, y),
+                euclid::Size2D::new(2, 2),
+            ))
+        };
+        let selection = Selection::from_area(&UpdateArea::from_pixels(
+            square(0).pixels().chain(square(4).pixels()).collect(),
+        ));
+        let action = DocAction::FloodFill {
+            area: UpdateArea::from_pixel(PixelPoint::new(0, 0)),
+            color: PixelColor::CharColor(1),
+        };
+        doc.apply(&action.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_eq!(color(1, 1), Some(PixelColor::CharColor(1)));
+        assert_eq!(color(0, 2), Some(PixelColor::Background));
+        assert_eq!(color(0, 4), Some(PixelColor::Background));
+    }
+
+    #[test]
+    fn replace_color_everywhere_only_replaces_in_selection() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        let selection = Selection::from_area(&UpdateArea::from_pixel(PixelPoint::new(1, 1)));
+        let action = DocAction::ReplaceColorEverywhere {
+            to_replace: PixelColor::Background,
+            replacement: PixelColor::CharColor(1),
+        };
+        doc.apply(&action.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_eq!(color(1, 1), Some(PixelColor::CharColor(1)));
+        assert_eq!(color(4, 4), Some(PixelColor::Background));
+    }
+
+    #[test]
+    fn invert_image_only_inverts_selection() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        let selection = Selection::from_area(&UpdateArea::from_pixel(PixelPoint::new(1, 1)));
+        doc.apply(&DocAction::InvertImage.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_ne!(color(1, 1), Some(PixelColor::Background));
+        assert_eq!(color(4, 4), Some(PixelColor::Background));
+    }
+
+    #[test]
+    fn char_brush_only_paints_cells_that_are_entirely_selected() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        // All of the first cell and one pixel of the second
+        let selection = Selection::from_area(&UpdateArea::from_pixels(
+            UpdateArea::rectangle(PixelRect::new(
+                PixelPoint::new(0, 0),
+                euclid::Size2D::new(8, 8),
+            ))
+            .pixels()
+            .chain(std::iter::once(PixelPoint::new(8, 0)))
+            .collect(),
+        ));
+        let action = DocAction::CharBrushPaint {
+            pos: CellPos::new(0, 0),
+            chars: ImgVec::new(vec![Char::new([0xff; 8], 1); 2], 2, 1),
+            mode: PasteMode::Replace,
+        };
+        doc.apply(&action.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_eq!(color(0, 0), Some(PixelColor::Aux));
+        assert_eq!(color(8, 0), Some(PixelColor::Background));
+    }
+
+    #[test]
+    fn clear_only_clears_selection() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        let selection = Selection::from_area(&UpdateArea::from_pixel(PixelPoint::new(1, 1)));
+        let action = DocAction::Clear {
+            color: PixelColor::CharColor(1),
+        };
+        doc.apply(&action.restricted_to(&selection, &doc.image))
+            .unwrap();
+        let color = |x, y| doc.image.pixel_color(PixelPoint::new(x, y));
+        assert_eq!(color(1, 1), Some(PixelColor::CharColor(1)));
+        assert_eq!(color(2, 1), Some(PixelColor::Background));
+    }
+}
@@ -1,28 +1,41 @@
 use crate::cell_image::CellImageSize;
 use crate::egui_extensions::EnhancedResponse;
-use crate::vic::{Char, VicImage};
+use crate::import::Import;
+use crate::vic::{Char, CustomPalette, VicImage, VicPalette, ViewSettings};
 use crate::{
-    actions::{Action, UiAction},
+    actions::{Action, DocAction, UiAction},
+    autosave::{self, Autosave},
     editor::Editor,
-    mode::Mode,
+    error::Error,
+    image_io::{self, FileFormat},
+    keymap::{self, Keymap},
+    playback::{self, Playback},
+    settings::{self, Settings},
     storage,
-    system::{self, OpenFileOptions, SystemFunctions},
+    system::{self, OpenFileOptions, SaveFileOptions, SystemFunctions},
     tool::ToolType,
+    ui::brush_library::BrushLibrary,
     Document,
 };
-use crate::{brush, coords};
+use crate::{brush, coords, ui};
 use eframe::{
     egui::{self, Color32, Label, Rgba, RichText, Sense, Shape, Stroke},
     epi,
 };
+use image::DynamicImage;
 use imgref::ImgVec;
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 const POPUP_MESSAGE_TIME: f32 = 3.0;
 const POPUP_HIGHLIGHT_TIME: f32 = 0.4;
 const POPUP_FADE_OUT_TIME: f32 = 0.8;
 
+/// How often to check whether open files have been changed by other programs.
+#[cfg(not(target_arch = "wasm32"))]
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
 const TAB_SPACING: f32 = 5.0;
 const TAB_STROKE: Stroke = Stroke {
     width: 0.1,
@@ -52,6 +65,17 @@ impl Editors {
         assert!(index < self.list.len());
         self.active = index;
     }
+    /// Activate the next editor, or the previous one if `forward` is false, wrapping around.
+    pub fn cycle_active(&mut self, forward: bool) {
+        let len = self.list.len();
+        if len > 0 {
+            self.active = if forward {
+                (self.active + 1) % len
+            } else {
+                (self.active + len - 1) % len
+            };
+        }
+    }
     pub fn has_active(&self) -> bool {
         !self.list.is_empty()
     }
@@ -61,6 +85,16 @@ impl Editors {
     pub fn active_mut(&mut self) -> Option<&mut Editor> {
         self.list.get_mut(self.active)
     }
+    /// Get the active editor, and the images of the editors before and after it.
+    pub fn active_with_neighbors(&mut self) -> Option<(&mut Editor, [Option<&VicImage>; 2])> {
+        let (before, rest) = self.list.split_at_mut(self.active);
+        let (active, after) = rest.split_first_mut()?;
+        let neighbors = [
+            before.last().map(|ed| &*ed.doc.image),
+            after.first().map(|ed| &*ed.doc.image),
+        ];
+        Some((active, neighbors))
+    }
     pub fn get(&self, index: usize) -> Option<&Editor> {
         self.list.get(index)
     }
@@ -70,6 +104,9 @@ impl Editors {
     pub fn iter(&self) -> impl Iterator<Item = &Editor> {
         self.list.iter()
     }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Editor> {
+        self.list.iter_mut()
+    }
     fn remove(&mut self, index: usize) {
         self.list.remove(index);
         if self.active > index || self.active == index && self.active == self.list.len() {
@@ -93,6 +130,36 @@ pub struct Application {
     /// For giving each new document its own number
     next_document_index: u32,
     brush: ImgVec<Char>,
+    /// Where the brush was last saved or loaded.
+    brush_filename: Option<PathBuf>,
+    /// How often to save unsaved documents for recovery. None disables it.
+    autosave_interval: Option<Duration>,
+    /// Created on the first update, if the system supports it.
+    autosave: Option<Autosave>,
+    /// Whether the first update has been done.
+    started: bool,
+    /// The title last set on the window.
+    window_title: String,
+    /// When open files were last checked for changes by other programs.
+    #[cfg(not(target_arch = "wasm32"))]
+    last_file_check: Option<Instant>,
+    settings: Settings,
+    /// How many steps can be undone in each editor, if not the limit in the settings.
+    undo_limit_override: Option<usize>,
+    /// The Settings dialog with the settings entered so far, if it is open.
+    settings_dialog: Option<Settings>,
+    keymap: Keymap,
+    /// The Keyboard Shortcuts dialog, if it is open.
+    keymap_dialog: Option<ui::keymap::KeymapDialog>,
+    /// The New Document dialog, if it is open.
+    new_document_dialog: Option<ui::new_document::NewDocumentDialog>,
+    brush_library: BrushLibrary,
+    /// Showing the open documents as an animation.
+    playback: Playback,
+    /// Where the playback controls were last shown.
+    playback_rect: Option<egui::Rect>,
+    /// When unsaved documents were last copied to the system's recovery storage, in `egui::InputState::time`.
+    last_recovery_copy: Option<f64>,
 }
 
 impl Default for Application {
@@ -109,22 +176,72 @@ impl epi::App for Application {
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame) {
+        if !self.started {
+            self.started = true;
+            self.load_settings();
+            self.load_keymap();
+            self.start_autosave();
+        }
+
         let mut user_actions = Vec::new();
 
         for e in ctx.input().events.iter() {
             if !ctx.wants_keyboard_input() {
-                if let egui::Event::Text(t) = e {
-                    create_actions_from_keyboard(t, &mut user_actions);
+                match e {
+                    egui::Event::Text(t) => {
+                        if let Some(action) = self.keymap.action(t) {
+                            user_actions.push(action);
+                        }
+                    }
+                    egui::Event::Copy => user_actions.push(Action::Ui(UiAction::CopyImage)),
+                    egui::Event::Key {
+                        key: egui::Key::Tab,
+                        pressed: true,
+                        modifiers,
+                    } if modifiers.ctrl => self.editors.cycle_active(!modifiers.shift),
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                    } => {
+                        if let Some(action) = keymap::key_name(*key, modifiers)
+                            .and_then(|name| self.keymap.action(&name))
+                        {
+                            user_actions.push(action);
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
 
+        self.open_dropped_files(ctx, &mut user_actions);
+        self.open_read_files(ctx, &mut user_actions);
+
+        if self.playback.is_playing() {
+            // Any interaction except with the playback controls stops the playback
+            let pointer = &ctx.input().pointer;
+            let pressed_elsewhere = pointer.any_pressed()
+                && !matches!(
+                    (pointer.interact_pos(), self.playback_rect),
+                    (Some(pos), Some(rect)) if rect.contains(pos)
+                );
+            if pressed_elsewhere || !user_actions.is_empty() {
+                self.playback.stop();
+            } else if self.playback.advance(Instant::now()) {
+                self.editors.cycle_active(true);
+            }
+            ctx.request_repaint();
+        }
+        self.update_playback_panel(ctx);
+
         if self.editors.has_active() {
             let actions = update_with_editor(
                 ctx,
                 frame,
                 &mut self.editors,
                 &self.brush,
+                &mut self.brush_library,
                 self.system.as_mut(),
                 &mut user_actions,
             );
@@ -133,7 +250,71 @@ impl epi::App for Application {
                 self.apply_action(action);
             }
         }
+
+        self.update_window_title(frame);
+        show_file_drop_indicator(ctx);
+
+        if let Some(settings) = ui::settings::render(ctx, &mut self.settings_dialog) {
+            self.set_settings(settings);
+        }
+        if let Some(keymap) = ui::keymap::render(ctx, &mut self.keymap_dialog) {
+            self.set_keymap(keymap);
+        }
+        match ui::new_document::render(ctx, &mut self.new_document_dialog) {
+            Some(Ok(doc)) => {
+                self.add_editor(doc);
+            }
+            Some(Err(message)) => {
+                if let Some(ed) = self.editors.active_mut() {
+                    ed.ui_state.show_warning(message);
+                }
+            }
+            None => {}
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.check_modified_files();
+
+        if let Some(autosave) = &mut self.autosave {
+            if let Err(e) = autosave.update(self.editors.iter()) {
+                if let Some(ed) = self.editors.active_mut() {
+                    ed.ui_state
+                        .show_warning(format!("Failed to save recovery file: {}", e));
+                }
+            }
+        } else {
+            let time = ctx.input().time;
+            self.store_recovery_copies(time);
+        }
+    }
+
+    fn on_exit(&mut self) {
+        if let Some(autosave) = &mut self.autosave {
+            if let Err(e) = autosave.remove_session_files() {
+                eprintln!("Failed to remove recovery files: {}", e);
+            }
+        }
+    }
+}
+
+/// Darken the window while files are dragged over it, to show that they can be dropped.
+fn show_file_drop_indicator(ctx: &egui::CtxRef) {
+    if ctx.input().raw.hovered_files.is_empty() {
+        return;
     }
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("file_drop_indicator"),
+    ));
+    let rect = ctx.input().screen_rect();
+    painter.rect_filled(rect, 0.0, Color32::from_black_alpha(192));
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        "Drop to open\nHold Shift to import images",
+        egui::TextStyle::Heading,
+        Color32::WHITE,
+    );
 }
 
 fn check_close(system: &mut dyn SystemFunctions, ed: &Editor) -> bool {
@@ -150,6 +331,12 @@ fn check_close(system: &mut dyn SystemFunctions, ed: &Editor) -> bool {
 }
 
 fn check_quit(system: &mut dyn SystemFunctions, editors: &Editors) -> bool {
+    check_unsaved(system, editors, "Are you sure you want to quit?")
+}
+
+/// If any editor has unsaved changes, list them and ask `question`.
+/// Returns true if there are no unsaved changes or the user confirmed.
+fn check_unsaved(system: &mut dyn SystemFunctions, editors: &Editors, question: &str) -> bool {
     let unsaved: Vec<String> = editors
         .iter()
         .filter(|ed| !ed.history.is_saved())
@@ -160,42 +347,20 @@ fn check_quit(system: &mut dyn SystemFunctions, editors: &Editors) -> bool {
     }
     system
         .request_confirmation(&format!(
-            "The following files are not saved:\n\n{}\n\nAre you sure you want to quit?",
-            unsaved.join("\n")
+            "The following files are not saved:\n\n{}\n\n{}",
+            unsaved.join("\n"),
+            question
         ))
         .unwrap_or(false)
 }
 
-fn create_actions_from_keyboard(keypress: &str, actions: &mut Vec<Action>) {
-    let action = match keypress {
-        "+" => Action::Ui(UiAction::ZoomIn),
-        "-" => Action::Ui(UiAction::ZoomOut),
-        "b" => Action::Ui(UiAction::SelectTool(ToolType::CharBrush)),
-        "c" => Action::Ui(UiAction::SelectMode(Mode::CellColor)),
-        "d" => Action::Ui(UiAction::SelectTool(ToolType::Paint)),
-        "f" => Action::Ui(UiAction::SelectMode(Mode::FillCell)),
-        "g" => Action::Ui(UiAction::ToggleGrid),
-        "h" => Action::Ui(UiAction::SelectMode(Mode::MakeHiRes)),
-        "H" => Action::Ui(UiAction::SelectMode(Mode::MakeMulticolor)),
-        "r" => Action::Ui(UiAction::SelectMode(Mode::ReplaceColor)),
-        "R" => Action::Ui(UiAction::SelectMode(Mode::SwapColors)),
-        "w" => Action::Ui(UiAction::ToggleRaw),
-        "u" => Action::Ui(UiAction::Undo),
-        "U" => Action::Ui(UiAction::Redo),
-        "v" => Action::Ui(UiAction::SelectTool(ToolType::Grab)),
-        "x" => Action::Ui(UiAction::MirrorBrushX),
-        "y" => Action::Ui(UiAction::MirrorBrushY),
-        _ => return,
-    };
-    actions.push(action);
-}
-
 /// UI for when there is an active editor.
 fn update_with_editor(
     ctx: &egui::CtxRef,
     frame: &epi::Frame,
     editors: &mut Editors,
     brush: &ImgVec<Char>,
+    brush_library: &mut BrushLibrary,
     system: &mut dyn SystemFunctions,
     user_actions: &mut Vec<Action>,
 ) -> Vec<Action> {
@@ -204,24 +369,53 @@ fn update_with_editor(
         let doc_filename = editors.active_mut().unwrap().doc.filename.clone();
         egui::menu::bar(ui, |ui| {
             egui::menu::menu_button(ui, "File", |ui| {
-                if ui.button("New").clicked_with_close(ui) {
-                    let doc = Document::new();
-                    user_actions.push(Action::Ui(UiAction::NewDocument(doc)));
+                if ui.button("New...").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::ShowNewDocumentDialog));
+                }
+                if ui.button("Duplicate").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::DuplicateDocument));
                 }
                 if system.has_open_file_dialog() && ui.button("Open...").clicked_with_close(ui) {
                     match system
-                        .open_file_dialog(OpenFileOptions::for_open(doc_filename.as_deref()))
+                        .open_files_dialog(OpenFileOptions::for_open(doc_filename.as_deref()))
                     {
-                        Ok(Some(filename)) => {
-                            open_file(filename, editors, system, user_actions);
+                        Ok(filenames) => {
+                            for filename in filenames {
+                                open_file(filename, editors, system, user_actions);
+                            }
                         }
-                        Ok(None) => {}
                         Err(e) => {
                             system.show_error(&format!("Could not get file name: {:?}", e));
                         }
                     }
                 }
-                editors.active_mut().unwrap().update_file_menu(ui, system);
+                editors
+                    .active_mut()
+                    .unwrap()
+                    .update_file_menu(ui, system, user_actions);
+                let ed = editors.active().unwrap();
+                if ui
+                    .add_enabled(
+                        ed.doc.filename.is_some() && !ed.history.is_saved(),
+                        egui::Button::new("Revert"),
+                    )
+                    .on_hover_text("Discard the changes since the file was saved")
+                    .clicked_with_close(ui)
+                {
+                    user_actions.push(Action::Ui(UiAction::Revert));
+                }
+                if system.has_save_file_dialog()
+                    && ui
+                        .add_enabled(
+                            editors.iter().any(|ed| !ed.history.is_saved()),
+                            egui::Button::new("Save All"),
+                        )
+                        .clicked_with_close(ui)
+                {
+                    for ed in editors.iter_mut().filter(|ed| !ed.history.is_saved()) {
+                        ed.save_or_ask(system);
+                    }
+                }
                 ui.separator();
                 ui.add_enabled_ui(editors.has_active() && editors.len() > 1, |ui| {
                     let ed = editors.active_mut().unwrap();
@@ -230,6 +424,26 @@ fn update_with_editor(
                             .push(Action::Ui(UiAction::CloseEditor(editors.active_index())));
                     }
                 });
+                if ui.button("Close All").clicked_with_close(ui)
+                    && check_unsaved(system, editors, "Are you sure you want to close them?")
+                {
+                    user_actions.push(Action::Ui(UiAction::CloseAll));
+                }
+                ui.separator();
+                if ui.button("Settings...").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::ShowSettingsDialog));
+                }
+                if ui.button("Keyboard Shortcuts...").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::ShowKeymapDialog));
+                }
+                if system.has_open_file_dialog()
+                    && ui
+                        .button("Load Palette...")
+                        .on_hover_text("Load the colors of the Custom palette from a file")
+                        .clicked_with_close(ui)
+                {
+                    user_actions.push(Action::Ui(UiAction::LoadPalette));
+                }
                 ui.separator();
                 if ui.button("Quit").clicked_with_close(ui) && check_quit(system, editors) {
                     frame.quit();
@@ -246,6 +460,30 @@ fn update_with_editor(
                 if ui.button("Mirror Y").clicked_with_close(ui) {
                     user_actions.push(Action::Ui(UiAction::MirrorBrushY));
                 }
+                if ui.button("Rotate Clockwise").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::RotateBrush));
+                }
+                if system.has_save_file_dialog() {
+                    ui.separator();
+                    if ui.button("Save Brush...").clicked_with_close(ui) {
+                        user_actions.push(Action::Ui(UiAction::SaveBrush));
+                    }
+                }
+                if system.has_open_file_dialog()
+                    && ui.button("Load Brush...").clicked_with_close(ui)
+                {
+                    user_actions.push(Action::Ui(UiAction::LoadBrush));
+                }
+                ui.separator();
+                if ui.button("Add to Library").clicked_with_close(ui) {
+                    user_actions.push(Action::Ui(UiAction::AddBrushToLibrary));
+                }
+                if system.has_open_directory_dialog()
+                    && ui.button("Import Library...").clicked_with_close(ui)
+                {
+                    user_actions.push(Action::Ui(UiAction::ImportBrushDirectory));
+                }
+                ui.checkbox(&mut brush_library.visible, "Show Library");
                 ui.separator();
                 if ui.button("Create Image").clicked_with_close(ui) {
                     let mut image = VicImage::with_content(brush.clone());
@@ -254,7 +492,7 @@ fn update_with_editor(
                         image.set_global_colors(editor.doc.image.global_colors().clone());
                     }
                     let doc = Document::from_image(image);
-                    user_actions.push(Action::Ui(UiAction::NewDocument(doc)));
+                    user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
                 }
             });
         });
@@ -267,7 +505,11 @@ fn update_with_editor(
                 ui.horizontal(|ui| {
                     for (index, ed) in editors.iter().enumerate() {
                         let selected = selected_index == index;
-                        let name = ed.doc.short_name();
+                        let name = if ed.history.is_saved() {
+                            ed.doc.short_name()
+                        } else {
+                            format!("{}*", ed.doc.short_name())
+                        };
                         let response = if selected {
                             ui.add_space(TAB_SPACING);
                             let response = ui.add(
@@ -294,6 +536,11 @@ fn update_with_editor(
                             }
                             response
                         };
+                        // Middle click closes the tab, unless it's the last one
+                        if response.middle_clicked() && editors.len() > 1 && check_close(system, ed)
+                        {
+                            user_actions.push(Action::Ui(UiAction::CloseEditor(index)));
+                        }
                         if let Some(filename) = &ed.doc.filename {
                             response.on_hover_text(filename.to_string_lossy().to_string());
                         }
@@ -320,28 +567,50 @@ fn update_with_editor(
     });
 
     egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
-        let ed = editors.active_mut().unwrap();
-        if ed.ui_state.message.is_some() {
-            let (time, message) = ed.ui_state.message.clone().unwrap();
-            let age = Instant::now().saturating_duration_since(time).as_secs_f32();
-            let highlight =
-                1.0 - ((age - POPUP_HIGHLIGHT_TIME) / POPUP_FADE_OUT_TIME).clamp(0.0, 1.0);
-            let bg_color = Rgba::RED * highlight;
-            let text_color = (Rgba::WHITE * highlight)
-                + (Rgba::from(ctx.style().visuals.text_color()) * (1.0 - highlight));
-            ui.add(Label::new(
-                RichText::new(message)
-                    .color(text_color)
-                    .background_color(bg_color),
-            ));
-            if age >= POPUP_MESSAGE_TIME {
-                ed.ui_state.message = None;
-            } else if highlight > 0.0 {
-                ctx.request_repaint(); // to animate color highlight
+        ui::message_log::render(ui, &mut editors.active_mut().unwrap().ui_state.message_log);
+        ui.horizontal(|ui| {
+            let ed = editors.active_mut().unwrap();
+            if ed.ui_state.message.is_some() {
+                let (time, message) = ed.ui_state.message.clone().unwrap();
+                let age = Instant::now().saturating_duration_since(time).as_secs_f32();
+                let highlight =
+                    1.0 - ((age - POPUP_HIGHLIGHT_TIME) / POPUP_FADE_OUT_TIME).clamp(0.0, 1.0);
+                let bg_color = Rgba::RED * highlight;
+                let text_color = (Rgba::WHITE * highlight)
+                    + (Rgba::from(ctx.style().visuals.text_color()) * (1.0 - highlight));
+                ui.add(Label::new(
+                    RichText::new(message)
+                        .color(text_color)
+                        .background_color(bg_color),
+                ));
+                if age >= POPUP_MESSAGE_TIME {
+                    ed.ui_state.message = None;
+                } else if highlight > 0.0 {
+                    ctx.request_repaint(); // to animate color highlight
+                }
+            } else {
+                ui.label(ed.ui_state.tool.instructions(&ed.ui_state.mode));
             }
-        } else {
-            ui.label(ed.ui_state.tool.instructions(&ed.ui_state.mode));
-        }
+            let char_count = ed.doc.image.unique_char_count();
+            ui.with_layout(egui::Layout::right_to_left(), |ui| {
+                if char_count > VicImage::MAX_CHARACTERS {
+                    ui.label(
+                        RichText::new(format!(
+                            "{} characters (maximum is {})",
+                            char_count,
+                            VicImage::MAX_CHARACTERS
+                        ))
+                        .color(Color32::WHITE)
+                        .background_color(Color32::RED),
+                    )
+                    .on_hover_text(
+                        "The image uses more unique characters than the Vic-20 can show at once",
+                    );
+                } else {
+                    ui.label(format!("{} characters", char_count));
+                }
+            });
+        });
     });
 
     // Left toolbar
@@ -350,12 +619,49 @@ fn update_with_editor(
         ed.update_left_toolbar(ui, user_actions);
     });
 
+    if editors.active().unwrap().ui_state.show_navigator {
+        egui::SidePanel::right("navigator").show(ctx, |ui| {
+            let ed = editors.active_mut().unwrap();
+            ed.update_navigator_panel(ui, frame);
+        });
+    }
+
+    if editors.active().unwrap().ui_state.show_char_editor {
+        egui::SidePanel::right("char_editor").show(ctx, |ui| {
+            let ed = editors.active_mut().unwrap();
+            ed.update_char_editor_panel(ui, user_actions);
+        });
+    }
+
+    if editors.active().unwrap().ui_state.show_history {
+        egui::SidePanel::right("history").show(ctx, |ui| {
+            let ed = editors.active().unwrap();
+            ed.update_history_panel(ui, user_actions);
+        });
+    }
+
+    ui::brush_library::render(
+        ctx,
+        frame,
+        brush_library,
+        &editors.active().unwrap().doc.image,
+        user_actions,
+    );
+
     let mut cursor_icon = None;
 
     // Main image.
     egui::CentralPanel::default().show(ctx, |ui| {
-        let ed = editors.active_mut().unwrap();
-        ed.update_central_panel(ui, frame, ctx, &mut cursor_icon, brush, user_actions);
+        let (ed, neighbors) = editors.active_with_neighbors().unwrap();
+        ed.update_central_panel(
+            ui,
+            frame,
+            ctx,
+            &mut cursor_icon,
+            brush,
+            neighbors,
+            user_actions,
+        );
     });
 
     let ed = editors.active_mut().unwrap();
@@ -373,6 +679,14 @@ fn update_with_editor(
     unhandled_actions
 }
 
+/// Name to show for a brush loaded from a file.
+fn brush_name(filename: &Path) -> String {
+    filename
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
 /// Open file or show error to user.
 /// Switches to an existing editor if the document is already open.
 fn open_file(
@@ -385,12 +699,60 @@ fn open_file(
         editors.set_active_index(i);
         return;
     }
+    if storage::is_tilemap(&filename) {
+        open_tilemap(&filename, system, user_actions);
+        return;
+    }
     match storage::load_any_file(std::path::Path::new(&filename)) {
         Ok(doc) => {
-            user_actions.push(Action::Ui(UiAction::NewDocument(doc)));
+            user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
         }
         Err(e) => {
-            system.show_error(&format!("Failed to load: {:?}", e));
+            system.show_error_details(&format!("Failed to load {}", filename.display()), &e);
+        }
+    }
+}
+
+/// Open a CSV tile map. Asks the user for the character set the tile map refers to.
+fn open_tilemap(
+    filename: &std::path::Path,
+    system: &mut dyn SystemFunctions,
+    user_actions: &mut Vec<Action>,
+) {
+    let prompt = format!(
+        "{} only contains character numbers.\nSelect the character set file that it uses.",
+        filename.display()
+    );
+    match system.request_confirmation(&prompt) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            system.show_error(&e.to_string());
+            return;
+        }
+    }
+    let charset_filename =
+        match system.open_file_dialog(OpenFileOptions::for_charset(Some(filename))) {
+            Ok(Some(f)) => f,
+            Ok(None) => return,
+            Err(e) => {
+                system.show_error(&e.to_string());
+                return;
+            }
+        };
+    match storage::load_tilemap(filename, &charset_filename) {
+        Ok(doc) => {
+            user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
+        }
+        Err(e) => {
+            system.show_error_details(
+                &format!(
+                    "Failed to load tile map {} with character set {}",
+                    filename.display(),
+                    charset_filename.display()
+                ),
+                &e,
+            );
         }
     }
 }
@@ -403,32 +765,560 @@ impl Application {
             system,
             next_document_index: 1,
             brush: ImgVec::new(vec![Char::DEFAULT_BRUSH], 1, 1),
+            brush_filename: None,
+            autosave_interval: Some(autosave::DEFAULT_INTERVAL),
+            autosave: None,
+            started: false,
+            window_title: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            last_file_check: None,
+            settings: Settings::default(),
+            undo_limit_override: None,
+            settings_dialog: None,
+            keymap: Keymap::default(),
+            keymap_dialog: None,
+            new_document_dialog: None,
+            brush_library: BrushLibrary::default(),
+            playback: Playback::default(),
+            playback_rect: None,
+            last_recovery_copy: None,
+        }
+    }
+
+    /// Open the files the user dropped on the window.
+    /// If Shift is held, images in standard formats are imported into the current document instead.
+    fn open_dropped_files(&mut self, ctx: &egui::CtxRef, user_actions: &mut Vec<Action>) {
+        let (paths, import) = {
+            let input = ctx.input();
+            let paths: Vec<PathBuf> = input
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect();
+            (paths, input.modifiers.shift)
+        };
+        for path in paths {
+            let is_image = matches!(
+                image_io::identify_file(&path),
+                Ok(FileFormat::StandardImage(_))
+            );
+            match self.editors.active_mut() {
+                Some(ed) if import && is_image => {
+                    if let Err(e) = ed.start_import_mode(&path) {
+                        self.system.show_error_details(
+                            &format!("Failed to import {}", path.display()),
+                            &e,
+                        );
+                    }
+                }
+                _ => open_file(path, &mut self.editors, self.system.as_mut(), user_actions),
+            }
         }
     }
 
+    /// Open the files that the system has read into memory after the user picked them.
+    fn open_read_files(&mut self, ctx: &egui::CtxRef, user_actions: &mut Vec<Action>) {
+        for (name, data) in self.system.take_opened_files() {
+            match storage::load_any_bytes(Path::new(&name), &data) {
+                Ok(doc) => user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc)))),
+                Err(e) => self
+                    .system
+                    .show_error_details(&format!("Failed to load {}", name), &e),
+            }
+        }
+        if self.system.is_reading_files() {
+            // Check again soon
+            ctx.request_repaint();
+        }
+    }
+
+    /// Show the controls for playing the open documents as an animation,
+    /// if there is more than one.
+    fn update_playback_panel(&mut self, ctx: &egui::CtxRef) {
+        if self.editors.len() < 2 {
+            self.playback.stop();
+            self.playback_rect = None;
+            return;
+        }
+        let playback = &mut self.playback;
+        let response = egui::TopBottomPanel::bottom("playback_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if playback.is_playing() {
+                    if ui.button("Stop").clicked() {
+                        playback.stop();
+                    }
+                } else if ui
+                    .button("Play")
+                    .on_hover_text("Show the open documents one after the other, as an animation")
+                    .clicked()
+                {
+                    playback.start(Instant::now());
+                    ctx.request_repaint();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut playback.fps)
+                        .clamp_range(playback::FPS_RANGE)
+                        .speed(0.1)
+                        .suffix(" fps"),
+                );
+            });
+        });
+        self.playback_rect = Some(response.response.rect);
+    }
+
+    /// Set how many steps can be undone in editors that are created after this,
+    /// instead of the limit in the settings.
+    /// Undo history uses memory, so a lower limit may be needed for large images.
+    /// The limit is at least 1.
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit_override = Some(limit.max(1));
+    }
+
+    fn undo_limit(&self) -> usize {
+        self.undo_limit_override.unwrap_or(self.settings.undo_limit)
+    }
+
+    /// Set how often unsaved documents are saved for recovery. None disables it.
+    pub fn set_autosave_interval(&mut self, interval: Option<Duration>) {
+        self.autosave_interval = interval;
+    }
+
     pub fn add_editor(&mut self, mut doc: Document) -> usize {
         doc.index_number = self.next_document_index;
         self.next_document_index += 1;
-        let editor = Editor::with_doc(doc);
+        let editor = Editor::with_doc(doc, self.undo_limit());
         let i = self.editors.add(editor);
         self.editors.set_active_index(i);
         i
     }
 
+    /// Show the active document's name in the window title, with a "*" if it is not saved.
+    fn update_window_title(&mut self, frame: &epi::Frame) {
+        let title = match self.editors.active() {
+            Some(ed) => format!(
+                "{}{} - Pixel Pen",
+                ed.doc.visible_name(),
+                if ed.history.is_saved() { "" } else { "*" }
+            ),
+            None => "Pixel Pen".to_string(),
+        };
+        if title != self.window_title {
+            frame.set_window_title(&title);
+            self.window_title = title;
+        }
+    }
+
+    /// Load the user's settings, if they have changed them.
+    fn load_settings(&mut self) {
+        let filename = match self.system.config_directory() {
+            Some(directory) => directory.join(settings::SETTINGS_FILENAME),
+            None => return,
+        };
+        if !filename.exists() {
+            return;
+        }
+        match Settings::load(&filename) {
+            Ok(settings) => self.settings = settings,
+            Err(e) => self.system.show_error(&format!(
+                "Could not load settings from {}: {}",
+                filename.to_string_lossy(),
+                e
+            )),
+        }
+    }
+
+    /// Start using new settings, and save them if the system supports it.
+    /// The undo limit is used for editors that are created after this,
+    /// even if another limit has been set with `set_undo_limit`.
+    fn set_settings(&mut self, settings: Settings) {
+        if let Some(directory) = self.system.config_directory() {
+            let filename = directory.join(settings::SETTINGS_FILENAME);
+            if let Err(e) = settings.save(&filename) {
+                self.system
+                    .show_error(&format!("Could not save settings: {}", e));
+            }
+        }
+        self.settings = settings;
+        self.undo_limit_override = None;
+    }
+
+    /// Load the user's keyboard shortcuts, if they have changed them.
+    fn load_keymap(&mut self) {
+        let filename = match self.system.config_directory() {
+            Some(directory) => directory.join(keymap::KEYMAP_FILENAME),
+            None => return,
+        };
+        if !filename.exists() {
+            return;
+        }
+        match Keymap::load(&filename) {
+            Ok(keymap) => self.keymap = keymap,
+            Err(e) => self.system.show_error(&format!(
+                "Could not load keyboard shortcuts from {}: {}",
+                filename.to_string_lossy(),
+                e
+            )),
+        }
+    }
+
+    /// Start using new keyboard shortcuts, and save them if the system supports it.
+    fn set_keymap(&mut self, keymap: Keymap) {
+        if let Some(directory) = self.system.config_directory() {
+            let filename = directory.join(keymap::KEYMAP_FILENAME);
+            if let Err(e) = keymap.save(&filename) {
+                self.system
+                    .show_error(&format!("Could not save keyboard shortcuts: {}", e));
+            }
+        }
+        self.keymap = keymap;
+    }
+
+    /// Start autosaving if enabled,
+    /// and offer to restore documents that were not saved when the application last exited.
+    fn start_autosave(&mut self) {
+        let interval = match self.autosave_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let directory = match self.system.recovery_directory() {
+            Some(directory) => directory,
+            None => {
+                if self.system.has_recovery_storage() {
+                    self.offer_recovery_copies();
+                }
+                return;
+            }
+        };
+        let autosave = Autosave::new(directory, interval);
+        match autosave.leftover_files() {
+            Ok(files) if !files.is_empty() => self.offer_recovery(&files),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to look for recovery files: {}", e),
+        }
+        if let Err(e) = autosave.remove_dead_locks() {
+            eprintln!("Failed to remove old lock files: {}", e);
+        }
+        self.autosave = Some(autosave);
+    }
+
+    /// Ask the user whether to restore recovery files. They are removed after that.
+    fn offer_recovery(&mut self, files: &[PathBuf]) {
+        let restore = self
+            .system
+            .request_confirmation(&format!(
+                "Found {} unsaved document(s) from a previous session.\n\n\
+                Do you want to restore them? If not, they will be deleted.",
+                files.len()
+            ))
+            .unwrap_or(false);
+        for filename in files {
+            if restore {
+                match storage::load_own(filename) {
+                    Ok(mut doc) => {
+                        doc.filename = None;
+                        let index = self.add_editor(doc);
+                        self.editors
+                            .get_mut(index)
+                            .unwrap()
+                            .history
+                            .set_saved(false);
+                    }
+                    Err(e) => {
+                        self.system.show_error(&format!(
+                            "Could not restore {}: {}",
+                            filename.to_string_lossy(),
+                            e
+                        ));
+                        continue;
+                    }
+                }
+            }
+            if let Err(e) = std::fs::remove_file(filename) {
+                eprintln!("Failed to remove {}: {}", filename.to_string_lossy(), e);
+            }
+        }
+    }
+
+    /// Ask the user whether to restore the documents in the system's recovery storage.
+    /// They are removed from the storage either way.
+    fn offer_recovery_copies(&mut self) {
+        let copies = match self.system.take_recovery_copies() {
+            Ok(copies) if !copies.is_empty() => copies,
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!("Failed to look for recovery copies: {}", e);
+                return;
+            }
+        };
+        let restore = self
+            .system
+            .request_confirmation(&format!(
+                "Found {} unsaved document(s) from a previous session.\n\n\
+                Do you want to restore them? If not, they will be deleted.",
+                copies.len()
+            ))
+            .unwrap_or(false);
+        if !restore {
+            return;
+        }
+        for (name, data) in copies {
+            match storage::load_any_bytes(Path::new(&name), &data) {
+                Ok(doc) => {
+                    let index = self.add_editor(doc);
+                    self.editors
+                        .get_mut(index)
+                        .unwrap()
+                        .history
+                        .set_saved(false);
+                }
+                Err(e) => self
+                    .system
+                    .show_error(&format!("Could not restore {}: {}", name, e)),
+            }
+        }
+    }
+
+    /// For systems without a recovery directory, keep copies of unsaved documents
+    /// in the system's recovery storage instead, if it has any.
+    /// `time` is the current time in seconds.
+    fn store_recovery_copies(&mut self, time: f64) {
+        let interval = match self.autosave_interval {
+            Some(interval) => interval.as_secs_f64(),
+            None => return,
+        };
+        if !self.system.has_recovery_storage()
+            || matches!(self.last_recovery_copy, Some(last) if time - last < interval)
+        {
+            return;
+        }
+        self.last_recovery_copy = Some(time);
+        let result = self
+            .editors
+            .iter()
+            .filter(|ed| !ed.history.is_saved())
+            .map(|ed| {
+                Ok((
+                    ed.doc.index_number,
+                    ed.doc.visible_name(),
+                    storage::save_to_bytes(&ed.doc)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .and_then(|copies| self.system.store_recovery_copies(&copies));
+        if let Err(e) = result {
+            if let Some(ed) = self.editors.active_mut() {
+                ed.ui_state
+                    .show_warning(format!("Failed to save recovery copy: {}", e));
+            }
+        }
+    }
+
+    /// Offer to reload documents whose files have been changed by other programs.
+    /// The user is only asked once for each change.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn check_modified_files(&mut self) {
+        let now = Instant::now();
+        if matches!(self.last_file_check, Some(t) if now.duration_since(t) < FILE_CHECK_INTERVAL) {
+            return;
+        }
+        self.last_file_check = Some(now);
+        for index in 0..self.editors.len() {
+            let ed = self.editors.get_mut(index).unwrap();
+            let filename = match &ed.doc.filename {
+                Some(filename) => filename.clone(),
+                None => continue,
+            };
+            let modified = storage::modified_time(&filename);
+            if modified.is_none() || modified == ed.doc.file_modified {
+                continue;
+            }
+            ed.doc.file_modified = modified;
+            let mut message = format!(
+                "This file has been changed by another program:\n\n{}\n\nDo you want to reload it?",
+                filename.display()
+            );
+            if !ed.history.is_saved() {
+                message.push_str("\n\nYour unsaved changes will be lost.");
+            }
+            if self.system.request_confirmation(&message).unwrap_or(false) {
+                self.reload(index);
+            }
+        }
+    }
+
+    /// Replace the document in an editor with the contents of its file.
+    fn reload(&mut self, index: usize) {
+        let undo_limit = self.undo_limit();
+        let ed = match self.editors.get_mut(index) {
+            Some(ed) => ed,
+            None => return,
+        };
+        let filename = match &ed.doc.filename {
+            Some(filename) => filename.clone(),
+            None => return,
+        };
+        match storage::load_any_file(&filename) {
+            Ok(mut doc) => {
+                doc.index_number = ed.doc.index_number;
+                *ed = Editor::with_doc(doc, undo_limit);
+            }
+            Err(e) => self.system.show_error(&format!("Failed to reload: {}", e)),
+        }
+    }
+
+    /// Ask for a file name and save the character brush to it.
+    fn save_brush(&mut self) {
+        if !self.system.has_save_file_dialog() {
+            return;
+        }
+        let options = SaveFileOptions::for_brush(self.brush_filename.as_deref());
+        match self.system.save_file_dialog(options) {
+            Ok(Some(filename)) => match storage::save_brush(&self.brush, &filename) {
+                Ok(()) => self.brush_filename = Some(filename),
+                Err(e) => self
+                    .system
+                    .show_error(&format!("Failed to save brush: {}", e)),
+            },
+            Ok(None) => {}
+            Err(e) => self
+                .system
+                .show_error(&format!("Could not get file name: {:?}", e)),
+        }
+    }
+
+    /// Ask for a file name and load a character brush from it.
+    fn load_brush(&mut self) {
+        if !self.system.has_open_file_dialog() {
+            return;
+        }
+        let options = OpenFileOptions::for_brush(self.brush_filename.as_deref());
+        match self.system.open_file_dialog(options) {
+            Ok(Some(filename)) => match storage::load_brush(&filename) {
+                Ok(brush) => {
+                    self.brush_library.add(brush_name(&filename), brush.clone());
+                    self.use_brush(brush);
+                    self.brush_filename = Some(filename);
+                }
+                Err(e) => self
+                    .system
+                    .show_error(&format!("Failed to load brush: {}", e)),
+            },
+            Ok(None) => {}
+            Err(e) => self
+                .system
+                .show_error(&format!("Could not get file name: {:?}", e)),
+        }
+    }
+
+    /// Ask for a palette file and use it in the current document.
+    fn load_palette(&mut self) {
+        match self
+            .system
+            .open_file_dialog(OpenFileOptions::for_palette(None))
+        {
+            Ok(Some(filename)) => match CustomPalette::load(&filename) {
+                Ok(palette) => {
+                    if let Some(ed) = self.editors.active_mut() {
+                        ed.apply_action(Action::Document(DocAction::SetPalette(
+                            VicPalette::Custom(Arc::new(palette)),
+                        )));
+                    }
+                }
+                Err(e) => self.system.show_error(&format!(
+                    "Failed to load palette {}: {}",
+                    filename.display(),
+                    e
+                )),
+            },
+            Ok(None) => {}
+            Err(e) => self
+                .system
+                .show_error(&format!("Could not get file name: {:?}", e)),
+        }
+    }
+
+    /// Ask for a directory and add all brushes in it to the library.
+    fn import_brush_directory(&mut self) {
+        let initial_path = self.brush_filename.as_deref().and_then(Path::parent);
+        let directory = match self.system.open_directory_dialog(initial_path) {
+            Ok(Some(directory)) => directory,
+            Ok(None) => return,
+            Err(e) => {
+                self.system
+                    .show_error(&format!("Could not get directory: {:?}", e));
+                return;
+            }
+        };
+        let results = match storage::load_brush_directory(&directory) {
+            Ok(results) => results,
+            Err(e) => {
+                self.system
+                    .show_error(&format!("Failed to read directory: {}", e));
+                return;
+            }
+        };
+        let mut failures = Vec::new();
+        for (filename, result) in results {
+            match result {
+                Ok(brush) => self.brush_library.add(brush_name(&filename), brush),
+                Err(e) => failures.push(format!("{}: {}", filename.display(), e)),
+            }
+        }
+        if !failures.is_empty() {
+            self.system.show_error(&format!(
+                "Failed to load some brushes:\n\n{}",
+                failures.join("\n")
+            ));
+        }
+        self.brush_library.visible = true;
+    }
+
+    /// Set the character brush and switch to the Char Brush tool.
+    fn use_brush(&mut self, brush: ImgVec<Char>) {
+        self.brush = brush;
+        if let Some(ed) = self.editors.active_mut() {
+            ed.ui_state.tool = ToolType::CharBrush;
+        }
+    }
+
     pub fn editor_mut(&mut self, index: usize) -> Option<&mut Editor> {
         self.editors.get_mut(index)
     }
 
     fn apply_action(&mut self, action: Action) {
         match action {
-            Action::Document(_) => eprintln!("Unhandled Document action"),
+            Action::Document(_) | Action::ContinueStroke(_) => {
+                eprintln!("Unhandled Document action")
+            }
             Action::Ui(ui_action) => match ui_action {
                 UiAction::NewDocument(doc) => {
-                    self.add_editor(doc);
+                    self.add_editor(*doc);
+                }
+                UiAction::DuplicateDocument => {
+                    if let Some(ed) = self.editors.active() {
+                        let mut doc = ed.doc.clone();
+                        doc.filename = None;
+                        doc.file_modified = None;
+                        let index = self.add_editor(doc);
+                        // The copy is not saved anywhere yet
+                        self.editors
+                            .get_mut(index)
+                            .unwrap()
+                            .history
+                            .set_saved(false);
+                    }
                 }
                 UiAction::CloseEditor(index) => {
                     self.editors.remove(index);
                 }
+                UiAction::CloseAll => {
+                    for index in (0..self.editors.len()).rev() {
+                        self.editors.remove(index);
+                    }
+                    // There must always be a document to show
+                    self.add_editor(Document::new());
+                }
                 UiAction::CreateCharBrush { rect } => {
                     if let Some(ed) = self.editors.active_mut() {
                         if let Some(rect) =
@@ -447,6 +1337,81 @@ impl Application {
                 UiAction::MirrorBrushY => {
                     brush::mirror_y(&mut self.brush);
                 }
+                UiAction::RotateBrush => {
+                    if let Err(e) = brush::rotate_cw(&mut self.brush) {
+                        if let Some(ed) = self.editors.active_mut() {
+                            ed.ui_state.show_warning(e.to_string());
+                        }
+                    }
+                }
+                UiAction::SaveBrush => self.save_brush(),
+                UiAction::LoadBrush => self.load_brush(),
+                UiAction::AddBrushToLibrary => {
+                    let name = format!("Brush {}", self.brush_library.len() + 1);
+                    self.brush_library.add(name, self.brush.clone());
+                }
+                UiAction::ImportBrushDirectory => self.import_brush_directory(),
+                UiAction::UseLibraryBrush(index) => {
+                    if let Some(entry) = self.brush_library.get(index) {
+                        let brush = entry.brush.clone();
+                        self.use_brush(brush);
+                    }
+                }
+                UiAction::CopyImage => {
+                    if let Some(ed) = self.editors.active() {
+                        let image = ed.doc.render(&ViewSettings::default());
+                        if let Err(e) = self.system.copy_image_to_clipboard(&image) {
+                            self.system
+                                .show_error(&format!("Could not copy image: {}", e));
+                        }
+                    }
+                }
+                UiAction::PasteImage => {
+                    if let Some(ed) = self.editors.active_mut() {
+                        match self.system.paste_image() {
+                            Ok(Some(image)) => {
+                                let mut import =
+                                    Import::from_image(DynamicImage::ImageRgba8(image), None);
+                                import.clamp_to(&ed.doc.image);
+                                ed.start_import(import);
+                            }
+                            Ok(None) => ed.ui_state.show_warning(
+                                "The clipboard does not contain an image".to_string(),
+                            ),
+                            Err(e) => {
+                                self.system
+                                    .show_error(&format!("Could not paste image: {}", e));
+                            }
+                        }
+                    }
+                }
+                UiAction::LoadPalette => self.load_palette(),
+                UiAction::ShowSettingsDialog => {
+                    self.settings_dialog = Some(Settings {
+                        undo_limit: self.undo_limit(),
+                    });
+                }
+                UiAction::ShowKeymapDialog => {
+                    self.keymap_dialog = Some(ui::keymap::KeymapDialog::new(&self.keymap));
+                }
+                UiAction::ShowNewDocumentDialog => {
+                    self.new_document_dialog = Some(Default::default());
+                }
+                UiAction::Revert => {
+                    let index = self.editors.active_index();
+                    if let Some(ed) = self.editors.active() {
+                        let confirmed = self
+                            .system
+                            .request_confirmation(&format!(
+                                "Discard all changes to {} since it was saved?",
+                                ed.doc.visible_name()
+                            ))
+                            .unwrap_or(false);
+                        if confirmed {
+                            self.reload(index);
+                        }
+                    }
+                }
                 _action => {
                     eprintln!("Unhandled UiAction");
                 }
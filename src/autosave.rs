@@ -0,0 +1,258 @@
+//! Periodic saving of unsaved documents to a recovery directory,
+//! so work is not lost if the application crashes.
+//!
+//! Each instance of the application holds a lock on a lock file for as long as it runs.
+//! Recovery files of other instances are only treated as left over after a crash
+//! if their lock file is not locked, as the operating system releases the lock when a process dies.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{editor::Editor, error::Error, storage, Document};
+
+/// How often to save unless configured otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// All recovery files start with this.
+const FILE_PREFIX: &str = "recovery-";
+
+/// All lock files start with this.
+const LOCK_PREFIX: &str = "session-";
+
+pub struct Autosave {
+    directory: PathBuf,
+    interval: Duration,
+    last_save: Instant,
+    /// Identifies the recovery files saved by this instance of the application.
+    session: String,
+    /// This session's lock file, locked while the application runs.
+    /// None if it could not be locked.
+    lock: Option<File>,
+}
+
+impl Autosave {
+    pub fn new(directory: PathBuf, interval: Duration) -> Self {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self::with_session(
+            directory,
+            interval,
+            format!("{}-{}", start_time, std::process::id()),
+        )
+    }
+
+    fn with_session(directory: PathBuf, interval: Duration, session: String) -> Self {
+        let lock = match lock_session(&directory, &session) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                eprintln!("Failed to lock recovery files: {}", e);
+                None
+            }
+        };
+        Self {
+            directory,
+            interval,
+            last_save: Instant::now(),
+            session,
+            lock,
+        }
+    }
+
+    /// If it is time, save all unsaved documents,
+    /// and remove the recovery files for documents that are saved or closed.
+    pub fn update<'a>(&mut self, editors: impl Iterator<Item = &'a Editor>) -> Result<(), Error> {
+        if self.last_save.elapsed() < self.interval {
+            return Ok(());
+        }
+        self.last_save = Instant::now();
+        fs::create_dir_all(&self.directory)?;
+        let mut saved = HashSet::new();
+        for ed in editors.filter(|ed| !ed.history.is_saved()) {
+            let filename = self.filename(&ed.doc);
+            // Only saves the document, so the history's saved state is not affected.
+            storage::save(&ed.doc, &filename)?;
+            saved.insert(filename);
+        }
+        for filename in self.session_files()? {
+            if !saved.contains(&filename) {
+                fs::remove_file(&filename)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all recovery files saved by this instance of the application, and its lock file.
+    pub fn remove_session_files(&mut self) -> Result<(), Error> {
+        for filename in self.session_files()? {
+            fs::remove_file(&filename)?;
+        }
+        if self.lock.take().is_some() {
+            fs::remove_file(lock_filename(&self.directory, &self.session))?;
+        }
+        Ok(())
+    }
+
+    /// Get the recovery files left by instances of the application that are no longer running.
+    /// Files of other instances that are still running are not included.
+    pub fn leftover_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut dead_sessions = HashSet::new();
+        let mut files = Vec::new();
+        for filename in recovery_files(&self.directory)? {
+            let session = match session_of(&filename) {
+                Some(session) if session != self.session => session,
+                _ => continue,
+            };
+            if dead_sessions.contains(&session) || !self.is_running(&session) {
+                dead_sessions.insert(session);
+                files.push(filename);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Remove the lock files of instances of the application that are no longer running.
+    pub fn remove_dead_locks(&self) -> Result<(), Error> {
+        if !self.directory.exists() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let session = match path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| f.strip_prefix(LOCK_PREFIX))
+            {
+                Some(session) => session.to_string(),
+                None => continue,
+            };
+            if session != self.session && !self.is_running(&session) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether the instance of the application that saved files with the given session
+    /// is still running, by checking whether its lock file is locked.
+    /// If that can't be determined, it is assumed to be running so its files are left alone.
+    fn is_running(&self, session: &str) -> bool {
+        let file = match File::open(lock_filename(&self.directory, session)) {
+            Ok(file) => file,
+            // Without a lock file, the files are from a version that didn't lock
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return false,
+            Err(_) => return true,
+        };
+        try_lock(&file).is_err()
+    }
+
+    fn session_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let session_prefix = self.session_prefix();
+        Ok(recovery_files(&self.directory)?
+            .into_iter()
+            .filter(|f| file_name_starts_with(f, &session_prefix))
+            .collect())
+    }
+
+    fn session_prefix(&self) -> String {
+        format!("{}{}-", FILE_PREFIX, self.session)
+    }
+
+    fn filename(&self, doc: &Document) -> PathBuf {
+        self.directory.join(format!(
+            "{}{}.{}",
+            self.session_prefix(),
+            doc.index_number,
+            storage::NATIVE_EXTENSION
+        ))
+    }
+}
+
+/// Create and lock the lock file of a session.
+fn lock_session(directory: &Path, session: &str) -> Result<File, Error> {
+    fs::create_dir_all(directory)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(lock_filename(directory, session))?;
+    try_lock(&file)?;
+    Ok(file)
+}
+
+/// Lock a file for exclusive use without waiting for it to be unlocked.
+#[cfg(not(target_arch = "wasm32"))]
+fn try_lock(file: &File) -> std::io::Result<()> {
+    fs2::FileExt::try_lock_exclusive(file)
+}
+
+/// There are no files to lock on the web.
+#[cfg(target_arch = "wasm32")]
+fn try_lock(_file: &File) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+fn lock_filename(directory: &Path, session: &str) -> PathBuf {
+    directory.join(format!("{}{}", LOCK_PREFIX, session))
+}
+
+/// Get the session of a recovery file, from a file name like `recovery-<session>-<index>.pixelpen`.
+fn session_of(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.strip_prefix(FILE_PREFIX)?;
+    let (session, _) = name.rsplit_once('-')?;
+    Some(session.to_string())
+}
+
+/// Get all recovery files in a directory.
+fn recovery_files(directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if file_name_starts_with(&path, FILE_PREFIX) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn file_name_starts_with(path: &Path, prefix: &str) -> bool {
+    path.file_name()
+        .map(|f| f.to_string_lossy().starts_with(prefix))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Autosave;
+    use crate::{storage, Document};
+
+    #[test]
+    fn files_of_running_instances_are_not_leftovers() {
+        let directory =
+            std::env::temp_dir().join(format!("pixel_pen_test_autosave_{}", std::process::id()));
+        let mut other = Autosave::with_session(directory.clone(), Duration::ZERO, "1-1".into());
+        let filename = other.filename(&Document::new());
+        storage::save(&Document::new(), &filename).unwrap();
+
+        let this = Autosave::with_session(directory.clone(), Duration::ZERO, "2-2".into());
+        assert!(this.leftover_files().unwrap().is_empty());
+
+        // Simulate a crash by releasing the lock without removing any files
+        other.lock = None;
+        assert_eq!(this.leftover_files().unwrap(), vec![filename]);
+        this.remove_dead_locks().unwrap();
+        assert!(!super::lock_filename(&directory, "1-1").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 use imgref::ImgVec;
 
 use crate::vic::Char;
+#[cfg(feature = "gui")]
+use crate::vic::DisallowedEdit;
 
 pub fn mirror_x(brush: &mut ImgVec<Char>) {
     for row in brush.rows_mut() {
@@ -28,3 +30,73 @@ pub fn mirror_y(brush: &mut ImgVec<Char>) {
         c.mirror_y();
     }
 }
+
+/// Rotate the brush 90 degrees clockwise.
+/// Multicolor pixels are not square, so this is only allowed if all cells are high-res.
+#[cfg(feature = "gui")]
+pub fn rotate_cw(brush: &mut ImgVec<Char>) -> Result<(), DisallowedEdit> {
+    if brush.pixels().any(|char| char.is_multicolor()) {
+        return Err(DisallowedEdit::RotateMulticolor);
+    }
+    let (width, height) = (brush.width(), brush.height());
+    let mut rotated = ImgVec::new(vec![Char::default(); width * height], height, width);
+    for (row, chars) in brush.rows().enumerate() {
+        for (column, char) in chars.iter().enumerate() {
+            let mut char = *char;
+            char.rotate_cw_bitmap();
+            rotated[(height - 1 - row, column)] = char;
+        }
+    }
+    *brush = rotated;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    #[cfg(feature = "gui")]
+    use super::rotate_cw;
+    use super::{mirror_x, mirror_y};
+    use crate::vic::Char;
+
+    fn brush() -> ImgVec<Char> {
+        let a = Char::new_highres([0x80, 0, 0, 0, 0, 0, 0, 0], 1);
+        let b = Char::new_highres([0; 8], 2);
+        ImgVec::new(vec![a, b, b, b], 2, 2)
+    }
+
+    #[test]
+    fn mirror_x_moves_and_mirrors_cells() {
+        let mut brush = brush();
+        mirror_x(&mut brush);
+        assert!(brush[(1usize, 0usize)] == Char::new_highres([0x01, 0, 0, 0, 0, 0, 0, 0], 1));
+        assert!(brush[(0usize, 0usize)] == Char::new_highres([0; 8], 2));
+    }
+
+    #[test]
+    fn mirror_y_moves_and_mirrors_cells() {
+        let mut brush = brush();
+        mirror_y(&mut brush);
+        assert!(brush[(0usize, 1usize)] == Char::new_highres([0, 0, 0, 0, 0, 0, 0, 0x80], 1));
+        assert!(brush[(0usize, 0usize)] == Char::new_highres([0; 8], 2));
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn rotate_cw_moves_and_rotates_cells() {
+        let a = Char::new_highres([0x80, 0, 0, 0, 0, 0, 0, 0], 1);
+        let b = Char::new_highres([0; 8], 2);
+        let mut brush = ImgVec::new(vec![a, b, b], 3, 1);
+        rotate_cw(&mut brush).unwrap();
+        assert_eq!((brush.width(), brush.height()), (1, 3));
+        assert!(brush[(0usize, 0usize)] == Char::new_highres([0x01, 0, 0, 0, 0, 0, 0, 0], 1));
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn rotate_multicolor_is_disallowed() {
+        let mut brush = ImgVec::new(vec![Char::new([0; 8], 1)], 1, 1);
+        assert!(rotate_cw(&mut brush).is_err());
+    }
+}
@@ -75,6 +75,7 @@ pub trait CellCoordinates: CellImageSize {
 
     /// Return the top-left edge of the character that is closest to the given point.
     /// May return coordinates outside the image
+    #[cfg(feature = "gui")]
     fn cell_rounded_unclipped(&self, point: PixelPoint) -> (CellPos, i32, i32) {
         self.cell_unclipped(PixelPoint::new(
             point.x + Self::CELL_WIDTH as i32 / 2,
@@ -82,6 +83,7 @@ pub trait CellCoordinates: CellImageSize {
         ))
     }
 
+    #[cfg(feature = "gui")]
     fn cell_selection(&self, p0: PixelPoint, p1: PixelPoint) -> WithinBounds<CellRect> {
         let (c0, _, _) = self.cell_rounded_unclipped(p0);
         let (c1, _, _) = self.cell_rounded_unclipped(p1);
@@ -1,7 +1,15 @@
 //! Command-line interface
 
-use pixel_pen::{error::Error, storage, Application, Document};
-use std::path::PathBuf;
+use pixel_pen::{
+    error::Error,
+    import::{self, Import},
+    storage, Application, CustomPalette, Document, VicPalette,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -10,13 +18,70 @@ struct Opts {
     /// Open the given file in import mode
     #[structopt(long = "--import")]
     import_file: Option<PathBuf>,
+    /// Width in pixels to scale the imported image to.
+    /// The height is calculated from the width.
+    #[structopt(long = "--import-width", requires = "import-file")]
+    import_width: Option<u32>,
+    /// Color format to import the image as.
+    #[structopt(
+        long = "--import-format",
+        requires = "import-file",
+        possible_values = import::FORMAT_NAMES
+    )]
+    import_format: Option<String>,
+    /// Filter to use when scaling the imported image.
+    #[structopt(
+        long = "--import-filter",
+        requires = "import-file",
+        possible_values = import::FILTER_NAMES
+    )]
+    import_filter: Option<String>,
+    /// Pixel position of the left edge of the imported image.
+    #[structopt(
+        long = "--import-left",
+        requires = "import-file",
+        allow_hyphen_values = true
+    )]
+    import_left: Option<i32>,
+    /// Pixel position of the top edge of the imported image.
+    #[structopt(
+        long = "--import-top",
+        requires = "import-file",
+        allow_hyphen_values = true
+    )]
+    import_top: Option<i32>,
     /// Files to load
     #[structopt(parse(from_os_str))]
     filenames: Vec<PathBuf>,
     /// Save the image to the given file and quit.
     /// File may be in pixelpen format or the image may be exported as a standard image file.
+    /// If --import is given, the image is imported before saving.
     #[structopt(long = "--save")]
     save_file: Option<PathBuf>,
+    /// Convert each of the given files and save them in this directory, then quit.
+    /// The output files get the same names as the input files, with the extension given by --format.
+    #[structopt(
+        long = "--out-dir",
+        requires = "format",
+        conflicts_with_all = &["save-file", "import-file"]
+    )]
+    out_dir: Option<PathBuf>,
+    /// File name extension of the format to convert to with --out-dir,
+    /// e.g. "pixelpen", "png", or "asm".
+    #[structopt(long = "--format", requires = "out-dir")]
+    format: Option<String>,
+    /// How often to save unsaved documents for recovery after a crash, in seconds.
+    /// 0 disables it.
+    #[structopt(long = "--autosave")]
+    autosave_seconds: Option<u64>,
+    /// How many steps can be undone, instead of the limit in the settings.
+    /// Each step keeps what it changed in memory.
+    #[structopt(long = "--undo-limit")]
+    undo_limit: Option<usize>,
+    /// Display the loaded and converted files with the colors from a file in GIMP (.gpl),
+    /// Adobe Color Table (.act) or JSON format. The file must have 16 colors.
+    #[structopt(long = "--palette")]
+    palette_file: Option<PathBuf>,
 }
 
 /// Parses command-line arguments and prints any errors, returns Application ready to start.
@@ -24,21 +89,56 @@ struct Opts {
 /// On error, returns the exit code for `process::exit`.
 pub fn main() -> Result<Option<Application>, i32> {
     let opts = Opts::from_args();
-    let docs = opts
+    let palette = match &opts.palette_file {
+        Some(filename) => match CustomPalette::load(filename) {
+            Ok(palette) => Some(VicPalette::Custom(Arc::new(palette))),
+            Err(e) => {
+                eprintln!(
+                    "Could not load palette {}: {}",
+                    filename.to_string_lossy(),
+                    e
+                );
+                return Err(1);
+            }
+        },
+        None => None,
+    };
+    if let (Some(out_dir), Some(format)) = (&opts.out_dir, &opts.format) {
+        return if convert_files(&opts.filenames, out_dir, format, palette.as_ref()) {
+            Ok(None)
+        } else {
+            Err(1)
+        };
+    }
+    let mut docs = opts
         .filenames
         .iter()
         .map(|filename| {
-            storage::load_any_file(filename).map_err(|err| {
-                eprintln!(
-                    "Could not load file {}: {}",
-                    filename.to_string_lossy(),
+            storage::load_any_file(filename)
+                .map(|doc| with_palette(doc, palette.as_ref()))
+                .map_err(|err| {
+                    eprintln!(
+                        "Could not load file {}: {}",
+                        filename.to_string_lossy(),
+                        err
+                    );
                     err
-                );
-                err
-            })
+                })
         })
         .collect::<Result<Vec<Document>, Error>>()
         .map_err(|_| 1)?;
+    if let (Some(filename), Some(_)) = (&opts.import_file, &opts.save_file) {
+        if docs.is_empty() {
+            docs.push(with_palette(Document::new(), palette.as_ref()));
+        }
+        let doc = docs.last_mut().unwrap();
+        if let Err(e) = create_import(&opts, filename, doc)
+            .and_then(|mut import| import.paste_into(&mut doc.image))
+        {
+            eprintln!("Failed to import {}: {}", filename.to_string_lossy(), e);
+            return Err(1);
+        }
+    }
     let doc = docs.last(); // Apply any commands on the last document
     match execute_commands(&opts, doc) {
         Err(err) => {
@@ -48,6 +148,16 @@ pub fn main() -> Result<Option<Application>, i32> {
         Ok(true) => Ok(None),
         Ok(false) => {
             let mut app = Application::new();
+            if let Some(limit) = opts.undo_limit {
+                app.set_undo_limit(limit);
+            }
+            if let Some(seconds) = opts.autosave_seconds {
+                app.set_autosave_interval(if seconds == 0 {
+                    None
+                } else {
+                    Some(Duration::from_secs(seconds))
+                });
+            }
             let indices = docs
                 .into_iter()
                 .map(|doc| app.add_editor(doc))
@@ -55,14 +165,14 @@ pub fn main() -> Result<Option<Application>, i32> {
             let editor_index = indices
                 .last()
                 .copied()
-                .unwrap_or_else(|| app.add_editor(Document::new()));
-            if let Some(filename) = opts.import_file {
-                match app
-                    .editor_mut(editor_index)
-                    .unwrap()
-                    .start_import_mode(&filename)
-                {
-                    Ok(_) => Ok(Some(app)),
+                .unwrap_or_else(|| app.add_editor(with_palette(Document::new(), palette.as_ref())));
+            if let Some(filename) = &opts.import_file {
+                let editor = app.editor_mut(editor_index).unwrap();
+                match create_import(&opts, filename, &editor.doc) {
+                    Ok(import) => {
+                        editor.start_import(import);
+                        Ok(Some(app))
+                    }
                     Err(e) => {
                         eprintln!("Failed to open {:?} for import: {}", filename, e);
                         Err(1)
@@ -75,6 +185,116 @@ pub fn main() -> Result<Option<Application>, i32> {
     }
 }
 
+/// Load an image to import into `target`, with the settings given on the command line.
+fn create_import(opts: &Opts, filename: &Path, target: &Document) -> Result<Import, Error> {
+    let mut import = Import::load(filename)?;
+    import.clamp_to(&target.image);
+    let settings = &mut import.settings;
+    if let Some(width) = opts.import_width {
+        settings.width = width;
+    }
+    // The names have already been checked by structopt
+    if let Some(format) = &opts.import_format {
+        settings.format = import::format_from_name(format).unwrap();
+    }
+    if let Some(filter) = &opts.import_filter {
+        settings.filter = import::filter_from_name(filter).unwrap();
+    }
+    if let Some(left) = opts.import_left {
+        settings.left = left;
+    }
+    if let Some(top) = opts.import_top {
+        settings.top = top;
+    }
+    Ok(import)
+}
+
+/// Use the palette given on the command line, if any, in a document.
+fn with_palette(mut doc: Document, palette: Option<&VicPalette>) -> Document {
+    if let Some(palette) = palette {
+        doc.image.set_palette(palette.clone());
+    }
+    doc
+}
+
+/// Load each file and save it in `out_dir` in the format given by the extension `format`.
+/// Documents get `palette` if it is given.
+/// Prints the result for each file.
+/// Returns false if any file failed.
+/// Nothing is converted if two files would be saved with the same name.
+fn convert_files(
+    filenames: &[PathBuf],
+    out_dir: &Path,
+    format: &str,
+    palette: Option<&VicPalette>,
+) -> bool {
+    let out_files = match output_filenames(filenames, out_dir, format) {
+        Ok(out_files) => out_files,
+        Err(message) => {
+            eprintln!("{}", message);
+            return false;
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(out_dir) {
+        eprintln!(
+            "Could not create directory {}: {}",
+            out_dir.to_string_lossy(),
+            e
+        );
+        return false;
+    }
+    let mut failures = 0;
+    for (filename, out_file) in filenames.iter().zip(out_files) {
+        let result = storage::load_any_file(filename)
+            .and_then(|doc| storage::save_any_file(&with_palette(doc, palette), &out_file));
+        match result {
+            Ok(()) => println!(
+                "{} -> {}",
+                filename.to_string_lossy(),
+                out_file.to_string_lossy()
+            ),
+            Err(e) => {
+                eprintln!("{}: Failed: {}", filename.to_string_lossy(), e);
+                failures += 1;
+            }
+        }
+    }
+    if failures != 0 {
+        eprintln!("{} of {} files failed", failures, filenames.len());
+    }
+    failures == 0
+}
+
+/// The file in `out_dir` to save each input file to when converting to `format`.
+/// Returns an error message if an input is not a file name,
+/// or if two inputs would be saved to the same file.
+fn output_filenames(
+    filenames: &[PathBuf],
+    out_dir: &Path,
+    format: &str,
+) -> Result<Vec<PathBuf>, String> {
+    let mut out_files: Vec<PathBuf> = Vec::with_capacity(filenames.len());
+    for (i, filename) in filenames.iter().enumerate() {
+        let stem = filename
+            .file_stem()
+            .ok_or_else(|| format!("{}: Not a file name", filename.to_string_lossy()))?;
+        let mut name = stem.to_os_string();
+        name.push(".");
+        name.push(format);
+        let out_file = out_dir.join(name);
+        if let Some(other) = out_files.iter().position(|f| *f == out_file) {
+            return Err(format!(
+                "{} and {} would both be saved as {}",
+                filenames[other].to_string_lossy(),
+                filenames[i].to_string_lossy(),
+                out_file.to_string_lossy()
+            ));
+        }
+        out_files.push(out_file);
+    }
+    Ok(out_files)
+}
+
 /// Returns Ok(true) if a command was executed and the app should quit.
 /// Returns Ok(false) if the app should start the GUI.
 fn execute_commands(opts: &Opts, doc: Option<&Document>) -> Result<bool, Error> {
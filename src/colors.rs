@@ -1,7 +1,8 @@
+#[cfg(feature = "gui")]
 use eframe::egui::Color32;
 use rgb::RGBA8;
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TrueColor(image::Rgba<u8>);
 
 impl TrueColor {
@@ -50,6 +51,7 @@ impl From<image::Rgba<u8>> for TrueColor {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<TrueColor> for Color32 {
     fn from(c: TrueColor) -> Self {
         let c = c.0;
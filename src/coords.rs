@@ -1,9 +1,13 @@
 //! Screen, pixel and character cell coordinate systems.
 
+pub use anchor::Anchor;
 pub use bounds::{clamp_rect_to_bounds, rect_within_size, within_bounds, WithinBounds};
+#[cfg(feature = "gui")]
 pub use transform::PixelTransform;
 
+mod anchor;
 mod bounds;
+#[cfg(feature = "gui")]
 mod transform;
 
 pub struct PixelUnit;
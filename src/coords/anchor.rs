@@ -0,0 +1,96 @@
+//! Placement of content when the size of its container changes.
+
+use euclid::Vector2D;
+
+use super::{CellUnit, SizeInCells};
+
+/// Which part of an image that stays in place when the image is resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// All anchors, row by row from the top left.
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+
+    /// Horizontal and vertical placement in half sizes:
+    /// 0 = left/top, 1 = center, 2 = right/bottom.
+    fn halves(&self) -> (i32, i32) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+
+    /// Get how far to move the content when resizing from `old_size` to `new_size`.
+    pub fn offset(&self, old_size: SizeInCells, new_size: SizeInCells) -> Vector2D<i32, CellUnit> {
+        let (h, v) = self.halves();
+        Vector2D::new(
+            (new_size.width - old_size.width) * h / 2,
+            (new_size.height - old_size.height) * v / 2,
+        )
+    }
+
+    /// A symbol to show for the anchor in the user interface.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "↖",
+            Anchor::Top => "↑",
+            Anchor::TopRight => "↗",
+            Anchor::Left => "←",
+            Anchor::Center => "•",
+            Anchor::Right => "→",
+            Anchor::BottomLeft => "↙",
+            Anchor::Bottom => "↓",
+            Anchor::BottomRight => "↘",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Anchor;
+    use crate::coords::SizeInCells;
+
+    #[test]
+    fn offset_when_growing() {
+        let old = SizeInCells::new(2, 2);
+        let new = SizeInCells::new(6, 4);
+        assert_eq!(Anchor::TopLeft.offset(old, new).to_tuple(), (0, 0));
+        assert_eq!(Anchor::Center.offset(old, new).to_tuple(), (2, 1));
+        assert_eq!(Anchor::BottomRight.offset(old, new).to_tuple(), (4, 2));
+    }
+
+    #[test]
+    fn offset_when_shrinking() {
+        let old = SizeInCells::new(6, 4);
+        let new = SizeInCells::new(2, 2);
+        assert_eq!(Anchor::Right.offset(old, new).to_tuple(), (-4, -1));
+    }
+}
@@ -1,16 +1,40 @@
 //! Data for the "document" the user is working on.
 //! The document is what is saved to file.
 
-use std::path::PathBuf;
+use std::{cell::RefCell, path::PathBuf, time::SystemTime};
 
+use image::{imageops::FilterType, RgbaImage};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    actions::DocAction, error::DisallowedAction, mutation_monitor::MutationMonitor, vic::VicImage,
+    actions::{DocAction, UndoData},
+    cell_image::CellImageSize,
+    coords::{self, CellRect, WithinBounds},
+    error::DisallowedAction,
+    metadata::Metadata,
+    mutation_monitor::MutationMonitor,
+    vic::{DisallowedEdit, VicImage, VicPalette, ViewSettings},
 };
 
 const ERROR_FILENAME: &str = "INVALID FILENAME";
 
+/// Maximum width and height of a document's thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+/// The last image rendered from a document, so it can be reused while the image is unchanged.
+#[derive(Default)]
+struct RenderCache {
+    /// The settings and image generation the image was rendered with, and the rendered image.
+    last: RefCell<Option<(ViewSettings, u64, RgbaImage)>>,
+}
+
+/// Copies start empty, as the rendered image would otherwise be copied along with e.g. undo states.
+impl Clone for RenderCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
 /// A "document" the user is working on.
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -20,7 +44,26 @@ pub struct Document {
     /// Number for the document. For generating "Untitled-X" temporary name for unsaved files.
     #[serde(skip)]
     pub index_number: u32,
+    /// When the file was modified, as of when it was last loaded or saved.
+    /// For detecting when another program changes it.
+    #[serde(skip)]
+    pub file_modified: Option<SystemTime>,
     pub image: MutationMonitor<VicImage>,
+    /// If set, edits that would make the image use more unique characters than this are disallowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars: Option<usize>,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+    #[serde(skip)]
+    render_cache: RenderCache,
+}
+
+/// The settings of a document that are changed in the Document Settings dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentSettings {
+    pub max_chars: Option<usize>,
+    pub palette: VicPalette,
+    pub stable_characters: bool,
 }
 
 impl Default for Document {
@@ -34,7 +77,11 @@ impl Document {
         Self {
             filename: None,
             index_number: 0,
+            file_modified: None,
             image: MutationMonitor::new_dirty(VicImage::default()),
+            max_chars: None,
+            metadata: Metadata::default(),
+            render_cache: RenderCache::default(),
         }
     }
 
@@ -42,7 +89,11 @@ impl Document {
         Self {
             filename: None,
             index_number: 0,
+            file_modified: None,
             image: MutationMonitor::new_dirty(image),
+            max_chars: None,
+            metadata: Metadata::default(),
+            render_cache: RenderCache::default(),
         }
     }
 
@@ -66,40 +117,340 @@ impl Document {
         }
     }
 
+    /// Render the image as true color pixels.
+    /// The result is cached, so rendering again with the same settings is cheap until the image changes.
+    pub fn render(&self, settings: &ViewSettings) -> RgbaImage {
+        let generation = self.image.generation();
+        let mut last = self.render_cache.last.borrow_mut();
+        match &*last {
+            Some((s, g, image)) if s == settings && *g == generation => image.clone(),
+            _ => {
+                let image = self.image.render_with_settings(settings);
+                *last = Some((settings.clone(), generation, image.clone()));
+                image
+            }
+        }
+    }
+
+    /// Create a new document from some cells of this one, using the same colors and palette.
+    pub fn extract(&self, rect: &WithinBounds<CellRect>) -> Document {
+        let mut image = VicImage::with_content(self.image.grab_cells(rect));
+        image.set_global_colors(self.image.global_colors().clone());
+        image.set_palette(self.image.palette().clone());
+        let mut doc = Document::from_image(image);
+        doc.max_chars = self.max_chars;
+        doc
+    }
+
+    /// Create an empty document with the same size, colors and palette as this one.
+    pub fn blank_copy(&self) -> Document {
+        let size = self.image.size_in_cells();
+        let mut image = VicImage::new(size.width as usize, size.height as usize);
+        image.set_global_colors(self.image.global_colors().clone());
+        image.set_palette(self.image.palette().clone());
+        let mut doc = Document::from_image(image);
+        doc.max_chars = self.max_chars;
+        doc
+    }
+
+    /// Render a small version of the image, at most `THUMBNAIL_SIZE` pixels wide and high.
+    /// The pixel aspect ratio is taken into account, so the thumbnail looks like the image on screen.
+    pub fn thumbnail(&self) -> RgbaImage {
+        let image = self.render(&ViewSettings::default());
+        let width = image.width() as f32 * self.image.pixel_aspect_ratio();
+        let height = image.height() as f32;
+        let scale = (THUMBNAIL_SIZE as f32 / width).min(THUMBNAIL_SIZE as f32 / height);
+        image::imageops::resize(
+            &image,
+            ((width * scale).round() as u32).max(1),
+            ((height * scale).round() as u32).max(1),
+            FilterType::Nearest,
+        )
+    }
+
+    /// The current values of the settings in the Document Settings dialog.
+    pub fn settings(&self) -> DocumentSettings {
+        DocumentSettings {
+            max_chars: self.max_chars,
+            palette: self.image.palette().clone(),
+            stable_characters: self.image.stable_characters(),
+        }
+    }
+
+    /// Change the settings in the Document Settings dialog.
+    /// Returns true if any of them changed.
+    fn set_settings(&mut self, settings: &DocumentSettings) -> bool {
+        if self.settings() == *settings {
+            return false;
+        }
+        self.max_chars = settings.max_chars;
+        self.image.set_palette(settings.palette.clone());
+        self.image.set_stable_characters(settings.stable_characters);
+        true
+    }
+
     /// Execute an action on this document
     pub fn apply(&mut self, action: &DocAction) -> Result<bool, Box<dyn DisallowedAction>> {
-        let image = &mut self.image;
         match action {
-            DocAction::ChangeRegister { index, value } => {
-                Ok(image.set_global_color(*index, *value))
+            DocAction::SetMaxChars(max_chars) => {
+                if self.max_chars == *max_chars {
+                    return Ok(false);
+                }
+                self.max_chars = *max_chars;
+                Ok(true)
+            }
+            DocAction::SetSettings(settings) => Ok(self.set_settings(settings)),
+            DocAction::SetMetadata(metadata) => {
+                if self.metadata == **metadata {
+                    return Ok(false);
+                }
+                self.metadata = (**metadata).clone();
+                Ok(true)
             }
-            DocAction::PasteTrueColor {
-                source,
-                target,
-                format,
-            } => {
-                image.paste_image(source, *target, *format);
+            _ => match self.max_chars {
+                Some(max_chars) => self.apply_with_max_chars(action, max_chars),
+                None => apply_to_image(&mut self.image, action),
+            },
+        }
+    }
+
+    /// Apply an action that changes the image, unless it would make the image use
+    /// more than `max_chars` characters. An action that doesn't add characters
+    /// is allowed even if the image already uses too many.
+    fn apply_with_max_chars(
+        &mut self,
+        action: &DocAction,
+        max_chars: usize,
+    ) -> Result<bool, Box<dyn DisallowedAction>> {
+        let chars_before = self.image.current_unique_char_count();
+        let undo_data = action.invert(self);
+        match apply_to_image(&mut self.image, action) {
+            Ok(true) => {
+                let chars_after = self.image.current_unique_char_count();
+                if chars_after > max_chars && chars_after > chars_before {
+                    self.restore(undo_data);
+                    return Err(Box::new(DisallowedEdit::TooManyCharacters(max_chars)));
+                }
                 Ok(true)
             }
-            DocAction::Plot { area, color } => image.plot(area, *color),
-            DocAction::Fill { area, color } => image.fill_cells(area, *color),
-            DocAction::CellColor { area, color } => {
-                let c = image.color_index_from_paint_color(color);
-                image.set_color(area, c)
+            Ok(false) => Ok(false),
+            Err(e) => {
+                // The action may have changed some cells before it failed
+                self.restore(undo_data);
+                Err(e)
             }
-            DocAction::MakeHighRes { area } => image.make_high_res(area),
-            DocAction::MakeMulticolor { area } => image.make_multicolor(area),
-            DocAction::ReplaceColor {
-                area,
-                to_replace,
-                replacement,
-            } => image.replace_color(area, *to_replace, *replacement),
-            DocAction::SwapColors {
-                area,
-                color_1,
-                color_2,
-            } => image.swap_colors(area, *color_1, *color_2),
-            DocAction::CharBrushPaint { pos, chars } => image.paste_chars(pos, chars.as_ref()),
         }
     }
+
+    /// Put back what an action changed, as saved by [`DocAction::invert`] before it was applied.
+    pub fn restore(&mut self, undo_data: UndoData) {
+        match undo_data {
+            UndoData::Chars(chars) => self.image.restore_chars(&chars),
+            UndoData::ColorsAndChars(colors, chars) => {
+                self.image.set_global_colors(colors);
+                self.image.restore_chars(&chars);
+            }
+            UndoData::Register { index, value } => {
+                self.image.set_global_color(index, value);
+            }
+            UndoData::Palette(palette) => {
+                self.image.set_palette(palette);
+            }
+            UndoData::StableCharacters(stable) => {
+                self.image.set_stable_characters(stable);
+            }
+            UndoData::MaxChars(max_chars) => self.max_chars = max_chars,
+            UndoData::Metadata(metadata) => self.metadata = *metadata,
+            UndoData::Settings(settings) => {
+                self.set_settings(&settings);
+            }
+            UndoData::Image(image) => *self.image = *image,
+        }
+    }
+}
+
+/// Execute an action that changes the image.
+fn apply_to_image(
+    image: &mut MutationMonitor<VicImage>,
+    action: &DocAction,
+) -> Result<bool, Box<dyn DisallowedAction>> {
+    match action {
+        DocAction::ChangeRegister { index, value } => Ok(image.set_global_color(*index, *value)),
+        DocAction::PasteTrueColor {
+            source,
+            target,
+            format,
+            dithering,
+            allowed_colors,
+            global_colors,
+        } => {
+            if let Some(colors) = global_colors {
+                image.set_global_colors(colors.clone());
+            }
+            image.paste_image(source, *target, *format, *dithering, *allowed_colors);
+            Ok(true)
+        }
+        DocAction::Plot { area, color } => image.plot(area, *color),
+        DocAction::Fill { area, color } => image.fill_cells(area, *color),
+        DocAction::FloodFill { area, color } => image.flood_fill(area, *color),
+        DocAction::GlobalFill { area, color } => image.global_fill(area, *color),
+        DocAction::CellColor { area, color } => {
+            let c = image.color_index_from_paint_color(color);
+            image.set_color(area, c)
+        }
+        DocAction::MakeHighRes { area } => image.make_high_res(area),
+        DocAction::MakeMulticolor { area } => image.make_multicolor(area),
+        DocAction::ReplaceColor {
+            area,
+            to_replace,
+            replacement,
+        } => image.replace_color(area, *to_replace, *replacement),
+        DocAction::ReplaceColorEverywhere {
+            to_replace,
+            replacement,
+        } => image.replace_color_everywhere(*to_replace, *replacement),
+        DocAction::SwapColors {
+            area,
+            color_1,
+            color_2,
+        } => image.swap_colors(area, *color_1, *color_2),
+        DocAction::CharBrushPaint { pos, chars, mode } => {
+            image.paste_chars(pos, chars.as_ref(), *mode)
+        }
+        DocAction::Clear { color } => image.clear(*color),
+        DocAction::FixCells { area } => Ok(image.fix_cells(area)),
+        DocAction::InvertColors { area } => Ok(image.invert_colors(area)),
+        DocAction::InvertImage => {
+            image.invert_all_colors();
+            Ok(true)
+        }
+        DocAction::FlipX => {
+            image.flip_x();
+            Ok(true)
+        }
+        DocAction::FlipY => {
+            image.flip_y();
+            Ok(true)
+        }
+        DocAction::Shift { dx, dy, wrap } => Ok(image.shift(*dx, *dy, *wrap)),
+        DocAction::RotateCw => image.rotate_cw(),
+        DocAction::RotateCcw => image.rotate_ccw(),
+        DocAction::Crop { rect } => match coords::rect_within_size(*rect, image.size_in_cells()) {
+            Some(rect) => Ok(image.crop(&rect)),
+            None => Err(Box::new(DisallowedEdit::SelectionOutsideImage)),
+        },
+        DocAction::ResizeCanvas { size, anchor } => Ok(image.resize_canvas(*size, *anchor)),
+        DocAction::SetPalette(palette) => Ok(image.set_palette(palette.clone())),
+        DocAction::SetStableCharacters(stable) => Ok(image.set_stable_characters(*stable)),
+        // Don't change the image, see `Document::apply`
+        DocAction::SetMaxChars(_) | DocAction::SetSettings(_) | DocAction::SetMetadata(_) => {
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Document, DocumentSettings};
+    use crate::{
+        actions::DocAction,
+        cell_image::CellImageSize,
+        coords::{self, CellRect, PixelPoint},
+        update_area::UpdateArea,
+        vic::{PixelColor, VicImage, VicPalette, ViewSettings},
+    };
+
+    #[test]
+    fn render_is_updated_after_change() {
+        let mut doc = Document::from_image(VicImage::new(1, 1));
+        let before = doc.render(&ViewSettings::Normal);
+        assert!(doc.render(&ViewSettings::Normal) == before);
+        doc.apply(&DocAction::Plot {
+            area: UpdateArea::from_pixel(PixelPoint::new(0, 0)),
+            color: PixelColor::CharColor(1),
+        })
+        .unwrap();
+        let after = doc.render(&ViewSettings::Normal);
+        assert!(after != before);
+        assert!(after == doc.image.render());
+    }
+
+    #[test]
+    fn max_chars_disallows_new_characters() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        doc.apply(&DocAction::SetMaxChars(Some(1))).unwrap();
+        let plot = DocAction::Plot {
+            area: UpdateArea::from_pixel(PixelPoint::new(0, 0)),
+            color: PixelColor::CharColor(1),
+        };
+        assert!(doc.apply(&plot).is_err());
+        assert_eq!(doc.image.map_characters().len(), 1);
+        // Only the changed cells are counted once the image has been updated
+        doc.image.update();
+        assert!(doc.apply(&plot).is_err());
+        assert_eq!(doc.image.map_characters().len(), 1);
+
+        doc.apply(&DocAction::SetMaxChars(Some(2))).unwrap();
+        assert!(doc.apply(&plot).unwrap());
+        assert_eq!(doc.image.map_characters().len(), 2);
+    }
+
+    #[test]
+    fn settings_are_changed_and_restored_together() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        let before = doc.settings();
+        let action = DocAction::SetSettings(Box::new(DocumentSettings {
+            max_chars: Some(2),
+            palette: VicPalette::Colodore,
+            stable_characters: true,
+        }));
+        let undo_data = action.invert(&doc);
+        assert!(doc.apply(&action).unwrap());
+        assert!(!doc.apply(&action).unwrap());
+        assert_eq!(doc.image.palette(), &VicPalette::Colodore);
+        doc.restore(undo_data);
+        assert_eq!(doc.settings(), before);
+    }
+
+    #[test]
+    fn palette_is_saved() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        assert!(doc
+            .apply(&DocAction::SetPalette(VicPalette::Colodore))
+            .unwrap());
+        let json = serde_json::to_string(&doc).unwrap();
+        let loaded: Document = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.image.palette(), &VicPalette::Colodore);
+    }
+
+    #[test]
+    fn extract_keeps_colors() {
+        let mut doc = Document::from_image(VicImage::new(3, 2));
+        doc.apply(&DocAction::SetPalette(VicPalette::Colodore))
+            .unwrap();
+        let rect = coords::rect_within_size(
+            CellRect::new(euclid::point2(1, 1), euclid::size2(2, 1)),
+            doc.image.size_in_cells(),
+        )
+        .unwrap();
+        let extracted = doc.extract(&rect);
+        assert_eq!(extracted.image.size_in_cells(), euclid::size2(2, 1));
+        assert_eq!(extracted.image.palette(), &VicPalette::Colodore);
+        assert!(extracted.image.global_colors() == doc.image.global_colors());
+    }
+
+    #[test]
+    fn metadata_is_optional() {
+        let mut doc = Document::from_image(VicImage::new(2, 1));
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(!json.contains("metadata"));
+        let loaded: Document = serde_json::from_str(&json).unwrap();
+        assert!(loaded.metadata.is_empty());
+
+        doc.metadata.author = "Pixel Artist".to_string();
+        let json = serde_json::to_string(&doc).unwrap();
+        let loaded: Document = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.metadata.author, "Pixel Artist");
+        assert!(loaded.metadata.title.is_empty());
+    }
 }
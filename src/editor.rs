@@ -3,7 +3,7 @@ use std::path::Path;
 use eframe::{
     egui::{
         self, epaint::Mesh, Align, Align2, Color32, CursorIcon, Painter, PointerButton, Pos2, Rect,
-        Response, Shape, Stroke, TextStyle, Ui, Vec2,
+        Response, RichText, Shape, Stroke, TextStyle, Ui, Vec2,
     },
     epi::TextureAllocator,
 };
@@ -11,75 +11,175 @@ use imgref::ImgVec;
 use undo::Record;
 
 use crate::{
-    actions::{self, Action, UiAction, Undoable},
-    cell_image::CellImageSize,
-    coords::{PixelPoint, PixelTransform},
+    actions::{self, Action, DocAction, UiAction, Undoable},
+    cell_image::{CellCoordinates, CellImageSize},
+    coords::{self, CellRect, PixelPoint, PixelRect, PixelTransform, SizeInCells},
     egui_extensions::EnhancedResponse,
     error::{Error, Severity},
     import::Import,
     mode::Mode,
     mutation_monitor::MutationMonitor,
-    storage,
+    selection::Selection,
+    storage::{self, ExportSettings},
     system::{OpenFileOptions, SaveFileOptions, SystemFunctions},
     texture::{self, Texture},
     tool::{ImportTool, ToolType, ToolUiContext, Toolbox},
-    ui::{self, text, UiState, ViewSettings},
-    vic::{Char, VicImage},
+    ui::{
+        self, document_info::DocumentInfoDialog, document_settings::DocumentSettingsDialog,
+        resize_canvas::ResizeCanvasDialog, text, GridMode, UiState,
+    },
+    update_area::{PenShape, Symmetry, UpdateArea},
+    vic::{Char, PasteMode, PixelColor, VicImage, ViewSettings},
     Document,
 };
 
 const BORDER_CORNER_RADIUS: f32 = 15.0;
 const BORDER_SIZE: Vec2 = Vec2::new(25.0, 20.0);
 
-const GRID_COLOR: Color32 = Color32::GRAY;
+/// How many steps can be undone, unless configured otherwise.
+/// Each step only keeps the parts of the document it changed,
+/// except for steps that change the size of the image, which keep the whole image.
+pub const DEFAULT_UNDO_LIMIT: usize = 100;
+
+/// Pixel grid lines are not drawn when zoomed out more than this, as they would hide the image.
+const PIXEL_GRID_MIN_ZOOM: f32 = 4.0;
+const SYMMETRY_AXIS_COLOR: Color32 = Color32::from_rgb(0x40, 0xa0, 0xff);
+/// Drawn over multicolor cells when showing cell modes.
+const MULTICOLOR_TINT: Color32 = Color32::from_rgba_premultiplied(0x30, 0x18, 0x00, 0x30);
+/// How many steps per second the "marching ants" around the selection move.
+const SELECTION_ANTS_SPEED: f64 = 8.0;
+/// Length of each of the "marching ants", and the gaps between them, in screen pixels.
+const SELECTION_ANTS_LENGTH: f32 = 4.0;
+/// Outline of cells with colors that the hardware can't show.
+const COLOR_ERROR_COLOR: Color32 = Color32::from_rgb(0xff, 0x30, 0x30);
+/// Maximum width and height of the image in the navigator panel.
+const NAVIGATOR_SIZE: f32 = 200.0;
+/// Outline of the visible part of the image in the navigator panel.
+const NAVIGATOR_VIEW_COLOR: Color32 = Color32::from_rgb(0xff, 0xff, 0x40);
+/// Height of a pixel in the character editor panel.
+const CHAR_EDITOR_PIXEL_SIZE: f32 = 20.0;
+const CHAR_EDITOR_GRID_STROKE: Stroke = Stroke {
+    width: 1.0,
+    color: Color32::GRAY,
+};
 
 /// An open document and its state.
 pub struct Editor {
     pub doc: Document,
     pub ui_state: UiState,
     pub image_texture: Option<Texture>,
+    /// The small version of the image shown in the navigator panel.
+    navigator_texture: Option<Texture>,
+    /// The images of the previous and next editors, for the onion skin.
+    onion_textures: [Option<Texture>; 2],
+    /// The pixel last painted in the character editor panel, while the user is painting there.
+    char_editor_paint: Option<PixelPoint>,
     pub history: Record<actions::Undoable>,
+    /// A label for each entry in `history`, to show to the user.
+    history_labels: Vec<String>,
+    /// Whether the last entry in `history` is a stroke that `Action::ContinueStroke` may add to.
+    stroke_in_history: bool,
+    /// The grab selection that the last crop removed, and the position in `history`
+    /// right after the crop, so the selection can be put back if the crop is undone.
+    cropped_selection: Option<(usize, CellRect)>,
     pub toolbox: Toolbox,
 }
 
 impl Editor {
-    pub fn with_doc(doc: Document) -> Self {
+    /// Create an editor for a document.
+    /// At most `undo_limit` steps can be undone. Must not be 0.
+    pub fn with_doc(doc: Document, undo_limit: usize) -> Self {
+        let (width, height) = doc.image.size_in_pixels();
+        let mut ui_state = UiState::default();
+        ui_state.symmetry.axis = PixelPoint::new(width as i32 / 2, height as i32 / 2);
         Self {
             doc,
-            ui_state: Default::default(),
+            ui_state,
             image_texture: None,
-            history: Default::default(),
+            navigator_texture: None,
+            onion_textures: [None, None],
+            char_editor_paint: None,
+            history: undo::record::Builder::new().limit(undo_limit).build(),
+            history_labels: Vec::new(),
+            stroke_in_history: false,
+            cropped_selection: None,
             toolbox: Toolbox::new(),
         }
     }
 
     pub fn start_import_mode(&mut self, filename: &Path) -> Result<(), Error> {
         let mut i = Import::load(filename)?;
-        i.settings.width = i
-            .settings
-            .width
-            .min(self.doc.image.size_in_pixels().0 as u32);
-        i.settings.height = i
-            .settings
-            .height
-            .min(self.doc.image.size_in_pixels().1 as u32);
+        i.clamp_to(&self.doc.image);
+        self.start_import(i);
+        Ok(())
+    }
+
+    /// Switch to the Import tool with the given image to import.
+    /// The import settings are used as they are, see `Import::clamp_to`.
+    pub fn start_import(&mut self, i: Import) {
         self.toolbox.import = ImportTool::new(i);
         self.ui_state.tool = ToolType::Import;
+    }
+
+    /// Import each frame of an animation into a new document like this one.
+    /// The frames are pasted with their default import settings.
+    fn import_frames(&self, frames: Vec<Import>) -> Result<Vec<Document>, Error> {
+        frames
+            .into_iter()
+            .map(|mut frame| {
+                let mut doc = self.doc.blank_copy();
+                frame.clamp_to(&doc.image);
+                frame.paste_into(&mut doc.image)?;
+                Ok(doc)
+            })
+            .collect()
+    }
+
+    /// Ask the user whether to import all frames of a file as separate documents.
+    /// If not, the first frame is imported into this document with the Import tool.
+    fn import_file(
+        &mut self,
+        filename: &Path,
+        system: &mut dyn SystemFunctions,
+        user_actions: &mut Vec<Action>,
+    ) -> Result<(), Error> {
+        let mut frames = Import::load_frames(filename)?;
+        if frames.len() > 1
+            && system
+                .request_confirmation(&format!(
+                    "{} has {} frames. Import each frame as a new document?",
+                    filename.display(),
+                    frames.len()
+                ))
+                .unwrap_or(false)
+        {
+            for doc in self.import_frames(frames)? {
+                user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
+            }
+            return Ok(());
+        }
+        let mut i = frames.swap_remove(0);
+        i.clamp_to(&self.doc.image);
+        self.start_import(i);
         Ok(())
     }
 
-    pub fn update_file_menu(&mut self, ui: &mut Ui, system: &mut dyn SystemFunctions) {
+    pub fn update_file_menu(
+        &mut self,
+        ui: &mut Ui,
+        system: &mut dyn SystemFunctions,
+        user_actions: &mut Vec<Action>,
+    ) {
         if system.has_open_file_dialog() && ui.button("Import...").clicked_with_close(ui) {
             match system
                 .open_file_dialog(OpenFileOptions::for_import(self.toolbox.import.filename()))
             {
-                Ok(Some(filename)) => match self.start_import_mode(&filename) {
+                Ok(Some(filename)) => match self.import_file(&filename, system, user_actions) {
                     Ok(()) => {}
-                    Err(e) => system.show_error(&format!(
-                        "Could not import file {}: {:?}",
-                        filename.display(),
-                        e
-                    )),
+                    Err(e) => system.show_error_details(
+                        &format!("Failed to import {}", filename.display()),
+                        &e,
+                    ),
                 },
                 Ok(None) => {}
                 Err(e) => system.show_error(&format!("Could not get file name: {:?}", e)),
@@ -112,11 +212,47 @@ impl Editor {
                 save_as(&mut self.history, &mut self.doc, system);
             }
             if ui.button("Export...").clicked_with_close(ui) {
-                export(&self.doc, system);
+                export(&self.doc, &self.ui_state.export_settings, system);
+            }
+            let settings = &mut self.ui_state.export_settings;
+            ui.horizontal(|ui| {
+                ui.label("Export scale");
+                ui.add(
+                    egui::DragValue::new(&mut settings.scale)
+                        .clamp_range(1..=16)
+                        .suffix("x"),
+                );
+            });
+            ui.checkbox(&mut settings.apply_aspect_ratio, "Export with wide pixels")
+                .on_hover_text("Make exported pixels as wide as on a Vic-20 instead of square");
+            ui.checkbox(&mut settings.include_border, "Export with border")
+                .on_hover_text("Surround exported images with the border color");
+            ui.checkbox(
+                &mut settings.transparent_background,
+                "Export with transparent background",
+            )
+            .on_hover_text("Make pixels with the background color transparent");
+        } else if system.has_download() {
+            ui.separator();
+            if ui
+                .button("Download")
+                .on_hover_text("Save the document by downloading it")
+                .clicked_with_close(ui)
+            {
+                download(&mut self.history, &self.doc, system);
             }
         }
     }
 
+    /// Save the document to its file, or ask for a file name if it doesn't have one.
+    /// Returns false if the file was not saved.
+    pub fn save_or_ask(&mut self, system: &mut dyn SystemFunctions) -> bool {
+        match self.doc.filename.clone() {
+            Some(filename) => save(&mut self.history, &mut self.doc, &filename, system),
+            None => save_as(&mut self.history, &mut self.doc, system),
+        }
+    }
+
     pub fn update_edit_menu(&mut self, ui: &mut Ui, user_actions: &mut Vec<Action>) {
         ui.set_enabled(self.history.can_undo());
         if ui.button("Undo").clicked_with_close(ui) {
@@ -126,6 +262,107 @@ impl Editor {
         if ui.button("Redo").clicked_with_close(ui) {
             user_actions.push(Action::Ui(UiAction::Redo));
         }
+        ui.set_enabled(true);
+        ui.separator();
+        if ui.button("Copy Image").clicked_with_close(ui) {
+            user_actions.push(Action::Ui(UiAction::CopyImage));
+        }
+        if ui.button("Paste Image").clicked_with_close(ui) {
+            user_actions.push(Action::Ui(UiAction::PasteImage));
+        }
+        ui.set_enabled(self.ui_state.selection.is_some());
+        if ui
+            .button("Deselect")
+            .on_hover_text("Remove the selection, so editing affects the whole image (Ctrl+D)")
+            .clicked_with_close(ui)
+        {
+            user_actions.push(Action::Ui(UiAction::SetSelection(None)));
+        }
+        ui.set_enabled(true);
+        ui.separator();
+        if ui.button("Flip Horizontally").clicked_with_close(ui) {
+            user_actions.push(Action::Document(DocAction::FlipX));
+        }
+        if ui.button("Flip Vertically").clicked_with_close(ui) {
+            user_actions.push(Action::Document(DocAction::FlipY));
+        }
+        if ui.button("Rotate Clockwise").clicked_with_close(ui) {
+            user_actions.push(Action::Document(DocAction::RotateCw));
+        }
+        if ui.button("Rotate Counterclockwise").clicked_with_close(ui) {
+            user_actions.push(Action::Document(DocAction::RotateCcw));
+        }
+        if ui
+            .button("Clear")
+            .on_hover_text("Set the whole image, or the selected pixels, to the background color")
+            .clicked_with_close(ui)
+        {
+            user_actions.push(Action::Document(DocAction::Clear {
+                color: PixelColor::Background,
+            }));
+        }
+        if ui
+            .button("Fill with Primary Color")
+            .on_hover_text("Set the whole image, or the selected pixels, to the primary color")
+            .clicked_with_close(ui)
+        {
+            user_actions.push(Action::Document(DocAction::Clear {
+                color: self.ui_state.primary_color,
+            }));
+        }
+        if ui
+            .button("Fix Invalid Cells")
+            .on_hover_text("Convert cells with colors the hardware can't show")
+            .clicked_with_close(ui)
+        {
+            let (width, height) = self.doc.image.size_in_pixels();
+            let area = UpdateArea::rectangle(PixelRect::from_points([
+                PixelPoint::zero(),
+                PixelPoint::new(width as i32, height as i32),
+            ]));
+            user_actions.push(Action::Document(DocAction::FixCells { area }));
+        }
+        if ui.button("Invert Colors").clicked_with_close(ui) {
+            user_actions.push(Action::Document(DocAction::InvertImage));
+        }
+        let selection = self.toolbox.grab.selection();
+        ui.set_enabled(selection.is_some());
+        if ui.button("Invert Selection Colors").clicked_with_close(ui) {
+            if let Some(rect) = selection {
+                let (top_left, bottom_right) = self.doc.image.cell_rectangle(&rect);
+                let area = UpdateArea::rectangle(PixelRect::from_points([top_left, bottom_right]));
+                user_actions.push(Action::Document(DocAction::InvertColors { area }));
+            }
+        }
+        if ui
+            .button("New Document from Selection")
+            .clicked_with_close(ui)
+        {
+            if let Some(rect) =
+                selection.and_then(|r| coords::rect_within_size(r, self.doc.image.size_in_cells()))
+            {
+                let doc = self.doc.extract(&rect);
+                user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
+            }
+        }
+        if ui.button("Crop to Selection").clicked_with_close(ui) {
+            if let Some(rect) = selection {
+                user_actions.push(Action::Document(DocAction::Crop { rect }));
+            }
+        }
+        ui.set_enabled(true);
+        if ui.button("Resize Canvas...").clicked_with_close(ui) {
+            self.ui_state.resize_canvas_dialog =
+                Some(ResizeCanvasDialog::new(self.doc.image.size_in_cells()));
+        }
+        ui.separator();
+        if ui.button("Document Settings...").clicked_with_close(ui) {
+            self.ui_state.document_settings_dialog =
+                Some(DocumentSettingsDialog::new(self.doc.settings()));
+        }
+        if ui.button("Document Info...").clicked_with_close(ui) {
+            self.ui_state.document_info_dialog = Some(DocumentInfoDialog::new(&self.doc.metadata));
+        }
     }
 
     pub fn update_top_toolbar(&mut self, ui: &mut Ui, user_actions: &mut Vec<Action>) {
@@ -145,9 +382,59 @@ impl Editor {
                 if ui.button("+").on_hover_text("Zoom in").clicked() {
                     user_actions.push(Action::Ui(UiAction::ZoomIn));
                 }
+                if ui
+                    .button("Fit")
+                    .on_hover_text("Zoom to fit the whole image")
+                    .clicked()
+                {
+                    user_actions.push(Action::Ui(UiAction::ZoomToFit));
+                }
                 ui.separator();
-                ui.checkbox(&mut self.ui_state.grid, "Grid")
+                egui::ComboBox::from_label("Grid")
+                    .selected_text(self.ui_state.grid.title())
+                    .show_ui(ui, |ui| {
+                        for mode in GridMode::ALL {
+                            ui.selectable_value(&mut self.ui_state.grid, mode, mode.title());
+                        }
+                    })
+                    .response
                     .on_hover_text(text::GRID_TOOLTIP);
+                let options = &mut self.ui_state.view_options;
+                let mut grid_rgb = [
+                    options.grid_color.r(),
+                    options.grid_color.g(),
+                    options.grid_color.b(),
+                ];
+                if ui
+                    .color_edit_button_srgb(&mut grid_rgb)
+                    .on_hover_text("Grid color")
+                    .changed()
+                {
+                    options.grid_color = Color32::from_rgb(grid_rgb[0], grid_rgb[1], grid_rgb[2]);
+                }
+                ui.add(egui::Slider::new(&mut options.grid_opacity, 0.0..=1.0).show_value(false))
+                    .on_hover_text("Grid opacity");
+                ui.checkbox(&mut options.show_border, "Border")
+                    .on_hover_text("Show the border around the image");
+                ui.checkbox(&mut self.ui_state.show_history, "History")
+                    .on_hover_text("Show the undo history");
+                ui.checkbox(&mut self.ui_state.show_navigator, "Navigator")
+                    .on_hover_text("Show an overview of the whole image");
+                ui.checkbox(&mut self.ui_state.show_char_editor, "Char Editor")
+                    .on_hover_text("Show an enlarged view of one character for editing its pixels");
+                ui.checkbox(&mut self.ui_state.show_cell_modes, "Cell Modes")
+                    .on_hover_text("Tint multicolor cells to tell them apart from high-res cells");
+                let onion_skin = &mut self.ui_state.onion_skin;
+                ui.checkbox(&mut onion_skin.previous, "Onion Prev")
+                    .on_hover_text("Show the document in the previous tab faintly over the image");
+                ui.checkbox(&mut onion_skin.next, "Onion Next")
+                    .on_hover_text("Show the document in the next tab faintly over the image");
+                if onion_skin.previous || onion_skin.next {
+                    ui.add(egui::Slider::new(&mut onion_skin.opacity, 0.0..=1.0).show_value(false))
+                        .on_hover_text("Onion skin opacity");
+                }
+                ui.checkbox(&mut self.ui_state.show_color_errors, "Check Colors")
+                    .on_hover_text("Highlight cells that use colors the hardware can't show");
                 let mut raw_mode = self.ui_state.image_view_settings == ViewSettings::Raw;
                 if ui
                     .checkbox(&mut raw_mode, "Raw")
@@ -162,10 +449,12 @@ impl Editor {
                 }
             });
             ui.separator();
+            self.ui_state.remember_primary_color();
             if let Some(action) = ui::palette::render_palette(
                 ui,
                 &mut self.ui_state.primary_color,
                 &mut self.ui_state.secondary_color,
+                &self.ui_state.recent_colors,
                 &mut self.doc.image,
             ) {
                 user_actions.push(action);
@@ -180,9 +469,188 @@ impl Editor {
                 ui.separator();
                 select_mode_ui(ui, &self.ui_state.mode, user_actions);
             }
+            if let ToolType::Paint = self.ui_state.tool {
+                ui.separator();
+                select_pen_ui(ui, &self.ui_state, user_actions);
+                ui.separator();
+                symmetry_ui(ui, &self.ui_state.symmetry, &self.doc.image, user_actions);
+            }
+            if let ToolType::CharBrush = self.ui_state.tool {
+                ui.separator();
+                brush_ui(ui, self.ui_state.paste_mode, user_actions);
+            }
+            if let Some(selection) = &self.ui_state.selection {
+                ui.separator();
+                selection_ui(ui, selection, user_actions);
+            }
+        });
+    }
+
+    /// Show the undo history, and let the user click an entry to go back or forward to it.
+    pub fn update_history_panel(&self, ui: &mut Ui, user_actions: &mut Vec<Action>) {
+        ui.heading("History");
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.with_layout(egui::Layout::top_down_justified(Align::LEFT), |ui| {
+                let current = self.history.current();
+                let labels =
+                    std::iter::once("Start").chain(self.history_labels.iter().map(String::as_str));
+                for (position, label) in labels.enumerate() {
+                    let mut text = if position == current && self.history.is_saved() {
+                        RichText::new(format!("{} (saved)", label))
+                    } else {
+                        RichText::new(label)
+                    };
+                    if position > current {
+                        // Undone, but can be redone
+                        text = text.weak();
+                    }
+                    if ui.selectable_label(position == current, text).clicked() {
+                        for _ in position..current {
+                            user_actions.push(Action::Ui(UiAction::Undo));
+                        }
+                        for _ in current..position {
+                            user_actions.push(Action::Ui(UiAction::Redo));
+                        }
+                    }
+                }
+            });
         });
     }
 
+    /// Show the whole image at a small scale, with the part that is visible in the main view outlined.
+    /// Clicking or dragging in it moves the main view there.
+    pub fn update_navigator_panel(&mut self, ui: &mut Ui, frame: &eframe::epi::Frame) {
+        ui.heading("Navigator");
+        let (width, height) = self.doc.image.size_in_pixels();
+        let par = self.doc.image.pixel_aspect_ratio();
+        let image_size = Vec2::new(width as f32 * par, height as f32);
+        let scale = (NAVIGATOR_SIZE / image_size.x).min(NAVIGATOR_SIZE / image_size.y);
+        let (response, painter) =
+            ui.allocate_painter(image_size * scale, egui::Sense::click_and_drag());
+        let pixel_transform = PixelTransform {
+            screen_rect: response.rect,
+            pixel_width: width as i32,
+            pixel_height: height as i32,
+        };
+
+        let texture = texture::update_texture(
+            &mut self.doc.image,
+            &mut self.navigator_texture,
+            frame as &dyn TextureAllocator,
+            par,
+            scale,
+            &self.ui_state.image_view_settings,
+        );
+        draw_texture(
+            &self.doc.image,
+            texture,
+            &painter,
+            &pixel_transform,
+            Color32::WHITE,
+        );
+
+        // The pan is how far the image's center is from the view's center, in zoomed points
+        let ui_state = &mut self.ui_state;
+        let visible = Rect::from_center_size(
+            response.rect.center() - ui_state.pan / ui_state.zoom * scale,
+            ui_state.view_size / ui_state.zoom * scale,
+        );
+        painter.rect_stroke(
+            visible.intersect(response.rect),
+            0.0,
+            Stroke::new(1.0, NAVIGATOR_VIEW_COLOR),
+        );
+        if let Some(pos) = response.interact_pointer_pos() {
+            ui_state.pan = (response.rect.center() - pos) / scale * ui_state.zoom;
+        }
+    }
+
+    /// The top left pixel of the cell to show in the character editor panel:
+    /// the cell at the keyboard cursor, or else the first cell of the grab selection.
+    fn char_editor_cell(&self) -> Option<PixelPoint> {
+        let image = &self.doc.image;
+        let cell = match self.ui_state.keyboard_cursor {
+            Some(pos) => image.cell(pos).map(|(cell, _, _)| *cell),
+            None => self
+                .toolbox
+                .grab
+                .selection()
+                .and_then(|rect| coords::within_bounds(rect.origin, image.size_in_cells()))
+                .map(|cell| *cell),
+        }?;
+        Some(image.cell_coordinates_unclipped(&cell))
+    }
+
+    /// Show one character enlarged, and let the user paint its pixels
+    /// with the primary color, or the secondary color with the right mouse button.
+    pub fn update_char_editor_panel(&mut self, ui: &mut Ui, user_actions: &mut Vec<Action>) {
+        ui.heading("Character");
+        let top_left = match self.char_editor_cell() {
+            Some(p) => p,
+            None => {
+                ui.label("Select a cell with the Grab tool,\nor move the keyboard cursor to it");
+                return;
+            }
+        };
+        let image = &self.doc.image;
+        let (width, height) = (Char::WIDTH as i32, Char::HEIGHT as i32);
+        let size = Vec2::new(width as f32 * image.pixel_aspect_ratio(), height as f32)
+            * CHAR_EDITOR_PIXEL_SIZE;
+        let (response, painter) = ui.allocate_painter(size, egui::Sense::click_and_drag());
+        let transform = PixelTransform {
+            screen_rect: response.rect,
+            pixel_width: width,
+            pixel_height: height,
+        };
+        let pixel_width = image.pixel_width_at(top_left);
+        for y in 0..height {
+            for x in (0..width).step_by(pixel_width as usize) {
+                let color = image
+                    .pixel_color(top_left + euclid::vec2(x, y))
+                    .map(|c| image.true_color_from_paint_color(&c))
+                    .unwrap_or_default();
+                painter.rect(
+                    Rect::from_min_max(
+                        transform.screen_pos(PixelPoint::new(x, y)),
+                        transform.screen_pos(PixelPoint::new(x + pixel_width, y + 1)),
+                    ),
+                    0.0,
+                    Color32::from(color),
+                    CHAR_EDITOR_GRID_STROKE,
+                );
+            }
+        }
+
+        match response.interact_pointer_pos() {
+            Some(pos) => {
+                let local = transform
+                    .pixel_pos(pos)
+                    .clamp(PixelPoint::origin(), PixelPoint::new(width - 1, height - 1));
+                let p = top_left + local.to_vector();
+                if self.char_editor_paint != Some(p) {
+                    let color = if ui.input().pointer.button_down(PointerButton::Secondary) {
+                        self.ui_state.secondary_color
+                    } else {
+                        self.ui_state.primary_color
+                    };
+                    let action = DocAction::Plot {
+                        area: UpdateArea::from_pixel(p),
+                        color,
+                    };
+                    // Undo the whole drag in one step
+                    user_actions.push(if self.char_editor_paint.is_some() {
+                        Action::ContinueStroke(action)
+                    } else {
+                        Action::Document(action)
+                    });
+                    self.char_editor_paint = Some(p);
+                }
+            }
+            None => self.char_editor_paint = None,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn update_central_panel(
         &mut self,
         ui: &mut Ui,
@@ -190,11 +658,21 @@ impl Editor {
         ctx: &egui::CtxRef,
         cursor_icon: &mut Option<CursorIcon>,
         brush: &ImgVec<Char>,
+        neighbors: [Option<&VicImage>; 2],
         user_actions: &mut Vec<Action>,
     ) {
         let (width, height) = self.doc.image.size_in_pixels();
         let par = self.doc.image.pixel_aspect_ratio();
         let (response, painter) = image_painter(ui);
+        self.ui_state.view_size = response.rect.size();
+        let border_size = if self.ui_state.view_options.show_border {
+            BORDER_SIZE * 2.0 * self.ui_state.zoom
+        } else {
+            Vec2::ZERO
+        };
+        self.ui_state.clamp_pan(
+            Vec2::new(width as f32 * par, height as f32) * self.ui_state.zoom + border_size,
+        );
         let pixel_transform = PixelTransform {
             screen_rect: Rect::from_center_size(
                 response.rect.center() + self.ui_state.pan,
@@ -212,14 +690,21 @@ impl Editor {
 
         let input = ui.input();
         if input.modifiers.command {
+            let focus = hover_pos_screen
+                .map(|p| p - response.rect.center())
+                .unwrap_or(Vec2::ZERO);
             if input.scroll_delta.y < 0.0 {
-                user_actions.push(Action::Ui(UiAction::ZoomOut));
+                user_actions.push(Action::Ui(UiAction::ZoomOutAt(focus)));
             } else if input.scroll_delta.y > 0.0 {
-                user_actions.push(Action::Ui(UiAction::ZoomIn));
+                user_actions.push(Action::Ui(UiAction::ZoomInAt(focus)));
             }
         } else {
             self.ui_state.pan += input.scroll_delta;
         }
+        if hover_pos_screen.is_some() && input.pointer.delta() != Vec2::ZERO {
+            // Using the mouse, so hide the keyboard cursor
+            self.ui_state.keyboard_cursor = None;
+        }
 
         if response.drag_started() && input.pointer.button_down(PointerButton::Middle)
             || (input.pointer.button_down(PointerButton::Secondary) && input.modifiers.shift)
@@ -240,13 +725,79 @@ impl Editor {
             &painter,
             &pixel_transform,
             frame,
-            self.ui_state.zoom,
-            &self.ui_state.image_view_settings,
+            &self.ui_state,
         );
 
+        // Onion skin: the previous and next documents, if they are the same size as this one
+        let onion_skin = self.ui_state.onion_skin;
+        let tint = Color32::from_white_alpha((onion_skin.opacity * 255.0).round() as u8);
+        for ((&enabled, &neighbor), texture) in [onion_skin.previous, onion_skin.next]
+            .iter()
+            .zip(neighbors.iter())
+            .zip(self.onion_textures.iter_mut())
+        {
+            match neighbor {
+                Some(image) if enabled && image.size_in_pixels() == (width, height) => {
+                    let texture = texture::update_unmonitored_texture(
+                        image,
+                        texture,
+                        frame as &dyn TextureAllocator,
+                        par,
+                        self.ui_state.zoom,
+                        &self.ui_state.image_view_settings,
+                    );
+                    draw_texture(image, texture, &painter, &pixel_transform, tint);
+                }
+                _ => {}
+            }
+        }
+
         // Grid lines
-        if self.ui_state.grid {
-            draw_grid(&self.doc.image, &painter, &pixel_transform);
+        let options = &self.ui_state.view_options;
+        match self.ui_state.grid {
+            GridMode::Off => {}
+            GridMode::Cell => draw_grid(
+                &self.doc.image,
+                &painter,
+                &pixel_transform,
+                options.cell_grid_color(),
+            ),
+            GridMode::Pixel => {
+                if self.ui_state.zoom >= PIXEL_GRID_MIN_ZOOM {
+                    draw_pixel_grid(
+                        &self.doc.image,
+                        &painter,
+                        &pixel_transform,
+                        options.pixel_grid_color(),
+                    );
+                }
+                draw_grid(
+                    &self.doc.image,
+                    &painter,
+                    &pixel_transform,
+                    options.cell_grid_color(),
+                );
+            }
+        }
+        if self.ui_state.show_cell_modes {
+            draw_multicolor_tint(&self.doc.image, &painter, &pixel_transform);
+        }
+        if self.ui_state.show_color_errors {
+            draw_color_errors(&self.doc.image, &painter, &pixel_transform);
+        }
+        if let Some(selection) = &self.ui_state.selection {
+            draw_selection(selection, &painter, &pixel_transform, ctx);
+        }
+        if let Some(pos) = self.ui_state.keyboard_cursor {
+            ui::crosshair::draw_pixel_cursor(&painter, &pixel_transform, pos);
+        }
+        if let ToolType::Paint = self.ui_state.tool {
+            draw_symmetry_axes(
+                &self.ui_state.symmetry,
+                &self.doc.image,
+                &painter,
+                &pixel_transform,
+            );
         }
 
         // Tool UI
@@ -267,6 +818,14 @@ impl Editor {
             tool.update_ui(&mut tool_ui_context, user_actions);
         }
 
+        ui::resize_canvas::render(ctx, &mut self.ui_state.resize_canvas_dialog, user_actions);
+        ui::document_settings::render(
+            ctx,
+            &mut self.ui_state.document_settings_dialog,
+            user_actions,
+        );
+        ui::document_info::render(ctx, &mut self.ui_state.document_info_dialog, user_actions);
+
         let info_text = {
             let t = self.doc.image.image_info();
             if let Some(p) = hover_pos {
@@ -294,20 +853,37 @@ impl Editor {
         let Editor {
             doc,
             history,
+            history_labels,
+            stroke_in_history,
+            cropped_selection,
             ui_state,
+            toolbox,
             ..
         } = self;
 
         match action {
             Action::Document(action) => {
-                let was_dirty = doc.image.dirty;
-                match history.apply(doc, Undoable::new(action)) {
-                    Ok(true) => (),
-                    Ok(false) => doc.image.dirty = was_dirty,
-                    Err(e) => match e.severity() {
-                        Severity::Silent => {}
-                        Severity::Notification => ui_state.show_warning(e.to_string()),
-                    },
+                let crop_rect = match &action {
+                    DocAction::Crop { rect } => Some(*rect),
+                    _ => None,
+                };
+                *stroke_in_history = false;
+                if apply_document_action(doc, history, history_labels, ui_state, action, false) {
+                    *stroke_in_history = true;
+                    // The selection is outside the image after a successful crop
+                    *cropped_selection = crop_rect.map(|rect| (history.current(), rect));
+                    if crop_rect.is_some() {
+                        toolbox.grab.clear_selection();
+                    }
+                }
+            }
+            Action::ContinueStroke(action) => {
+                // Only merge if the start of the stroke made it into the history,
+                // and not into a saved state, as the undo crate doesn't merge those.
+                let merge = *stroke_in_history && !history.is_saved();
+                if apply_document_action(doc, history, history_labels, ui_state, action, merge) {
+                    *stroke_in_history = true;
+                    *cropped_selection = None;
                 }
             }
             Action::Ui(ref ui_action) => match ui_action {
@@ -315,30 +891,46 @@ impl Editor {
                     if history.can_undo() {
                         history.undo(doc);
                         doc.image.dirty = true;
+                        *stroke_in_history = false;
+                        match cropped_selection {
+                            Some((position, rect)) if history.current() + 1 == *position => {
+                                toolbox.grab.set_selection(*rect)
+                            }
+                            _ => {}
+                        }
                     }
                 }
                 UiAction::Redo => {
                     if history.can_redo() {
                         history.redo(doc);
                         doc.image.dirty = true;
+                        *stroke_in_history = false;
+                        match cropped_selection {
+                            Some((position, _)) if history.current() == *position => {
+                                toolbox.grab.clear_selection()
+                            }
+                            _ => {}
+                        }
                     }
                 }
                 UiAction::SelectTool(tool) => ui_state.tool = *tool,
                 UiAction::SelectMode(mode) => ui_state.mode = mode.clone(),
-                UiAction::ZoomIn => {
-                    if ui_state.zoom < 16.0 {
-                        ui_state.zoom *= 2.0;
-                    }
+                UiAction::SelectPasteMode(mode) => ui_state.paste_mode = *mode,
+                UiAction::ZoomIn => ui_state.zoom_around(ui_state.zoom * 2.0, Vec2::ZERO),
+                UiAction::ZoomOut => ui_state.zoom_around(ui_state.zoom / 2.0, Vec2::ZERO),
+                UiAction::ZoomInAt(focus) => ui_state.zoom_around(ui_state.zoom * 2.0, *focus),
+                UiAction::ZoomOutAt(focus) => ui_state.zoom_around(ui_state.zoom / 2.0, *focus),
+                UiAction::SetZoom(amount) => ui_state.zoom_around(*amount, Vec2::ZERO),
+                UiAction::ZoomToFit => {
+                    ui_state.zoom = fit_zoom(ui_state.view_size, &doc.image);
+                    ui_state.pan = Vec2::ZERO;
                 }
-                UiAction::ZoomOut => {
-                    if ui_state.zoom > 1.0 {
-                        ui_state.zoom /= 2.0;
-                    }
+                UiAction::ChangePenSize(delta) => {
+                    ui_state.pen_size = (ui_state.pen_size + delta).clamp(1, UiState::MAX_PEN_SIZE);
                 }
-                UiAction::SetZoom(amount) => {
-                    ui_state.zoom = *amount;
-                }
-                UiAction::ToggleGrid => ui_state.grid = !ui_state.grid,
+                UiAction::SetPenShape(shape) => ui_state.pen_shape = *shape,
+                UiAction::SetSymmetry(symmetry) => ui_state.symmetry = *symmetry,
+                UiAction::ToggleGrid => ui_state.grid = ui_state.grid.next(),
                 UiAction::ToggleRaw => {
                     ui_state.image_view_settings = match ui_state.image_view_settings {
                         ViewSettings::Normal => ViewSettings::Raw,
@@ -348,12 +940,67 @@ impl Editor {
                 UiAction::ViewSettings(settings) => {
                     ui_state.image_view_settings = settings.clone();
                 }
+                UiAction::MoveCursor { dx, dy } => {
+                    let (width, height) = doc.image.size_in_pixels();
+                    let pos = match ui_state.keyboard_cursor {
+                        Some(pos) => PixelPoint::new(pos.x + dx, pos.y + dy),
+                        None => PixelPoint::new(width as i32 / 2, height as i32 / 2),
+                    };
+                    ui_state.keyboard_cursor = Some(pos.clamp(
+                        PixelPoint::origin(),
+                        PixelPoint::new(width as i32 - 1, height as i32 - 1),
+                    ));
+                }
+                UiAction::PaintAtCursor => {
+                    if let Some(pos) = ui_state.keyboard_cursor {
+                        let area = UpdateArea::from_pixel(pos);
+                        let action = ui_state
+                            .mode
+                            .paint_action(area, (ui_state.primary_color, ui_state.secondary_color));
+                        *stroke_in_history = false;
+                        if apply_document_action(
+                            doc,
+                            history,
+                            history_labels,
+                            ui_state,
+                            action,
+                            false,
+                        ) {
+                            *cropped_selection = None;
+                        }
+                    }
+                }
+                UiAction::SetPrimaryColor(color) => {
+                    if ui_state.secondary_color == *color {
+                        ui_state.secondary_color = ui_state.primary_color;
+                    }
+                    ui_state.primary_color = *color;
+                }
+                UiAction::SetSelection(selection) => ui_state.selection = selection.clone(),
+                UiAction::SwapPaintColors => {
+                    std::mem::swap(&mut ui_state.primary_color, &mut ui_state.secondary_color);
+                }
                 // Not handled by Editor
                 UiAction::NewDocument(_)
+                | UiAction::DuplicateDocument
                 | UiAction::CloseEditor(_)
                 | UiAction::CreateCharBrush { .. }
                 | UiAction::MirrorBrushX
-                | UiAction::MirrorBrushY => {
+                | UiAction::MirrorBrushY
+                | UiAction::RotateBrush
+                | UiAction::SaveBrush
+                | UiAction::LoadBrush
+                | UiAction::AddBrushToLibrary
+                | UiAction::ImportBrushDirectory
+                | UiAction::UseLibraryBrush(_)
+                | UiAction::CopyImage
+                | UiAction::PasteImage
+                | UiAction::ShowSettingsDialog
+                | UiAction::ShowKeymapDialog
+                | UiAction::ShowNewDocumentDialog
+                | UiAction::LoadPalette
+                | UiAction::Revert
+                | UiAction::CloseAll => {
                     return Some(action);
                 }
             },
@@ -362,21 +1009,84 @@ impl Editor {
     }
 }
 
+/// Apply a document action and record it in the history. Show any error to the user.
+/// If `merge` is true, the action is merged with the last entry in the history.
+/// Returns true if the action changed the document.
+fn apply_document_action(
+    doc: &mut Document,
+    history: &mut Record<Undoable>,
+    history_labels: &mut Vec<String>,
+    ui_state: &mut UiState,
+    action: DocAction,
+    merge: bool,
+) -> bool {
+    let was_dirty = doc.image.dirty;
+    let position = history.current();
+    let label = action.label();
+    let action = match &ui_state.selection {
+        Some(selection) => action.restricted_to(selection, &doc.image),
+        None => action,
+    };
+    let undoable = if merge {
+        Undoable::merged(action)
+    } else {
+        Undoable::new(action)
+    };
+    match history.apply(doc, undoable) {
+        Ok(true) => {
+            if !merge {
+                // Any redoable entries are gone, and the oldest may have been dropped
+                history_labels.truncate(position);
+                history_labels.push(label);
+                let dropped = history_labels.len().saturating_sub(history.len());
+                history_labels.drain(..dropped);
+            }
+            true
+        }
+        Ok(false) => {
+            doc.image.dirty = was_dirty;
+            false
+        }
+        Err(e) => {
+            match e.severity() {
+                Severity::Silent => {}
+                Severity::Notification => ui_state.show_warning(e.to_string()),
+            }
+            false
+        }
+    }
+}
+
+/// Get the largest power of two zoom factor that makes the image and its border fit in a view of size `view_size`.
+fn fit_zoom(view_size: Vec2, image: &VicImage) -> f32 {
+    let (width, height) = image.size_in_pixels();
+    // The border scales with the zoom, just like the image
+    let size_at_zoom_1 =
+        Vec2::new(width as f32 * image.pixel_aspect_ratio(), height as f32) + BORDER_SIZE * 2.0;
+    let zoom = (view_size.x / size_at_zoom_1.x).min(view_size.y / size_at_zoom_1.y);
+    if zoom <= UiState::MIN_ZOOM {
+        UiState::MIN_ZOOM
+    } else {
+        2.0f32.powf(zoom.log2().floor()).min(UiState::MAX_ZOOM)
+    }
+}
+
 fn draw_image(
     image: &mut MutationMonitor<VicImage>,
     image_texture: &mut Option<Texture>,
     painter: &Painter,
     pixel_transform: &PixelTransform,
     frame: &eframe::epi::Frame,
-    zoom: f32,
-    view_settings: &ViewSettings,
+    ui_state: &UiState,
 ) {
-    // Draw border
-    painter.rect_filled(
-        pixel_transform.screen_rect.expand2(BORDER_SIZE * zoom),
-        BORDER_CORNER_RADIUS * zoom,
-        image.border(),
-    );
+    let zoom = ui_state.zoom;
+    if ui_state.view_options.show_border {
+        painter.rect_filled(
+            pixel_transform.screen_rect.expand2(BORDER_SIZE * zoom),
+            BORDER_CORNER_RADIUS * zoom,
+            image.border(),
+        );
+    }
 
     // Draw the main image
     let texture = texture::update_texture(
@@ -385,15 +1095,32 @@ fn draw_image(
         frame as &dyn TextureAllocator,
         image.pixel_aspect_ratio(),
         zoom,
-        view_settings,
-    );
-    let mut mesh = Mesh::with_texture(texture);
-    mesh.add_rect_with_uv(
-        pixel_transform.screen_rect,
-        Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)),
-        Color32::WHITE,
+        &ui_state.image_view_settings,
     );
-    painter.add(Shape::Mesh(mesh));
+    draw_texture(image, texture, painter, pixel_transform, Color32::WHITE);
+}
+
+/// Draw the tiles of an image's texture, multiplying the colors with `tint`.
+fn draw_texture(
+    image: &VicImage,
+    texture: &Texture,
+    painter: &Painter,
+    pixel_transform: &PixelTransform,
+    tint: Color32,
+) {
+    for tile in &texture.tiles {
+        let (top_left, bottom_right) = image.cell_rectangle(&tile.rect);
+        let mut mesh = Mesh::with_texture(tile.id);
+        mesh.add_rect_with_uv(
+            Rect::from_min_max(
+                pixel_transform.screen_pos(top_left),
+                pixel_transform.screen_pos(bottom_right),
+            ),
+            Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)),
+            tint,
+        );
+        painter.add(Shape::Mesh(mesh));
+    }
 }
 
 /// Ask for filename and save the document. Show any error message to the user.
@@ -413,11 +1140,27 @@ fn save_as(
     }
 }
 
+/// Save the document by letting the user download it. Show any error message to the user.
+fn download(
+    history: &mut Record<actions::Undoable>,
+    doc: &Document,
+    system: &mut dyn SystemFunctions,
+) {
+    let filename = match doc.filename.as_deref().and_then(Path::file_name) {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => format!("{}.{}", doc.visible_name(), storage::NATIVE_EXTENSION),
+    };
+    match storage::save_to_bytes(doc).and_then(|data| system.download(&filename, &data)) {
+        Ok(()) => history.set_saved(true),
+        Err(e) => system.show_error_details(&format!("Failed to download {}", filename), &e),
+    }
+}
+
 /// Ask for filename and export the document.
-fn export(doc: &Document, system: &mut dyn SystemFunctions) {
+fn export(doc: &Document, settings: &ExportSettings, system: &mut dyn SystemFunctions) {
     match system.save_file_dialog(SaveFileOptions::for_export(doc.filename.as_deref())) {
         Ok(Some(filename)) => {
-            if let Err(e) = storage::save_any_file(doc, &filename) {
+            if let Err(e) = storage::export_any_file(doc, &filename, settings) {
                 system.show_error(&format!("Failed to save image: {}", e));
             }
         }
@@ -438,14 +1181,18 @@ fn save(
     system: &mut dyn SystemFunctions,
 ) -> bool {
     println!("Saving as {}", filename.display());
+    let metadata = doc.metadata.clone();
+    doc.metadata.touch();
     match storage::save(doc, filename) {
         Ok(()) => {
             doc.filename = Some(filename.to_owned());
+            doc.file_modified = storage::modified_time(filename);
             history.set_saved(true);
             true
         }
         Err(e) => {
-            system.show_error(&format!("Failed to save: {:?}", e));
+            doc.metadata = metadata;
+            system.show_error_details(&format!("Failed to save {}", filename.display()), &e);
             false
         }
     }
@@ -460,13 +1207,189 @@ fn image_painter(ui: &mut egui::Ui) -> (Response, Painter) {
     (response, painter)
 }
 
-fn draw_grid(image: &VicImage, painter: &Painter, pixel_transform: &PixelTransform) {
+fn draw_grid(
+    image: &VicImage,
+    painter: &Painter,
+    pixel_transform: &PixelTransform,
+    color: Color32,
+) {
+    let (width, height) = image.size_in_pixels();
+    let stroke = Stroke { width: 1.0, color };
+    for x in image.vertical_grid_lines() {
+        painter.line_segment(
+            [
+                pixel_transform.screen_pos(PixelPoint::new(x, 0)),
+                pixel_transform.screen_pos(PixelPoint::new(x, height as i32)),
+            ],
+            stroke,
+        )
+    }
+    for y in image.horizontal_grid_lines() {
+        painter.line_segment(
+            [
+                pixel_transform.screen_pos(PixelPoint::new(0, y)),
+                pixel_transform.screen_pos(PixelPoint::new(width as i32, y)),
+            ],
+            stroke,
+        )
+    }
+}
+
+/// Draw lines between the pixels inside each cell.
+/// In multicolor cells, the pixels are twice as wide, so there are fewer vertical lines.
+fn draw_pixel_grid(
+    image: &VicImage,
+    painter: &Painter,
+    pixel_transform: &PixelTransform,
+    color: Color32,
+) {
+    let (width, height) = image.size_in_pixels();
+    let (width, height) = (width as i32, height as i32);
+    let stroke = Stroke { width: 1.0, color };
+    for y in (0..height).filter(|y| y % Char::HEIGHT as i32 != 0) {
+        painter.line_segment(
+            [
+                pixel_transform.screen_pos(PixelPoint::new(0, y)),
+                pixel_transform.screen_pos(PixelPoint::new(width, y)),
+            ],
+            stroke,
+        )
+    }
+    for top in (0..height).step_by(Char::HEIGHT) {
+        for left in (0..width).step_by(Char::WIDTH) {
+            let pixel_width = image.pixel_width_at(PixelPoint::new(left, top));
+            for x in
+                ((left + pixel_width)..(left + Char::WIDTH as i32)).step_by(pixel_width as usize)
+            {
+                painter.line_segment(
+                    [
+                        pixel_transform.screen_pos(PixelPoint::new(x, top)),
+                        pixel_transform.screen_pos(PixelPoint::new(x, top + Char::HEIGHT as i32)),
+                    ],
+                    stroke,
+                )
+            }
+        }
+    }
+}
+
+/// Draw the outline of the selection with "marching ants".
+/// Only the visible part of the outline is drawn,
+/// and the animation only keeps repainting while some of it is visible.
+fn draw_selection(
+    selection: &Selection,
+    painter: &Painter,
+    pixel_transform: &PixelTransform,
+    ctx: &egui::CtxRef,
+) {
+    let clip = painter.clip_rect();
+    let period = SELECTION_ANTS_LENGTH * 2.0;
+    // Moves the ants half their length each step
+    let phase = ((ctx.input().time * SELECTION_ANTS_SPEED) as i64 % 4) as f32 * period / 4.0;
+    let mut visible = false;
+    for &(p0, p1) in selection.outline() {
+        let (start, end) = (
+            pixel_transform.screen_pos(p0),
+            pixel_transform.screen_pos(p1),
+        );
+        // The segments are horizontal or vertical, with `start` at the top or left
+        let horizontal = start.y == end.y;
+        let (along_min, along_max, across, clip_along, clip_across) = if horizontal {
+            (start.x, end.x, start.y, clip.x_range(), clip.y_range())
+        } else {
+            (start.y, end.y, start.x, clip.y_range(), clip.x_range())
+        };
+        let from = along_min.max(*clip_along.start());
+        let to = along_max.min(*clip_along.end());
+        if from >= to || !clip_across.contains(&across) {
+            continue;
+        }
+        visible = true;
+        let point = |along: f32| {
+            if horizontal {
+                Pos2::new(along, across)
+            } else {
+                Pos2::new(across, along)
+            }
+        };
+        painter.line_segment([point(from), point(to)], Stroke::new(1.0, Color32::WHITE));
+        // Dashes are placed by the sum of x and y, so they continue around corners
+        let offset = across + phase;
+        let mut dash = ((from + offset) / period).floor() * period - offset;
+        while dash < to {
+            let (a, b) = (dash.max(from), (dash + SELECTION_ANTS_LENGTH).min(to));
+            if a < b {
+                painter.line_segment([point(a), point(b)], Stroke::new(1.0, Color32::BLACK));
+            }
+            dash += period;
+        }
+    }
+    if visible {
+        ctx.request_repaint();
+    }
+}
+
+/// Tint the multicolor cells. Only draws the cells that are visible.
+fn draw_multicolor_tint(image: &VicImage, painter: &Painter, pixel_transform: &PixelTransform) {
+    let visible = painter.clip_rect();
+    let top_left = pixel_transform.pixel_pos(visible.min);
+    let bottom_right = pixel_transform.pixel_pos(visible.max);
+    let (first, _, _) = image.cell_unclipped(top_left);
+    let (last, _, _) = image.cell_unclipped(bottom_right);
+    let cells = coords::clamp_rect_to_bounds(
+        CellRect::new(
+            first,
+            SizeInCells::new(last.x - first.x + 1, last.y - first.y + 1),
+        ),
+        image.size_in_cells(),
+    );
+    for cell in image.multicolor_cells(&cells) {
+        let (top_left, bottom_right) =
+            image.cell_rectangle(&CellRect::new(cell, SizeInCells::new(1, 1)));
+        painter.rect_filled(
+            Rect::from_min_max(
+                pixel_transform.screen_pos(top_left),
+                pixel_transform.screen_pos(bottom_right),
+            ),
+            0.0,
+            MULTICOLOR_TINT,
+        );
+    }
+}
+
+/// Outline the cells that can't be shown on the hardware.
+fn draw_color_errors(image: &VicImage, painter: &Painter, pixel_transform: &PixelTransform) {
+    let stroke = Stroke {
+        width: 2.0,
+        color: COLOR_ERROR_COLOR,
+    };
+    for cell in image.invalid_cells() {
+        let (top_left, bottom_right) =
+            image.cell_rectangle(&CellRect::new(cell, SizeInCells::new(1, 1)));
+        painter.rect_stroke(
+            Rect::from_min_max(
+                pixel_transform.screen_pos(top_left),
+                pixel_transform.screen_pos(bottom_right),
+            ),
+            0.0,
+            stroke,
+        );
+    }
+}
+
+fn draw_symmetry_axes(
+    symmetry: &Symmetry,
+    image: &VicImage,
+    painter: &Painter,
+    pixel_transform: &PixelTransform,
+) {
     let (width, height) = image.size_in_pixels();
     let stroke = Stroke {
         width: 1.0,
-        color: GRID_COLOR,
+        color: SYMMETRY_AXIS_COLOR,
     };
-    for x in image.vertical_grid_lines() {
+    let PixelPoint { x, y, .. } = symmetry.axis;
+    if symmetry.mirror_x {
         painter.line_segment(
             [
                 pixel_transform.screen_pos(PixelPoint::new(x, 0)),
@@ -475,7 +1398,7 @@ fn draw_grid(image: &VicImage, painter: &Painter, pixel_transform: &PixelTransfo
             stroke,
         )
     }
-    for y in image.horizontal_grid_lines() {
+    if symmetry.mirror_y {
         painter.line_segment(
             [
                 pixel_transform.screen_pos(PixelPoint::new(0, y)),
@@ -486,6 +1409,20 @@ fn draw_grid(image: &VicImage, painter: &Painter, pixel_transform: &PixelTransfo
     }
 }
 
+/// Shows that editing is restricted to a selection, with a button for removing it.
+fn selection_ui(ui: &mut egui::Ui, selection: &Selection, user_actions: &mut Vec<Action>) {
+    ui.label("Selection");
+    ui.label(format!("{} pixels", selection.len()))
+        .on_hover_text("Editing only affects the selected pixels");
+    if ui
+        .button("Deselect")
+        .on_hover_text("Remove the selection (Ctrl+D)")
+        .clicked()
+    {
+        user_actions.push(Action::Ui(UiAction::SetSelection(None)));
+    }
+}
+
 /// Renders the UI for tool selection.
 /// Returns which tool to switch to, or None if the user did not change tool.
 fn select_tool_ui(ui: &mut egui::Ui, current_tool: &ToolType, user_actions: &mut Vec<Action>) {
@@ -514,6 +1451,13 @@ fn select_tool_ui(ui: &mut egui::Ui, current_tool: &ToolType, user_actions: &mut
         {
             new_tool = Some(ToolType::Grab);
         }
+        if ui
+            .selectable_label(matches!(current_tool, ToolType::MagicWand), "Magic Wand")
+            .on_hover_text("Select connected pixels with the same color, to only paint those")
+            .clicked()
+        {
+            new_tool = Some(ToolType::MagicWand);
+        }
         if ui
             .selectable_label(
                 matches!(current_tool, ToolType::CharBrush { .. }),
@@ -530,6 +1474,127 @@ fn select_tool_ui(ui: &mut egui::Ui, current_tool: &ToolType, user_actions: &mut
     }
 }
 
+/// Controls for the size and shape of the pen used by the paint modes.
+fn select_pen_ui(ui: &mut egui::Ui, ui_state: &UiState, user_actions: &mut Vec<Action>) {
+    ui.with_layout(egui::Layout::top_down_justified(Align::LEFT), |ui| {
+        ui.style_mut().body_text_style = egui::TextStyle::Heading;
+        ui.label("Pen");
+    });
+    ui.horizontal(|ui| {
+        if ui.button("-").on_hover_text("Smaller pen").clicked() {
+            user_actions.push(Action::Ui(UiAction::ChangePenSize(-1)));
+        }
+        ui.label(format!("{}", ui_state.pen_size));
+        if ui.button("+").on_hover_text("Larger pen").clicked() {
+            user_actions.push(Action::Ui(UiAction::ChangePenSize(1)));
+        }
+    });
+    ui.horizontal(|ui| {
+        for (shape, title) in [(PenShape::Square, "Square"), (PenShape::Round, "Round")] {
+            if ui
+                .selectable_label(ui_state.pen_shape == shape, title)
+                .clicked()
+            {
+                user_actions.push(Action::Ui(UiAction::SetPenShape(shape)));
+            }
+        }
+    });
+}
+
+/// Buttons for changing the character brush.
+fn brush_ui(ui: &mut egui::Ui, paste_mode: PasteMode, user_actions: &mut Vec<Action>) {
+    ui.with_layout(egui::Layout::top_down_justified(Align::LEFT), |ui| {
+        ui.style_mut().body_text_style = egui::TextStyle::Heading;
+        ui.label("Brush");
+    });
+    ui.horizontal(|ui| {
+        if ui
+            .button("Mirror X")
+            .on_hover_text("Mirror the brush horizontally (X)")
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::MirrorBrushX));
+        }
+        if ui
+            .button("Mirror Y")
+            .on_hover_text("Mirror the brush vertically (Y)")
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::MirrorBrushY));
+        }
+        if ui
+            .button("Rotate")
+            .on_hover_text(
+                "Rotate the brush 90 degrees clockwise. Only works for high-res brushes.",
+            )
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::RotateBrush));
+        }
+    });
+    ui.horizontal(|ui| {
+        if ui
+            .button("Save...")
+            .on_hover_text("Save the brush to a file")
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::SaveBrush));
+        }
+        if ui
+            .button("Load...")
+            .on_hover_text("Load a brush from a file")
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::LoadBrush));
+        }
+    });
+    ui.label("Paint mode");
+    for mode in PasteMode::ALL {
+        if ui
+            .radio(paste_mode == mode, mode.title())
+            .on_hover_text(mode.description())
+            .clicked()
+        {
+            user_actions.push(Action::Ui(UiAction::SelectPasteMode(mode)));
+        }
+    }
+}
+
+/// Controls for mirroring what is painted.
+fn symmetry_ui(
+    ui: &mut egui::Ui,
+    symmetry: &Symmetry,
+    image: &VicImage,
+    user_actions: &mut Vec<Action>,
+) {
+    ui.with_layout(egui::Layout::top_down_justified(Align::LEFT), |ui| {
+        ui.style_mut().body_text_style = egui::TextStyle::Heading;
+        ui.label("Symmetry");
+    });
+    let (width, height) = image.size_in_pixels();
+    let mut s = *symmetry;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut s.mirror_x, "Left/right")
+            .on_hover_text("Mirror what you paint across a vertical axis");
+        ui.add(egui::DragValue::new(&mut s.axis.x).clamp_range(0..=width as i32));
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut s.mirror_y, "Top/bottom")
+            .on_hover_text("Mirror what you paint across a horizontal axis");
+        ui.add(egui::DragValue::new(&mut s.axis.y).clamp_range(0..=height as i32));
+    });
+    if ui
+        .button("Center")
+        .on_hover_text("Move the axes to the center of the image")
+        .clicked()
+    {
+        s.axis = PixelPoint::new(width as i32 / 2, height as i32 / 2);
+    }
+    if s != *symmetry {
+        user_actions.push(Action::Ui(UiAction::SetSymmetry(s)));
+    }
+}
+
 /// Renders the UI for mode selection.
 fn select_mode_ui(ui: &mut egui::Ui, current_mode: &Mode, user_actions: &mut Vec<Action>) {
     ui.with_layout(egui::Layout::top_down_justified(Align::LEFT), |ui| {
@@ -537,7 +1602,10 @@ fn select_mode_ui(ui: &mut egui::Ui, current_mode: &Mode, user_actions: &mut Vec
         ui.label("Mode");
         for mode in [
             Mode::PixelPaint,
+            Mode::Erase,
             Mode::FillCell,
+            Mode::FloodFill,
+            Mode::GlobalFill,
             Mode::CellColor,
             Mode::ReplaceColor,
             Mode::SwapColors,
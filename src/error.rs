@@ -1,6 +1,8 @@
 use std::{fmt, io};
 use thiserror::Error;
 
+use crate::vic::GlobalColors;
+
 /// How serious an error is.
 pub enum Severity {
     /// There is no need to report this to the user.
@@ -17,30 +19,85 @@ pub trait DisallowedAction: fmt::Debug + fmt::Display {
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error("file operation failed")]
+    Io(#[from] io::Error),
     #[error("failed to read from file")]
-    ReadFailure(#[from] io::Error),
+    ReadFailure(#[source] io::Error),
+    #[error("failed to write to file")]
+    WriteFailure(#[source] io::Error),
     #[error("truncated data")]
     TruncatedData,
     #[error("incorrect file identifier - wrong file type?")]
     WrongMagic,
     #[error("invalid image size: {0} columns x {1} rows")]
     InvalidSize(usize, usize),
+    #[error("invalid global colors: background {}, border {}, aux {}", .0.background, .0.border, .0.aux)]
+    InvalidGlobalColors(GlobalColors),
+    #[error("invalid compressed data: {0}")]
+    DecompressionError(String),
     #[error("deserializing struct")]
     Deserialization(Box<bincode::ErrorKind>),
     #[error("failed to load JSON data: {0}")]
     JsonError(#[from] serde_json::Error),
     #[error("No characters defined")]
     NoCharacters,
+    #[error("Wrong length of {0}: {1} (expected {2})")]
+    WrongDataLength(&'static str, usize, usize),
+    #[error("Character {0} is used at position {1} but is not defined")]
+    UndefinedCharacter(usize, usize),
+    #[error("Too many characters: {0} (maximum is 256)")]
+    TooManyCharacters(usize),
     #[error("Invalid hexadecimal value: {0}")]
     HexError(#[from] hex::FromHexError),
     #[error("Internal error: {0}")]
     InternalError(String),
     #[error("Invalid image")]
     ImageError(#[from] image::ImageError),
+    #[error("Unsupported image type: {0}")]
+    UnsupportedImageType(String),
+    #[error("Unsupported palette: {0}. Only Vic-20 colors can be loaded.")]
+    UnsupportedPalette(String),
     #[error("Unknown file format on file \"{0}\"")]
     UnknownFileFormat(std::path::PathBuf),
     #[error("Dialog failed: {0}")]
     DialogError(String),
     #[error("No file name given")]
     NoFileName,
+    #[error("Clipboard failed: {0}")]
+    ClipboardError(String),
+    #[error("Invalid import settings: {0}")]
+    InvalidImportSettings(String),
+    #[error("Invalid palette: {0}")]
+    InvalidPalette(String),
+    #[error("Invalid tile map: {0}")]
+    InvalidTileMap(String),
+}
+
+impl Error {
+    /// The error message followed by the errors that caused it, one per line.
+    /// Meant for bug reports, so it includes more than the user usually needs to see.
+    pub fn details(&self) -> String {
+        let mut text = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(e) = source {
+            text.push_str(&format!("\nCaused by: {}", e));
+            source = e.source();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use std::io;
+
+    #[test]
+    fn details_include_source() {
+        let error = Error::ReadFailure(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(
+            error.details(),
+            "failed to read from file\nCaused by: no such file"
+        );
+    }
 }
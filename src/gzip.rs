@@ -0,0 +1,105 @@
+//! Minimal reading and writing of gzip data (RFC 1952).
+//! Only single-member files are supported, which is what gzip itself writes.
+
+use crate::error::Error;
+
+/// The first bytes of gzip data: the identifier and the compression method (deflate).
+const MAGIC: [u8; 3] = [0x1f, 0x8b, 8];
+
+/// Header flags.
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Compression level, from 0 to 10.
+const LEVEL: u8 = 9;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Whether the data starts like gzip data.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Compress data into gzip format.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    result.extend_from_slice(&MAGIC);
+    // Flags, modification time, extra flags, OS (unknown)
+    result.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xff]);
+    result.extend(miniz_oxide::deflate::compress_to_vec(data, LEVEL));
+    result.extend_from_slice(&crc32(data).to_le_bytes());
+    result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    result
+}
+
+/// Decompress gzip data.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_gzip(data) {
+        return Err(Error::WrongMagic);
+    }
+    if data.len() < 18 {
+        return Err(Error::TruncatedData);
+    }
+    let flags = data[3];
+    let mut position = 10;
+    if flags & FEXTRA != 0 {
+        let length = u16::from_le_bytes([data[position], data[position + 1]]) as usize;
+        position += 2 + length;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // Zero-terminated string
+            position += data
+                .get(position..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or(Error::TruncatedData)?
+                + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        position += 2;
+    }
+    let trailer = data.len() - 8;
+    if position > trailer {
+        return Err(Error::TruncatedData);
+    }
+    let result = miniz_oxide::inflate::decompress_to_vec(&data[position..trailer])
+        .map_err(|status| Error::DecompressionError(format!("{:?}", status)))?;
+    let crc = u32::from_le_bytes([
+        data[trailer],
+        data[trailer + 1],
+        data[trailer + 2],
+        data[trailer + 3],
+    ]);
+    if crc != crc32(&result) {
+        return Err(Error::DecompressionError("checksum mismatch".to_string()));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress, decompress, is_gzip};
+
+    #[test]
+    fn round_trip() {
+        let data = b"Pixel Pen Pixel Pen Pixel Pen".to_vec();
+        let compressed = compress(&data);
+        assert!(is_gzip(&compressed));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn corrupt_data_is_rejected() {
+        let mut compressed = compress(b"Pixel Pen");
+        let last = compressed.len() - 5;
+        compressed[last] ^= 0xff;
+        assert!(decompress(&compressed).is_err());
+    }
+}
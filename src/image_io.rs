@@ -1,13 +1,16 @@
 //! Loading (and saving) image files.
 
+pub mod asm;
+pub mod csv;
 mod fluff;
+pub mod packed;
 
 use bincode::Options;
 use image::{self, GenericImageView};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     fs::File,
-    io::{self, BufReader, Read},
+    io::{self, BufReader, Read, Write},
     path::Path,
 };
 
@@ -19,6 +22,8 @@ pub enum FileFormat {
     Unknown,
     /// Turbo Rascal's format
     Fluff,
+    /// Our own packed binary format
+    Packed,
     /// Any image format supported by the `image` crate
     StandardImage(image::ImageFormat),
 }
@@ -26,13 +31,19 @@ pub enum FileFormat {
 pub fn identify_file(filename: &Path) -> Result<FileFormat, Error> {
     let mut buffer = [0u8; 256];
     let num_bytes = std::fs::File::open(filename)?.read(&mut buffer)?;
-    let buffer = &buffer[..num_bytes];
+    Ok(identify_bytes(&buffer[..num_bytes]))
+}
+
+/// Identify the file format from the start of a file's content.
+pub fn identify_bytes(buffer: &[u8]) -> FileFormat {
     if buffer.starts_with(fluff::FILE_IDENTIFIER) {
-        Ok(FileFormat::Fluff)
+        FileFormat::Fluff
+    } else if buffer.starts_with(packed::FILE_IDENTIFIER) {
+        FileFormat::Packed
     } else if let Ok(format) = image::guess_format(buffer) {
-        Ok(FileFormat::StandardImage(format))
+        FileFormat::StandardImage(format)
     } else {
-        Ok(FileFormat::Unknown)
+        FileFormat::Unknown
     }
 }
 
@@ -43,11 +54,28 @@ pub fn load_file(filename: &Path, format: FileFormat) -> Result<VicImage, Error>
             let mut reader = BufReader::new(file);
             fluff::load_fluff64(&mut reader)
         }
+        FileFormat::Packed => {
+            let file = File::open(filename)?;
+            let mut reader = BufReader::new(file);
+            packed::load_packed(&mut reader)
+        }
         FileFormat::StandardImage(..) => load_standard_image(filename),
         FileFormat::Unknown => Err(Error::UnknownFileFormat(filename.to_owned())),
     }
 }
 
+/// Load an image from a file's content, for when there is no file to read from, e.g. on the web.
+pub fn load_bytes(name: &Path, mut data: &[u8], format: FileFormat) -> Result<VicImage, Error> {
+    match format {
+        FileFormat::Fluff => fluff::load_fluff64(&mut data),
+        FileFormat::Packed => packed::load_packed(&mut data),
+        FileFormat::StandardImage(format) => {
+            VicImage::from_image(&image::load_from_memory_with_format(data, format)?.into_rgba8())
+        }
+        FileFormat::Unknown => Err(Error::UnknownFileFormat(name.to_owned())),
+    }
+}
+
 /// Load an image in any format supported by `image` crate.
 pub fn load_standard_image(filename: &Path) -> Result<VicImage, Error> {
     let img = image::open(filename)?;
@@ -78,3 +106,16 @@ where
             _ => Error::Deserialization(e),
         })
 }
+
+pub fn write_struct<T>(writer: &mut impl Write, value: &T) -> Result<(), Error>
+where
+    T: Serialize,
+{
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .serialize_into(writer, value)
+        .map_err(|e| match *e {
+            bincode::ErrorKind::Io(e) => Error::WriteFailure(e),
+            _ => Error::InternalError(e.to_string()),
+        })
+}
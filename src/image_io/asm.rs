@@ -0,0 +1,90 @@
+//! Export to 6502 assembly source.
+//! The output uses `.byte` directives and `name = value` constants,
+//! so it can be included in e.g. a KickAssembler or ACME project.
+
+use std::io::Write;
+
+use crate::{cell_image::CellImageSize, error::Error, vic::VicImage};
+
+/// Write the character bitmaps, screen RAM, and color RAM of an image as assembly source.
+pub fn write_asm(image: &VicImage, writer: &mut impl Write) -> Result<(), Error> {
+    let charset = image.to_charset_bytes();
+    let (screen, colors) = image.to_screen_and_color_ram()?;
+    let size = image.size_in_cells();
+    let global_colors = image.global_colors();
+
+    writeln!(writer, "; Generated by Pixel Pen")?;
+    writeln!(writer)?;
+    writeln!(writer, "columns = {}", size.width)?;
+    writeln!(writer, "rows = {}", size.height)?;
+    writeln!(writer, "background_color = {}", global_colors.background)?;
+    writeln!(writer, "border_color = {}", global_colors.border)?;
+    writeln!(writer, "aux_color = {}", global_colors.aux)?;
+    writeln!(writer)?;
+    write_bytes(writer, "charset", &charset, 8)?;
+    writeln!(writer)?;
+    write_bytes(writer, "screen", &screen, size.width as usize)?;
+    writeln!(writer)?;
+    write_bytes(writer, "colors", &colors, size.width as usize)?;
+    Ok(())
+}
+
+/// Write a label followed by the bytes, `per_line` bytes on each line.
+fn write_bytes(
+    writer: &mut impl Write,
+    label: &str,
+    bytes: &[u8],
+    per_line: usize,
+) -> Result<(), Error> {
+    writeln!(writer, "{}:", label)?;
+    for line in bytes.chunks(per_line) {
+        let values = line
+            .iter()
+            .map(|b| format!("${:02x}", b))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(writer, "    .byte {}", values)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::write_asm;
+    use crate::vic::{Char, VicImage};
+
+    #[test]
+    fn small_image() {
+        let a = Char::new_highres([0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80], 1);
+        let b = Char::new([0xff; 8], 3);
+        let image = VicImage::with_content(ImgVec::new(vec![a, b, b, a], 2, 2));
+        let mut output = Vec::new();
+        write_asm(&image, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+; Generated by Pixel Pen
+
+columns = 2
+rows = 2
+background_color = 0
+border_color = 1
+aux_color = 2
+
+charset:
+    .byte $01,$02,$04,$08,$10,$20,$40,$80
+    .byte $ff,$ff,$ff,$ff,$ff,$ff,$ff,$ff
+
+screen:
+    .byte $00,$01
+    .byte $01,$00
+
+colors:
+    .byte $01,$0b
+    .byte $0b,$01
+"
+        );
+    }
+}
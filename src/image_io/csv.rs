@@ -0,0 +1,177 @@
+//! Export the character numbers as a tile map in CSV format.
+//! Each row of cells is a line of comma-separated character numbers.
+//! The numbers refer to the characters in the character set export.
+//! A tile map can also be loaded together with a character set,
+//! though the character colors are not part of the format.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use crate::{
+    coords::SizeInCells,
+    error::Error,
+    vic::{Char, GlobalColors, VicImage},
+};
+
+/// Write the character numbers of an image as CSV,
+/// preceded by the global colors as comment lines.
+pub fn write_csv(image: &VicImage, writer: &mut impl Write) -> Result<(), Error> {
+    let global_colors = image.global_colors();
+    writeln!(writer, "# Generated by Pixel Pen")?;
+    writeln!(writer, "# background_color = {}", global_colors.background)?;
+    writeln!(writer, "# border_color = {}", global_colors.border)?;
+    writeln!(writer, "# aux_color = {}", global_colors.aux)?;
+    for row in image.character_numbers().rows() {
+        let values = row
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(writer, "{}", values)?;
+    }
+    Ok(())
+}
+
+/// Create an image from a CSV tile map and the bytes of a character set.
+/// Global colors are read from comment lines like the ones `write_csv` writes,
+/// and are the defaults if there are none.
+/// All cells get the default character color.
+pub fn read_tilemap(reader: impl BufRead, charset: &[u8]) -> Result<VicImage, Error> {
+    if !charset.chunks_exact(Char::HEIGHT).remainder().is_empty() {
+        return Err(Error::InvalidTileMap(format!(
+            "The size of the character set ({} bytes) is not a multiple of {}",
+            charset.len(),
+            Char::HEIGHT
+        )));
+    }
+    let characters: HashMap<usize, [u8; Char::HEIGHT]> = charset
+        .chunks_exact(Char::HEIGHT)
+        .map(|bits| {
+            let mut char = [0; Char::HEIGHT];
+            char.copy_from_slice(bits);
+            char
+        })
+        .enumerate()
+        .collect();
+
+    let mut global_colors = GlobalColors::default();
+    let mut numbers = Vec::new();
+    let mut columns = None;
+    let mut rows = 0;
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        let invalid =
+            |message: &str| Error::InvalidTileMap(format!("Line {}: {}", line_number + 1, message));
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some((name, value)) = comment.split_once('=') {
+                let register = match name.trim() {
+                    "background_color" => &mut global_colors.background,
+                    "border_color" => &mut global_colors.border,
+                    "aux_color" => &mut global_colors.aux,
+                    _ => continue,
+                };
+                *register = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|c| *c < 16)
+                    .ok_or_else(|| invalid("Invalid color"))?;
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let row = line
+            .split(',')
+            .map(|n| n.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("Expected comma-separated character numbers"))?;
+        match columns {
+            None => columns = Some(row.len()),
+            Some(c) if c != row.len() => {
+                return Err(invalid(&format!(
+                    "Expected {} columns but found {}",
+                    c,
+                    row.len()
+                )))
+            }
+            Some(_) => {}
+        }
+        numbers.extend(row);
+        rows += 1;
+    }
+    let columns = columns.ok_or_else(|| Error::InvalidTileMap("No rows".to_string()))?;
+    let size = SizeInCells::new(columns as i32, rows);
+    if size.width >= VicImage::MAX_SIZE.width || size.height >= VicImage::MAX_SIZE.height {
+        return Err(Error::InvalidTileMap(
+            "Too many rows or columns".to_string(),
+        ));
+    }
+    let colors = vec![Char::default().raw_nibble(); numbers.len()];
+    VicImage::from_data(size, global_colors, numbers, colors, characters)
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::{read_tilemap, write_csv};
+    use crate::{
+        error::Error,
+        vic::{Char, Register, VicImage},
+    };
+
+    #[test]
+    fn small_image() {
+        let a = Char::new_highres([0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80], 1);
+        let b = Char::new([0xff; 8], 3);
+        let image = VicImage::with_content(ImgVec::new(vec![a, b, a, b, b, a], 3, 2));
+        let mut output = Vec::new();
+        write_csv(&image, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+# Generated by Pixel Pen
+# background_color = 0
+# border_color = 1
+# aux_color = 2
+0,1,0
+1,1,0
+"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let a = Char::new_highres([0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80], 1);
+        let b = Char::new([0xff; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, b], 2, 2));
+        image.set_global_color(Register::Aux, 5);
+        let mut csv = Vec::new();
+        write_csv(&image, &mut csv).unwrap();
+        let loaded = read_tilemap(&csv[..], &image.to_charset_bytes()).unwrap();
+        assert_eq!(loaded.global_colors().aux, 5);
+        assert_eq!(loaded.to_charset_bytes(), image.to_charset_bytes());
+        assert_eq!(
+            loaded.character_numbers().into_buf(),
+            image.character_numbers().into_buf()
+        );
+    }
+
+    #[test]
+    fn undefined_character() {
+        let result = read_tilemap(&b"0,1\n"[..], &[0; 8]);
+        assert!(matches!(result, Err(Error::UndefinedCharacter(1, 1))));
+    }
+
+    #[test]
+    fn too_many_columns() {
+        let row = vec!["0"; VicImage::MAX_SIZE.width as usize].join(",") + "\n";
+        let result = read_tilemap(row.as_bytes(), &[0; 8]);
+        assert!(matches!(result, Err(Error::InvalidTileMap(_))));
+    }
+}
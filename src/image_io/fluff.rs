@@ -8,12 +8,78 @@ use std::io::Read;
 use crate::{
     error::Error,
     image_io,
-    vic::{self, GlobalColors, VicImage},
+    vic::{self, ColorFormat, GlobalColors, VicImage},
 };
 
 /// The first 7 bytes of a Fluff file
 pub const FILE_IDENTIFIER: &[u8; 7] = b"FLUFF64";
 
+/// The names of the image types, indexed by `FluffHeader::image_type`.
+const IMAGE_TYPE_NAMES: [&str; 27] = [
+    "QImageBitmap",
+    "MultiColorBitmap",
+    "HiresBitmap",
+    "LevelEditor",
+    "CharMapMulticolor",
+    "Sprites",
+    "CharmapRegular",
+    "FullScreenChar",
+    "CharMapMultiColorFixed",
+    "VIC20_MultiColorbitmap",
+    "Sprites2",
+    "CGA",
+    "AMIGA320x200",
+    "AMIGA320x256",
+    "OK64_256x256",
+    "X16_640x480",
+    "NES",
+    "LMetaChunk",
+    "LevelEditorNES",
+    "SpritesNES",
+    "GAMEBOY",
+    "LevelEditorGameboy",
+    "ATARI320x200",
+    "HybridCharset",
+    "AmstradCPC",
+    "AmstradCPCGeneric",
+    "BBC",
+];
+
+/// Image type for high-res images, stored as a bitmap and colors for each character.
+const HIRES_BITMAP: u8 = 2;
+/// Image type for character sets, stored like `VIC20_MULTICOLOR_BITMAP` with one cell per character.
+const CHARMAP_MULTICOLOR: u8 = 4;
+/// Image type for Vic-20 images, stored as a bitmap and colors for each character.
+const VIC20_MULTICOLOR_BITMAP: u8 = 9;
+
+/// The names of the palettes, indexed by `FluffHeader::palette_type`.
+const PALETTE_NAMES: [&str; 13] = [
+    "C64",
+    "C64_ORG",
+    "CGA1_LOW",
+    "CGA1_HIGH",
+    "CGA2_LOW",
+    "CGA2_HIGH",
+    "VIC20",
+    "PICO8",
+    "OK64",
+    "X16",
+    "NES",
+    "AMSTRADCPC",
+    "BBC",
+];
+
+/// The palette type for Vic-20 colors.
+const VIC20_PALETTE: u8 = 6;
+
+/// Get the name of a value from a list of names, for error messages.
+fn name_of(names: &[&str], value: u8) -> String {
+    names
+        .get(value as usize)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("unknown ({})", value))
+}
+
 #[allow(dead_code)] // some fields are never read
 #[derive(Deserialize, Copy, Clone, Debug)]
 #[repr(packed(1))]
@@ -21,52 +87,10 @@ struct FluffHeader {
     /// Version number. 2 on the files I have tested.
     pub version: u32,
 
-    /// Image type.
-    ///
-    /// 0: QImageBitmap
-    /// 1: MultiColorBitmap
-    /// 2: HiresBitmap
-    /// 3: LevelEditor
-    /// 4: CharMapMulticolor
-    /// 5: Sprites
-    /// 6: CharmapRegular
-    /// 7: FullScreenChar
-    /// 8: CharMapMultiColorFixed
-    /// 9: VIC20_MultiColorbitmap
-    /// 10: Sprites2
-    /// 11: CGA
-    /// 12: AMIGA320x200
-    /// 13: AMIGA320x256
-    /// 14: OK64_256x256
-    /// 15: X16_640x480
-    /// 16: NES
-    /// 17: LMetaChunk
-    /// 18: LevelEditorNES
-    /// 19: SpritesNES
-    /// 20: GAMEBOY
-    /// 21: LevelEditorGameboy
-    /// 22: ATARI320x200
-    /// 23: HybridCharset
-    /// 24: AmstradCPC
-    /// 25: AmstradCPCGeneric
-    /// 26: BBC
+    /// Image type. See `IMAGE_TYPE_NAMES`.
     pub image_type: u8,
 
-    /// Palette.
-    ///
-    /// 0: C64
-    /// 1: C64_ORG
-    /// 2: CGA1_LOW
-    /// 3: CGA1_HIGH
-    /// 4: CGA2_LOW
-    /// 5: CGA2_HIGH
-    /// 6: VIC20
-    /// 7: PICO8
-    /// 8: OK64
-    /// 9: X16
-    /// 10: NES
-    /// 11: AMSTRADCPC
-    /// 12: BBC
+    /// Palette. See `PALETTE_NAMES`.
     pub palette_type: u8,
 
     /// Background color
@@ -86,16 +110,52 @@ struct FluffHeader {
     pub height_chars: u8,
 }
 
+/// A character cell. All the image types that are supported store their cells like this.
 #[derive(Deserialize, Debug)]
 #[repr(packed(1))]
 struct FluffChar {
-    /// Bitmap bits. In reverse order compared to memory layout, and with aux=0b10 and color=0b11,
-    // compared to hardware's aux=0b11 and color=0b10.
+    /// Bitmap bits. The pixels are in reverse order compared to memory layout.
+    /// A pixel's value is the index in `colors` of its color, so in multicolor cells
+    /// aux=0b10 and color=0b11, compared to hardware's aux=0b11 and color=0b10.
     bits: [u8; 8],
-    _background: u8,
-    _border: u8,
-    _aux: u8,
-    color: u8,
+    /// Background, border (or foreground in high-res cells), aux and character color.
+    colors: [u8; 4],
+}
+
+impl FluffChar {
+    /// Convert to a Vic-20 character of the given format.
+    fn to_char(&self, format: ColorFormat) -> vic::Char {
+        let (bits, color) = match format {
+            ColorFormat::HighRes => (self.bits.map(u8::reverse_bits), self.colors[1]),
+            ColorFormat::Multicolor => (
+                self.bits.map(|flf_bits| {
+                    // Swap aux and color and reverse the pixels.
+                    (0..8)
+                        .step_by(2)
+                        .map(|bit| {
+                            let value = match (flf_bits >> (6 - bit)) & 0b11 {
+                                0b10 => 0b11,
+                                0b11 => 0b10,
+                                a => a,
+                            };
+                            value << bit
+                        })
+                        .sum()
+                }),
+                self.colors[3],
+            ),
+        };
+        // Color may be 255 for characters with no color.
+        let color = if vic::ALLOWED_CHAR_COLORS.contains(&color) {
+            color
+        } else {
+            1
+        };
+        match format {
+            ColorFormat::HighRes => vic::Char::new_highres(bits, color),
+            ColorFormat::Multicolor => vic::Char::new(bits, color),
+        }
+    }
 }
 
 pub fn load_fluff64(reader: &mut impl Read) -> Result<VicImage, Error> {
@@ -111,7 +171,34 @@ pub fn load_fluff64(reader: &mut impl Read) -> Result<VicImage, Error> {
     }
 
     let header: FluffHeader = image_io::read_struct(reader)?;
+    // The color numbers in the file are indices into the palette,
+    // so they would give the wrong colors with any other palette than the Vic-20's.
+    if header.palette_type != VIC20_PALETTE {
+        return Err(Error::UnsupportedPalette(name_of(
+            &PALETTE_NAMES,
+            header.palette_type,
+        )));
+    }
+    let format = match header.image_type {
+        HIRES_BITMAP => ColorFormat::HighRes,
+        CHARMAP_MULTICOLOR | VIC20_MULTICOLOR_BITMAP => ColorFormat::Multicolor,
+        image_type => {
+            return Err(Error::UnsupportedImageType(name_of(
+                &IMAGE_TYPE_NAMES,
+                image_type,
+            )))
+        }
+    };
+    load_cells(reader, &header, format)
+}
 
+/// Load the cells that follow the header, `width_chars` times `height_chars` of them.
+/// For a `CHARMAP_MULTICOLOR` file, the cells are the characters of the character set.
+fn load_cells(
+    reader: &mut impl Read,
+    header: &FluffHeader,
+    format: ColorFormat,
+) -> Result<VicImage, Error> {
     let width = header.width_chars as usize;
     let height = header.height_chars as usize;
     if width == 0 || height == 0 {
@@ -120,31 +207,7 @@ pub fn load_fluff64(reader: &mut impl Read) -> Result<VicImage, Error> {
     let video_buffer = (0..width * height)
         .map(|_| -> Result<vic::Char, Error> {
             let flf_char: FluffChar = image_io::read_struct(reader)?;
-            let mut bits = [0; 8];
-            for (flf_bits, result_bits) in flf_char.bits.iter().zip(bits.iter_mut()) {
-                // Fluff stores multicolor pixels in reverse order.
-                // Swap aux and color and reverse the pixels.
-                let fixed = (0..8)
-                    .step_by(2)
-                    .map(|bit|
-                        match (flf_bits >> (6 - bit)) & 0b11 {
-                                0b10 => 0b11,
-                                0b11 => 0b10,
-                                a => a,
-                            } << bit
-                    )
-                    .sum();
-                *result_bits = fixed;
-            }
-            Ok(vic::Char::new(
-                bits,
-                if vic::ALLOWED_CHAR_COLORS.contains(&flf_char.color) {
-                    flf_char.color
-                } else {
-                    // Color may be 255 for characters with no color.
-                    1
-                },
-            ))
+            Ok(flf_char.to_char(format))
         })
         .collect::<Result<Vec<vic::Char>, Error>>()?;
     let mut image = VicImage::with_content(ImgVec::new(video_buffer, width, height));
@@ -155,3 +218,165 @@ pub fn load_fluff64(reader: &mut impl Read) -> Result<VicImage, Error> {
     });
     Ok(image)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{load_fluff64, FILE_IDENTIFIER};
+    use crate::{
+        cell_image::CellImageSize,
+        coords::PixelPoint,
+        error::Error,
+        vic::{Char, PixelColor, VicImage},
+    };
+
+    // The test files are written by hand, byte by byte, following the layout of
+    // `FluffHeader` and `FluffChar`. They are not saved by FLUFF itself.
+    // TODO: Add files saved by FLUFF for each supported image type.
+    const HIRES_BITMAP_FILE: &[u8] = include_bytes!("testdata/hires_bitmap.flf");
+    const CHARMAP_MULTICOLOR_FILE: &[u8] = include_bytes!("testdata/charmap_multicolor.flf");
+    const VIC20_MULTICOLOR_BITMAP_FILE: &[u8] =
+        include_bytes!("testdata/vic20_multicolor_bitmap.flf");
+
+    /// Encode an image as a Fluff file of the given type, in the same way as the test files.
+    fn encode(image: &VicImage, image_type: u8) -> Vec<u8> {
+        let colors = image.global_colors();
+        let size = image.size_in_cells();
+        let mut data = FILE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[image_type, 6]);
+        data.extend_from_slice(&[colors.background, colors.background, colors.border]);
+        data.extend_from_slice(&[colors.aux, 5, size.width as u8, size.height as u8]);
+        let (_, color_ram) = image.to_screen_and_color_ram().unwrap();
+        for (i, raw) in color_ram.iter().enumerate() {
+            let (column, row) = (i as i32 % size.width, i as i32 / size.width);
+            let pixel = |x: usize, y: usize| {
+                image
+                    .pixel_color(PixelPoint::new(
+                        column * Char::WIDTH as i32 + x as i32,
+                        row * Char::HEIGHT as i32 + y as i32,
+                    ))
+                    .unwrap()
+            };
+            let multicolor = raw & 0b1000 != 0;
+            let color = raw & 0b111;
+            for y in 0..Char::HEIGHT {
+                let bits: u8 = if multicolor {
+                    (0..4)
+                        .map(|i| {
+                            let value = match pixel(i * 2, y) {
+                                PixelColor::Background => 0,
+                                PixelColor::Border => 1,
+                                PixelColor::Aux => 2,
+                                PixelColor::CharColor(_) => 3,
+                            };
+                            value << (i * 2)
+                        })
+                        .sum()
+                } else {
+                    (0..8)
+                        .filter(|&x| matches!(pixel(x, y), PixelColor::CharColor(_)))
+                        .map(|x| 1 << x)
+                        .sum()
+                };
+                data.push(bits);
+            }
+            if multicolor {
+                data.extend_from_slice(&[colors.background, colors.border, colors.aux]);
+                // Uncolored characters are loaded with color 1
+                data.push(if color == 1 { 255 } else { color });
+            } else {
+                data.extend_from_slice(&[colors.background, color, colors.aux, 255]);
+            }
+        }
+        data
+    }
+
+    fn pixel_color(image: &VicImage, x: i32, y: i32) -> PixelColor {
+        image.pixel_color(PixelPoint::new(x, y)).unwrap()
+    }
+
+    /// Create a Fluff file that is one character wide, with the given character colors.
+    fn fluff_data(colors: &[u8]) -> Vec<u8> {
+        fluff_data_with_type(9, colors)
+    }
+
+    fn fluff_data_with_type(image_type: u8, colors: &[u8]) -> Vec<u8> {
+        let mut data = FILE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes()); // version
+        data.extend_from_slice(&[image_type, 6]); // image_type, palette_type
+        data.extend_from_slice(&[0, 0, 1, 2, 5]); // background, background2, border, aux, pen3
+        data.extend_from_slice(&[1, colors.len() as u8]); // width, height
+        for &color in colors {
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&[0, 1, 2, color]);
+        }
+        data
+    }
+
+    #[test]
+    fn char_colors() {
+        let data = fluff_data(&[0, 7, 255]);
+        let image = load_fluff64(&mut data.as_slice()).unwrap();
+        assert_eq!(image.size_in_cells().to_tuple(), (1, 3));
+        let (_, colors) = image.to_screen_and_color_ram().unwrap();
+        let colors: Vec<u8> = colors.iter().map(|c| c & 0b111).collect();
+        assert_eq!(colors, vec![0, 7, 1]);
+    }
+
+    #[test]
+    fn hires_bitmap() {
+        let image = load_fluff64(&mut &HIRES_BITMAP_FILE[..]).unwrap();
+        assert_eq!(image.size_in_cells().to_tuple(), (2, 1));
+        assert_eq!(image.global_colors().background, 1);
+        assert_eq!(pixel_color(&image, 3, 3), PixelColor::CharColor(2));
+        assert_eq!(pixel_color(&image, 4, 3), PixelColor::Background);
+        assert_eq!(pixel_color(&image, 8, 7), PixelColor::CharColor(6));
+        assert_eq!(pixel_color(&image, 12, 7), PixelColor::Background);
+        assert_eq!(encode(&image, 2), HIRES_BITMAP_FILE);
+    }
+
+    #[test]
+    fn charmap_multicolor() {
+        let image = load_fluff64(&mut &CHARMAP_MULTICOLOR_FILE[..]).unwrap();
+        assert_eq!(image.size_in_cells().to_tuple(), (2, 2));
+        assert_eq!(pixel_color(&image, 0, 0), PixelColor::Background);
+        assert_eq!(pixel_color(&image, 2, 0), PixelColor::Border);
+        assert_eq!(pixel_color(&image, 4, 0), PixelColor::Aux);
+        assert_eq!(pixel_color(&image, 14, 0), PixelColor::CharColor(3));
+        assert_eq!(pixel_color(&image, 6, 8), PixelColor::CharColor(2));
+        assert_eq!(pixel_color(&image, 14, 8), PixelColor::CharColor(1));
+        assert_eq!(encode(&image, 4), CHARMAP_MULTICOLOR_FILE);
+    }
+
+    #[test]
+    fn vic20_multicolor_bitmap() {
+        let image = load_fluff64(&mut &VIC20_MULTICOLOR_BITMAP_FILE[..]).unwrap();
+        assert_eq!(image.size_in_cells().to_tuple(), (1, 1));
+        let colors = image.global_colors();
+        assert_eq!((colors.background, colors.border, colors.aux), (3, 5, 10));
+        // Each row is 0b00_01_10_11, with the leftmost pixel in the lowest bits
+        assert_eq!(pixel_color(&image, 0, 0), PixelColor::CharColor(6));
+        assert_eq!(pixel_color(&image, 2, 0), PixelColor::Aux);
+        assert_eq!(pixel_color(&image, 4, 0), PixelColor::Border);
+        assert_eq!(pixel_color(&image, 6, 7), PixelColor::Background);
+    }
+
+    #[test]
+    fn unsupported_image_type() {
+        let data = fluff_data_with_type(5, &[1]);
+        match load_fluff64(&mut data.as_slice()) {
+            Err(Error::UnsupportedImageType(name)) => assert_eq!(name, "Sprites"),
+            _ => panic!("expected UnsupportedImageType"),
+        }
+    }
+
+    #[test]
+    fn unsupported_palette() {
+        let mut data = fluff_data(&[1]);
+        data[FILE_IDENTIFIER.len() + 5] = 0; // C64 palette
+        match load_fluff64(&mut data.as_slice()) {
+            Err(Error::UnsupportedPalette(name)) => assert_eq!(name, "C64"),
+            _ => panic!("expected UnsupportedPalette"),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Packed binary format, similar to C64's Koala format but with Vic-20 data.
+//!
+//! Layout (all integers little-endian):
+//!
+//! | Size                  | Content
+//! | --------------------- | ------------------------------------------
+//! | 8                     | File identifier ([`FILE_IDENTIFIER`])
+//! | 2                     | Width in characters
+//! | 2                     | Height in characters
+//! | 1                     | Background color
+//! | 1                     | Border color
+//! | 1                     | Aux color
+//! | 2                     | Number of characters
+//! | characters x 8        | Bitmap for each character
+//! | width x height        | Screen RAM (character number for each cell)
+//! | width x height        | Color RAM (color and multicolor bit for each cell)
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::{
+    cell_image::CellImageSize,
+    coords::SizeInCells,
+    error::Error,
+    image_io,
+    vic::{Char, GlobalColors, VicImage},
+};
+
+/// The first 8 bytes of a packed file
+pub const FILE_IDENTIFIER: &[u8; 8] = b"PIXPACK1";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PackedHeader {
+    width_chars: u16,
+    height_chars: u16,
+    background: u8,
+    border: u8,
+    aux: u8,
+    num_characters: u16,
+}
+
+pub fn save_packed(image: &VicImage, writer: &mut impl Write) -> Result<(), Error> {
+    let charset = image.to_charset_bytes();
+    let (screen, colors) = image.to_screen_and_color_ram()?;
+    let size = image.size_in_cells();
+    let global_colors = image.global_colors();
+    let header = PackedHeader {
+        width_chars: size.width as u16,
+        height_chars: size.height as u16,
+        background: global_colors.background,
+        border: global_colors.border,
+        aux: global_colors.aux,
+        num_characters: (charset.len() / Char::HEIGHT) as u16,
+    };
+    writer
+        .write_all(FILE_IDENTIFIER)
+        .map_err(Error::WriteFailure)?;
+    image_io::write_struct(writer, &header)?;
+    for data in [&charset, &screen, &colors] {
+        writer.write_all(data).map_err(Error::WriteFailure)?;
+    }
+    Ok(())
+}
+
+pub fn load_packed(reader: &mut impl Read) -> Result<VicImage, Error> {
+    let mut identifier = [0u8; 8];
+    read_bytes(reader, &mut identifier)?;
+    if &identifier != FILE_IDENTIFIER {
+        return Err(Error::WrongMagic);
+    }
+
+    let header: PackedHeader = image_io::read_struct(reader)?;
+    let width = header.width_chars as usize;
+    let height = header.height_chars as usize;
+    if width == 0
+        || height == 0
+        || width >= VicImage::MAX_SIZE.width as usize
+        || height >= VicImage::MAX_SIZE.height as usize
+    {
+        return Err(Error::InvalidSize(width, height));
+    }
+    let global_colors = GlobalColors {
+        background: header.background,
+        border: header.border,
+        aux: header.aux,
+    };
+    if !global_colors.are_valid(true) {
+        return Err(Error::InvalidGlobalColors(global_colors));
+    }
+
+    let mut charset = vec![0u8; header.num_characters as usize * Char::HEIGHT];
+    read_bytes(reader, &mut charset)?;
+    let mut screen = vec![0u8; width * height];
+    read_bytes(reader, &mut screen)?;
+    let mut colors = vec![0u8; width * height];
+    read_bytes(reader, &mut colors)?;
+
+    let characters = charset
+        .chunks_exact(Char::HEIGHT)
+        .enumerate()
+        .map(|(num, bits)| {
+            let mut bitmap = Char::EMPTY_BITMAP;
+            bitmap.copy_from_slice(bits);
+            (num, bitmap)
+        })
+        .collect::<HashMap<usize, [u8; Char::HEIGHT]>>();
+    VicImage::from_data(
+        SizeInCells::new(width as i32, height as i32),
+        global_colors,
+        screen.into_iter().map(usize::from).collect(),
+        colors,
+        characters,
+    )
+}
+
+fn read_bytes(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buffer).map_err(|err| match err.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::TruncatedData,
+        _ => Error::ReadFailure(err),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::{load_packed, save_packed};
+    use crate::{
+        cell_image::CellImageSize,
+        error::Error,
+        vic::{Char, GlobalColors, VicImage},
+    };
+
+    #[test]
+    fn round_trip() {
+        let a = Char::new_highres([0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80], 7);
+        let b = Char::new([0x1b; 8], 3);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, a, a, b], 3, 2));
+        image.set_global_colors(GlobalColors {
+            background: 5,
+            border: 6,
+            aux: 12,
+        });
+
+        let mut data = Vec::new();
+        save_packed(&image, &mut data).unwrap();
+        let loaded = load_packed(&mut data.as_slice()).unwrap();
+
+        assert_eq!(loaded.size_in_cells(), image.size_in_cells());
+        assert_eq!(loaded.global_colors().aux, 12);
+        assert_eq!(loaded.to_charset_bytes(), image.to_charset_bytes());
+        assert_eq!(
+            loaded.to_screen_and_color_ram().unwrap(),
+            image.to_screen_and_color_ram().unwrap()
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let image = VicImage::new(2, 2);
+        let mut data = Vec::new();
+        save_packed(&image, &mut data).unwrap();
+        data.pop();
+        assert!(load_packed(&mut data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn write_error() {
+        let mut buffer = [0u8; 20];
+        assert!(matches!(
+            save_packed(&VicImage::new(2, 2), &mut &mut buffer[..]),
+            Err(Error::WriteFailure(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut data = Vec::new();
+        save_packed(&VicImage::new(2, 2), &mut data).unwrap();
+        let mut bad_color = data.clone();
+        bad_color[12] = 16; // background
+        assert!(matches!(
+            load_packed(&mut bad_color.as_slice()),
+            Err(Error::InvalidGlobalColors(_))
+        ));
+        let mut too_wide = data;
+        too_wide[8..10].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(matches!(
+            load_packed(&mut too_wide.as_slice()),
+            Err(Error::InvalidSize(..))
+        ));
+    }
+}
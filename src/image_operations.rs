@@ -1,44 +1,151 @@
 use crate::colors::TrueColor;
 use image::RgbaImage;
 use imgref::ImgVec;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How much ordered dithering may change each color component.
+const DITHER_SPREAD: f32 = 48.0;
+
+/// Ordered dithering to use when reducing an image to the palette.
+/// Only used when built without the `imagequant` feature,
+/// as imagequant does its own dithering.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dithering {
+    None,
+    Bayer4,
+    Bayer8,
+}
+
+impl Default for Dithering {
+    fn default() -> Self {
+        Dithering::None
+    }
+}
+
+impl Dithering {
+    pub const ALL: [Dithering; 3] = [Dithering::None, Dithering::Bayer4, Dithering::Bayer8];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Dithering::None => "None",
+            Dithering::Bayer4 => "Bayer 4x4",
+            Dithering::Bayer8 => "Bayer 8x8",
+        }
+    }
+
+    /// Width and height of the threshold matrix, or 0 for no dithering.
+    fn matrix_size(&self) -> u32 {
+        match self {
+            Dithering::None => 0,
+            Dithering::Bayer4 => 4,
+            Dithering::Bayer8 => 8,
+        }
+    }
+
+    /// Get the threshold for a pixel, between -0.5 and 0.5.
+    fn threshold(&self, x: u32, y: u32) -> f32 {
+        let size = self.matrix_size();
+        if size == 0 {
+            return 0.0;
+        }
+        let index = bayer_index(x % size, y % size, size);
+        (index as f32 + 0.5) / (size * size) as f32 - 0.5
+    }
+
+    /// Perturb the color of the pixel at `x`, `y` before finding the closest palette color.
+    #[cfg_attr(feature = "imagequant", allow(dead_code))]
+    fn apply(&self, color: image::Rgba<u8>, x: u32, y: u32) -> image::Rgba<u8> {
+        let offset = self.threshold(x, y) * DITHER_SPREAD;
+        let mut result = color;
+        for c in result.0.iter_mut().take(3) {
+            *c = (*c as f32 + offset).round().clamp(0.0, 255.0) as u8;
+        }
+        result
+    }
+}
+
+/// Get the value in a Bayer threshold matrix of the given size (a power of 2).
+/// The values are between 0 and size * size - 1.
+fn bayer_index(x: u32, y: u32, size: u32) -> u32 {
+    // The 2x2 matrix is [[0, 2], [3, 1]].
+    // Larger matrices are built recursively as 4 * M(x mod n, y mod n) + M2(x / n, y / n).
+    let mut index = 0;
+    let mut bit = size / 2;
+    let mut weight = 1;
+    while bit > 0 {
+        let bx = (x & bit != 0) as u32;
+        let by = (y & bit != 0) as u32;
+        index += weight * [[0, 2], [3, 1]][by as usize][bx as usize];
+        weight *= 4;
+        bit /= 2;
+    }
+    index
+}
 
 /// Generate an image by attempting different color settings and finding the one that gives the least error.
 /// Tries different character colors and finds the one that gives the least quantization error.
 /// The colors in `fixed_colors` will be used in every attempt, in addition to the varying character color.
+/// If there are no colors to attempt, only the fixed colors are used.
+/// Returns the resulting color numbers and the quantization error.
 pub fn optimized_image(
     original: &image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
     fixed_colors: &[u8],
     colors_to_attempt: impl Iterator<Item = u8>,
     palette: &[TrueColor],
-) -> imgref::Img<Vec<u8>> {
-    let (pixels, colors, _error) = colors_to_attempt
+    dithering: Dithering,
+) -> (imgref::Img<Vec<u8>>, f64) {
+    let mut candidates = colors_to_attempt
         .filter(|attempted_color| !fixed_colors.contains(attempted_color))
-        .map(|attempted_color| {
-            // Generate a list of the color combinations to try
-            let mut colors = Vec::with_capacity(fixed_colors.len() + 1);
-            colors.extend_from_slice(fixed_colors);
-            colors.push(attempted_color);
-            // Generate RGBA palette from those colors.
-            let palette = colors
-                .iter()
-                .map(|&c| palette[c as usize])
-                .collect::<Vec<_>>();
-            let (pixels, error) = palettize(original, &palette);
-            (pixels, colors, error)
-        })
+        .map(Some)
+        .collect::<Vec<Option<u8>>>();
+    if candidates.is_empty() {
+        candidates.push(None);
+    }
+    let attempt = |&attempted_color: &Option<u8>| {
+        // Generate a list of the color combinations to try
+        let mut colors = Vec::with_capacity(fixed_colors.len() + 1);
+        colors.extend_from_slice(fixed_colors);
+        colors.extend(attempted_color);
+        // Generate RGBA palette from those colors.
+        let palette = colors
+            .iter()
+            .map(|&c| palette[c as usize])
+            .collect::<Vec<_>>();
+        let (pixels, error) = palettize(original, &palette, dithering);
+        (pixels, colors, error)
+    };
+    // The results are collected in order before picking the best one,
+    // so the result is the same as when running sequentially, even if several attempts are equally good.
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let attempts = candidates.par_iter().map(attempt).collect::<Vec<_>>();
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let attempts = candidates.iter().map(attempt).collect::<Vec<_>>();
+
+    let (pixels, colors, error) = attempts
+        .into_iter()
         .min_by(|(_, _, error0), (_, _, error1)| error0.partial_cmp(error1).unwrap())
         .unwrap();
 
-    ImgVec::new(
-        pixels.iter().map(|&c| colors[c as usize]).collect(),
-        original.width() as usize,
-        original.height() as usize,
+    (
+        ImgVec::new(
+            pixels.iter().map(|&c| colors[c as usize]).collect(),
+            original.width() as usize,
+            original.height() as usize,
+        ),
+        error,
     )
 }
 
 /// Returns (pixels, palette, error).
 #[cfg(feature = "imagequant")]
-pub fn palettize(image: &RgbaImage, palette: &[TrueColor]) -> (Vec<u8>, f64) {
+pub fn palettize(
+    image: &RgbaImage,
+    palette: &[TrueColor],
+    _dithering: Dithering,
+) -> (Vec<u8>, f64) {
     use rgb::AsPixels;
 
     let mut liq = imagequant::new();
@@ -75,14 +182,38 @@ pub fn palettize(image: &RgbaImage, palette: &[TrueColor]) -> (Vec<u8>, f64) {
 
 /// Returns (pixels, palette, error).
 #[cfg(not(feature = "imagequant"))]
-pub fn palettize(image: &RgbaImage, palette: &[TrueColor]) -> (Vec<u8>, f64) {
+pub fn palettize(image: &RgbaImage, palette: &[TrueColor], dithering: Dithering) -> (Vec<u8>, f64) {
     use crate::colors;
 
     let it = image
-        .pixels()
-        .map(|color| colors::closest_palette_entry((*color).into(), palette.iter()))
+        .enumerate_pixels()
+        .map(|(x, y, color)| {
+            colors::closest_palette_entry(dithering.apply(*color, x, y).into(), palette.iter())
+        })
         .map(|(index, error)| (index, error as f64));
     let indices = it.clone().map(|(index, _)| index as u8).collect();
     let error_sum = it.map(|(_, error)| error).sum();
     (indices, error_sum)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{bayer_index, Dithering};
+
+    #[test]
+    fn bayer_4x4_first_rows() {
+        let rows: Vec<Vec<u32>> = (0..2)
+            .map(|y| (0..4).map(|x| bayer_index(x, y, 4)).collect())
+            .collect();
+        assert_eq!(rows, vec![vec![0, 8, 2, 10], vec![12, 4, 14, 6]]);
+    }
+
+    #[test]
+    fn dithering_spreads_flat_color() {
+        let gray = image::Rgba([128, 128, 128, 255]);
+        assert_eq!(Dithering::None.apply(gray, 1, 0), gray);
+        let darker = Dithering::Bayer8.apply(gray, 0, 0);
+        let lighter = Dithering::Bayer8.apply(gray, 0, 1);
+        assert!(darker[0] < 128 && lighter[0] > 128);
+    }
+}
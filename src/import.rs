@@ -1,15 +1,25 @@
 //! Some functionality for importing images.
 
+use crate::cell_image::CellImageSize;
+use crate::coords::PixelPoint;
 use crate::error::Error;
-use crate::vic::ColorFormat;
+use crate::image_operations::Dithering;
+use crate::vic::{self, ColorFormat, GlobalColors, PixelColor, Register, VicImage};
+use image::codecs::gif::GifDecoder;
 use image::imageops::FilterType;
+use image::AnimationDecoder;
 use image::DynamicImage;
 use image::GenericImageView;
 use image::RgbaImage;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(remote = "FilterType")]
@@ -21,6 +31,33 @@ enum FilterTypeForSerialization {
     Lanczos3,
 }
 
+/// Names of the scaling filters, for use on the command line.
+pub const FILTER_NAMES: &[&str] = &["nearest", "triangle", "catmull-rom", "gaussian", "lanczos3"];
+
+/// Get a scaling filter from its name in `FILTER_NAMES`.
+pub fn filter_from_name(name: &str) -> Option<FilterType> {
+    match name {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" => Some(FilterType::Triangle),
+        "catmull-rom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" => Some(FilterType::Lanczos3),
+        _ => None,
+    }
+}
+
+/// Names of the color formats, for use on the command line.
+pub const FORMAT_NAMES: &[&str] = &["multicolor", "hires"];
+
+/// Get a color format from its name in `FORMAT_NAMES`.
+pub fn format_from_name(name: &str) -> Option<ColorFormat> {
+    match name {
+        "multicolor" => Some(ColorFormat::Multicolor),
+        "hires" => Some(ColorFormat::HighRes),
+        _ => None,
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone)]
 pub enum PixelAspectRatio {
     Square,
@@ -42,6 +79,54 @@ impl Display for PixelAspectRatio {
     }
 }
 
+/// Which global colors the importer may change to get a better result.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlobalColorSearch {
+    /// Keep the current global colors.
+    Fixed,
+    /// Find the best background color.
+    Background,
+    /// Find the best background, border and aux colors.
+    /// Only the background is used in high-res images.
+    All,
+}
+
+impl Default for GlobalColorSearch {
+    fn default() -> Self {
+        GlobalColorSearch::Fixed
+    }
+}
+
+impl GlobalColorSearch {
+    pub const ALL: [GlobalColorSearch; 3] = [
+        GlobalColorSearch::Fixed,
+        GlobalColorSearch::Background,
+        GlobalColorSearch::All,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            GlobalColorSearch::Fixed => "Keep current",
+            GlobalColorSearch::Background => "Find background",
+            GlobalColorSearch::All => "Find all",
+        }
+    }
+
+    /// The registers to search for the given color format.
+    fn registers(&self, format: ColorFormat) -> &'static [Register] {
+        match (self, format) {
+            (GlobalColorSearch::Fixed, _) => &[],
+            (GlobalColorSearch::Background, _) | (GlobalColorSearch::All, ColorFormat::HighRes) => {
+                &[Register::Background]
+            }
+            (GlobalColorSearch::All, ColorFormat::Multicolor) => {
+                &[Register::Background, Register::Border, Register::Aux]
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ImportSettings {
@@ -53,6 +138,18 @@ pub struct ImportSettings {
 
     pub format: ColorFormat,
 
+    /// Ordered dithering to use when reducing the colors
+    #[serde(default)]
+    pub dithering: Dithering,
+
+    /// Bit mask of the character colors the image may use. Bit 0 is color 0 etc.
+    #[serde(default = "all_char_colors")]
+    pub allowed_colors: u8,
+
+    /// Whether to change the global colors to fit the image
+    #[serde(default)]
+    pub global_colors: GlobalColorSearch,
+
     /// Aspect ratio to assume for the source pixels
     pub pixel_aspect_ratio: PixelAspectRatio,
 
@@ -63,6 +160,148 @@ pub struct ImportSettings {
     pub height: u32,
 }
 
+/// The values a global color register can have.
+fn register_candidates(register: Register) -> impl Iterator<Item = u8> {
+    let color = match register {
+        Register::Background => PixelColor::Background,
+        Register::Border => PixelColor::Border,
+        Register::Aux => PixelColor::Aux,
+    };
+    color.selectable_colors()
+}
+
+/// Number of conversions `search_global_colors` makes.
+fn conversion_count(settings: &ImportSettings) -> usize {
+    let registers = settings.global_colors.registers(settings.format);
+    if registers.is_empty() {
+        return 0;
+    }
+    1 + registers
+        .iter()
+        .map(|&register| register_candidates(register).count())
+        .sum::<usize>()
+}
+
+/// See `Import::find_global_colors`.
+/// Adds one to `progress` for each conversion. Gives up and returns None if `cancel` is set.
+fn search_global_colors(
+    settings: &ImportSettings,
+    scaled: &RgbaImage,
+    target: &VicImage,
+    progress: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> Option<GlobalColors> {
+    let registers = settings.global_colors.registers(settings.format);
+    if registers.is_empty() {
+        return None;
+    }
+    let mut image = target.clone();
+    let target_pos = PixelPoint::new(settings.left, settings.top);
+    let mut error_with = |colors: &GlobalColors| {
+        image.set_global_colors(colors.clone());
+        let error = image.conversion_error(
+            scaled,
+            target_pos,
+            settings.format,
+            settings.dithering,
+            settings.allowed_colors,
+        );
+        progress.fetch_add(1, Ordering::Relaxed);
+        error
+    };
+    let mut best = target.global_colors().clone();
+    let mut best_error = error_with(&best);
+    for &register in registers {
+        for value in register_candidates(register) {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let mut colors = best.clone();
+            colors[register] = value;
+            let error = error_with(&colors);
+            if error < best_error {
+                best = colors;
+                best_error = error;
+            }
+        }
+    }
+    Some(best)
+}
+
+/// A search for global colors started with `Import::start_global_color_search`.
+/// Dropping it cancels the search.
+pub struct GlobalColorJob {
+    /// Number of conversions made so far.
+    progress: Arc<AtomicUsize>,
+    /// Number of conversions the search makes in total.
+    total: usize,
+    cancel: Arc<AtomicBool>,
+    result: Receiver<Option<GlobalColors>>,
+}
+
+impl GlobalColorJob {
+    /// How much of the search is done, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        self.progress.load(Ordering::Relaxed) as f32 / self.total.max(1) as f32
+    }
+
+    /// The result of the search, once it has finished. See `Import::find_global_colors`.
+    pub fn result(&self) -> Option<Option<GlobalColors>> {
+        self.result.try_recv().ok()
+    }
+}
+
+impl Drop for GlobalColorJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+fn all_char_colors() -> u8 {
+    vic::ALL_CHAR_COLORS_MASK
+}
+
+impl ImportSettings {
+    /// Check that the settings are within the ranges the Import tool allows
+    /// for a target image of the given size in pixels.
+    pub fn validate(&self, target_width: u32, target_height: u32) -> Result<(), Error> {
+        if vic::char_colors_in_mask(self.allowed_colors)
+            .next()
+            .is_none()
+        {
+            return Err(Error::InvalidImportSettings(
+                "at least one character color must be allowed".to_string(),
+            ));
+        }
+        let max_width = target_width * 4;
+        if self.width == 0 || self.width > max_width {
+            return Err(Error::InvalidImportSettings(format!(
+                "width must be between 1 and {} pixels",
+                max_width
+            )));
+        }
+        let valid_left = -(self.width as i32)..target_width as i32;
+        if !valid_left.contains(&self.left) {
+            return Err(Error::InvalidImportSettings(format!(
+                "left must be between {} and {} for an image {} pixels wide",
+                valid_left.start,
+                valid_left.end - 1,
+                self.width
+            )));
+        }
+        let valid_top = -(self.height as i32)..target_height as i32;
+        if !valid_top.contains(&self.top) {
+            return Err(Error::InvalidImportSettings(format!(
+                "top must be between {} and {} for an image {} pixels high",
+                valid_top.start,
+                valid_top.end - 1,
+                self.height
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// State of an ongoing import.
 #[derive(Debug, Clone)]
 pub struct Import {
@@ -84,12 +323,44 @@ impl Import {
             image.dimensions(),
             image.color()
         );
+        Ok(Self::from_image(image, Some(filename.to_owned())))
+    }
+
+    /// Load every frame of an image file, one Import per frame.
+    /// Only GIF files can have more than one frame; other files are loaded as by `load`.
+    pub fn load_frames(filename: &Path) -> Result<Vec<Import>, Error> {
+        if image::ImageFormat::from_path(filename).ok() != Some(image::ImageFormat::Gif) {
+            return Ok(vec![Self::load(filename)?]);
+        }
+        let file = BufReader::new(File::open(filename)?);
+        let frames = GifDecoder::new(file)?.into_frames().collect_frames()?;
+        println!(
+            "Import animation {}: {} frames",
+            filename.display(),
+            frames.len()
+        );
+        Ok(frames
+            .into_iter()
+            .map(|frame| {
+                Self::from_image(
+                    DynamicImage::ImageRgba8(frame.into_buffer()),
+                    Some(filename.to_owned()),
+                )
+            })
+            .collect())
+    }
 
-        Ok(Import {
+    /// Create an Import from an image in memory.
+    /// `filename` is where the image came from, if it was loaded from a file.
+    pub fn from_image(image: DynamicImage, filename: Option<PathBuf>) -> Import {
+        Import {
             settings: ImportSettings {
-                filename: Some(filename.to_owned()),
+                filename,
                 filter: FilterType::Gaussian,
                 format: ColorFormat::Multicolor,
+                dithering: Dithering::None,
+                allowed_colors: vic::ALL_CHAR_COLORS_MASK,
+                global_colors: GlobalColorSearch::Fixed,
                 pixel_aspect_ratio: PixelAspectRatio::Square,
                 left: 0,
                 top: 0,
@@ -97,7 +368,104 @@ impl Import {
                 height: image.dimensions().1,
             },
             image,
-        })
+        }
+    }
+
+    /// Make the import no larger than the target image.
+    pub fn clamp_to(&mut self, target: &VicImage) {
+        let (target_width, target_height) = target.size_in_pixels();
+        self.settings.width = self.settings.width.min(target_width as u32);
+        self.settings.height = self.settings.height.min(target_height as u32);
+    }
+
+    /// Calculate the height of the scaled image from its width and the pixel aspect ratio.
+    pub fn update_height(&mut self, target_pixel_aspect_ratio: f32) {
+        let settings = &mut self.settings;
+        let (source_width, source_height) = self.image.dimensions();
+        let height = settings.width as f32 / source_width as f32 * source_height as f32;
+        settings.height = (match settings.pixel_aspect_ratio {
+            PixelAspectRatio::Square => height * target_pixel_aspect_ratio,
+            PixelAspectRatio::Target => height,
+            PixelAspectRatio::TargetHalfResolution => height / 2.0,
+        }
+        .round() as u32)
+            .max(1);
+    }
+
+    /// Scale the image and paste it into `target` without any user interaction.
+    /// Returns an error if the settings are not valid for the target image.
+    pub fn paste_into(&mut self, target: &mut VicImage) -> Result<(), Error> {
+        self.update_height(target.pixel_aspect_ratio());
+        let (target_width, target_height) = target.size_in_pixels();
+        self.settings
+            .validate(target_width as u32, target_height as u32)?;
+        let scaled = self.scale_image();
+        if let Some(colors) = self.find_global_colors(&scaled, target) {
+            target.set_global_colors(colors);
+        }
+        target.paste_image(
+            &scaled,
+            PixelPoint::new(self.settings.left, self.settings.top),
+            self.settings.format,
+            self.settings.dithering,
+            self.settings.allowed_colors,
+        );
+        Ok(())
+    }
+
+    /// Search for the global colors that give the lowest total quantization error
+    /// when pasting the scaled image into `target`, according to `settings.global_colors`.
+    /// Returns None if the current colors should be kept.
+    ///
+    /// Each try converts the whole image, so trying every combination of the registers
+    /// would take too long. Instead, the registers are optimized one at a time,
+    /// keeping the best value found for the previous ones.
+    /// This is an approximation: it may miss the best combination
+    /// if the best value of one register depends on the value of another.
+    pub fn find_global_colors(
+        &self,
+        scaled: &RgbaImage,
+        target: &VicImage,
+    ) -> Option<GlobalColors> {
+        search_global_colors(
+            &self.settings,
+            scaled,
+            target,
+            &AtomicUsize::new(0),
+            &AtomicBool::new(false),
+        )
+    }
+
+    /// Start a `find_global_colors` on a thread of its own,
+    /// so the user interface stays responsive while it runs.
+    /// On the web, where there are no threads, the search is done before this returns.
+    pub fn start_global_color_search(
+        &self,
+        scaled: &RgbaImage,
+        target: &VicImage,
+    ) -> GlobalColorJob {
+        let progress = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, result) = mpsc::channel();
+        let job = GlobalColorJob {
+            progress: progress.clone(),
+            total: conversion_count(&self.settings),
+            cancel: cancel.clone(),
+            result,
+        };
+        let settings = self.settings.clone();
+        let scaled = scaled.clone();
+        let target = target.clone();
+        let search = move || {
+            let colors = search_global_colors(&settings, &scaled, &target, &progress, &cancel);
+            // Fails if the job has been dropped, and then nobody wants the result
+            let _ = sender.send(colors);
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(search);
+        #[cfg(target_arch = "wasm32")]
+        search();
+        job
     }
 
     /// Get the scaled image
@@ -111,3 +479,75 @@ impl Import {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, RgbaImage};
+
+    use super::{GlobalColorSearch, Import};
+    use crate::vic::{ColorFormat, VicImage};
+
+    #[test]
+    fn finds_background_color() {
+        let target = VicImage::new(1, 1);
+        let palette = target.palette();
+        let red = image::Rgba::from(palette.color(2u8));
+        let mut import = Import::from_image(
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, red)),
+            None,
+        );
+        import.settings.format = ColorFormat::HighRes;
+        import.settings.allowed_colors = 1 << 5;
+        assert!(import
+            .find_global_colors(&import.scale_image(), &target)
+            .is_none());
+        import.settings.global_colors = GlobalColorSearch::Background;
+        let colors = import
+            .find_global_colors(&import.scale_image(), &target)
+            .unwrap();
+        assert_eq!(colors.background, 2);
+
+        let job = import.start_global_color_search(&import.scale_image(), &target);
+        let colors = loop {
+            if let Some(result) = job.result() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert_eq!(colors.background, 2);
+        assert_eq!(job.progress(), 1.0);
+    }
+
+    #[test]
+    fn paste_into_rejects_placement_outside_image() {
+        let mut target = VicImage::new(2, 2);
+        let mut import = Import::from_image(DynamicImage::new_rgba8(8, 8), None);
+        import.settings.left = 16;
+        assert!(import.paste_into(&mut target).is_err());
+        import.settings.left = -7;
+        assert!(import.paste_into(&mut target).is_ok());
+    }
+
+    #[test]
+    fn loads_all_gif_frames() {
+        let filename =
+            std::env::temp_dir().join(format!("pixel_pen_test_{}.gif", std::process::id()));
+        {
+            let file = std::fs::File::create(&filename).unwrap();
+            let mut encoder = image::codecs::gif::GifEncoder::new(file);
+            for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+                let frame = RgbaImage::from_pixel(8, 4, image::Rgba(color));
+                encoder.encode_frame(image::Frame::new(frame)).unwrap();
+            }
+        }
+        let frames = Import::load_frames(&filename);
+        std::fs::remove_file(&filename).unwrap();
+        let frames = frames.unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].settings.width, 8);
+        assert_eq!(
+            frames[1].image.to_rgba8().get_pixel(0, 0).0,
+            [0, 0, 255, 255]
+        );
+    }
+}
@@ -0,0 +1,493 @@
+//! Keyboard shortcuts that the user can change.
+
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+use eframe::egui::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, DocAction, UiAction},
+    error::Error,
+    mode::Mode,
+    tool::ToolType,
+    vic::PixelColor,
+};
+
+/// Name of the file in the configuration directory where the user's shortcuts are stored.
+pub const KEYMAP_FILENAME: &str = "keymap.json";
+
+/// Something a keyboard shortcut can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    ZoomIn,
+    ZoomOut,
+    ZoomToFit,
+    DecreasePenSize,
+    IncreasePenSize,
+    PaintTool,
+    CharBrushTool,
+    GrabTool,
+    EraseMode,
+    CellColorMode,
+    FillCellMode,
+    FloodFillMode,
+    GlobalFillMode,
+    MakeHiResMode,
+    MakeMulticolorMode,
+    ReplaceColorMode,
+    SwapColorsMode,
+    ToggleGrid,
+    ToggleRaw,
+    Undo,
+    Redo,
+    MirrorBrushX,
+    MirrorBrushY,
+    RotateBrush,
+    SwapPaintColors,
+    Clear,
+    Deselect,
+    Paste,
+    MoveCursorLeft,
+    MoveCursorRight,
+    MoveCursorUp,
+    MoveCursorDown,
+    PaintAtCursor,
+    ShiftImageLeft,
+    ShiftImageRight,
+    ShiftImageUp,
+    ShiftImageDown,
+    #[serde(rename = "char-color-0")]
+    CharColor0,
+    #[serde(rename = "char-color-1")]
+    CharColor1,
+    #[serde(rename = "char-color-2")]
+    CharColor2,
+    #[serde(rename = "char-color-3")]
+    CharColor3,
+    #[serde(rename = "char-color-4")]
+    CharColor4,
+    #[serde(rename = "char-color-5")]
+    CharColor5,
+    #[serde(rename = "char-color-6")]
+    CharColor6,
+    #[serde(rename = "char-color-7")]
+    CharColor7,
+}
+
+impl Command {
+    /// All commands, in the order to show them in the user interface.
+    pub const ALL: [Command; 45] = [
+        Command::ZoomIn,
+        Command::ZoomOut,
+        Command::ZoomToFit,
+        Command::DecreasePenSize,
+        Command::IncreasePenSize,
+        Command::PaintTool,
+        Command::CharBrushTool,
+        Command::GrabTool,
+        Command::EraseMode,
+        Command::CellColorMode,
+        Command::FillCellMode,
+        Command::FloodFillMode,
+        Command::GlobalFillMode,
+        Command::MakeHiResMode,
+        Command::MakeMulticolorMode,
+        Command::ReplaceColorMode,
+        Command::SwapColorsMode,
+        Command::ToggleGrid,
+        Command::ToggleRaw,
+        Command::Undo,
+        Command::Redo,
+        Command::MirrorBrushX,
+        Command::MirrorBrushY,
+        Command::RotateBrush,
+        Command::SwapPaintColors,
+        Command::Clear,
+        Command::Deselect,
+        Command::Paste,
+        Command::MoveCursorLeft,
+        Command::MoveCursorRight,
+        Command::MoveCursorUp,
+        Command::MoveCursorDown,
+        Command::PaintAtCursor,
+        Command::ShiftImageLeft,
+        Command::ShiftImageRight,
+        Command::ShiftImageUp,
+        Command::ShiftImageDown,
+        Command::CharColor0,
+        Command::CharColor1,
+        Command::CharColor2,
+        Command::CharColor3,
+        Command::CharColor4,
+        Command::CharColor5,
+        Command::CharColor6,
+        Command::CharColor7,
+    ];
+
+    /// Create the action to execute for this command.
+    pub fn action(&self) -> Action {
+        match self.document_action() {
+            Some(action) => Action::Document(action),
+            None => Action::Ui(self.ui_action()),
+        }
+    }
+
+    /// The action for the commands that change the document directly.
+    fn document_action(&self) -> Option<DocAction> {
+        let shift = |dx, dy| DocAction::Shift { dx, dy, wrap: true };
+        Some(match self {
+            Command::Clear => DocAction::Clear {
+                color: PixelColor::Background,
+            },
+            Command::ShiftImageLeft => shift(-1, 0),
+            Command::ShiftImageRight => shift(1, 0),
+            Command::ShiftImageUp => shift(0, -1),
+            Command::ShiftImageDown => shift(0, 1),
+            _ => return None,
+        })
+    }
+
+    /// The action for the commands that don't have a document action.
+    fn ui_action(&self) -> UiAction {
+        match self {
+            Command::ZoomIn => UiAction::ZoomIn,
+            Command::ZoomOut => UiAction::ZoomOut,
+            Command::ZoomToFit => UiAction::ZoomToFit,
+            Command::DecreasePenSize => UiAction::ChangePenSize(-1),
+            Command::IncreasePenSize => UiAction::ChangePenSize(1),
+            Command::PaintTool => UiAction::SelectTool(ToolType::Paint),
+            Command::CharBrushTool => UiAction::SelectTool(ToolType::CharBrush),
+            Command::GrabTool => UiAction::SelectTool(ToolType::Grab),
+            Command::EraseMode => UiAction::SelectMode(Mode::Erase),
+            Command::CellColorMode => UiAction::SelectMode(Mode::CellColor),
+            Command::FillCellMode => UiAction::SelectMode(Mode::FillCell),
+            Command::FloodFillMode => UiAction::SelectMode(Mode::FloodFill),
+            Command::GlobalFillMode => UiAction::SelectMode(Mode::GlobalFill),
+            Command::MakeHiResMode => UiAction::SelectMode(Mode::MakeHiRes),
+            Command::MakeMulticolorMode => UiAction::SelectMode(Mode::MakeMulticolor),
+            Command::ReplaceColorMode => UiAction::SelectMode(Mode::ReplaceColor),
+            Command::SwapColorsMode => UiAction::SelectMode(Mode::SwapColors),
+            Command::ToggleGrid => UiAction::ToggleGrid,
+            Command::ToggleRaw => UiAction::ToggleRaw,
+            Command::Undo => UiAction::Undo,
+            Command::Redo => UiAction::Redo,
+            Command::MirrorBrushX => UiAction::MirrorBrushX,
+            Command::MirrorBrushY => UiAction::MirrorBrushY,
+            Command::RotateBrush => UiAction::RotateBrush,
+            Command::SwapPaintColors => UiAction::SwapPaintColors,
+            Command::Deselect => UiAction::SetSelection(None),
+            Command::Paste => UiAction::PasteImage,
+            Command::MoveCursorLeft => UiAction::MoveCursor { dx: -1, dy: 0 },
+            Command::MoveCursorRight => UiAction::MoveCursor { dx: 1, dy: 0 },
+            Command::MoveCursorUp => UiAction::MoveCursor { dx: 0, dy: -1 },
+            Command::MoveCursorDown => UiAction::MoveCursor { dx: 0, dy: 1 },
+            Command::PaintAtCursor => UiAction::PaintAtCursor,
+            Command::CharColor0 => UiAction::SetPrimaryColor(PixelColor::CharColor(0)),
+            Command::CharColor1 => UiAction::SetPrimaryColor(PixelColor::CharColor(1)),
+            Command::CharColor2 => UiAction::SetPrimaryColor(PixelColor::CharColor(2)),
+            Command::CharColor3 => UiAction::SetPrimaryColor(PixelColor::CharColor(3)),
+            Command::CharColor4 => UiAction::SetPrimaryColor(PixelColor::CharColor(4)),
+            Command::CharColor5 => UiAction::SetPrimaryColor(PixelColor::CharColor(5)),
+            Command::CharColor6 => UiAction::SetPrimaryColor(PixelColor::CharColor(6)),
+            Command::CharColor7 => UiAction::SetPrimaryColor(PixelColor::CharColor(7)),
+            Command::Clear
+            | Command::ShiftImageLeft
+            | Command::ShiftImageRight
+            | Command::ShiftImageUp
+            | Command::ShiftImageDown => unreachable!("{:?} is a document action", self),
+        }
+    }
+
+    /// Description of the command to show to the user.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Command::ZoomIn => "Zoom in",
+            Command::ZoomOut => "Zoom out",
+            Command::ZoomToFit => "Zoom to fit",
+            Command::DecreasePenSize => "Decrease pen size",
+            Command::IncreasePenSize => "Increase pen size",
+            Command::PaintTool => "Paint tool",
+            Command::CharBrushTool => "Character Brush tool",
+            Command::GrabTool => "Grab tool",
+            Command::EraseMode => "Erase mode",
+            Command::CellColorMode => "Cell Color mode",
+            Command::FillCellMode => "Fill Cell mode",
+            Command::FloodFillMode => "Flood Fill mode",
+            Command::GlobalFillMode => "Global Fill mode",
+            Command::MakeHiResMode => "Make High-Res mode",
+            Command::MakeMulticolorMode => "Make Multicolor mode",
+            Command::ReplaceColorMode => "Replace Color mode",
+            Command::SwapColorsMode => "Swap Colors mode",
+            Command::ToggleGrid => "Cycle grid mode",
+            Command::ToggleRaw => "Raw mode on/off",
+            Command::Undo => "Undo",
+            Command::Redo => "Redo",
+            Command::MirrorBrushX => "Mirror brush horizontally",
+            Command::MirrorBrushY => "Mirror brush vertically",
+            Command::RotateBrush => "Rotate brush clockwise",
+            Command::SwapPaintColors => "Swap primary and secondary colors",
+            Command::Clear => "Clear the image or selection",
+            Command::Deselect => "Remove the selection",
+            Command::Paste => "Paste image",
+            Command::MoveCursorLeft => "Move cursor left",
+            Command::MoveCursorRight => "Move cursor right",
+            Command::MoveCursorUp => "Move cursor up",
+            Command::MoveCursorDown => "Move cursor down",
+            Command::PaintAtCursor => "Paint at the cursor",
+            Command::ShiftImageLeft => "Shift image left",
+            Command::ShiftImageRight => "Shift image right",
+            Command::ShiftImageUp => "Shift image up",
+            Command::ShiftImageDown => "Shift image down",
+            Command::CharColor0 => "Character color 0 as primary color",
+            Command::CharColor1 => "Character color 1 as primary color",
+            Command::CharColor2 => "Character color 2 as primary color",
+            Command::CharColor3 => "Character color 3 as primary color",
+            Command::CharColor4 => "Character color 4 as primary color",
+            Command::CharColor5 => "Character color 5 as primary color",
+            Command::CharColor6 => "Character color 6 as primary color",
+            Command::CharColor7 => "Character color 7 as primary color",
+        }
+    }
+}
+
+/// The name of a key that doesn't generate text, or is pressed together with Ctrl or Alt,
+/// e.g. "Delete" or "Ctrl+Shift+D". Ctrl means the Command key on Mac.
+/// Returns None for other keys, which are named by the text they generate.
+pub fn key_name(key: Key, modifiers: &Modifiers) -> Option<String> {
+    // Without Ctrl or Alt, other keys generate text or have fixed functions
+    let named = matches!(
+        key,
+        Key::Insert
+            | Key::Delete
+            | Key::Home
+            | Key::End
+            | Key::PageUp
+            | Key::PageDown
+            | Key::ArrowLeft
+            | Key::ArrowRight
+            | Key::ArrowUp
+            | Key::ArrowDown
+            | Key::Space
+    ) || modifiers.command
+        || modifiers.alt;
+    if !named {
+        return None;
+    }
+    let name = match key {
+        Key::Num0 => "0".to_string(),
+        Key::Num1 => "1".to_string(),
+        Key::Num2 => "2".to_string(),
+        Key::Num3 => "3".to_string(),
+        Key::Num4 => "4".to_string(),
+        Key::Num5 => "5".to_string(),
+        Key::Num6 => "6".to_string(),
+        Key::Num7 => "7".to_string(),
+        Key::Num8 => "8".to_string(),
+        Key::Num9 => "9".to_string(),
+        _ => format!("{:?}", key),
+    };
+    let mut prefix = String::new();
+    if modifiers.command {
+        prefix.push_str("Ctrl+");
+    }
+    if modifiers.alt {
+        prefix.push_str("Alt+");
+    }
+    if modifiers.shift {
+        prefix.push_str("Shift+");
+    }
+    Some(prefix + &name)
+}
+
+/// Which command each key triggers.
+/// Keys are the text the key generates, e.g. "H" for shift+h,
+/// or the name given by [`key_name`] for other keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: BTreeMap<String, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            ("0", Command::CharColor0),
+            ("1", Command::CharColor1),
+            ("2", Command::CharColor2),
+            ("3", Command::CharColor3),
+            ("4", Command::CharColor4),
+            ("5", Command::CharColor5),
+            ("6", Command::CharColor6),
+            ("7", Command::CharColor7),
+            ("+", Command::ZoomIn),
+            ("-", Command::ZoomOut),
+            ("[", Command::DecreasePenSize),
+            ("]", Command::IncreasePenSize),
+            ("b", Command::CharBrushTool),
+            ("c", Command::CellColorMode),
+            ("d", Command::PaintTool),
+            ("e", Command::EraseMode),
+            ("f", Command::FillCellMode),
+            ("g", Command::ToggleGrid),
+            ("h", Command::MakeHiResMode),
+            ("H", Command::MakeMulticolorMode),
+            ("r", Command::ReplaceColorMode),
+            ("R", Command::SwapColorsMode),
+            ("s", Command::SwapPaintColors),
+            ("w", Command::ToggleRaw),
+            ("u", Command::Undo),
+            ("U", Command::Redo),
+            ("v", Command::GrabTool),
+            ("x", Command::MirrorBrushX),
+            ("y", Command::MirrorBrushY),
+            ("z", Command::ZoomToFit),
+            ("Ctrl+Delete", Command::Clear),
+            ("Ctrl+D", Command::Deselect),
+            ("Ctrl+V", Command::Paste),
+            ("ArrowLeft", Command::MoveCursorLeft),
+            ("ArrowRight", Command::MoveCursorRight),
+            ("ArrowUp", Command::MoveCursorUp),
+            ("ArrowDown", Command::MoveCursorDown),
+            ("Space", Command::PaintAtCursor),
+            ("Alt+ArrowLeft", Command::ShiftImageLeft),
+            ("Alt+ArrowRight", Command::ShiftImageRight),
+            ("Alt+ArrowUp", Command::ShiftImageUp),
+            ("Alt+ArrowDown", Command::ShiftImageDown),
+        ]
+        .iter()
+        .map(|(key, command)| (key.to_string(), *command))
+        .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Create a keymap from key and command pairs.
+    pub fn from_bindings(bindings: impl IntoIterator<Item = (String, Command)>) -> Self {
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+
+    /// Get the action to execute when a key is pressed, if any.
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.bindings.get(key).map(|command| command.action())
+    }
+
+    /// Get the key that triggers a command, if any.
+    pub fn key_for(&self, command: Command) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(_, c)| **c == command)
+            .map(|(key, _)| key.as_str())
+    }
+
+    /// Load the user's shortcuts from a file.
+    /// The file only contains the differences from the default keymap,
+    /// with `null` for default keys that have been removed.
+    pub fn load(filename: &Path) -> Result<Keymap, Error> {
+        let reader = BufReader::new(File::open(filename)?);
+        let overrides: BTreeMap<String, Option<Command>> = serde_json::from_reader(reader)?;
+        Ok(Self::with_overrides(overrides))
+    }
+
+    /// Save the differences from the default keymap to a file.
+    pub fn save(&self, filename: &Path) -> Result<(), Error> {
+        if let Some(directory) = filename.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let writer = File::create(filename)?;
+        serde_json::to_writer_pretty(writer, &self.overrides())?;
+        Ok(())
+    }
+
+    fn with_overrides(overrides: BTreeMap<String, Option<Command>>) -> Self {
+        let mut keymap = Self::default();
+        for (key, command) in overrides {
+            match command {
+                Some(command) => keymap.bindings.insert(key, command),
+                None => keymap.bindings.remove(&key),
+            };
+        }
+        keymap
+    }
+
+    fn overrides(&self) -> BTreeMap<String, Option<Command>> {
+        let default = Self::default();
+        let removed = default
+            .bindings
+            .keys()
+            .filter(|key| !self.bindings.contains_key(*key))
+            .map(|key| (key.clone(), None));
+        let changed = self
+            .bindings
+            .iter()
+            .filter(|(key, command)| default.bindings.get(*key) != Some(command))
+            .map(|(key, command)| (key.clone(), Some(*command)));
+        removed.chain(changed).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{key_name, Command, Keymap};
+    use crate::actions::{Action, DocAction, UiAction};
+    use eframe::egui::{Key, Modifiers};
+
+    #[test]
+    fn overrides_only_contain_changes() {
+        let mut keymap = Keymap::default();
+        keymap.bindings.remove("h");
+        keymap
+            .bindings
+            .insert("j".to_string(), Command::MakeHiResMode);
+        let overrides = keymap.overrides();
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides["h"], None);
+        assert_eq!(overrides["j"], Some(Command::MakeHiResMode));
+        assert_eq!(Keymap::with_overrides(overrides), keymap);
+    }
+
+    #[test]
+    fn keys_without_text_are_named() {
+        let ctrl = Modifiers {
+            ctrl: true,
+            command: true,
+            ..Default::default()
+        };
+        let shift = Modifiers {
+            shift: true,
+            ..Default::default()
+        };
+        assert_eq!(key_name(Key::Delete, &ctrl).as_deref(), Some("Ctrl+Delete"));
+        assert_eq!(
+            key_name(Key::Delete, &shift).as_deref(),
+            Some("Shift+Delete")
+        );
+        assert_eq!(key_name(Key::D, &ctrl).as_deref(), Some("Ctrl+D"));
+        assert_eq!(
+            key_name(Key::ArrowLeft, &Modifiers::default()).as_deref(),
+            Some("ArrowLeft")
+        );
+        // These generate text
+        assert_eq!(key_name(Key::D, &shift), None);
+        assert_eq!(key_name(Key::Num1, &Modifiers::default()), None);
+    }
+
+    #[test]
+    fn default_keymap_has_document_and_ui_actions() {
+        let keymap = Keymap::default();
+        assert!(matches!(
+            keymap.action("Alt+ArrowLeft"),
+            Some(Action::Document(DocAction::Shift {
+                dx: -1,
+                dy: 0,
+                wrap: true
+            }))
+        ));
+        assert!(matches!(
+            keymap.action("Ctrl+V"),
+            Some(Action::Ui(UiAction::PasteImage))
+        ));
+    }
+}
@@ -1,47 +1,93 @@
+//! Pixel Pen is an editor for graphics on 8 bit computers.
+//!
+//! Besides the [`Application`], the library can be used without any user interface,
+//! e.g. in a build pipeline that converts images:
+//!
+//! ```no_run
+//! use pixel_pen::{storage, ViewSettings};
+//! use std::path::Path;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let doc = storage::load_any_file(Path::new("image.pixelpen"))?;
+//! let image = doc.render(&ViewSettings::Normal);
+//! image.save("image.png")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`storage::load_any_file`] supports the native format as well as
+//! the other formats Pixel Pen can load, e.g. FLUFF and standard image files.
+//! Loading and rendering does not open any windows or require a graphics context.
+//! Build without the default `gui` feature to leave out the editor and its dependencies.
+
 #![forbid(unsafe_code)]
 #![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod actions;
+#[cfg(feature = "gui")]
 mod app;
+#[cfg(feature = "gui")]
+mod autosave;
 mod brush;
 mod cell_image;
 mod colors;
 mod coords;
 mod document;
+#[cfg(feature = "gui")]
 mod editor;
+#[cfg(feature = "gui")]
 mod egui_extensions;
 pub mod error;
+mod gzip;
 mod image_io;
 mod image_operations;
-mod import;
+pub mod import;
+#[cfg(feature = "gui")]
+mod keymap;
 mod line;
+mod metadata;
+#[cfg(feature = "gui")]
 mod mode;
 mod mutation_monitor;
+#[cfg(feature = "gui")]
+mod playback;
+mod selection;
+#[cfg(feature = "gui")]
+mod settings;
 pub mod storage;
 pub mod system;
+#[cfg(feature = "gui")]
 mod texture;
+#[cfg(feature = "gui")]
 mod tool;
+#[cfg(feature = "gui")]
 mod ui;
 mod update_area;
 mod vic;
+#[cfg(all(feature = "gui", target_arch = "wasm32"))]
+mod web;
+#[cfg(feature = "gui")]
 mod widgets;
+#[cfg(feature = "gui")]
 pub use app::Application;
 pub use document::Document;
+pub use vic::{Char, CustomPalette, VicImage, VicPalette, ViewSettings};
 
 // ----------------------------------------------------------------------------
 // When compiling for web:
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "gui", target_arch = "wasm32"))]
 use eframe::wasm_bindgen::{self, prelude::*};
 
 /// This is the entry-point for all the web-assembly.
 /// This is called once from the HTML.
 /// It loads the app, installs some callbacks, then returns.
 /// You can add more callbacks like this if you want to call in to your code.
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "gui", target_arch = "wasm32"))]
 #[wasm_bindgen]
 pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
-    let app = Application::default();
+    let mut app = Application::default();
+    app.system = Box::new(web::WebSystemFunctions::new());
     eframe::start_web(canvas_id, Box::new(app))
 }
@@ -28,13 +28,16 @@ fn main() {
 
 #[cfg(not(target_arch = "wasm32"))]
 mod native {
-    use directories::UserDirs;
+    use arboard::{Clipboard, ImageData};
+    use directories::{ProjectDirs, UserDirs};
     use eframe::epi::IconData;
-    use image::{GenericImageView, ImageFormat};
+    use image::{GenericImageView, ImageFormat, RgbaImage};
     use native_dialog::{FileDialog, MessageDialog, MessageType};
     use pixel_pen::error::Error;
     use pixel_pen::storage;
     use pixel_pen::system::{OpenFileOptions, SaveFileOptions, SystemFunctions};
+    use std::borrow::Cow;
+    use std::cell::{RefCell, RefMut};
     use std::ffi::{OsStr, OsString};
     use std::path::{Path, PathBuf};
 
@@ -44,6 +47,8 @@ mod native {
     pub struct NativeSystemFunctions {
         location: PathBuf,
         filename: String,
+        /// Created when first used. Kept alive so the clipboard content stays available.
+        clipboard: RefCell<Option<Clipboard>>,
     }
 
     impl NativeSystemFunctions {
@@ -51,14 +56,27 @@ mod native {
             Self {
                 location: PathBuf::default(),
                 filename: String::default(),
+                clipboard: RefCell::new(None),
             }
         }
 
+        fn clipboard(&self) -> Result<RefMut<'_, Clipboard>, Error> {
+            let mut clipboard = self.clipboard.borrow_mut();
+            if clipboard.is_none() {
+                *clipboard =
+                    Some(Clipboard::new().map_err(|e| Error::ClipboardError(e.to_string()))?);
+            }
+            Ok(RefMut::map(clipboard, |c| c.as_mut().unwrap()))
+        }
+
         fn create_file_dialog(
             &mut self,
             initial_path: Option<&Path>,
             include_native: bool,
             include_images: bool,
+            include_exports: bool,
+            include_brushes: bool,
+            include_palettes: bool,
         ) -> FileDialog<'_> {
             let dialog = FileDialog::new();
             let (location, filename) = directory_and_file_or_default(initial_path);
@@ -66,7 +84,12 @@ mod native {
             if include_native {
                 dialog = dialog
                     .add_filter("Pixel Pen Image", &[storage::NATIVE_EXTENSION])
-                    .add_filter("Turbo Rascal FLUFF", &["flf"]);
+                    .add_filter(
+                        "Compressed Pixel Pen Image",
+                        &[storage::COMPRESSED_EXTENSION],
+                    )
+                    .add_filter("Turbo Rascal FLUFF", &["flf"])
+                    .add_filter("Pixel Pen Packed", &[storage::PACKED_EXTENSION]);
             }
             if include_images {
                 dialog = dialog.add_filter(
@@ -74,6 +97,41 @@ mod native {
                     &["png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff"],
                 );
             }
+            if include_exports {
+                dialog = dialog
+                    .add_filter("Character set", storage::CHARSET_EXTENSIONS)
+                    .add_filter("Screen RAM", &[storage::SCREEN_RAM_EXTENSION])
+                    .add_filter("Color RAM", &[storage::COLOR_RAM_EXTENSION])
+                    .add_filter("Assembly source", &[storage::ASM_EXTENSION])
+                    .add_filter("Tile map CSV", &[storage::CSV_EXTENSION])
+                    .add_filter("Pixel Pen Packed", &[storage::PACKED_EXTENSION]);
+            }
+            if include_brushes {
+                dialog = dialog.add_filter("Pixel Pen Brush", &[storage::BRUSH_EXTENSION]);
+            }
+            if include_palettes {
+                dialog = dialog.add_filter("Palette", &["gpl", "act", "json"]);
+            }
+            dialog
+        }
+
+        /// Create a file dialog for opening files.
+        /// Adds the filters for formats that can be opened but not saved.
+        fn create_open_dialog(&mut self, options: &OpenFileOptions<'_>) -> FileDialog<'_> {
+            let mut dialog = self.create_file_dialog(
+                options.initial_path,
+                options.include_native,
+                options.include_images,
+                false,
+                options.include_brushes,
+                options.include_palettes,
+            );
+            if options.include_tilemaps {
+                dialog = dialog.add_filter("Tile map CSV", &[storage::CSV_EXTENSION]);
+            }
+            if options.include_charsets {
+                dialog = dialog.add_filter("Character set", storage::CHARSET_EXTENSIONS);
+            }
             dialog
         }
 
@@ -108,17 +166,40 @@ mod native {
             &mut self,
             options: OpenFileOptions<'_>,
         ) -> Result<Option<PathBuf>, Error> {
-            let dialog = self.create_file_dialog(
-                options.initial_path,
-                options.include_native,
-                options.include_images,
-            );
+            let dialog = self.create_open_dialog(&options);
             let path = dialog
                 .show_open_single_file()
                 .map_err(|e| Error::DialogError(format!("File dialog failed: {0}", e)))?;
             Ok(path)
         }
 
+        fn open_files_dialog(
+            &mut self,
+            options: OpenFileOptions<'_>,
+        ) -> Result<Vec<PathBuf>, Error> {
+            let dialog = self.create_open_dialog(&options);
+            dialog
+                .show_open_multiple_file()
+                .map_err(|e| Error::DialogError(format!("File dialog failed: {0}", e)))
+        }
+
+        fn has_open_directory_dialog(&self) -> bool {
+            true
+        }
+
+        fn open_directory_dialog(
+            &mut self,
+            initial_path: Option<&Path>,
+        ) -> Result<Option<PathBuf>, Error> {
+            let dialog = FileDialog::new();
+            let (location, _) = directory_and_file_or_default(initial_path);
+            let dialog = self.set_default(dialog, location, None);
+            let path = dialog
+                .show_open_single_dir()
+                .map_err(|e| Error::DialogError(format!("File dialog failed: {0}", e)))?;
+            Ok(path)
+        }
+
         fn save_file_dialog(
             &mut self,
             options: SaveFileOptions<'_>,
@@ -127,6 +208,9 @@ mod native {
                 options.initial_path,
                 options.include_native,
                 options.include_images,
+                options.include_exports,
+                options.include_brushes,
+                false,
             );
             let path = dialog
                 .show_save_single_file()
@@ -134,7 +218,17 @@ mod native {
 
             match path {
                 Some(filename) if filename.extension().is_none() => {
-                    Ok(Some(filename.with_extension(options.default_extension)))
+                    // The dialog only asked about overwriting the name without the extension
+                    let filename = filename.with_extension(options.default_extension);
+                    if filename.exists()
+                        && !self.request_confirmation(&format!(
+                            "{} already exists.\n\nDo you want to replace it?",
+                            filename.display()
+                        ))?
+                    {
+                        return Ok(None);
+                    }
+                    Ok(Some(filename))
                 }
                 p => Ok(p),
             }
@@ -151,6 +245,31 @@ mod native {
             }
         }
 
+        fn show_error_details(&self, summary: &str, error: &Error) {
+            let details = format!("{}\n\n{}", summary, error.details());
+            let copy = MessageDialog::new()
+                .set_type(MessageType::Error)
+                .set_title("Error")
+                .set_text(&format!(
+                    "{}\n\nCopy this message to the clipboard?",
+                    details
+                ))
+                .show_confirm();
+            match copy {
+                Ok(true) => {
+                    let result = self.clipboard().and_then(|mut c| {
+                        c.set_text(details.clone())
+                            .map_err(|e| Error::ClipboardError(e.to_string()))
+                    });
+                    if let Err(e) = result {
+                        self.show_error(&format!("Could not copy the message: {}", e));
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("Failed to show error message \"{0}\": {1}", details, e),
+            }
+        }
+
         fn request_confirmation(&self, prompt: &str) -> Result<bool, Error> {
             MessageDialog::new()
                 .set_type(MessageType::Warning)
@@ -159,6 +278,40 @@ mod native {
                 .show_confirm()
                 .map_err(|e| Error::DialogError(format!("Failed to show dialog: {0}", e)))
         }
+
+        fn recovery_directory(&self) -> Option<PathBuf> {
+            ProjectDirs::from("com", "librador", "Pixel Pen")
+                .map(|dirs| dirs.cache_dir().join("recovery"))
+        }
+
+        fn config_directory(&self) -> Option<PathBuf> {
+            ProjectDirs::from("com", "librador", "Pixel Pen").map(|dirs| dirs.config_dir().into())
+        }
+
+        fn copy_image_to_clipboard(&mut self, image: &RgbaImage) -> Result<(), Error> {
+            let data = ImageData {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                bytes: Cow::from(image.as_raw().as_slice()),
+            };
+            self.clipboard()?
+                .set_image(data)
+                .map_err(|e| Error::ClipboardError(e.to_string()))
+        }
+
+        fn paste_image(&mut self) -> Result<Option<RgbaImage>, Error> {
+            match self.clipboard()?.get_image() {
+                Ok(data) => RgbaImage::from_raw(
+                    data.width as u32,
+                    data.height as u32,
+                    data.bytes.into_owned(),
+                )
+                .map(Some)
+                .ok_or_else(|| Error::ClipboardError("Invalid image data".to_string())),
+                Err(arboard::Error::ContentNotAvailable) => Ok(None),
+                Err(e) => Err(Error::ClipboardError(e.to_string())),
+            }
+        }
     }
 
     /// Get directory and filename from the path `default`,
@@ -0,0 +1,94 @@
+//! Information about a document that is not part of the image.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Information about who made a document and when.
+/// All fields are optional, so files without them can still be loaded.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub author: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// When the document was first saved, as an ISO 8601 UTC timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// When the document was last saved, as an ISO 8601 UTC timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Update the timestamps when saving.
+    pub fn touch(&mut self) {
+        let now = format_timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        );
+        if self.created.is_none() {
+            self.created = Some(now.clone());
+        }
+        self.modified = Some(now);
+    }
+}
+
+/// Format seconds since 1970-01-01 as an ISO 8601 UTC timestamp, e.g. "2021-12-24T15:00:00Z".
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // Convert days to a date in the proleptic Gregorian calendar.
+    // Algorithm from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_timestamp, Metadata};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951825600), "2000-02-29T12:00:00Z");
+        assert_eq!(format_timestamp(1640358000), "2021-12-24T15:00:00Z");
+    }
+
+    #[test]
+    fn touch_keeps_created() {
+        let mut metadata = Metadata {
+            created: Some("2000-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        metadata.touch();
+        assert_eq!(metadata.created.as_deref(), Some("2000-01-01T00:00:00Z"));
+        assert!(metadata.modified.is_some());
+    }
+}
@@ -4,7 +4,10 @@ use crate::{actions::DocAction, update_area::UpdateArea, vic::PixelColor};
 #[derive(Debug, Clone, PartialEq)]
 pub enum Mode {
     PixelPaint,
+    Erase,
     FillCell,
+    FloodFill,
+    GlobalFill,
     CellColor,
     MakeHiRes,
     MakeMulticolor,
@@ -16,7 +19,10 @@ impl Mode {
     pub fn title(&self) -> &str {
         match self {
             Mode::PixelPaint => "Pixel Paint",
+            Mode::Erase => "Erase",
             Mode::FillCell => "Fill Cell",
+            Mode::FloodFill => "Flood Fill",
+            Mode::GlobalFill => "Global Fill",
             Mode::CellColor => "Cell Color",
             Mode::MakeHiRes => "Make Hi-Res",
             Mode::MakeMulticolor => "Make Multicolor",
@@ -28,7 +34,10 @@ impl Mode {
     pub fn tip(&self) -> &str {
         match self {
             Mode::PixelPaint => "Paint pixels",
+            Mode::Erase => "Paint pixels with the background color",
             Mode::FillCell => "Fill the whole character cell with a color",
+            Mode::FloodFill => "Fill an area of connected pixels with the same color",
+            Mode::GlobalFill => "Replace a color in the whole image",
             Mode::CellColor => "Change the color of character cells",
             Mode::MakeHiRes => "Set character cells to high-resolution mode",
             Mode::MakeMulticolor => "Set character cells to multicolor mode",
@@ -40,9 +49,16 @@ impl Mode {
     pub fn instructions(&self) -> &'static str {
         match self {
             Mode::PixelPaint => "Click to paint. Right-click to paint with background color.",
+            Mode::Erase => "Click to erase pixels to the background color.",
             Mode::FillCell => {
                 "Click to fill the character cell with a color. Right-click to fill with background color."
             }
+            Mode::FloodFill => {
+                "Click to fill the area of the same color. Right-click to fill with background color."
+            }
+            Mode::GlobalFill => {
+                "Click to replace the clicked color everywhere. Right-click to replace with background color."
+            }
             Mode::CellColor => {
                 "Click to change the color of the character cell. Right-click for background color."
             }
@@ -53,6 +69,12 @@ impl Mode {
         }
     }
 
+    /// Whether the mode fills a whole area from the clicked pixel,
+    /// so it should not be repeated while dragging.
+    pub fn fills_from_pixel(&self) -> bool {
+        matches!(self, Mode::FloodFill | Mode::GlobalFill)
+    }
+
     /// Create an Action from a paint Mode.
     /// The `colors` are the selected primary and secondary color.
     /// If `secondary` is true, the secondary color becomes the primary and vice versa.
@@ -60,7 +82,13 @@ impl Mode {
         let (color, other_color) = colors;
         match self {
             Mode::PixelPaint => DocAction::Plot { area, color },
+            Mode::Erase => DocAction::Plot {
+                area,
+                color: PixelColor::Background,
+            },
             Mode::FillCell => DocAction::Fill { area, color },
+            Mode::FloodFill => DocAction::FloodFill { area, color },
+            Mode::GlobalFill => DocAction::GlobalFill { area, color },
             Mode::CellColor => DocAction::CellColor { area, color },
             Mode::MakeHiRes => DocAction::MakeHighRes { area },
             Mode::MakeMulticolor => DocAction::MakeMulticolor { area },
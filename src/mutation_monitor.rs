@@ -1,12 +1,27 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use serde::{Deserialize, Serialize};
 
+/// Source of generation numbers. Shared by all monitors,
+/// so two monitors never have the same generation.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
 /// Wraps an object and sets a "dirty" flag whenever any code accesses it mutably.
 pub struct MutationMonitor<T> {
     target: T,
     /// Is set to true whenever the target is dereferenced via [`DerefMut`].
     pub dirty: bool,
+    /// Changes whenever the target is dereferenced via [`DerefMut`].
+    /// Unlike `dirty`, this is never reset, so it can be used to tell whether
+    /// something derived from the target is still up to date.
+    generation: u64,
 }
 
 impl<T> Clone for MutationMonitor<T>
@@ -17,6 +32,7 @@ where
         Self {
             target: self.target.clone(),
             dirty: self.dirty,
+            generation: self.generation,
         }
     }
 }
@@ -27,8 +43,14 @@ impl<T> MutationMonitor<T> {
         Self {
             target,
             dirty: true,
+            generation: next_generation(),
         }
     }
+
+    /// A number that identifies the current state of the target.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 
 impl<T> Deref for MutationMonitor<T> {
@@ -41,6 +63,7 @@ impl<T> Deref for MutationMonitor<T> {
 impl<T> DerefMut for MutationMonitor<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.dirty = true;
+        self.generation = next_generation();
         &mut self.target
     }
 }
@@ -68,6 +91,7 @@ where
         Ok(Self {
             target: T::deserialize(deserializer)?,
             dirty: true,
+            generation: next_generation(),
         })
     }
 }
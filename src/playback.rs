@@ -0,0 +1,82 @@
+//! Playing the open documents one after the other, as the frames of an animation.
+
+use std::time::{Duration, Instant};
+
+/// The frame rates the user can choose between.
+pub const FPS_RANGE: std::ops::RangeInclusive<f32> = 1.0..=50.0;
+
+pub struct Playback {
+    /// Frames per second.
+    pub fps: f32,
+    /// When the current frame started showing, or None if not playing.
+    frame_start: Option<Instant>,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            fps: 8.0,
+            frame_start: None,
+        }
+    }
+}
+
+impl Playback {
+    pub fn is_playing(&self) -> bool {
+        self.frame_start.is_some()
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.frame_start = Some(now);
+    }
+
+    pub fn stop(&mut self) {
+        self.frame_start = None;
+    }
+
+    /// How long each frame is shown.
+    fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.fps.clamp(*FPS_RANGE.start(), *FPS_RANGE.end()))
+    }
+
+    /// Check whether it is time to show the next frame.
+    /// Frames are not skipped if the application can't keep up, but the timing doesn't drift otherwise.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let duration = self.frame_duration();
+        match self.frame_start {
+            Some(start) if now.saturating_duration_since(start) >= duration => {
+                let next = start + duration;
+                self.frame_start = Some(if now.saturating_duration_since(next) >= duration {
+                    now
+                } else {
+                    next
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Playback;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn advances_at_frame_rate() {
+        let mut playback = Playback {
+            fps: 10.0,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        assert!(!playback.advance(start + Duration::from_secs(1)));
+        playback.start(start);
+        assert!(!playback.advance(start + Duration::from_millis(50)));
+        assert!(playback.advance(start + Duration::from_millis(120)));
+        assert!(!playback.advance(start + Duration::from_millis(150)));
+        assert!(playback.advance(start + Duration::from_millis(210)));
+        playback.stop();
+        assert!(!playback.advance(start + Duration::from_secs(1)));
+    }
+}
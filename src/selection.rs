@@ -0,0 +1,201 @@
+//! The pixels that editing is restricted to.
+
+use bit_vec::BitVec;
+use euclid::Size2D;
+
+use crate::{
+    coords::{PixelPoint, PixelRect},
+    update_area::UpdateArea,
+};
+
+/// A set of selected pixels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    /// Smallest rectangle containing all the selected pixels.
+    bounds: PixelRect,
+    /// Whether each pixel in `bounds` is selected, row by row.
+    pixels: BitVec,
+    /// Number of selected pixels.
+    len: usize,
+    /// The edges between selected and unselected pixels.
+    /// A selection never changes, so this is calculated once
+    /// instead of every time the selection is drawn.
+    outline: Vec<(PixelPoint, PixelPoint)>,
+}
+
+impl Selection {
+    /// Select the pixels in an area.
+    pub fn from_area(area: &UpdateArea) -> Self {
+        let (min, max) = match area.pixels().next() {
+            Some(first) => area
+                .pixels()
+                .fold((first, first), |(min, max), p| (min.min(p), max.max(p))),
+            None => return Self::default(),
+        };
+        let bounds = PixelRect::new(min, Size2D::new(max.x - min.x + 1, max.y - min.y + 1));
+        let mut selection = Self {
+            bounds,
+            pixels: BitVec::from_elem(bounds.size.area() as usize, false),
+            len: 0,
+            outline: Vec::new(),
+        };
+        for p in area.pixels() {
+            let i = selection.index(p).unwrap();
+            if !selection.pixels[i] {
+                selection.pixels.set(i, true);
+                selection.len += 1;
+            }
+        }
+        selection.outline = selection.find_outline();
+        selection
+    }
+
+    /// Position of a pixel in `pixels`, or None if it is outside the bounds.
+    fn index(&self, p: PixelPoint) -> Option<usize> {
+        if self.bounds.contains(p) {
+            let x = p.x - self.bounds.min_x();
+            let y = p.y - self.bounds.min_y();
+            Some((y * self.bounds.width() + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, p: PixelPoint) -> bool {
+        self.index(p).map_or(false, |i| self.pixels[i])
+    }
+
+    /// Check whether all pixels in a rectangle are selected.
+    pub fn contains_rect(&self, rect: &PixelRect) -> bool {
+        self.bounds.contains_rect(rect)
+            && (rect.min_y()..rect.max_y())
+                .all(|y| (rect.min_x()..rect.max_x()).all(|x| self.contains(PixelPoint::new(x, y))))
+    }
+
+    /// Number of selected pixels.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// An area covering the selected pixels.
+    pub fn to_area(&self) -> UpdateArea {
+        let pixels = (self.bounds.min_y()..self.bounds.max_y())
+            .flat_map(|y| {
+                (self.bounds.min_x()..self.bounds.max_x()).map(move |x| PixelPoint::new(x, y))
+            })
+            .filter(|p| self.contains(*p))
+            .collect();
+        UpdateArea::from_pixels(pixels)
+    }
+
+    /// Remove the pixels that are not selected from an area.
+    pub fn restrict(&self, area: UpdateArea) -> UpdateArea {
+        area.filter(|p| self.contains(*p))
+    }
+
+    /// The edges between selected and unselected pixels, as horizontal and vertical
+    /// line segments in pixel coordinates. Edges that continue in a straight line
+    /// are joined into a single segment. The start of a segment is always
+    /// above or to the left of the end.
+    pub fn outline(&self) -> &[(PixelPoint, PixelPoint)] {
+        &self.outline
+    }
+
+    fn find_outline(&self) -> Vec<(PixelPoint, PixelPoint)> {
+        let b = &self.bounds;
+        let mut edges = Vec::new();
+        // The horizontal edges above each row, and below the last one
+        for y in b.min_y()..=b.max_y() {
+            for (x0, x1) in runs(b.min_x()..b.max_x(), |x| {
+                self.contains(PixelPoint::new(x, y - 1)) != self.contains(PixelPoint::new(x, y))
+            }) {
+                edges.push((PixelPoint::new(x0, y), PixelPoint::new(x1, y)));
+            }
+        }
+        // The vertical edges left of each column, and right of the last one
+        for x in b.min_x()..=b.max_x() {
+            for (y0, y1) in runs(b.min_y()..b.max_y(), |y| {
+                self.contains(PixelPoint::new(x - 1, y)) != self.contains(PixelPoint::new(x, y))
+            }) {
+                edges.push((PixelPoint::new(x, y0), PixelPoint::new(x, y1)));
+            }
+        }
+        edges
+    }
+}
+
+/// Find the runs of consecutive positions in `range` where `f` returns true.
+/// Returns the start and the (exclusive) end of each run.
+fn runs(range: std::ops::Range<i32>, f: impl Fn(i32) -> bool) -> Vec<(i32, i32)> {
+    let mut result = Vec::new();
+    let mut start = None;
+    for i in range.clone() {
+        match (start, f(i)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                result.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        result.push((s, range.end));
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::Selection;
+    use crate::{
+        coords::{PixelPoint, PixelRect},
+        update_area::UpdateArea,
+    };
+
+    #[test]
+    fn restrict_and_outline() {
+        let selection = Selection::from_area(&UpdateArea::rectangle(PixelRect::new(
+            PixelPoint::new(1, 1),
+            euclid::Size2D::new(2, 2),
+        )));
+        assert_eq!(selection.len(), 4);
+        assert_eq!(
+            selection.outline(),
+            &[
+                (PixelPoint::new(1, 1), PixelPoint::new(3, 1)),
+                (PixelPoint::new(1, 3), PixelPoint::new(3, 3)),
+                (PixelPoint::new(1, 1), PixelPoint::new(1, 3)),
+                (PixelPoint::new(3, 1), PixelPoint::new(3, 3)),
+            ]
+        );
+        let area = selection.restrict(UpdateArea::pixel_line(
+            PixelPoint::new(0, 1),
+            PixelPoint::new(4, 1),
+        ));
+        assert_eq!(
+            area.pixels().collect::<Vec<_>>(),
+            vec![PixelPoint::new(1, 1), PixelPoint::new(2, 1)]
+        );
+    }
+
+    #[test]
+    fn outline_is_split_where_the_edge_is_not_straight() {
+        // An L shape:
+        // X.
+        // XX
+        let selection = Selection::from_area(&UpdateArea::from_pixels(vec![
+            PixelPoint::new(0, 0),
+            PixelPoint::new(0, 1),
+            PixelPoint::new(1, 1),
+        ]));
+        assert_eq!(selection.len(), 3);
+        assert!(!selection.contains(PixelPoint::new(1, 0)));
+        assert_eq!(selection.outline().len(), 6);
+        assert_eq!(selection.to_area().pixels().count(), 3);
+    }
+}
@@ -0,0 +1,61 @@
+//! Settings that the user can change, which are kept between sessions.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{editor, error::Error};
+
+/// Name of the file in the configuration directory where the user's settings are stored.
+pub const SETTINGS_FILENAME: &str = "settings.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// How many steps can be undone in each document. At least 1.
+    pub undo_limit: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            undo_limit: editor::DEFAULT_UNDO_LIMIT,
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings from a file. Settings that are missing in the file get their default values.
+    pub fn load(filename: &Path) -> Result<Settings, Error> {
+        let reader = BufReader::new(File::open(filename)?);
+        let settings: Settings = serde_json::from_reader(reader)?;
+        Ok(Settings {
+            undo_limit: settings.undo_limit.max(1),
+        })
+    }
+
+    pub fn save(&self, filename: &Path) -> Result<(), Error> {
+        if let Some(directory) = filename.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let writer = File::create(filename)?;
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Settings;
+
+    #[test]
+    fn missing_settings_get_default_values() {
+        let filename = std::env::temp_dir().join(format!(
+            "pixel_pen_test_settings_{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&filename, "{}").unwrap();
+        assert_eq!(Settings::load(&filename).unwrap(), Settings::default());
+        std::fs::remove_file(&filename).unwrap();
+    }
+}
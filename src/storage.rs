@@ -1,22 +1,40 @@
 //! File I/O
 
 use std::{
-    ffi::OsString,
     fs::File,
-    io::{BufReader, BufWriter},
-    path::Path,
-    str::FromStr,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use image::{codecs::png::PngEncoder, imageops::FilterType, ColorType, RgbaImage};
+use imgref::ImgVec;
+use serde::Serialize;
+
 use crate::{
+    cell_image::CellImageSize,
+    coords::PixelPoint,
     error::Error,
+    gzip,
     image_io::{self, FileFormat},
+    vic::{BrushFile, Char, PixelColor, ViewSettings},
     Document,
 };
 
 /// File name extension (without the ".") for our own file format.
 pub const NATIVE_EXTENSION: &str = "pixelpen";
 
+/// File name extension (without the ".") for our own file format, compressed with gzip.
+/// Files are saved compressed if their name ends with ".pixelpen.gz".
+/// As `Path::extension` only gives the last part, this is what it returns for such files.
+pub const COMPRESSED_EXTENSION: &str = "gz";
+
+/// Whether a file should be saved in the compressed native format.
+fn is_compressed_native(filename: &Path) -> bool {
+    let name = filename.to_string_lossy().to_lowercase();
+    name.ends_with(&format!(".{}.{}", NATIVE_EXTENSION, COMPRESSED_EXTENSION))
+}
+
 /// Load a file in any supported file format.
 pub fn load_any_file(filename: &Path) -> Result<Document, Error> {
     match image_io::identify_file(filename)? {
@@ -29,30 +47,457 @@ pub fn load_any_file(filename: &Path) -> Result<Document, Error> {
     }
 }
 
-/// Save or export the file to any supported file format.
+/// Whether a file is a CSV tile map, which needs a character set to be loaded.
+pub fn is_tilemap(filename: &Path) -> bool {
+    filename
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase() == CSV_EXTENSION)
+        .unwrap_or(false)
+}
+
+/// Load a CSV tile map with the character set in a separate binary file.
+pub fn load_tilemap(filename: &Path, charset_filename: &Path) -> Result<Document, Error> {
+    let charset = std::fs::read(charset_filename)?;
+    let reader = BufReader::new(File::open(filename)?);
+    let image = image_io::csv::read_tilemap(reader, &charset)?;
+    Ok(Document::from_image(image))
+}
+
+/// Load a file in any supported file format from its content.
+/// Used where there is no file system, e.g. on the web.
+/// `name` is the name of the file, for error messages.
+pub fn load_any_bytes(name: &Path, data: &[u8]) -> Result<Document, Error> {
+    match image_io::identify_bytes(data) {
+        FileFormat::Unknown => from_native_bytes(data),
+        format => Ok(Document::from_image(image_io::load_bytes(
+            name, data, format,
+        )?)),
+    }
+}
+
+/// File name extensions (without the ".") for exporting the character set as raw binary.
+pub const CHARSET_EXTENSIONS: &[&str] = &["bin", "chr"];
+
+/// File name extension (without the ".") for exporting the screen RAM (character numbers).
+pub const SCREEN_RAM_EXTENSION: &str = "scr";
+
+/// File name extension (without the ".") for exporting the color RAM.
+pub const COLOR_RAM_EXTENSION: &str = "col";
+
+/// File name extension (without the ".") for exporting as assembly source.
+pub const ASM_EXTENSION: &str = "asm";
+
+/// File name extension (without the ".") for exporting the character numbers as a CSV tile map.
+pub const CSV_EXTENSION: &str = "csv";
+
+/// File name extension (without the ".") for the packed binary format.
+pub const PACKED_EXTENSION: &str = "ppk";
+
+/// File name extension (without the ".") for character brushes.
+pub const BRUSH_EXTENSION: &str = "ppbrush";
+
+/// How images are rendered when exporting to a true color image format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSettings {
+    /// How many times larger than the original pixels the exported pixels are.
+    pub scale: u32,
+    /// Make the pixels as wide as they are on a Vic-20, instead of square.
+    pub apply_aspect_ratio: bool,
+    /// Surround the image with the border color, like on a Vic-20 screen.
+    pub include_border: bool,
+    /// Make background pixels fully transparent.
+    /// This uses the background color register, not the true color,
+    /// so character colors that happen to look like the background stay opaque,
+    /// in both high-res and multicolor cells.
+    pub transparent_background: bool,
+}
+
+/// How many cells of border color are added on each side when exporting with a border.
+pub const EXPORT_BORDER_CELLS: u32 = 2;
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            apply_aspect_ratio: false,
+            include_border: false,
+            transparent_background: false,
+        }
+    }
+}
+
+/// Render the image as it is exported to a true color image format.
+pub fn export_image(document: &Document, settings: &ExportSettings) -> RgbaImage {
+    let (mut image, border_x, border_y) = if settings.include_border {
+        (
+            document.image.render_with_border(EXPORT_BORDER_CELLS),
+            EXPORT_BORDER_CELLS * Char::WIDTH as u32,
+            EXPORT_BORDER_CELLS * Char::HEIGHT as u32,
+        )
+    } else {
+        (document.render(&ViewSettings::default()), 0, 0)
+    };
+    if settings.transparent_background {
+        let (width, height) = document.image.size_in_pixels();
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                let color = document
+                    .image
+                    .pixel_color(PixelPoint::new(x as i32, y as i32));
+                if color == Some(PixelColor::Background) {
+                    image.get_pixel_mut(x + border_x, y + border_y)[3] = 0;
+                }
+            }
+        }
+    }
+    let scale_x = if settings.apply_aspect_ratio {
+        document.image.pixel_aspect_ratio() * settings.scale as f32
+    } else {
+        settings.scale as f32
+    };
+    let width = (image.width() as f32 * scale_x).round() as u32;
+    let height = image.height() * settings.scale;
+    if (width, height) == image.dimensions() {
+        image
+    } else {
+        image::imageops::resize(&image, width, height, FilterType::Nearest)
+    }
+}
+
+/// Save or export the file to any supported file format, with default export settings.
 pub fn save_any_file(document: &Document, filename: &Path) -> Result<(), Error> {
-    let native_extension = OsString::from_str(NATIVE_EXTENSION).unwrap();
-    if filename.extension() == Some(&native_extension) {
+    export_any_file(document, filename, &ExportSettings::default())
+}
+
+/// Save or export the file to any supported file format.
+/// The settings are used when exporting to a true color image format.
+pub fn export_any_file(
+    document: &Document,
+    filename: &Path,
+    settings: &ExportSettings,
+) -> Result<(), Error> {
+    let extension = filename
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if extension == NATIVE_EXTENSION || is_compressed_native(filename) {
         save(document, filename)
+    } else if CHARSET_EXTENSIONS.contains(&extension.as_str()) {
+        write_bytes(filename, &document.image.to_charset_bytes())
+    } else if extension == SCREEN_RAM_EXTENSION {
+        let (screen, _) = document.image.to_screen_and_color_ram()?;
+        write_bytes(filename, &screen)
+    } else if extension == COLOR_RAM_EXTENSION {
+        let (_, colors) = document.image.to_screen_and_color_ram()?;
+        write_bytes(filename, &colors)
+    } else if extension == ASM_EXTENSION {
+        write_atomically(filename, |path| {
+            let mut writer = BufWriter::new(File::create(path)?);
+            image_io::asm::write_asm(&document.image, &mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })
+    } else if extension == CSV_EXTENSION {
+        write_atomically(filename, |path| {
+            let mut writer = BufWriter::new(File::create(path)?);
+            image_io::csv::write_csv(&document.image, &mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })
+    } else if extension == PACKED_EXTENSION {
+        write_atomically(filename, |path| {
+            let mut writer = BufWriter::new(File::create(path)?);
+            image_io::packed::save_packed(&document.image, &mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })
     } else {
-        let image = document.image.render();
-        image.save(filename).map_err(Error::from)
+        let image = export_image(document, settings);
+        write_atomically(filename, |path| image.save(path).map_err(Error::from))
+    }
+}
+
+/// The name of the temporary file used when writing `filename`.
+/// It is in the same directory, so it can be renamed to the final name,
+/// and has the same extension, as some writers choose the format from it.
+fn temporary_filename(filename: &Path) -> PathBuf {
+    let name = filename
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    filename.with_file_name(format!(".~{}", name))
+}
+
+/// Write a file by letting `write` create a temporary file,
+/// which replaces the file at `filename` only if it was written successfully.
+/// That way an existing file is not destroyed if writing fails, e.g. because the disk is full.
+fn write_atomically(
+    filename: &Path,
+    write: impl FnOnce(&Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let temporary = temporary_filename(filename);
+    let result = write(&temporary).and_then(|()| {
+        File::open(&temporary)?.sync_all()?;
+        std::fs::rename(&temporary, filename)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
     }
+    result
+}
+
+/// Write a file with the given contents, using `write_atomically`.
+fn write_bytes(filename: &Path, data: &[u8]) -> Result<(), Error> {
+    write_atomically(filename, |path| {
+        std::fs::write(path, data)?;
+        Ok(())
+    })
 }
 
-/// Load a file in our own (native) format
+/// Load a file in our own (native) format, compressed or not.
 pub fn load_own(filename: &Path) -> Result<Document, Error> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let mut doc: Document = serde_json::from_reader(reader)?;
+    let mut doc = from_native_bytes(&std::fs::read(filename)?)?;
     doc.filename = Some(filename.to_owned());
+    doc.file_modified = modified_time(filename);
     Ok(doc)
 }
 
-/// Save a file in our own (native) format
+/// When a file was last modified, or None if it is not known, e.g. because the file does not exist.
+pub fn modified_time(filename: &Path) -> Option<SystemTime> {
+    std::fs::metadata(filename).and_then(|m| m.modified()).ok()
+}
+
+/// Save a file in our own (native) format.
+/// The file is compressed if the name ends with ".pixelpen.gz".
 pub fn save(document: &Document, filename: &Path) -> Result<(), Error> {
-    let file = File::create(filename)?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, document)?;
-    Ok(())
+    let data = to_native_bytes(document, is_compressed_native(filename))?;
+    write_bytes(filename, &data)
+}
+
+/// Serialize a document in the native format, for storing it somewhere else than in a file.
+/// Load it with `load_any_bytes`.
+pub fn save_to_bytes(document: &Document) -> Result<Vec<u8>, Error> {
+    to_native_bytes(document, false)
+}
+
+/// What is written to a file in the native format.
+#[derive(Serialize)]
+struct NativeFile<'a> {
+    #[serde(flatten)]
+    document: &'a Document,
+    /// A small version of the image as a hex encoded PNG, for file browsers etc.
+    /// It is ignored when loading, as the file may have been changed by other programs,
+    /// and `Document::thumbnail` can render it from the image anyway.
+    thumbnail: String,
+}
+
+/// Encode an image in PNG format.
+fn png_bytes(image: &RgbaImage) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    PngEncoder::new(&mut bytes).encode(image, image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(bytes)
+}
+
+/// Serialize a document to the native format, optionally compressed.
+fn to_native_bytes(document: &Document, compress: bool) -> Result<Vec<u8>, Error> {
+    let file = NativeFile {
+        document,
+        thumbnail: hex::encode(png_bytes(&document.thumbnail())?),
+    };
+    let json = serde_json::to_vec_pretty(&file)?;
+    Ok(if compress {
+        gzip::compress(&json)
+    } else {
+        json
+    })
+}
+
+/// Deserialize a document from the native format, detecting whether it is compressed.
+fn from_native_bytes(data: &[u8]) -> Result<Document, Error> {
+    if gzip::is_gzip(data) {
+        Ok(serde_json::from_slice(&gzip::decompress(data)?)?)
+    } else {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Save a character brush to a file.
+pub fn save_brush(brush: &ImgVec<Char>, filename: &Path) -> Result<(), Error> {
+    write_atomically(filename, |path| {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &BrushFile::from_brush(brush))?;
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+/// Load a character brush from a file.
+pub fn load_brush(filename: &Path) -> Result<ImgVec<Char>, Error> {
+    let reader = BufReader::new(File::open(filename)?);
+    let file: BrushFile = serde_json::from_reader(reader)?;
+    file.into_brush()
+}
+
+/// The file name of a brush file and the result of loading it.
+pub type BrushLoadResult = (PathBuf, Result<ImgVec<Char>, Error>);
+
+/// Load all brush files in a directory, sorted by file name.
+/// Returns the result of loading each file.
+pub fn load_brush_directory(directory: &Path) -> Result<Vec<BrushLoadResult>, Error> {
+    let mut filenames = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(BRUSH_EXTENSION.as_ref()) {
+            filenames.push(path);
+        }
+    }
+    filenames.sort();
+    Ok(filenames
+        .into_iter()
+        .map(|filename| {
+            let brush = load_brush(&filename);
+            (filename, brush)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::{
+        export_image, from_native_bytes, is_compressed_native, load_any_bytes, png_bytes,
+        temporary_filename, to_native_bytes, write_atomically, ExportSettings,
+    };
+    use crate::error::Error;
+    use crate::{
+        cell_image::CellImageSize,
+        coords::PixelPoint,
+        document::THUMBNAIL_SIZE,
+        update_area::UpdateArea,
+        vic::PixelColor,
+        vic::{Char, VicImage},
+        Document,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn thumbnail_is_saved_as_png() {
+        let doc = Document::from_image(VicImage::new(2, 1));
+        let json: serde_json::Value =
+            serde_json::from_slice(&to_native_bytes(&doc, false).unwrap()).unwrap();
+        let png = hex::decode(json["thumbnail"].as_str().unwrap()).unwrap();
+        let thumbnail = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert!(thumbnail == doc.thumbnail());
+        assert_eq!(thumbnail.width(), THUMBNAIL_SIZE);
+    }
+
+    #[test]
+    fn load_from_memory() {
+        let doc = Document::from_image(VicImage::new(2, 1));
+        let native = load_any_bytes(
+            Path::new("test.pixelpen"),
+            &to_native_bytes(&doc, false).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(native.image.size_in_cells(), doc.image.size_in_cells());
+        let png = load_any_bytes(
+            Path::new("test.png"),
+            &png_bytes(&doc.image.render()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(png.image.size_in_cells(), doc.image.size_in_cells());
+    }
+
+    #[test]
+    fn failed_write_keeps_existing_file() {
+        let filename =
+            std::env::temp_dir().join(format!("pixel_pen_test_{}.bin", std::process::id()));
+        std::fs::write(&filename, b"original").unwrap();
+        let result = write_atomically(&filename, |path| {
+            std::fs::write(path, b"partial")?;
+            Err(Error::InternalError("disk full".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&filename).unwrap(), b"original");
+        assert!(!temporary_filename(&filename).exists());
+
+        write_atomically(&filename, |path| {
+            std::fs::write(path, b"new")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read(&filename).unwrap(), b"new");
+        std::fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn export_scale_and_aspect_ratio() {
+        let doc = Document::from_image(VicImage::new(2, 1));
+        let mut settings = ExportSettings::default();
+        assert_eq!(export_image(&doc, &settings).dimensions(), (16, 8));
+        settings.scale = 3;
+        assert_eq!(export_image(&doc, &settings).dimensions(), (48, 24));
+        settings.apply_aspect_ratio = true;
+        assert_eq!(export_image(&doc, &settings).dimensions(), (79, 24));
+    }
+
+    #[test]
+    fn transparent_background() {
+        let mut image = VicImage::new(1, 1);
+        image
+            .plot(
+                &UpdateArea::from_pixel(PixelPoint::new(4, 0)),
+                PixelColor::CharColor(0),
+            )
+            .unwrap();
+        let doc = Document::from_image(image);
+        let settings = ExportSettings {
+            include_border: true,
+            transparent_background: true,
+            ..Default::default()
+        };
+        let exported = export_image(&doc, &settings);
+        let border = 8 * super::EXPORT_BORDER_CELLS;
+        assert_eq!(exported.get_pixel(0, 0)[3], 255);
+        assert_eq!(exported.get_pixel(border, border)[3], 0);
+        assert_eq!(exported.get_pixel(border + 4, border)[3], 255);
+    }
+
+    #[test]
+    fn compressed_file_names() {
+        assert!(is_compressed_native("a/image.pixelpen.gz".as_ref()));
+        assert!(is_compressed_native("IMAGE.PIXELPEN.GZ".as_ref()));
+        assert!(!is_compressed_native("image.pixelpen".as_ref()));
+        assert!(!is_compressed_native("image.gz".as_ref()));
+    }
+
+    #[test]
+    fn compression_makes_full_screen_image_smaller() {
+        // 22 x 23 cells is the size of the default Vic-20 screen
+        let chars = (0..22 * 23)
+            .map(|i| {
+                Char::new_highres(
+                    [(i % 97) as u8, 0x18, 0x3c, 0x7e, 0xff, 0, 0, (i / 3) as u8],
+                    1,
+                )
+            })
+            .collect();
+        let doc = Document::from_image(VicImage::with_content(ImgVec::new(chars, 22, 23)));
+        let uncompressed = to_native_bytes(&doc, false).unwrap();
+        let compressed = to_native_bytes(&doc, true).unwrap();
+        assert!(
+            compressed.len() * 5 < uncompressed.len(),
+            "compressed {} bytes to {} bytes",
+            uncompressed.len(),
+            compressed.len()
+        );
+        let loaded = from_native_bytes(&compressed).unwrap();
+        assert!(loaded.image.render() == doc.image.render());
+        assert!(from_native_bytes(&uncompressed).is_ok());
+        let thumbnail = loaded.thumbnail();
+        assert_eq!(thumbnail.width(), THUMBNAIL_SIZE);
+        assert!(thumbnail.height() < THUMBNAIL_SIZE);
+    }
 }
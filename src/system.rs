@@ -1,9 +1,16 @@
 use crate::{error::Error, storage};
+use image::RgbaImage;
 use std::path::{Path, PathBuf};
 
 pub struct OpenFileOptions<'a> {
     pub include_native: bool,
     pub include_images: bool,
+    pub include_brushes: bool,
+    pub include_palettes: bool,
+    /// Include CSV tile maps.
+    pub include_tilemaps: bool,
+    /// Include raw character sets.
+    pub include_charsets: bool,
     pub initial_path: Option<&'a Path>,
 }
 impl<'a> OpenFileOptions<'a> {
@@ -11,6 +18,10 @@ impl<'a> OpenFileOptions<'a> {
         Self {
             include_native: true,
             include_images: true,
+            include_brushes: false,
+            include_palettes: false,
+            include_tilemaps: true,
+            include_charsets: false,
             initial_path,
         }
     }
@@ -18,6 +29,43 @@ impl<'a> OpenFileOptions<'a> {
         Self {
             include_native: false,
             include_images: true,
+            include_brushes: false,
+            include_palettes: false,
+            include_tilemaps: false,
+            include_charsets: false,
+            initial_path,
+        }
+    }
+    pub fn for_brush(initial_path: Option<&'a Path>) -> Self {
+        Self {
+            include_native: false,
+            include_images: false,
+            include_brushes: true,
+            include_palettes: false,
+            include_tilemaps: false,
+            include_charsets: false,
+            initial_path,
+        }
+    }
+    pub fn for_palette(initial_path: Option<&'a Path>) -> Self {
+        Self {
+            include_native: false,
+            include_images: false,
+            include_brushes: false,
+            include_palettes: true,
+            include_tilemaps: false,
+            include_charsets: false,
+            initial_path,
+        }
+    }
+    pub fn for_charset(initial_path: Option<&'a Path>) -> Self {
+        Self {
+            include_native: false,
+            include_images: false,
+            include_brushes: false,
+            include_palettes: false,
+            include_tilemaps: false,
+            include_charsets: true,
             initial_path,
         }
     }
@@ -26,6 +74,9 @@ impl<'a> OpenFileOptions<'a> {
 pub struct SaveFileOptions<'a> {
     pub include_native: bool,
     pub include_images: bool,
+    /// Include formats for exporting raw data for the Vic-20.
+    pub include_exports: bool,
+    pub include_brushes: bool,
     pub default_extension: String,
     pub initial_path: Option<&'a Path>,
 }
@@ -34,6 +85,8 @@ impl<'a> SaveFileOptions<'a> {
         Self {
             include_native: true,
             include_images: false,
+            include_exports: false,
+            include_brushes: false,
             default_extension: storage::NATIVE_EXTENSION.to_string(),
             initial_path,
         }
@@ -42,21 +95,116 @@ impl<'a> SaveFileOptions<'a> {
         Self {
             include_native: false,
             include_images: true,
+            include_exports: true,
+            include_brushes: false,
             default_extension: "png".to_string(),
             initial_path,
         }
     }
+    pub fn for_brush(initial_path: Option<&'a Path>) -> Self {
+        Self {
+            include_native: false,
+            include_images: false,
+            include_exports: false,
+            include_brushes: true,
+            default_extension: storage::BRUSH_EXTENSION.to_string(),
+            initial_path,
+        }
+    }
 }
 
 pub trait SystemFunctions {
     fn has_open_file_dialog(&self) -> bool;
     fn has_save_file_dialog(&self) -> bool;
     fn open_file_dialog(&mut self, options: OpenFileOptions<'_>) -> Result<Option<PathBuf>, Error>;
+    /// Get the files the user has picked in `open_file_dialog` since this was last called,
+    /// as file name and content.
+    /// Used where files are read asynchronously, so the dialog can't return them.
+    fn take_opened_files(&mut self) -> Vec<(String, Vec<u8>)> {
+        Vec::new()
+    }
+    /// Whether files the user has picked are still being read. See `take_opened_files`.
+    fn is_reading_files(&self) -> bool {
+        false
+    }
+    /// Whether files can be saved with `download`, for platforms without a save dialog.
+    fn has_download(&self) -> bool {
+        false
+    }
+    /// Let the user download a file with the given name and content, as in a web browser.
+    fn download(&mut self, _filename: &str, _data: &[u8]) -> Result<(), Error> {
+        Err(Error::DialogError(
+            "Downloading is not supported on this platform".to_string(),
+        ))
+    }
+    /// Whether unsaved documents can be kept in storage that survives restarting the application,
+    /// for platforms without a `recovery_directory`, such as the web.
+    fn has_recovery_storage(&self) -> bool {
+        false
+    }
+    /// Replace the stored copies of unsaved documents with these,
+    /// as the document's index number, name, and content.
+    fn store_recovery_copies(&mut self, _copies: &[(u32, String, Vec<u8>)]) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Get the copies stored by `store_recovery_copies`, as name and content,
+    /// and remove them from the storage.
+    fn take_recovery_copies(&mut self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        Ok(Vec::new())
+    }
+    /// Ask the user for any number of files to open.
+    /// Returns an empty list if the user cancelled.
+    fn open_files_dialog(&mut self, options: OpenFileOptions<'_>) -> Result<Vec<PathBuf>, Error> {
+        Ok(self.open_file_dialog(options)?.into_iter().collect())
+    }
+    /// Ask the user for a file name to save to.
+    /// If the file exists, the user must have confirmed that it may be overwritten.
+    /// Returns `None` if the user cancelled.
     fn save_file_dialog(&mut self, options: SaveFileOptions<'_>) -> Result<Option<PathBuf>, Error>;
+    fn has_open_directory_dialog(&self) -> bool {
+        false
+    }
+    /// Ask the user for a directory.
+    /// Returns `None` if the user cancelled.
+    fn open_directory_dialog(
+        &mut self,
+        _initial_path: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        Err(Error::DialogError(
+            "Selecting directories is not supported on this platform".to_string(),
+        ))
+    }
     fn show_error(&self, message: &str) {
         eprintln!("{}\n", message);
     }
+    /// Show an error with all the details that may help when reporting a bug.
+    /// `summary` says what failed, e.g. which file could not be loaded.
+    fn show_error_details(&self, summary: &str, error: &Error) {
+        self.show_error(&format!("{}\n\n{}", summary, error.details()));
+    }
     fn request_confirmation(&self, prompt: &str) -> Result<bool, Error>;
+    /// Directory where unsaved documents are saved periodically, so they can be recovered after a crash.
+    /// Returns `None` if not supported.
+    fn recovery_directory(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Directory where user settings, such as keyboard shortcuts, are stored.
+    /// Returns `None` if not supported.
+    fn config_directory(&self) -> Option<PathBuf> {
+        None
+    }
+    fn copy_image_to_clipboard(&mut self, _image: &RgbaImage) -> Result<(), Error> {
+        Err(Error::ClipboardError(
+            "Copying images is not supported on this platform".to_string(),
+        ))
+    }
+    /// Get an image from the clipboard.
+    /// Returns `None` if the clipboard does not contain an image.
+    fn paste_image(&mut self) -> Result<Option<RgbaImage>, Error> {
+        Err(Error::ClipboardError(
+            "Pasting images is not supported on this platform".to_string(),
+        ))
+    }
 }
 
 pub struct DummySystemFunctions;
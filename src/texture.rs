@@ -1,60 +1,109 @@
-//! Manage textures that display the images
+//! Manage textures that display the images.
+//!
+//! The image is split into tiles, each with its own texture.
+//! As textures can't be changed, only re-allocated,
+//! this makes it possible to only render and upload the parts of the image that have changed.
 
 use eframe::{
     egui::{Color32, TextureId},
     epi::{self, TextureAllocator},
 };
 use image::imageops::FilterType;
+use itertools::Itertools;
 
 use crate::{
-    cell_image::CellImageSize, colors::TrueColor, mutation_monitor::MutationMonitor,
-    ui::ViewSettings, vic::VicImage,
+    cell_image::CellImageSize,
+    colors::TrueColor,
+    coords::{self, CellPos, CellRect, SizeInCells, WithinBounds},
+    mutation_monitor::MutationMonitor,
+    vic::{Revision, VicImage, ViewSettings},
 };
 
 // Don't scale the texture more than this to avoid huge textures when zooming.
 const MAX_SCALE: u32 = 8;
 
+/// Width and height of each tile, in cells.
+const TILE_SIZE: i32 = 8;
+
 pub struct Texture {
-    pub id: TextureId,
     pub settings: ViewSettings,
     pub width: usize,
     pub height: usize,
+    pub tiles: Vec<Tile>,
+    /// The state of the image when the tiles were rendered.
+    rendered: Revision,
+}
+
+/// A part of the image with its own texture.
+pub struct Tile {
+    pub id: TextureId,
+    /// The cells this tile shows.
+    pub rect: WithinBounds<CellRect>,
+}
+
+impl Texture {
+    fn free(&self, tex_allocator: &dyn TextureAllocator) {
+        for tile in &self.tiles {
+            tex_allocator.free(tile.id);
+        }
+    }
 }
 
 /// Updates the texture with the current image content, if needed.
-/// Returns the texture id.
-pub fn update_texture(
+/// Only the tiles that contain changed cells are updated,
+/// unless the size or settings have changed, in which case all of them are.
+pub fn update_texture<'a>(
     image: &mut MutationMonitor<VicImage>,
-    image_texture: &mut Option<Texture>,
+    image_texture: &'a mut Option<Texture>,
     tex_allocator: &dyn TextureAllocator,
     par: f32,
     zoom: f32,
     settings: &ViewSettings,
-) -> TextureId {
+) -> &'a Texture {
+    if image.dirty {
+        image.update();
+        image.dirty = false;
+    }
+    update_tiles(image, image_texture, tex_allocator, par, zoom, settings)
+}
+
+/// Like `update_texture`, but for an image that is not monitored for changes,
+/// such as one in another editor.
+pub fn update_unmonitored_texture<'a>(
+    image: &VicImage,
+    image_texture: &'a mut Option<Texture>,
+    tex_allocator: &dyn TextureAllocator,
+    par: f32,
+    zoom: f32,
+    settings: &ViewSettings,
+) -> &'a Texture {
+    update_tiles(image, image_texture, tex_allocator, par, zoom, settings)
+}
+
+/// Render the tiles of the texture that show cells that have changed since it was last updated.
+fn update_tiles<'a>(
+    image: &VicImage,
+    image_texture: &'a mut Option<Texture>,
+    tex_allocator: &dyn TextureAllocator,
+    par: f32,
+    zoom: f32,
+    settings: &ViewSettings,
+) -> &'a Texture {
     let scale_x = ((par * zoom).ceil() as u32).max(1).min(MAX_SCALE);
     let scale_y = (zoom.ceil() as u32).max(1).min(MAX_SCALE);
     let (source_width, source_height) = image.size_in_pixels();
     let texture_width = source_width * scale_x as usize;
     let texture_height = source_height * scale_y as usize;
 
-    // Recreate the texture if the size has changed or the image has been updated
+    // Recreate the texture if the size has changed
     if let Some(t) = image_texture {
-        if t.settings != *settings
-            || t.width != texture_width
-            || t.height != texture_height
-            || image.dirty
-        {
-            tex_allocator.free(t.id);
+        if t.settings != *settings || t.width != texture_width || t.height != texture_height {
+            t.free(tex_allocator);
             *image_texture = None;
         }
     }
-    if image.dirty {
-        image.update();
-    }
-    let texture = if let Some(texture) = image_texture {
-        texture.id
-    } else {
-        let unscaled_image = image.render_with_settings(settings);
+    let render_tile = |rect: &WithinBounds<CellRect>| {
+        let unscaled_image = image.render_region(rect, settings);
         let scaled_image = image::imageops::resize(
             &unscaled_image,
             unscaled_image.width() * scale_x,
@@ -65,19 +114,69 @@ pub fn update_texture(
             .pixels()
             .map(|p| (<image::Rgba<u8> as Into<TrueColor>>::into(*p)).into())
             .collect();
-        let image = epi::Image {
-            size: [texture_width, texture_height],
+        tex_allocator.alloc(epi::Image {
+            size: [
+                scaled_image.width() as usize,
+                scaled_image.height() as usize,
+            ],
             pixels,
-        };
-        let texture_id = tex_allocator.alloc(image);
-        *image_texture = Some(Texture {
-            id: texture_id,
-            settings: settings.clone(),
-            width: texture_width,
-            height: texture_height,
-        });
-        texture_id
+        })
     };
-    image.dirty = false;
-    texture
+    match image_texture {
+        None => {
+            let tiles = tile_rects(image.size_in_cells())
+                .map(|rect| Tile {
+                    id: render_tile(&rect),
+                    rect,
+                })
+                .collect();
+            *image_texture = Some(Texture {
+                settings: settings.clone(),
+                width: texture_width,
+                height: texture_height,
+                tiles,
+                rendered: image.revision(),
+            });
+        }
+        Some(texture) => {
+            for tile in texture.tiles.iter_mut() {
+                if image.changed_since(&texture.rendered, &tile.rect) {
+                    tex_allocator.free(tile.id);
+                    tile.id = render_tile(&tile.rect);
+                }
+            }
+            texture.rendered = image.revision();
+        }
+    }
+    image_texture.as_ref().unwrap()
+}
+
+/// Split an image into tiles.
+fn tile_rects(size: SizeInCells) -> impl Iterator<Item = WithinBounds<CellRect>> {
+    (0..size.height)
+        .step_by(TILE_SIZE as usize)
+        .cartesian_product((0..size.width).step_by(TILE_SIZE as usize))
+        .map(move |(row, column)| {
+            coords::clamp_rect_to_bounds(
+                CellRect::new(
+                    CellPos::new(column, row),
+                    SizeInCells::new(TILE_SIZE, TILE_SIZE),
+                ),
+                size,
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::tile_rects;
+    use crate::coords::SizeInCells;
+
+    #[test]
+    fn tiles_cover_image() {
+        let rects: Vec<_> = tile_rects(SizeInCells::new(10, 3))
+            .map(|r| (r.origin.to_tuple(), r.size.to_tuple()))
+            .collect();
+        assert_eq!(rects, vec![((0, 0), (8, 3)), ((8, 0), (2, 3))]);
+    }
 }
@@ -1,6 +1,7 @@
 mod brush;
 mod grab;
 mod import;
+mod magic_wand;
 mod paint;
 mod rectangle;
 mod ui;
@@ -9,6 +10,7 @@ use crate::{actions::Action, mode::Mode};
 pub use brush::CharBrushTool;
 pub use grab::GrabTool;
 pub use import::ImportTool;
+pub use magic_wand::MagicWandTool;
 pub use paint::PaintTool;
 pub use rectangle::RectangleTool;
 pub use ui::ToolUiContext;
@@ -20,6 +22,7 @@ pub enum ToolType {
     Rectangle,
     Grab,
     CharBrush,
+    MagicWand,
 }
 
 impl ToolType {
@@ -29,6 +32,9 @@ impl ToolType {
             ToolType::Paint | ToolType::Rectangle => mode.instructions(),
             ToolType::Grab => "Click and drag to select an area to create a brush from.",
             ToolType::CharBrush => "Click to draw with the character brush.",
+            ToolType::MagicWand => {
+                "Click to select the connected pixels with the same color. Painting only affects the selection."
+            }
         }
     }
 }
@@ -44,6 +50,7 @@ pub struct Toolbox {
     pub grab: GrabTool,
     pub rectangle: RectangleTool,
     pub char_brush: CharBrushTool,
+    pub magic_wand: MagicWandTool,
 }
 
 impl Toolbox {
@@ -58,6 +65,7 @@ impl Toolbox {
             ToolType::Rectangle => &mut self.rectangle,
             ToolType::Grab => &mut self.grab,
             ToolType::CharBrush => &mut self.char_brush,
+            ToolType::MagicWand => &mut self.magic_wand,
         }
     }
 }
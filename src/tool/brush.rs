@@ -53,6 +53,7 @@ impl Tool for CharBrushTool {
             user_actions.push(Action::Document(DocAction::CharBrushPaint {
                 pos: cell,
                 chars: ImgVec::new(buf.to_vec(), w, h),
+                mode: ui_ctx.ui_state.paste_mode,
             }));
         }
     }
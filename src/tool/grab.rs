@@ -1,9 +1,10 @@
-use eframe::egui::{Color32, CursorIcon, Stroke};
+use eframe::egui::{self, Color32, CursorIcon, DragValue, Label, Stroke};
 
 use crate::{
     actions::{Action, UiAction},
-    cell_image::CellCoordinates,
-    coords::PixelPoint,
+    cell_image::{CellCoordinates, CellImageSize},
+    coords::{self, CellRect, PixelPoint},
+    Document,
 };
 
 use super::{Tool, ToolUiContext};
@@ -16,6 +17,23 @@ const SELECTION_STROKE: Stroke = Stroke {
 #[derive(Default, Debug, Clone)]
 pub struct GrabTool {
     selection_start: Option<PixelPoint>,
+    /// The last area the user selected.
+    selection: Option<CellRect>,
+}
+
+impl GrabTool {
+    /// Get the last area the user selected, if any.
+    pub fn selection(&self) -> Option<CellRect> {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, rect: CellRect) {
+        self.selection = Some(rect);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
 }
 
 impl Tool for GrabTool {
@@ -26,6 +44,10 @@ impl Tool for GrabTool {
         let mut selection = None;
         match self.selection_start {
             None => {
+                if let Some(rect) = self.selection {
+                    let (top_left, bottom_right) = doc.image.cell_rectangle(&rect);
+                    ui_ctx.draw_rect(top_left, bottom_right, SELECTION_STROKE);
+                }
                 if let Some(hover_pos) = hover_pos {
                     *ui_ctx.cursor_icon = Some(CursorIcon::Crosshair);
                     let cell_rect = doc.image.cell_selection(hover_pos, hover_pos);
@@ -39,6 +61,9 @@ impl Tool for GrabTool {
                     }
                 }
             }
+            Some(_) if ui_ctx.cancel_pressed() => {
+                self.selection_start = None;
+            }
             Some(selection_start) => {
                 if let Some(hover_pos) = hover_pos {
                     *ui_ctx.cursor_icon = Some(CursorIcon::Crosshair);
@@ -62,8 +87,67 @@ impl Tool for GrabTool {
             self.selection_start = None;
             let rect = *doc.image.cell_selection(selection.0, selection.1);
             if rect.width() != 0 && rect.height() != 0 {
+                self.selection = Some(rect);
                 user_actions.push(Action::Ui(UiAction::CreateCharBrush { rect }));
             }
         }
+
+        egui::Window::new("Selection").show(&ui_ctx.ctx, |ui| {
+            selection_ui(ui, doc, &mut self.selection, user_actions)
+        });
+    }
+}
+
+/// Fields for typing in the exact selection, in cells, and buttons for using it.
+fn selection_ui(
+    ui: &mut egui::Ui,
+    doc: &Document,
+    selection: &mut Option<CellRect>,
+    user_actions: &mut Vec<Action>,
+) {
+    let size = doc.image.size_in_cells();
+    let rect = match selection {
+        Some(rect) => rect,
+        None => {
+            ui.label("Drag in the image to select");
+            return;
+        }
+    };
+    egui::Grid::new("grab_selection_grid").show(ui, |ui| {
+        ui.add(Label::new("Column"));
+        ui.add(DragValue::new(&mut rect.origin.x).clamp_range(0..=size.width - 1));
+        ui.end_row();
+
+        ui.add(Label::new("Row"));
+        ui.add(DragValue::new(&mut rect.origin.y).clamp_range(0..=size.height - 1));
+        ui.end_row();
+
+        ui.add(Label::new("Width"));
+        ui.add(DragValue::new(&mut rect.size.width).clamp_range(1..=size.width - rect.origin.x));
+        ui.end_row();
+
+        ui.add(Label::new("Height"));
+        ui.add(DragValue::new(&mut rect.size.height).clamp_range(1..=size.height - rect.origin.y));
+        ui.end_row();
+    });
+    // Moving the origin may have pushed the rectangle outside the image
+    rect.size.width = rect.size.width.min(size.width - rect.origin.x);
+    rect.size.height = rect.size.height.min(size.height - rect.origin.y);
+    if ui
+        .button("Create Brush")
+        .on_hover_text("Use the selected cells as the character brush")
+        .clicked()
+    {
+        user_actions.push(Action::Ui(UiAction::CreateCharBrush { rect: *rect }));
+    }
+    if ui
+        .button("New Document")
+        .on_hover_text("Open the selected cells as a new document")
+        .clicked()
+    {
+        if let Some(rect) = coords::rect_within_size(*rect, size) {
+            let doc = doc.extract(&rect);
+            user_actions.push(Action::Ui(UiAction::NewDocument(Box::new(doc))));
+        }
     }
 }
@@ -8,11 +8,14 @@ use crate::actions::UiAction;
 use crate::cell_image::CellImageSize;
 use crate::coords::PixelPoint;
 use crate::coords::PixelTransform;
+use crate::image_operations::Dithering;
+use crate::import::GlobalColorJob;
+use crate::import::GlobalColorSearch;
 use crate::import::Import;
 use crate::import::ImportSettings;
 use crate::import::PixelAspectRatio;
 use crate::tool::ToolType;
-use crate::vic::ColorFormat;
+use crate::vic::{self, ColorFormat};
 use crate::Document;
 use eframe::egui;
 use eframe::egui::Color32;
@@ -20,6 +23,7 @@ use eframe::egui::ComboBox;
 use eframe::egui::DragValue;
 use eframe::egui::Label;
 use eframe::egui::Painter;
+use eframe::egui::RichText;
 use eframe::egui::Stroke;
 use image::imageops::FilterType;
 use image::GenericImageView;
@@ -30,15 +34,19 @@ use super::ToolUiContext;
 const IMPORT_IMAGE_EXTENTS_COLOR: Color32 = Color32::GRAY;
 const UNKNOWN_SOURCE_TEXT: &str = "unknown source";
 
-#[derive(Clone, Default)]
+#[derive(Default)]
 pub struct ImportTool {
     import: Option<Import>,
+    /// The paste started with the Import button,
+    /// waiting for the search for global colors to finish.
+    pending: Option<(DocAction, GlobalColorJob)>,
 }
 
 impl ImportTool {
     pub fn new(import: Import) -> Self {
         Self {
             import: Some(import),
+            pending: None,
         }
     }
     pub fn filename(&self) -> Option<&Path> {
@@ -48,11 +56,12 @@ impl ImportTool {
 
 impl Tool for ImportTool {
     fn update_ui(&mut self, ui_ctx: &mut ToolUiContext<'_>, user_actions: &mut Vec<Action>) {
+        let pending = &mut self.pending;
         match self.import.as_mut() {
             Some(import) => {
                 image_ui(ui_ctx.painter, import, &ui_ctx.pixel_transform);
                 egui::Window::new("Import").show(&ui_ctx.ctx, |ui| {
-                    tool_ui(ui, ui_ctx.doc, import, user_actions)
+                    tool_ui(ui, ui_ctx.doc, import, pending, user_actions)
                 });
             }
             None => {
@@ -82,8 +91,55 @@ fn image_ui(painter: &Painter, import: &mut Import, transform: &PixelTransform)
     painter.line_segment([rect.left_bottom(), rect.right_top()], stroke);
 }
 
+/// Checkboxes for the character colors the import may use.
+/// At least one color is always allowed.
+fn allowed_colors_ui(ui: &mut egui::Ui, doc: &Document, allowed_colors: &mut u8) {
+    ui.horizontal(|ui| {
+        for color in vic::ALLOWED_CHAR_COLORS {
+            let bit = 1 << color;
+            let mut allowed = *allowed_colors & bit != 0;
+            let swatch = RichText::new("■")
+                .color(doc.image.palette().color(color))
+                .strong();
+            if ui
+                .checkbox(&mut allowed, swatch)
+                .on_hover_text(format!("Color {}", color))
+                .changed()
+            {
+                let mask = if allowed {
+                    *allowed_colors | bit
+                } else {
+                    *allowed_colors & !bit
+                };
+                if vic::char_colors_in_mask(mask).next().is_some() {
+                    *allowed_colors = mask;
+                }
+            }
+        }
+    });
+}
+
 /// Render the tool UI.
-fn tool_ui(ui: &mut egui::Ui, doc: &Document, import: &mut Import, user_actions: &mut Vec<Action>) {
+fn tool_ui(
+    ui: &mut egui::Ui,
+    doc: &Document,
+    import: &mut Import,
+    pending: &mut Option<(DocAction, GlobalColorJob)>,
+    user_actions: &mut Vec<Action>,
+) {
+    if let Some((_, job)) = pending {
+        searching_ui(ui, job);
+        if let Some(colors) = job.result() {
+            let (mut action, _) = pending.take().unwrap();
+            if let DocAction::PasteTrueColor { global_colors, .. } = &mut action {
+                *global_colors = colors;
+            }
+            user_actions.push(Action::Document(action));
+        } else if ui.button("Cancel").clicked() {
+            *pending = None;
+        }
+        return;
+    }
     egui::Grid::new("import_grid").show(ui, |ui| {
         let source = &import.image;
         let target = &doc.image;
@@ -147,21 +203,7 @@ fn tool_ui(ui: &mut egui::Ui, doc: &Document, import: &mut Import, user_actions:
         );
         ui.end_row();
 
-        import.settings.height = (match import.settings.pixel_aspect_ratio {
-            PixelAspectRatio::Square => {
-                import.settings.width as f32 / source_width as f32
-                    * source_height as f32
-                    * target.pixel_aspect_ratio()
-            }
-            PixelAspectRatio::Target => {
-                import.settings.width as f32 / source_width as f32 * source_height as f32
-            }
-            PixelAspectRatio::TargetHalfResolution => {
-                import.settings.width as f32 / source_width as f32 * source_height as f32 / 2.0
-            }
-        }
-        .round() as u32)
-            .max(1);
+        import.update_height(target.pixel_aspect_ratio());
 
         ui.label("Height");
         ui.label(format!("{}", import.settings.height));
@@ -214,19 +256,70 @@ fn tool_ui(ui: &mut egui::Ui, doc: &Document, import: &mut Import, user_actions:
                 );
             });
         ui.end_row();
+
+        ui.label("Global colors")
+            .on_hover_text("Change the background, border and aux colors to fit the image");
+        ComboBox::from_id_source("import_global_colors")
+            .selected_text(import.settings.global_colors.title())
+            .show_ui(ui, |ui| {
+                for search in GlobalColorSearch::ALL {
+                    ui.selectable_value(&mut import.settings.global_colors, search, search.title());
+                }
+            });
+        ui.end_row();
+
+        ui.label("Colors")
+            .on_hover_text("Which character colors the imported image may use");
+        allowed_colors_ui(ui, doc, &mut import.settings.allowed_colors);
+        ui.end_row();
+
+        // imagequant does its own dithering
+        if cfg!(not(feature = "imagequant")) {
+            ui.label("Dithering");
+            ComboBox::from_id_source("import_dithering")
+                .selected_text(import.settings.dithering.title())
+                .show_ui(ui, |ui| {
+                    for dithering in Dithering::ALL {
+                        ui.selectable_value(
+                            &mut import.settings.dithering,
+                            dithering,
+                            dithering.title(),
+                        );
+                    }
+                });
+            ui.end_row();
+        }
     });
     ui.separator();
     ui.horizontal(|ui| {
         if ui.button("Import").clicked() {
             let scaled = import.scale_image();
-            user_actions.push(Action::Document(DocAction::PasteTrueColor {
+            let job = match import.settings.global_colors {
+                GlobalColorSearch::Fixed => None,
+                _ => Some(import.start_global_color_search(&scaled, &doc.image)),
+            };
+            let action = DocAction::PasteTrueColor {
                 source: scaled,
                 target: PixelPoint::new(import.settings.left, import.settings.top),
                 format: import.settings.format,
-            }));
+                dithering: import.settings.dithering,
+                allowed_colors: import.settings.allowed_colors,
+                global_colors: None,
+            };
+            match job {
+                Some(job) => *pending = Some((action, job)),
+                None => user_actions.push(Action::Document(action)),
+            }
         } else if ui.button("Close").clicked() {
             user_actions.push(Action::Ui(UiAction::SelectTool(ToolType::Paint)));
         }
     });
     ui.end_row();
 }
+
+/// Show the progress of the search for global colors.
+fn searching_ui(ui: &mut egui::Ui, job: &GlobalColorJob) {
+    ui.label("Searching for the best global colors...");
+    ui.add(egui::ProgressBar::new(job.progress()).show_percentage());
+    ui.ctx().request_repaint();
+}
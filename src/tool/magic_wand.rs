@@ -0,0 +1,35 @@
+use eframe::egui::CursorIcon;
+
+use crate::{
+    actions::{Action, UiAction},
+    selection::Selection,
+    update_area::UpdateArea,
+};
+
+use super::{Tool, ToolUiContext};
+
+/// Selects the connected pixels with the same color as the clicked pixel.
+#[derive(Debug, Default, Clone)]
+pub struct MagicWandTool {}
+
+impl Tool for MagicWandTool {
+    fn update_ui(&mut self, ui_ctx: &mut ToolUiContext<'_>, user_actions: &mut Vec<Action>) {
+        let hover_pos = match ui_ctx.hover_pos {
+            Some(p) => p,
+            None => return,
+        };
+        *ui_ctx.cursor_icon = Some(CursorIcon::Crosshair);
+        ui_ctx.draw_crosshair(hover_pos);
+        if ui_ctx.widget_response.clicked() {
+            let area = ui_ctx
+                .doc
+                .image
+                .connected_pixels(&UpdateArea::from_pixel(hover_pos));
+            let selection = Selection::from_area(&area);
+            // Clicking outside the image removes the selection
+            user_actions.push(Action::Ui(UiAction::SetSelection(
+                Some(selection).filter(|s| !s.is_empty()),
+            )));
+        }
+    }
+}
@@ -87,21 +87,40 @@ impl Tool for PaintTool {
             Some(v) => v,
         };
 
+        let ui_state = ui_ctx.ui_state;
+        if ui_state.mode.fills_from_pixel() {
+            if self.paint_position.is_none() {
+                user_actions.push(Action::Document(ui_state.mode.paint_action(
+                    UpdateArea::from_pixel(hover_pos),
+                    ui_ctx.colors(secondary),
+                )));
+            }
+            self.paint_position = Some(hover_pos);
+            return;
+        }
+        let size = ui_state.pen_size;
+        let shape = ui_state.pen_shape;
+        let pixel_width = doc.image.pixel_width_at(hover_pos);
+        let continues_stroke = self.paint_position.is_some();
         let area = match self.paint_position {
             Some(p) => {
                 if p == hover_pos {
                     // Mouse is held and hasn't moved
                     return;
                 }
-                UpdateArea::pixel_line(p, hover_pos)
+                UpdateArea::pen_line(p, hover_pos, size, shape, pixel_width)
             }
-            None => UpdateArea::from_pixel(hover_pos),
+            None => UpdateArea::from_pen(hover_pos, size, shape, pixel_width),
         };
         self.paint_position = Some(hover_pos);
+        let area = area.with_symmetry(&ui_state.symmetry);
 
-        let ui_state = ui_ctx.ui_state;
-        user_actions.push(Action::Document(
-            ui_state.mode.paint_action(area, ui_ctx.colors(secondary)),
-        ));
+        let action = ui_state.mode.paint_action(area, ui_ctx.colors(secondary));
+        // Paint immediately, but undo the whole drag in one step
+        user_actions.push(if continues_stroke {
+            Action::ContinueStroke(action)
+        } else {
+            Action::Document(action)
+        });
     }
 }
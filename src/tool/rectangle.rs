@@ -21,6 +21,8 @@ pub struct RectangleTool {
     corner: Option<PixelPoint>,
     /// When dragging, the secondary mouse button is used so should swap primary/secondary colors.
     swap_colors: bool,
+    /// The user cancelled the drag, so ignore the mouse until it is released.
+    cancelled: bool,
 }
 
 impl Tool for RectangleTool {
@@ -47,6 +49,19 @@ impl Tool for RectangleTool {
             None
         };
 
+        if pressed.is_none() {
+            self.cancelled = false;
+        } else if self.cancelled {
+            ui_ctx.draw_crosshair(hover_pos);
+            return;
+        }
+        if self.corner.is_some() && ui_ctx.cancel_pressed() {
+            self.corner = None;
+            self.cancelled = true;
+            ui_ctx.draw_crosshair(hover_pos);
+            return;
+        }
+
         let (image_w, image_h) = ui_ctx.doc.image.size_in_pixels();
         let image_lower_right = Point2D::new(image_w as i32, image_h as i32);
         let cursor_position_clamped = hover_pos.clamp(PixelPoint::zero(), image_lower_right);
@@ -1,4 +1,4 @@
-use eframe::egui::{self, CtxRef, CursorIcon, Painter, Response, Stroke, Ui};
+use eframe::egui::{self, CtxRef, CursorIcon, Key, Painter, Response, Stroke, Ui};
 use imgref::ImgRef;
 
 use crate::{
@@ -38,6 +38,11 @@ impl<'a> ToolUiContext<'a> {
         );
     }
 
+    /// Whether the user wants to abort the current operation, e.g. a drag.
+    pub fn cancel_pressed(&self) -> bool {
+        self.ui.input().key_pressed(Key::Escape)
+    }
+
     pub fn colors(&self, swapped: bool) -> (PixelColor, PixelColor) {
         match swapped {
             false => (self.ui_state.primary_color, self.ui_state.secondary_color),
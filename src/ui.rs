@@ -1,11 +1,31 @@
+pub mod brush_library;
 pub mod crosshair;
+pub mod document_info;
+pub mod document_settings;
+pub mod keymap;
+pub mod message_log;
+pub mod new_document;
 pub mod palette;
+pub mod resize_canvas;
+pub mod settings;
+pub mod size_presets;
 pub mod text;
 
-use std::time::Instant;
+use std::{collections::VecDeque, time::Instant};
 
-use crate::{colors::TrueColor, mode::Mode, tool::ToolType, vic::PixelColor};
-use eframe::egui::Vec2;
+use crate::{
+    coords::PixelPoint,
+    mode::Mode,
+    selection::Selection,
+    storage::ExportSettings,
+    tool::ToolType,
+    update_area::{PenShape, Symmetry},
+    vic::{PasteMode, PixelColor, ViewSettings},
+};
+use document_info::DocumentInfoDialog;
+use document_settings::DocumentSettingsDialog;
+use eframe::egui::{Color32, Vec2};
+use resize_canvas::ResizeCanvasDialog;
 
 pub struct UiState {
     pub tool: ToolType,
@@ -16,13 +36,53 @@ pub struct UiState {
     pub primary_color: PixelColor,
     /// Secondary selected color. Typically used when using the right mouse button.
     pub secondary_color: PixelColor,
-    /// Enable showing the character grid
-    pub grid: bool,
+    /// Colors recently selected as primary color, most recent first.
+    pub recent_colors: VecDeque<PixelColor>,
+    /// Size of the pen used by the Paint tool, in pixels.
+    pub pen_size: i32,
+    pub pen_shape: PenShape,
+    /// Mirroring of what is painted with the Paint tool.
+    pub symmetry: Symmetry,
+    /// How the character brush is combined with the image.
+    pub paste_mode: PasteMode,
+    /// Show the undo history panel
+    pub show_history: bool,
+    /// Show the navigator panel with an overview of the image
+    pub show_navigator: bool,
+    /// Show the panel for editing a single character
+    pub show_char_editor: bool,
+    /// Which neighboring documents to show faintly over the image
+    pub onion_skin: OnionSkin,
+    /// Highlight cells that can't be shown on the hardware
+    pub show_color_errors: bool,
+    /// Tint multicolor cells so they can be told apart from high-res cells
+    pub show_cell_modes: bool,
+    /// Which grid lines to show over the image
+    pub grid: GridMode,
+    pub view_options: ViewOptions,
+    /// How the image is rendered when exported.
+    pub export_settings: ExportSettings,
+    /// The pixels that painting is restricted to, if any.
+    pub selection: Option<Selection>,
+    /// Pixel that is painted when drawing with the keyboard.
+    /// None if the keyboard hasn't been used since the mouse moved over the image.
+    pub keyboard_cursor: Option<PixelPoint>,
     /// Whether user is currently panning
     pub panning: bool,
     pub pan: Vec2,
+    /// Size of the area where the image is shown, as of the last frame.
+    pub view_size: Vec2,
 
     pub message: Option<(Instant, String)>,
+    /// The most recent messages, oldest first, so the user can read them after they disappear.
+    pub message_log: VecDeque<(Instant, String)>,
+
+    /// The Resize Canvas dialog, if it is open.
+    pub resize_canvas_dialog: Option<ResizeCanvasDialog>,
+    /// The Document Settings dialog, if it is open.
+    pub document_settings_dialog: Option<DocumentSettingsDialog>,
+    /// The Document Info dialog, if it is open.
+    pub document_info_dialog: Option<DocumentInfoDialog>,
 }
 impl Default for UiState {
     fn default() -> Self {
@@ -33,62 +93,165 @@ impl Default for UiState {
             image_view_settings: ViewSettings::Normal,
             primary_color: PixelColor::CharColor(7),
             secondary_color: PixelColor::Background,
-            grid: false,
+            recent_colors: VecDeque::new(),
+            pen_size: 1,
+            pen_shape: PenShape::Square,
+            symmetry: Symmetry::default(),
+            paste_mode: PasteMode::default(),
+            show_history: false,
+            show_navigator: false,
+            show_char_editor: false,
+            onion_skin: OnionSkin::default(),
+            show_color_errors: false,
+            show_cell_modes: false,
+            grid: GridMode::Off,
+            view_options: ViewOptions::default(),
+            export_settings: ExportSettings::default(),
+            selection: None,
+            keyboard_cursor: None,
             panning: false,
             pan: Vec2::ZERO,
+            view_size: Vec2::ZERO,
             message: None,
+            message_log: VecDeque::new(),
+            resize_canvas_dialog: None,
+            document_settings_dialog: None,
+            document_info_dialog: None,
         }
     }
 }
 impl UiState {
+    /// The smallest zoom factor the user can zoom out to.
+    pub const MIN_ZOOM: f32 = 1.0;
+    /// The largest zoom factor the user can zoom in to.
+    pub const MAX_ZOOM: f32 = 16.0;
+    /// How many points of the image are kept visible when panning.
+    pub const PAN_MARGIN: f32 = 40.0;
+    /// The largest pen size the user can select.
+    pub const MAX_PEN_SIZE: i32 = 8;
+    /// How many messages are kept in `message_log`.
+    pub const MESSAGE_LOG_SIZE: usize = 100;
+    /// How many colors are kept in `recent_colors`.
+    pub const RECENT_COLORS_SIZE: usize = 8;
+
     pub fn show_warning(&mut self, message: String) {
-        self.message = Some((Instant::now(), message));
+        let now = Instant::now();
+        if self.message_log.len() == Self::MESSAGE_LOG_SIZE {
+            self.message_log.pop_front();
+        }
+        self.message_log.push_back((now, message.clone()));
+        self.message = Some((now, message));
+    }
+
+    /// Add the primary color to the recently used colors, if it isn't already the most recent.
+    pub fn remember_primary_color(&mut self) {
+        let color = self.primary_color;
+        if self.recent_colors.front() == Some(&color) {
+            return;
+        }
+        self.recent_colors.retain(|c| *c != color);
+        self.recent_colors.push_front(color);
+        self.recent_colors.truncate(Self::RECENT_COLORS_SIZE);
+    }
+
+    /// Change the zoom factor, keeping the image point at `focus` in the same place on screen.
+    /// `focus` is relative to the center of the view.
+    pub fn zoom_around(&mut self, zoom: f32, focus: Vec2) {
+        let zoom = zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        let factor = zoom / self.zoom;
+        self.pan = focus - (focus - self.pan) * factor;
+        self.zoom = zoom;
+    }
+
+    /// Limit the pan so at least `PAN_MARGIN` points of the image stay inside the view.
+    /// `image_size` is the size of the image on screen, including any border.
+    pub fn clamp_pan(&mut self, image_size: Vec2) {
+        let margin = Vec2::splat(Self::PAN_MARGIN).min(image_size);
+        let max_pan = ((self.view_size + image_size) * 0.5 - margin).max(Vec2::ZERO);
+        self.pan = self.pan.clamp(-max_pan, max_pan);
     }
 }
 
-#[derive(Clone, PartialEq)]
-pub enum ViewSettings {
-    Normal,
-    Raw,
+/// Showing the documents in the previous and next tabs faintly over the image,
+/// for drawing animation frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnionSkin {
+    pub previous: bool,
+    pub next: bool,
+    /// How visible the other documents are, from 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
 }
-impl Default for ViewSettings {
+impl Default for OnionSkin {
     fn default() -> Self {
-        ViewSettings::Normal
+        Self {
+            previous: false,
+            next: false,
+            opacity: 0.3,
+        }
     }
 }
-impl ViewSettings {
-    /// Get the colors to use when displaying in raw mode.
-    pub fn raw_colors() -> (TrueColor, TrueColor, TrueColor, TrueColor) {
-        (
-            Self::raw_multicolor_background(),
-            Self::raw_multicolor_border(),
-            Self::raw_multicolor_aux(),
-            Self::raw_multicolor_char_color(),
-        )
-    }
 
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_highres_background() -> TrueColor {
-        TrueColor::from_u32(0x555555)
-    }
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_hires_char_color() -> TrueColor {
-        TrueColor::from_u32(0xeeeeee)
+/// Which grid lines to show over the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridMode {
+    Off,
+    /// Lines between character cells
+    Cell,
+    /// Lines between character cells and between pixels
+    Pixel,
+}
+impl GridMode {
+    pub const ALL: [GridMode; 3] = [GridMode::Off, GridMode::Cell, GridMode::Pixel];
+
+    /// The mode to switch to when toggling the grid.
+    pub fn next(self) -> Self {
+        match self {
+            GridMode::Off => GridMode::Cell,
+            GridMode::Cell => GridMode::Pixel,
+            GridMode::Pixel => GridMode::Off,
+        }
     }
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_multicolor_background() -> TrueColor {
-        TrueColor::from_u32(0x000000)
+
+    pub fn title(self) -> &'static str {
+        match self {
+            GridMode::Off => "Off",
+            GridMode::Cell => "Cell",
+            GridMode::Pixel => "Pixel",
+        }
     }
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_multicolor_border() -> TrueColor {
-        TrueColor::from_u32(0x0044ff)
+}
+
+/// How the grid and border around the image are displayed.
+/// Unlike `ViewSettings`, these do not affect how the image itself is rendered.
+pub struct ViewOptions {
+    /// Color of the grid lines, without transparency.
+    pub grid_color: Color32,
+    /// 0.0 = invisible grid, 1.0 = fully opaque.
+    pub grid_opacity: f32,
+    /// Draw the border color around the image, with rounded corners like on a TV.
+    pub show_border: bool,
+}
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            grid_color: Color32::GRAY,
+            grid_opacity: 1.0,
+            show_border: true,
+        }
     }
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_multicolor_aux() -> TrueColor {
-        TrueColor::from_u32(0xff0000)
+}
+impl ViewOptions {
+    /// How much dimmer the lines between pixels are than the lines between cells.
+    const PIXEL_GRID_DIMMING: f32 = 0.25;
+
+    /// The color to draw cell grid lines with.
+    pub fn cell_grid_color(&self) -> Color32 {
+        self.grid_color.linear_multiply(self.grid_opacity)
     }
-    /// Get color to use when displaying in raw mode.
-    pub fn raw_multicolor_char_color() -> TrueColor {
-        TrueColor::from_u32(0xffffff)
+
+    /// The color to draw pixel grid lines with.
+    pub fn pixel_grid_color(&self) -> Color32 {
+        self.grid_color
+            .linear_multiply(self.grid_opacity * Self::PIXEL_GRID_DIMMING)
     }
 }
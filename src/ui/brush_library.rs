@@ -0,0 +1,205 @@
+//! Panel with thumbnails of brushes the user has collected.
+
+use eframe::{
+    egui::{self, Color32, TextureId, Vec2},
+    epi::{self, TextureAllocator},
+};
+use imgref::ImgVec;
+
+use crate::{
+    actions::{Action, UiAction},
+    colors::TrueColor,
+    vic::{Char, GlobalColors, VicImage, VicPalette},
+};
+
+/// Maximum width and height of a thumbnail, in points.
+const THUMBNAIL_SIZE: f32 = 64.0;
+
+/// A brush in the library.
+pub struct LibraryBrush {
+    /// Name to show to the user, e.g. the file name.
+    pub name: String,
+    pub brush: ImgVec<Char>,
+    texture: Option<TextureId>,
+}
+
+/// The brushes available in the brush library panel.
+#[derive(Default)]
+pub struct BrushLibrary {
+    pub visible: bool,
+    brushes: Vec<LibraryBrush>,
+    /// The colors the thumbnail textures were rendered with.
+    rendered_with: Option<(GlobalColors, VicPalette)>,
+}
+
+impl BrushLibrary {
+    /// Add a brush to the library and show the library.
+    pub fn add(&mut self, name: String, brush: ImgVec<Char>) {
+        self.brushes.push(LibraryBrush {
+            name,
+            brush,
+            texture: None,
+        });
+        self.visible = true;
+    }
+
+    pub fn len(&self) -> usize {
+        self.brushes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brushes.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&LibraryBrush> {
+        self.brushes.get(index)
+    }
+
+    fn remove(&mut self, index: usize, tex_allocator: &dyn TextureAllocator) {
+        let removed = self.brushes.remove(index);
+        if let Some(id) = removed.texture {
+            tex_allocator.free(id);
+        }
+    }
+
+    /// Free the thumbnail textures if they were rendered with other colors.
+    fn invalidate_textures(
+        &mut self,
+        colors: &GlobalColors,
+        palette: &VicPalette,
+        tex_allocator: &dyn TextureAllocator,
+    ) {
+        if let Some((c, p)) = &self.rendered_with {
+            if c == colors && p == palette {
+                return;
+            }
+        }
+        for entry in self.brushes.iter_mut() {
+            if let Some(id) = entry.texture.take() {
+                tex_allocator.free(id);
+            }
+        }
+        self.rendered_with = Some((colors.clone(), palette.clone()));
+    }
+}
+
+/// Render a brush into a new texture.
+fn render_thumbnail(
+    brush: &ImgVec<Char>,
+    colors: &GlobalColors,
+    palette: &VicPalette,
+    tex_allocator: &dyn TextureAllocator,
+) -> TextureId {
+    let mut image = VicImage::with_content(brush.clone());
+    image.set_global_colors(colors.clone());
+    image.set_palette(palette.clone());
+    let rendered = image.render();
+    let pixels = rendered
+        .pixels()
+        .map(|p| Color32::from(TrueColor::from(*p)))
+        .collect();
+    tex_allocator.alloc(epi::Image {
+        size: [rendered.width() as usize, rendered.height() as usize],
+        pixels,
+    })
+}
+
+/// Size to show a brush's thumbnail at, keeping its aspect ratio.
+fn thumbnail_size(brush: &ImgVec<Char>, pixel_aspect_ratio: f32) -> Vec2 {
+    let width = (brush.width() * Char::WIDTH) as f32 * pixel_aspect_ratio;
+    let height = (brush.height() * Char::HEIGHT) as f32;
+    let scale = (THUMBNAIL_SIZE / width).min(THUMBNAIL_SIZE / height);
+    Vec2::new(width * scale, height * scale)
+}
+
+/// Show the library panel if it is visible.
+/// Thumbnails are rendered with the colors of `image`.
+pub fn render(
+    ctx: &egui::CtxRef,
+    frame: &epi::Frame,
+    library: &mut BrushLibrary,
+    image: &VicImage,
+    user_actions: &mut Vec<Action>,
+) {
+    if !library.visible {
+        return;
+    }
+    let tex_allocator = frame as &dyn TextureAllocator;
+    library.invalidate_textures(image.global_colors(), image.palette(), tex_allocator);
+    let mut remove = None;
+    egui::SidePanel::right("brush_library").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.heading("Brushes");
+            if ui.small_button("✖").on_hover_text("Hide").clicked() {
+                library.visible = false;
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("Add")
+                .on_hover_text("Add the current brush to the library")
+                .clicked()
+            {
+                user_actions.push(Action::Ui(UiAction::AddBrushToLibrary));
+            }
+            if ui
+                .button("Import...")
+                .on_hover_text("Load all brush files in a directory")
+                .clicked()
+            {
+                user_actions.push(Action::Ui(UiAction::ImportBrushDirectory));
+            }
+        });
+        ui.separator();
+        if library.is_empty() {
+            ui.label("No brushes");
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (index, entry) in library.brushes.iter_mut().enumerate() {
+                    let brush = &entry.brush;
+                    let texture = *entry.texture.get_or_insert_with(|| {
+                        render_thumbnail(
+                            brush,
+                            image.global_colors(),
+                            image.palette(),
+                            tex_allocator,
+                        )
+                    });
+                    let size = thumbnail_size(&entry.brush, image.pixel_aspect_ratio());
+                    let response = ui
+                        .add(egui::ImageButton::new(texture, size))
+                        .on_hover_text(&entry.name);
+                    if response.clicked() {
+                        user_actions.push(Action::Ui(UiAction::UseLibraryBrush(index)));
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Remove").clicked() {
+                            remove = Some(index);
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+        });
+    });
+    if let Some(index) = remove {
+        library.remove(index, tex_allocator);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::thumbnail_size;
+    use crate::vic::Char;
+
+    #[test]
+    fn thumbnail_keeps_aspect_ratio() {
+        let brush = ImgVec::new(vec![Char::DEFAULT_BRUSH; 2], 2, 1);
+        let size = thumbnail_size(&brush, 1.0);
+        assert_eq!((size.x, size.y), (64.0, 32.0));
+    }
+}
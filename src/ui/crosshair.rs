@@ -1,4 +1,4 @@
-use eframe::egui::{Color32, Painter, Stroke};
+use eframe::egui::{Color32, Painter, Rect, Stroke};
 
 use crate::coords::{PixelPoint, PixelTransform};
 
@@ -30,3 +30,16 @@ pub fn draw_crosshair(painter: &Painter, pixel_transform: &PixelTransform, pos:
         STROKE,
     );
 }
+
+/// Draw a crosshair with an outline around the given pixel, for showing the keyboard cursor.
+pub fn draw_pixel_cursor(painter: &Painter, pixel_transform: &PixelTransform, pos: PixelPoint) {
+    draw_crosshair(painter, pixel_transform, pos);
+    painter.rect_stroke(
+        Rect::from_min_max(
+            pixel_transform.screen_pos(pos),
+            pixel_transform.screen_pos(PixelPoint::new(pos.x + 1, pos.y + 1)),
+        ),
+        0.0,
+        STROKE,
+    );
+}
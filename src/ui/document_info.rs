@@ -0,0 +1,77 @@
+//! Dialog for editing the document's metadata.
+
+use eframe::egui::{self, TextEdit};
+
+use crate::{
+    actions::{Action, DocAction},
+    metadata::Metadata,
+};
+
+/// The metadata the user has entered in the Document Info dialog.
+pub struct DocumentInfoDialog {
+    metadata: Metadata,
+}
+
+impl DocumentInfoDialog {
+    /// Create the dialog state from the document's current metadata.
+    pub fn new(metadata: &Metadata) -> Self {
+        Self {
+            metadata: metadata.clone(),
+        }
+    }
+}
+
+/// Show the dialog if it is open. Closes it when the user is done with it.
+pub fn render(
+    ctx: &egui::CtxRef,
+    dialog: &mut Option<DocumentInfoDialog>,
+    user_actions: &mut Vec<Action>,
+) {
+    let state = match dialog {
+        Some(state) => state,
+        None => return,
+    };
+    let mut close = false;
+    egui::Window::new("Document Info")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let metadata = &mut state.metadata;
+            egui::Grid::new("document_info_grid").show(ui, |ui| {
+                ui.label("Title");
+                ui.text_edit_singleline(&mut metadata.title);
+                ui.end_row();
+
+                ui.label("Author");
+                ui.text_edit_singleline(&mut metadata.author);
+                ui.end_row();
+
+                ui.label("Notes");
+                ui.add(TextEdit::multiline(&mut metadata.notes).desired_rows(4));
+                ui.end_row();
+
+                ui.label("Created");
+                ui.label(metadata.created.as_deref().unwrap_or("Not saved"));
+                ui.end_row();
+
+                ui.label("Modified");
+                ui.label(metadata.modified.as_deref().unwrap_or("Not saved"));
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("OK").clicked() {
+                    user_actions.push(Action::Document(DocAction::SetMetadata(Box::new(
+                        state.metadata.clone(),
+                    ))));
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+}
@@ -0,0 +1,111 @@
+//! Dialog for settings that are stored in the document.
+
+use eframe::egui::{self, DragValue};
+
+use crate::{
+    actions::{Action, DocAction},
+    document::DocumentSettings,
+    vic::{VicImage, VicPalette},
+};
+
+/// Settings the user has entered in the Document Settings dialog.
+pub struct DocumentSettingsDialog {
+    limit_chars: bool,
+    max_chars: usize,
+    palette: VicPalette,
+    /// The custom palette of the document, so the user can switch back to it.
+    custom_palette: Option<VicPalette>,
+    stable_characters: bool,
+}
+
+impl DocumentSettingsDialog {
+    /// Create the dialog state from the current settings of the document.
+    pub fn new(settings: DocumentSettings) -> Self {
+        Self {
+            limit_chars: settings.max_chars.is_some(),
+            max_chars: settings.max_chars.unwrap_or(VicImage::MAX_CHARACTERS),
+            custom_palette: match settings.palette {
+                VicPalette::Custom(_) => Some(settings.palette.clone()),
+                _ => None,
+            },
+            palette: settings.palette,
+            stable_characters: settings.stable_characters,
+        }
+    }
+
+    /// The settings the user has entered.
+    fn settings(&self) -> DocumentSettings {
+        DocumentSettings {
+            max_chars: if self.limit_chars {
+                Some(self.max_chars)
+            } else {
+                None
+            },
+            palette: self.palette.clone(),
+            stable_characters: self.stable_characters,
+        }
+    }
+}
+
+/// Show the dialog if it is open. Closes it when the user is done with it.
+pub fn render(
+    ctx: &egui::CtxRef,
+    dialog: &mut Option<DocumentSettingsDialog>,
+    user_actions: &mut Vec<Action>,
+) {
+    let state = match dialog {
+        Some(state) => state,
+        None => return,
+    };
+    let mut close = false;
+    egui::Window::new("Document Settings")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.limit_chars, "Limit characters to")
+                    .on_hover_text(
+                        "Disallow edits that would make the image use more unique characters",
+                    );
+                ui.add_enabled(
+                    state.limit_chars,
+                    DragValue::new(&mut state.max_chars).clamp_range(1..=VicImage::MAX_CHARACTERS),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Palette")
+                    .on_hover_text("The colors to display the image with");
+                egui::ComboBox::from_id_source("document_palette")
+                    .selected_text(state.palette.title())
+                    .show_ui(ui, |ui| {
+                        let mut palettes = VicPalette::ALL.to_vec();
+                        if let Some(custom) = &state.custom_palette {
+                            palettes.push(custom.clone());
+                        }
+                        for palette in palettes {
+                            let title = palette.title();
+                            ui.selectable_value(&mut state.palette, palette, title);
+                        }
+                    });
+            });
+            ui.checkbox(&mut state.stable_characters, "Keep character numbers")
+                .on_hover_text(
+                    "Characters keep their numbers in exported and saved files when the image is edited",
+                );
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("OK").clicked() {
+                    user_actions.push(Action::Document(DocAction::SetSettings(Box::new(
+                        state.settings(),
+                    ))));
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+}
@@ -0,0 +1,119 @@
+//! Dialog for changing keyboard shortcuts.
+
+use eframe::egui::{self, Color32, TextEdit};
+
+use crate::keymap::{self, Command, Keymap};
+
+/// The shortcuts the user has entered in the Keyboard Shortcuts dialog.
+pub struct KeymapDialog {
+    /// The key for each command in `Command::ALL`. Empty if the command has no shortcut.
+    keys: Vec<String>,
+}
+
+impl KeymapDialog {
+    /// Create the dialog state from the current keymap.
+    pub fn new(keymap: &Keymap) -> Self {
+        Self {
+            keys: Command::ALL
+                .iter()
+                .map(|command| keymap.key_for(*command).unwrap_or_default().to_string())
+                .collect(),
+        }
+    }
+
+    /// Create a keymap from the entered keys.
+    /// Returns an error message if the same key is used for more than one command.
+    fn keymap(&self) -> Result<Keymap, String> {
+        let mut bindings = Vec::new();
+        for (command, key) in Command::ALL.iter().zip(&self.keys) {
+            if key.is_empty() {
+                continue;
+            }
+            if let Some((_, other)) = bindings.iter().find(|(k, _)| k == key) {
+                return Err(format!(
+                    "\"{}\" is used for both {} and {}",
+                    key,
+                    Command::title(other),
+                    command.title()
+                ));
+            }
+            bindings.push((key.clone(), *command));
+        }
+        Ok(Keymap::from_bindings(bindings))
+    }
+}
+
+/// The name of the last pressed key that has one, see [`keymap::key_name`].
+fn pressed_key_name(events: &[egui::Event]) -> Option<String> {
+    events.iter().rev().find_map(|event| match event {
+        egui::Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+        } => keymap::key_name(*key, modifiers),
+        _ => None,
+    })
+}
+
+/// Show the dialog if it is open. Closes it when the user is done with it.
+/// Returns the new keymap if the user accepted the changes.
+pub fn render(ctx: &egui::CtxRef, dialog: &mut Option<KeymapDialog>) -> Option<Keymap> {
+    let state = match dialog {
+        Some(state) => state,
+        None => return None,
+    };
+    let mut close = false;
+    let mut result = None;
+    egui::Window::new("Keyboard Shortcuts")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(400.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("keymap_grid").show(ui, |ui| {
+                        for (command, key) in Command::ALL.iter().zip(state.keys.iter_mut()) {
+                            ui.label(command.title());
+                            let response = ui.add(TextEdit::singleline(key).desired_width(80.0));
+                            if response.changed() {
+                                // Only keep the last typed character
+                                if let Some(c) = key.chars().last() {
+                                    *key = c.to_string();
+                                }
+                            }
+                            if response.has_focus() {
+                                // Keys that don't generate text are entered by pressing them
+                                if let Some(name) = pressed_key_name(&ui.input().events) {
+                                    *key = name;
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            ui.separator();
+            let keymap = state.keymap();
+            if let Err(message) = &keymap {
+                ui.colored_label(Color32::RED, message);
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(keymap.is_ok(), egui::Button::new("OK"))
+                    .clicked()
+                {
+                    result = keymap.ok();
+                    close = true;
+                }
+                if ui.button("Defaults").clicked() {
+                    *state = KeymapDialog::new(&Keymap::default());
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+    result
+}
@@ -0,0 +1,43 @@
+//! The list of recent messages shown to the user.
+
+use std::{collections::VecDeque, time::Instant};
+
+use eframe::egui::{self, RichText, Ui};
+
+/// How high the list of messages may be before it scrolls.
+const MAX_HEIGHT: f32 = 120.0;
+
+/// Show the messages in a collapsible section, newest first.
+pub fn render(ui: &mut Ui, log: &mut VecDeque<(Instant, String)>) {
+    if log.is_empty() {
+        return;
+    }
+    egui::CollapsingHeader::new(format!("Messages ({})", log.len()))
+        .id_source("message_log")
+        .show(ui, |ui| {
+            if ui.small_button("Clear").clicked() {
+                log.clear();
+            }
+            let now = Instant::now();
+            egui::ScrollArea::vertical()
+                .max_height(MAX_HEIGHT)
+                .show(ui, |ui| {
+                    for (time, message) in log.iter().rev() {
+                        let age = now.saturating_duration_since(*time).as_secs();
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(format_age(age)).weak());
+                            ui.label(message);
+                        });
+                    }
+                });
+        });
+}
+
+/// Describe how long ago something happened, given the number of seconds.
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{} s ago", seconds),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        _ => format!("{} h ago", seconds / 3600),
+    }
+}
@@ -0,0 +1,132 @@
+//! Dialog for creating a new document.
+
+use eframe::egui::{self, DragValue};
+use imgref::ImgVec;
+
+use crate::{
+    cell_image::CellImageSize,
+    ui::size_presets,
+    vic::{Char, ColorFormat, VicImage},
+    Document,
+};
+
+/// Settings the user has entered in the New Document dialog.
+pub struct NewDocumentDialog {
+    columns: i32,
+    rows: i32,
+    format: ColorFormat,
+}
+
+impl Default for NewDocumentDialog {
+    fn default() -> Self {
+        let size = VicImage::default().size_in_cells();
+        Self {
+            columns: size.width,
+            rows: size.height,
+            format: ColorFormat::Multicolor,
+        }
+    }
+}
+
+impl NewDocumentDialog {
+    /// Create the document with the entered settings,
+    /// or return a message saying what is wrong with them.
+    pub fn create(&self) -> Result<Document, String> {
+        let max = VicImage::MAX_SIZE;
+        if !(1..max.width).contains(&self.columns) || !(1..max.height).contains(&self.rows) {
+            return Err(format!(
+                "The size must be between 1 × 1 and {} × {} cells",
+                max.width - 1,
+                max.height - 1
+            ));
+        }
+        let char = match self.format {
+            ColorFormat::HighRes => Char::new_highres([0; Char::HEIGHT], 1),
+            ColorFormat::Multicolor => Char::default(),
+        };
+        let (columns, rows) = (self.columns as usize, self.rows as usize);
+        let video = ImgVec::new(vec![char; columns * rows], columns, rows);
+        Ok(Document::from_image(VicImage::with_content(video)))
+    }
+}
+
+/// Show the dialog if it is open. Closes it when the user has created a document or cancelled.
+/// Returns the new document, or a message if the settings were not valid.
+pub fn render(
+    ctx: &egui::CtxRef,
+    dialog: &mut Option<NewDocumentDialog>,
+) -> Option<Result<Document, String>> {
+    let state = match dialog {
+        Some(state) => state,
+        None => return None,
+    };
+    let mut close = false;
+    let mut result = None;
+    egui::Window::new("New Document")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("new_document_grid").show(ui, |ui| {
+                ui.label("Preset");
+                size_presets::preset_selector(
+                    ui,
+                    "new_document_preset",
+                    &mut state.columns,
+                    &mut state.rows,
+                );
+                ui.end_row();
+
+                ui.label("Width");
+                ui.add(DragValue::new(&mut state.columns).suffix(" columns"));
+                ui.end_row();
+
+                ui.label("Height");
+                ui.add(DragValue::new(&mut state.rows).suffix(" rows"));
+                ui.end_row();
+
+                ui.label("Cells")
+                    .on_hover_text("The color format of the empty cells");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut state.format, ColorFormat::Multicolor, "Multicolor");
+                    ui.radio_value(&mut state.format, ColorFormat::HighRes, "High-res");
+                });
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Create").clicked() {
+                    let created = state.create();
+                    close = created.is_ok();
+                    result = Some(created);
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::NewDocumentDialog;
+    use crate::{cell_image::CellImageSize, coords::SizeInCells, vic::ColorFormat};
+
+    #[test]
+    fn invalid_size_is_rejected() {
+        let mut dialog = NewDocumentDialog {
+            columns: 0,
+            rows: 4,
+            format: ColorFormat::HighRes,
+        };
+        assert!(dialog.create().is_err());
+        dialog.columns = 100_000;
+        assert!(dialog.create().is_err());
+        dialog.columns = 3;
+        let doc = dialog.create().unwrap();
+        assert_eq!(doc.image.size_in_cells(), SizeInCells::new(3, 4));
+    }
+}
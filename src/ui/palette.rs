@@ -1,9 +1,10 @@
-use crate::actions::{Action, DocAction};
+use crate::actions::{Action, DocAction, UiAction};
 use crate::mutation_monitor::MutationMonitor;
 use crate::vic::{self, PixelColor, Register, VicImage, VicPalette};
 use crate::widgets;
-use eframe::egui::{self, Color32, Painter, Rect, Sense, Shape, Vec2};
+use eframe::egui::{self, Align2, Color32, Painter, Rect, Sense, Shape, TextStyle, Vec2};
 use itertools::Itertools;
+use std::collections::VecDeque;
 
 const PATCH_CORNER_RADIUS_FRACTION: f32 = 0.1;
 
@@ -11,12 +12,20 @@ pub fn render_palette(
     ui: &mut egui::Ui,
     primary_color: &mut PixelColor,
     secondary_color: &mut PixelColor,
+    recent_colors: &VecDeque<PixelColor>,
     image: &mut MutationMonitor<VicImage>,
 ) -> Option<Action> {
     let mut action = None;
 
     let allocate = Vec2::new(0.0, ui.spacing().interact_size.y * 2.5);
     ui.horizontal_wrapped(|ui| {
+        if ui
+            .small_button("Swap")
+            .on_hover_text("Swap primary and secondary colors")
+            .clicked()
+        {
+            action = Some(Action::Ui(UiAction::SwapPaintColors));
+        }
         ui.vertical(|ui| {
             ui.small("Color Registers").on_hover_text("Global color settings that affect the whole screen.");
             ui.horizontal(|ui| {
@@ -29,10 +38,12 @@ pub fn render_palette(
                     (PixelColor::Border, "Border", "Can be used as an additional color in a multicolor cell. Also the color of the screen border. Click to change."),
                     (PixelColor::Aux, "Aux", "Can be used as an additional color in a multicolor cell. Click to change."),
                 ] {
-                    if let Some(a) = render_special_color_label(ui, patch, label, tooltip){
+                    if let Some(a) = render_special_color_label(ui, patch, image.palette(), label, tooltip){
+                        action = Some(a);
+                    }
+                    if let Some(a) = render_patch(ui, image, patch, primary_color, secondary_color) {
                         action = Some(a);
                     }
-                    render_patch(ui, image, patch, primary_color, secondary_color);
                 }
             });
         });
@@ -46,21 +57,67 @@ pub fn render_palette(
                 );
                 for color_number in vic::ALLOWED_CHAR_COLORS {
                     let patch = PixelColor::CharColor(color_number as u8);
-                    render_patch(ui, image, patch, primary_color, secondary_color);
+                    if let Some(a) = render_patch(ui, image, patch, primary_color, secondary_color) {
+                        action = Some(a);
+                    }
                 }
             });
         });
+        if recent_colors.len() > 1 {
+            ui.separator();
+            ui.vertical(|ui| {
+                ui.small("Recent").on_hover_text("Colors recently selected as primary color.");
+                ui.horizontal(|ui| {
+                    ui.allocate_at_least(
+                        allocate,
+                        Sense::hover(),
+                    );
+                    for &patch in recent_colors.iter().skip(1) {
+                        render_recent_patch(ui, image, patch, primary_color, secondary_color);
+                    }
+                });
+            });
+        }
     });
     action
 }
 
+/// Show a smaller patch for a recently used color. Clicking it selects it as primary color.
+fn render_recent_patch(
+    ui: &mut egui::Ui,
+    image: &VicImage,
+    patch: PixelColor,
+    primary_color: &mut PixelColor,
+    secondary_color: &mut PixelColor,
+) {
+    let patch_size = patch_size(ui) * 0.75;
+    let (patch_rect, response) = ui.allocate_exact_size(patch_size, Sense::click());
+    ui.painter().rect_filled(
+        patch_rect,
+        patch_rect.height() * PATCH_CORNER_RADIUS_FRACTION,
+        image.true_color_from_paint_color(&patch),
+    );
+    if response.clicked() {
+        if *secondary_color == patch {
+            *secondary_color = *primary_color;
+        }
+        *primary_color = patch;
+    }
+    response.on_hover_text(format!(
+        "{}\nClick to select as primary color.",
+        color_description(image, patch)
+    ));
+}
+
+/// Show a selectable color patch.
+/// Ctrl+right-click shows a menu with actions for the color.
 fn render_patch(
     ui: &mut egui::Ui,
     image: &mut MutationMonitor<VicImage>,
     patch: PixelColor,
     primary_color: &mut PixelColor,
     secondary_color: &mut PixelColor,
-) {
+) -> Option<Action> {
     let patch_size = patch_size(ui);
     let (patch_rect, response) = ui.allocate_exact_size(patch_size, Sense::click());
     draw_patch(
@@ -77,12 +134,16 @@ fn render_patch(
         }
         *primary_color = patch;
     }
-    if response.secondary_clicked() {
+    let menu_id = ui.make_persistent_id(format!("patch_menu_{:?}", patch));
+    if response.secondary_clicked() && ui.input().modifiers.command {
+        ui.memory().open_popup(menu_id);
+    } else if response.secondary_clicked() {
         if *primary_color == patch {
             *primary_color = *secondary_color;
         }
         *secondary_color = patch;
     }
+    let action = render_patch_menu(ui, &response, menu_id, patch, *primary_color);
     render_patch_popups(
         image,
         ui,
@@ -91,12 +152,40 @@ fn render_patch(
         *primary_color == patch,
         *secondary_color == patch,
     );
+    action
+}
+
+/// The menu shown when ctrl+right-clicking a color patch.
+fn render_patch_menu(
+    ui: &mut egui::Ui,
+    response: &egui::Response,
+    menu_id: egui::Id,
+    patch: PixelColor,
+    primary_color: PixelColor,
+) -> Option<Action> {
+    let mut action = None;
+    widgets::popup(ui, menu_id, response, |ui| {
+        if ui
+            .add_enabled(
+                patch != primary_color,
+                egui::Button::new("Replace with primary color everywhere"),
+            )
+            .clicked()
+        {
+            action = Some(Action::Document(DocAction::ReplaceColorEverywhere {
+                to_replace: patch,
+                replacement: primary_color,
+            }));
+        }
+    });
+    action
 }
 
 /// The clickable label for a special color. Shows a popup if clicked.
 fn render_special_color_label(
     ui: &mut egui::Ui,
     patch: PixelColor,
+    palette: &VicPalette,
     label: &str,
     tooltip: &str,
 ) -> Option<Action> {
@@ -108,7 +197,7 @@ fn render_special_color_label(
     if response.clicked() {
         ui.memory().open_popup(popup_id);
     }
-    render_color_popup(ui, &response, popup_id, patch)
+    render_color_popup(ui, &response, popup_id, patch, palette)
 }
 
 fn draw_patch(
@@ -175,30 +264,41 @@ fn render_patch_popups(
     selected_as_primary: bool,
     selected_as_secondary: bool,
 ) {
-    let color_description = match patch {
+    let selected_text = match (selected_as_primary, selected_as_secondary) {
+        (false, false) => {
+            "Left/right click to select as primary/secondary.\nCtrl+right-click for more."
+        }
+        (true, false) => "Selected primary color. Right-click to swap with secondary color.",
+        (false, true) => "Selected secondary color. Click to swap with primary color.",
+        (true, true) => "Selected primary and secondary color.",
+    };
+    response.on_hover_text(format!(
+        "{}\n{}",
+        color_description(image, patch),
+        selected_text
+    ));
+}
+
+/// A description of a color for showing to the user.
+fn color_description(image: &VicImage, patch: PixelColor) -> String {
+    let palette = image.palette();
+    match patch {
         PixelColor::Background => format!(
             "Background ({})",
-            VicPalette::name(image.global_colors().background)
+            image.palette().name(image.global_colors().background)
         ),
         PixelColor::Border => format!(
             "Border ({})",
-            VicPalette::name(image.global_colors().border)
+            image.palette().name(image.global_colors().border)
         ),
         PixelColor::Aux => format!(
             "Auxiliary ({})",
-            VicPalette::name(image.global_colors().aux)
+            image.palette().name(image.global_colors().aux)
         ),
         PixelColor::CharColor(index) => {
-            format!("Character color {}: {}", index, VicPalette::name(index))
+            format!("Character color {}: {}", index, palette.name(index))
         }
-    };
-    let selected_text = match (selected_as_primary, selected_as_secondary) {
-        (false, false) => "Left/right click to select as primary/secondary.",
-        (true, false) => "Selected primary color. Right-click to swap with secondary color.",
-        (false, true) => "Selected secondary color. Click to swap with primary color.",
-        (true, true) => "Selected primary and secondary color.",
-    };
-    response.on_hover_text(format!("{}\n{}", color_description, selected_text));
+    }
 }
 
 fn render_color_popup(
@@ -206,6 +306,7 @@ fn render_color_popup(
     response: &egui::Response,
     popup_id: egui::Id,
     patch: PixelColor,
+    palette: &VicPalette,
 ) -> Option<Action> {
     let mut action = None;
     widgets::popup(ui, popup_id, response, |ui| {
@@ -214,14 +315,24 @@ fn render_color_popup(
             ui.horizontal(|ui| {
                 for index in indices {
                     let index = index as u8;
-                    let label = VicPalette::name(index);
+                    let label = palette.name(index);
+                    let color = palette.color(index);
                     let (patch_rect, response) = ui.allocate_exact_size(patch_size, Sense::click());
                     ui.painter().rect_filled(
                         patch_rect,
                         patch_rect.size().y * PATCH_CORNER_RADIUS_FRACTION,
-                        VicPalette::color(index),
+                        color,
                     );
-                    response.clone().on_hover_text(label);
+                    ui.painter().text(
+                        patch_rect.left_top() + Vec2::splat(patch_rect.width() * 0.1),
+                        Align2::LEFT_TOP,
+                        index,
+                        TextStyle::Small,
+                        contrasting_color(color.into()),
+                    );
+                    response
+                        .clone()
+                        .on_hover_text(format!("{}: {}", index, label));
                     if response.clicked() {
                         match patch {
                             PixelColor::Background => {
@@ -252,6 +363,16 @@ fn render_color_popup(
     action
 }
 
+/// Black or white, whichever is most readable on top of the given color.
+fn contrasting_color(color: Color32) -> Color32 {
+    let luma = 0.299 * color.r() as f32 + 0.587 * color.g() as f32 + 0.114 * color.b() as f32;
+    if luma > 128.0 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
 fn patch_size(ui: &egui::Ui) -> Vec2 {
     let interact_size = ui.spacing().interact_size;
     let patch_width = interact_size.x.max(interact_size.y);
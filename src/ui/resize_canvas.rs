@@ -0,0 +1,100 @@
+//! Dialog for changing the size of the image.
+
+use eframe::egui::{self, DragValue};
+
+use crate::{
+    actions::{Action, DocAction},
+    coords::{Anchor, SizeInCells},
+    ui::size_presets,
+    vic::VicImage,
+};
+
+/// Settings the user has entered in the Resize Canvas dialog.
+pub struct ResizeCanvasDialog {
+    width: i32,
+    height: i32,
+    anchor: Anchor,
+}
+
+impl ResizeCanvasDialog {
+    /// Create the dialog state, starting with the current size of the image.
+    pub fn new(size: SizeInCells) -> Self {
+        Self {
+            width: size.width,
+            height: size.height,
+            anchor: Anchor::TopLeft,
+        }
+    }
+}
+
+/// Show the dialog if it is open. Closes it when the user is done with it.
+pub fn render(
+    ctx: &egui::CtxRef,
+    dialog: &mut Option<ResizeCanvasDialog>,
+    user_actions: &mut Vec<Action>,
+) {
+    let state = match dialog {
+        Some(state) => state,
+        None => return,
+    };
+    let mut close = false;
+    egui::Window::new("Resize Canvas")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("resize_canvas_grid").show(ui, |ui| {
+                ui.label("Preset");
+                size_presets::preset_selector(
+                    ui,
+                    "resize_canvas_preset",
+                    &mut state.width,
+                    &mut state.height,
+                );
+                ui.end_row();
+
+                ui.label("Width");
+                ui.add(
+                    DragValue::new(&mut state.width)
+                        .clamp_range(1..=VicImage::MAX_SIZE.width - 1)
+                        .suffix(" columns"),
+                );
+                ui.end_row();
+
+                ui.label("Height");
+                ui.add(
+                    DragValue::new(&mut state.height)
+                        .clamp_range(1..=VicImage::MAX_SIZE.height - 1)
+                        .suffix(" rows"),
+                );
+                ui.end_row();
+
+                ui.label("Anchor")
+                    .on_hover_text("Which part of the image to keep in place");
+                egui::Grid::new("resize_canvas_anchor").show(ui, |ui| {
+                    for (i, anchor) in Anchor::ALL.iter().enumerate() {
+                        ui.selectable_value(&mut state.anchor, *anchor, anchor.symbol());
+                        if i % 3 == 2 {
+                            ui.end_row();
+                        }
+                    }
+                });
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Resize").clicked() {
+                    user_actions.push(Action::Document(DocAction::ResizeCanvas {
+                        size: SizeInCells::new(state.width, state.height),
+                        anchor: state.anchor,
+                    }));
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+}
@@ -0,0 +1,53 @@
+//! Dialog for changing the user's settings.
+
+use eframe::egui::{self, DragValue};
+
+use crate::settings::Settings;
+
+/// Show the dialog if it is open, with the settings the user has entered so far.
+/// Closes it when the user is done with it.
+/// Returns the new settings if the user accepted the changes.
+pub fn render(ctx: &egui::CtxRef, dialog: &mut Option<Settings>) -> Option<Settings> {
+    let state = match dialog {
+        Some(state) => state,
+        None => return None,
+    };
+    let mut close = false;
+    let mut result = None;
+    egui::Window::new("Settings")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("settings_grid").show(ui, |ui| {
+                ui.label("Undo limit").on_hover_text(
+                    "How many steps can be undone in each document. \
+                    Each step keeps what it changed in memory, \
+                    so a lower limit may be needed for large images. \
+                    Used for documents opened after this is changed.",
+                );
+                ui.add(
+                    DragValue::new(&mut state.undo_limit)
+                        .clamp_range(1..=usize::MAX)
+                        .suffix(" steps"),
+                );
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("OK").clicked() {
+                    result = Some(state.clone());
+                    close = true;
+                }
+                if ui.button("Defaults").clicked() {
+                    *state = Settings::default();
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        *dialog = None;
+    }
+    result
+}
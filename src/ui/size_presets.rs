@@ -0,0 +1,66 @@
+//! Common image sizes the user can choose from when creating or resizing an image.
+
+use eframe::egui::{self, Ui};
+
+use crate::coords::SizeInCells;
+
+/// A named image size.
+pub struct SizePreset {
+    pub name: &'static str,
+    /// What the size is good for, to show to the user.
+    pub description: &'static str,
+    pub size: SizeInCells,
+}
+
+pub const SIZE_PRESETS: [SizePreset; 5] = [
+    SizePreset {
+        name: "VIC-20 screen",
+        description: "The default screen size of the VIC-20",
+        size: SizeInCells::new(22, 23),
+    },
+    SizePreset {
+        name: "Full bitmap",
+        description: "128 × 128 pixels, where every cell can have its own character",
+        size: SizeInCells::new(16, 16),
+    },
+    SizePreset {
+        name: "Super Expander graphics",
+        description: "160 × 160 pixels, the size of the Super Expander cartridge's graphics mode",
+        size: SizeInCells::new(20, 20),
+    },
+    SizePreset {
+        name: "Tile",
+        description: "2 × 2 characters, for designing a tile or sprite",
+        size: SizeInCells::new(2, 2),
+    },
+    SizePreset {
+        name: "Single character",
+        description: "A single cell, for designing one character",
+        size: SizeInCells::new(1, 1),
+    },
+];
+
+/// Show a drop-down list of the presets. Selecting one sets `columns` and `rows` to its size.
+pub fn preset_selector(ui: &mut Ui, id_source: &str, columns: &mut i32, rows: &mut i32) {
+    let current = SIZE_PRESETS
+        .iter()
+        .find(|preset| preset.size == SizeInCells::new(*columns, *rows));
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(current.map_or("Custom", |preset| preset.name))
+        .show_ui(ui, |ui| {
+            for preset in SIZE_PRESETS.iter() {
+                let label = format!(
+                    "{} ({} × {})",
+                    preset.name, preset.size.width, preset.size.height
+                );
+                if ui
+                    .selectable_label(current.map_or(false, |c| c.size == preset.size), label)
+                    .on_hover_text(preset.description)
+                    .clicked()
+                {
+                    *columns = preset.size.width;
+                    *rows = preset.size.height;
+                }
+            }
+        });
+}
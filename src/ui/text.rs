@@ -1,4 +1,7 @@
-pub const GRID_TOOLTIP: &str = "Show character cell grid";
+pub const GRID_TOOLTIP: &str = "Show a grid over the image:
+• Off = no grid
+• Cell = lines between character cells
+• Pixel = also lines between pixels, when zoomed in enough";
 
 pub const RAW_TOOLTIP: &str = "Show image with fixed colors:
 • Gray = background color in hi-res cells
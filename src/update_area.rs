@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bit_vec::BitVec;
 use itertools::Itertools;
@@ -8,6 +8,25 @@ use crate::{
     line,
 };
 
+/// The shape of the pen used by the Paint tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PenShape {
+    Square,
+    Round,
+}
+
+/// Mirroring of what is painted, for making symmetric drawings.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Symmetry {
+    /// Mirror across a vertical axis at `axis.x`.
+    pub mirror_x: bool,
+    /// Mirror across a horizontal axis at `axis.y`.
+    pub mirror_y: bool,
+    /// Position of the axes. They are on the edges between pixels,
+    /// so x = 1 means between pixel 0 and 1.
+    pub axis: PixelPoint,
+}
+
 /// Pixels or cells that are affected by an update
 pub struct UpdateArea {
     pixels: Vec<PixelPoint>,
@@ -18,6 +37,15 @@ impl UpdateArea {
         Self { pixels: vec![p] }
     }
 
+    pub fn from_pixels(pixels: Vec<PixelPoint>) -> Self {
+        Self { pixels }
+    }
+
+    /// The pixels in this area. May include pixels outside the image.
+    pub fn pixels(&self) -> impl Iterator<Item = PixelPoint> + '_ {
+        self.pixels.iter().copied()
+    }
+
     /// Create an UpdateArea from a line between two pixels.
     /// To avoid overdrawing the ending point of a previous line,
     /// the starting pixel `p0` is not included in the line.
@@ -27,6 +55,70 @@ impl UpdateArea {
         }
     }
 
+    /// Create an UpdateArea covering a pen of size `size` centered on the pixel `p`.
+    /// `pixel_width` is the width of the pixels to paint, in image pixels: 2 in multicolor cells.
+    /// The pen is `size` of those pixels wide, so it is placed symmetrically around them.
+    pub fn from_pen(p: PixelPoint, size: i32, shape: PenShape, pixel_width: i32) -> Self {
+        Self {
+            pixels: pen_footprint(p, size, shape, pixel_width),
+        }
+    }
+
+    /// Create an UpdateArea from a line between two pixels drawn with a pen.
+    /// See [`UpdateArea::from_pen`] for the meaning of the arguments.
+    /// Like with [`UpdateArea::pixel_line`], the pixels covered by the pen at `p0`
+    /// are not included.
+    pub fn pen_line(
+        p0: PixelPoint,
+        p1: PixelPoint,
+        size: i32,
+        shape: PenShape,
+        pixel_width: i32,
+    ) -> Self {
+        let start: HashSet<PixelPoint> = pen_footprint(p0, size, shape, pixel_width)
+            .into_iter()
+            .collect();
+        let pixels = line::line(p0, p1)
+            .skip(1)
+            .flat_map(|p| pen_footprint(p, size, shape, pixel_width))
+            .filter(|p| !start.contains(p))
+            .unique()
+            .collect();
+        Self { pixels }
+    }
+
+    /// Add the pixels mirrored according to `symmetry` to this area.
+    pub fn with_symmetry(self, symmetry: &Symmetry) -> Self {
+        if !symmetry.mirror_x && !symmetry.mirror_y {
+            return self;
+        }
+        let mut pixels = self.pixels;
+        if symmetry.mirror_x {
+            let mirrored: Vec<PixelPoint> = pixels
+                .iter()
+                .map(|p| PixelPoint::new(2 * symmetry.axis.x - 1 - p.x, p.y))
+                .collect();
+            pixels.extend(mirrored);
+        }
+        if symmetry.mirror_y {
+            let mirrored: Vec<PixelPoint> = pixels
+                .iter()
+                .map(|p| PixelPoint::new(p.x, 2 * symmetry.axis.y - 1 - p.y))
+                .collect();
+            pixels.extend(mirrored);
+        }
+        Self {
+            pixels: pixels.into_iter().unique().collect(),
+        }
+    }
+
+    /// Keep only the pixels for which `f` returns true.
+    pub fn filter(self, f: impl FnMut(&PixelPoint) -> bool) -> Self {
+        Self {
+            pixels: self.pixels.into_iter().filter(f).collect(),
+        }
+    }
+
     pub fn rectangle(rect: PixelRect) -> Self {
         let pixels = rect
             .y_range()
@@ -70,3 +162,86 @@ impl UpdateArea {
         cells
     }
 }
+
+/// Get the pixels covered by a pen centered on `p`.
+fn pen_footprint(p: PixelPoint, size: i32, shape: PenShape, pixel_width: i32) -> Vec<PixelPoint> {
+    let first = -(size - 1) / 2;
+    let center = (size - 1) as f32 / 2.0;
+    let radius = size as f32 / 2.0 - 0.25;
+    let left = p.x.div_euclid(pixel_width) + first;
+    let top = p.y + first;
+    (0..size)
+        .cartesian_product(0..size)
+        .filter(|&(dy, dx)| {
+            shape == PenShape::Square
+                || (dx as f32 - center).powi(2) + (dy as f32 - center).powi(2) <= radius * radius
+        })
+        .flat_map(|(dy, dx)| {
+            let x = (left + dx) * pixel_width;
+            (x..x + pixel_width).map(move |x| PixelPoint::new(x, top + dy))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pen_footprint, PenShape, Symmetry, UpdateArea};
+    use crate::coords::{PixelPoint, PixelRect};
+
+    fn tuples(pixels: &[PixelPoint]) -> Vec<(i32, i32)> {
+        pixels.iter().map(|p| p.to_tuple()).collect()
+    }
+
+    #[test]
+    fn round_pen() {
+        let pixels = pen_footprint(PixelPoint::new(10, 10), 3, PenShape::Round, 1);
+        assert_eq!(
+            tuples(&pixels),
+            vec![(10, 9), (9, 10), (10, 10), (11, 10), (10, 11)]
+        );
+    }
+
+    #[test]
+    fn multicolor_pen_covers_whole_pixels() {
+        let pixels = pen_footprint(PixelPoint::new(5, 0), 2, PenShape::Square, 2);
+        assert_eq!(
+            tuples(&pixels),
+            vec![
+                (4, 0),
+                (5, 0),
+                (6, 0),
+                (7, 0),
+                (4, 1),
+                (5, 1),
+                (6, 1),
+                (7, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn symmetry_in_both_directions() {
+        let symmetry = Symmetry {
+            mirror_x: true,
+            mirror_y: true,
+            axis: PixelPoint::new(4, 2),
+        };
+        let area = UpdateArea::from_pixel(PixelPoint::new(1, 0)).with_symmetry(&symmetry);
+        assert_eq!(tuples(&area.pixels), vec![(1, 0), (6, 0), (1, 3), (6, 3)]);
+    }
+
+    #[test]
+    fn pixels_on_the_axis_are_not_repeated() {
+        let symmetry = Symmetry {
+            mirror_x: true,
+            mirror_y: false,
+            axis: PixelPoint::new(4, 0),
+        };
+        let area = UpdateArea::rectangle(PixelRect::new(
+            PixelPoint::new(3, 0),
+            euclid::Size2D::new(2, 1),
+        ))
+        .with_symmetry(&symmetry);
+        assert_eq!(tuples(&area.pixels), vec![(3, 0), (4, 0)]);
+    }
+}
@@ -5,19 +5,35 @@ use serde::{Deserialize, Serialize};
 use std::ops::RangeInclusive;
 use thiserror::Error;
 
+mod cell_changes;
 mod char;
+mod char_usage;
 mod image;
 mod palette;
 mod registers;
 mod serialization;
+mod view_settings;
 
+#[cfg(feature = "gui")]
+pub use self::cell_changes::Revision;
 pub use self::{
-    char::Char, image::VicImage, palette::VicPalette, registers::GlobalColors, registers::Register,
+    char::Char, image::SavedChars, image::VicImage, palette::CustomPalette, palette::VicPalette,
+    registers::GlobalColors, registers::Register, serialization::BrushFile,
+    view_settings::ViewSettings,
 };
 
 /// Which colors are allowed as the "character" color.
 pub const ALLOWED_CHAR_COLORS: RangeInclusive<u8> = 0..=7;
 
+/// Bit mask where all character colors are set. See [`char_colors_in_mask`].
+pub const ALL_CHAR_COLORS_MASK: u8 = 0xff;
+
+/// Get the character colors that are set in a bit mask,
+/// where bit 0 is color 0, bit 1 is color 1 and so on.
+pub fn char_colors_in_mask(mask: u8) -> impl Iterator<Item = u8> {
+    ALLOWED_CHAR_COLORS.filter(move |c| mask & (1 << c) != 0)
+}
+
 /// A choice of color for an individual pixel.
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum PixelColor {
@@ -51,6 +67,46 @@ pub enum ColorFormat {
     Multicolor,
 }
 
+/// How the character brush is combined with the cells it is painted on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasteMode {
+    /// Replace the cells with the brush's cells.
+    Replace,
+    /// Like `Replace`, but leave cells alone where the brush cell is empty.
+    Transparent,
+    /// Like `Transparent`, but combine the pixels of high-res cells
+    /// so the pixels that were set in the image stay set.
+    Merge,
+}
+
+impl Default for PasteMode {
+    fn default() -> Self {
+        PasteMode::Replace
+    }
+}
+
+impl PasteMode {
+    pub const ALL: [PasteMode; 3] = [PasteMode::Replace, PasteMode::Transparent, PasteMode::Merge];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            PasteMode::Replace => "Replace",
+            PasteMode::Transparent => "Transparent",
+            PasteMode::Merge => "Merge",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            PasteMode::Replace => "Overwrite the cells under the brush",
+            PasteMode::Transparent => "Don't paint empty brush cells",
+            PasteMode::Merge => {
+                "Don't paint empty brush cells, and add the brush's pixels to high-res cells"
+            }
+        }
+    }
+}
+
 #[allow(clippy::enum_variant_names)] // All variants have the same prefix (Disallowed)
 #[derive(Error, Debug)]
 pub enum DisallowedEdit {
@@ -58,6 +114,12 @@ pub enum DisallowedEdit {
     DisallowedHiresColor,
     #[error("Character color must be between 0 and 7")]
     DisallowedCharacterColor,
+    #[error("The selection is outside the image")]
+    SelectionOutsideImage,
+    #[error("Only images with no multicolor cells can be rotated")]
+    RotateMulticolor,
+    #[error("The image would use more than {0} unique characters")]
+    TooManyCharacters(usize),
 }
 
 impl DisallowedAction for DisallowedEdit {}
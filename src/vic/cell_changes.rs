@@ -0,0 +1,129 @@
+//! Keeping track of which cells of an image have changed.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use itertools::Itertools;
+
+use crate::coords::{CellPos, CellRect, SizeInCells, WithinBounds};
+
+/// Source of revision numbers. Shared by all images,
+/// so a revision taken from one image is never mistaken for one from another.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Identifies the state of an image, so it can later be asked what has changed since then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Revision {
+    /// Which image the revision is of.
+    image: u64,
+    /// Changes with a higher number than this were made later.
+    number: u64,
+}
+
+/// When each cell of an image last changed,
+/// so something drawn from the image can update only the cells that have changed.
+pub(super) struct CellChanges {
+    /// Unique for each image. Copies get their own.
+    id: u64,
+    /// When something that affects every cell last changed, e.g. the size or the colors.
+    all: u64,
+    /// When each cell last changed, if it has since `all`. Size: `size`.
+    /// Empty until a single cell is changed.
+    cells: Vec<u64>,
+    size: SizeInCells,
+}
+
+/// Copies are new images as far as revisions are concerned,
+/// so nothing drawn from the original is considered up to date with the copy.
+impl Clone for CellChanges {
+    fn clone(&self) -> Self {
+        Self::new(self.size)
+    }
+}
+
+impl CellChanges {
+    pub fn new(size: SizeInCells) -> Self {
+        let revision = next_revision();
+        Self {
+            id: revision,
+            all: revision,
+            cells: Vec::new(),
+            size,
+        }
+    }
+
+    /// The current state of the image.
+    pub fn revision(&self) -> Revision {
+        Revision {
+            image: self.id,
+            number: NEXT_REVISION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Record that one cell has changed.
+    pub fn cell_changed(&mut self, cell: CellPos) {
+        if self.cells.is_empty() {
+            self.cells = vec![0; self.size.area() as usize];
+        }
+        self.cells[(cell.y * self.size.width + cell.x) as usize] = next_revision();
+    }
+
+    /// Record that every cell has changed, and that the image now has the given size.
+    pub fn all_changed(&mut self, size: SizeInCells) {
+        self.all = next_revision();
+        self.cells = Vec::new();
+        self.size = size;
+    }
+
+    /// Check whether any of the cells in a rectangle have changed since `revision`.
+    pub fn changed_since(&self, revision: &Revision, rect: &WithinBounds<CellRect>) -> bool {
+        if revision.image != self.id || self.all >= revision.number {
+            return true;
+        }
+        if self.cells.is_empty() {
+            return false;
+        }
+        (rect.min_y()..rect.max_y())
+            .cartesian_product(rect.min_x()..rect.max_x())
+            .any(|(row, column)| {
+                self.cells[(row * self.size.width + column) as usize] >= revision.number
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CellChanges;
+    use crate::coords::{self, CellPos, CellRect, SizeInCells};
+
+    #[test]
+    fn only_changed_cells_are_reported() {
+        let size = SizeInCells::new(4, 2);
+        let mut changes = CellChanges::new(size);
+        let left = coords::clamp_rect_to_bounds(
+            CellRect::new(CellPos::new(0, 0), SizeInCells::new(2, 2)),
+            size,
+        );
+        let right = coords::clamp_rect_to_bounds(
+            CellRect::new(CellPos::new(2, 0), SizeInCells::new(2, 2)),
+            size,
+        );
+        let before = changes.revision();
+        assert!(!changes.changed_since(&before, &left));
+
+        changes.cell_changed(CellPos::new(3, 1));
+        assert!(!changes.changed_since(&before, &left));
+        assert!(changes.changed_since(&before, &right));
+        assert!(!changes.changed_since(&changes.revision(), &right));
+
+        let copy = changes.clone();
+        assert!(copy.changed_since(&changes.revision(), &left));
+
+        let before = changes.revision();
+        changes.all_changed(size);
+        assert!(changes.changed_since(&before, &left));
+    }
+}
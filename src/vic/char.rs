@@ -1,11 +1,11 @@
 use super::ALLOWED_CHAR_COLORS;
-use crate::{colors::TrueColor, error::DisallowedAction, ui::ViewSettings};
+use crate::{colors::TrueColor, error::DisallowedAction};
 use bit_vec::BitVec;
 use imgref::ImgRef;
 
-use super::{DisallowedEdit, GlobalColors, PixelColor, VicPalette};
+use super::{DisallowedEdit, GlobalColors, PixelColor, VicPalette, ViewSettings};
 
-#[derive(Clone, Copy, Hash)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Char {
     pub(super) bits: [u8; 8],
     pub(super) color: u8,
@@ -117,6 +117,31 @@ impl Char {
         self.multicolor
     }
 
+    /// Get the color of the pixel at `cx`, `cy` inside the character.
+    /// In multicolor characters, both `cx` of a wide pixel give the same color.
+    pub fn pixel_color(&self, cx: usize, cy: usize) -> PixelColor {
+        let bits = self.bits[cy];
+        if self.multicolor {
+            match (bits >> (6 - (cx & !1))) & 0b11 {
+                0b00 => PixelColor::Background,
+                0b01 => PixelColor::Border,
+                0b10 => PixelColor::CharColor(self.color),
+                _ => PixelColor::Aux,
+            }
+        } else if bits & (0x80 >> cx) == 0 {
+            PixelColor::Background
+        } else {
+            PixelColor::CharColor(self.color)
+        }
+    }
+
+    /// Check whether the character can be shown on the hardware with the given global colors.
+    /// The character color must fit in color RAM, and the registers the cell uses
+    /// must be within their allowed ranges.
+    pub fn validate(&self, colors: &GlobalColors) -> bool {
+        ALLOWED_CHAR_COLORS.contains(&self.color) && colors.are_valid(self.multicolor)
+    }
+
     /// Return the 4 bit value as stored in color RAM.
     pub fn raw_nibble(&self) -> u8 {
         self.color + if self.multicolor { 8 } else { 0 }
@@ -125,25 +150,25 @@ impl Char {
     pub fn render(
         &self,
         colors: &GlobalColors,
+        palette: &VicPalette,
         settings: &ViewSettings,
     ) -> [TrueColor; Self::WIDTH * Self::HEIGHT] {
         if self.multicolor {
             let (background, border, aux, char_color) = match settings {
                 ViewSettings::Normal => (
-                    VicPalette::color(colors.background),
-                    VicPalette::color(colors.border),
-                    VicPalette::color(colors.aux),
-                    VicPalette::color(self.color),
+                    palette.color(colors.background),
+                    palette.color(colors.border),
+                    palette.color(colors.aux),
+                    palette.color(self.color),
                 ),
                 ViewSettings::Raw => ViewSettings::raw_colors(),
             };
             Self::render_multicolor(&self.bits, background, border, aux, char_color)
         } else {
             let (background, char_color) = match settings {
-                ViewSettings::Normal => (
-                    VicPalette::color(colors.background),
-                    VicPalette::color(self.color),
-                ),
+                ViewSettings::Normal => {
+                    (palette.color(colors.background), palette.color(self.color))
+                }
                 ViewSettings::Raw => (
                     ViewSettings::raw_highres_background(),
                     ViewSettings::raw_hires_char_color(),
@@ -324,6 +349,34 @@ impl Char {
         Ok(true)
     }
 
+    /// Swap the pixels in `mask` between background and foreground.
+    /// In high-res characters, background and character color are swapped.
+    /// In multicolor characters, background is swapped with character color, and border with aux.
+    /// Inverting twice gives back the original character.
+    /// Returns true if any pixel was changed.
+    pub fn invert_pixels(&mut self, mask: &BitVec) -> bool {
+        let mut changed = false;
+        for cy in 0..Self::HEIGHT {
+            let mut flip = 0u8;
+            if self.multicolor {
+                for cx in (0..Self::WIDTH).step_by(2) {
+                    if mask[cx + cy * Self::WIDTH] || mask[cx + cy * Self::WIDTH + 1] {
+                        flip |= 0b10 << (6 - cx);
+                    }
+                }
+            } else {
+                for cx in 0..Self::WIDTH {
+                    if mask[cx + cy * Self::WIDTH] {
+                        flip |= 0x80 >> cx;
+                    }
+                }
+            }
+            self.bits[cy] ^= flip;
+            changed |= flip != 0;
+        }
+        changed
+    }
+
     pub fn mirror_x(&mut self) {
         if self.multicolor {
             for b in &mut self.bits {
@@ -342,6 +395,46 @@ impl Char {
     pub fn mirror_y(&mut self) {
         self.bits.reverse();
     }
+
+    /// Rotate the bitmap 90 degrees clockwise.
+    /// Only makes sense for high-res characters, as multicolor pixels are not square.
+    pub fn rotate_cw_bitmap(&mut self) {
+        let mut bits = [0u8; Self::HEIGHT];
+        for (y, row) in bits.iter_mut().enumerate() {
+            for x in 0..Self::WIDTH {
+                // The pixel at (x, y) comes from (y, 7 - x) in the original
+                if self.bits[Self::HEIGHT - 1 - x] & (0x80 >> y) != 0 {
+                    *row |= 0x80 >> x;
+                }
+            }
+        }
+        self.bits = bits;
+    }
+
+    /// The smallest of the bitmaps this character can be turned into by mirroring,
+    /// and for high-res characters also rotating.
+    /// Characters with the same canonical bitmap could share one character in memory
+    /// if the hardware could flip characters.
+    pub fn canonical_bitmap(&self) -> [u8; 8] {
+        let mut char = *self;
+        let mut min = char.bits;
+        let rotations = if self.multicolor { 1 } else { 4 };
+        for rotation in 0..rotations {
+            if rotation != 0 {
+                char.rotate_cw_bitmap();
+            }
+            // Visit all four combinations of mirroring, ending up where we started
+            for step in 0..4 {
+                if step % 2 == 0 {
+                    char.mirror_x();
+                } else {
+                    char.mirror_y();
+                }
+                min = min.min(char.bits);
+            }
+        }
+        min
+    }
 }
 
 impl Default for Char {
@@ -349,3 +442,53 @@ impl Default for Char {
         Self::new([0u8; 8], 1)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bit_vec::BitVec;
+
+    use super::Char;
+    use crate::vic::{GlobalColors, PixelColor};
+
+    #[test]
+    fn validate_checks_registers_used_by_cell() {
+        let colors = GlobalColors {
+            background: 0,
+            border: 12,
+            aux: 2,
+        };
+        assert!(Char::new_highres([0xff; 8], 1).validate(&colors));
+        assert!(!Char::new([0xff; 8], 1).validate(&colors));
+        let mut char = Char::new_highres([0xff; 8], 1);
+        char.color = 9;
+        assert!(!char.validate(&GlobalColors::default()));
+    }
+
+    #[test]
+    fn invert_highres_twice() {
+        let original = Char::new_highres([0x01, 0x03, 0x07, 0x0f, 0xf0, 0x55, 0xaa, 0], 3);
+        let mask = BitVec::from_elem(Char::WIDTH * Char::HEIGHT, true);
+        let mut char = original;
+        assert!(char.invert_pixels(&mask));
+        assert_eq!(char.bits, [0xfe, 0xfc, 0xf8, 0xf0, 0x0f, 0xaa, 0x55, 0xff]);
+        char.invert_pixels(&mask);
+        assert!(char == original);
+    }
+
+    #[test]
+    fn invert_multicolor_swaps_color_pairs() {
+        // Pixels are background, border, char color and aux
+        let mut char = Char::new([0b00_01_10_11, 0, 0, 0, 0, 0, 0, 0], 2);
+        let mut mask = BitVec::from_elem(Char::WIDTH * Char::HEIGHT, false);
+        mask.set(0, true);
+        mask.set(3, true);
+        mask.set(5, true);
+        mask.set(6, true);
+        char.invert_pixels(&mask);
+        assert_eq!(char.pixel_color(0, 0), PixelColor::CharColor(2));
+        assert_eq!(char.pixel_color(2, 0), PixelColor::Aux);
+        assert_eq!(char.pixel_color(4, 0), PixelColor::Background);
+        assert_eq!(char.pixel_color(6, 0), PixelColor::Border);
+        assert_eq!(char.pixel_color(0, 1), PixelColor::Background);
+    }
+}
@@ -0,0 +1,184 @@
+//! Keeping track of which character bitmaps an image uses.
+
+use std::collections::{HashMap, HashSet};
+
+use imgref::ImgVec;
+
+use super::Char;
+
+/// How many cells use each bitmap.
+/// The image tells which cells have changed since the last update,
+/// so only those need to be counted again.
+#[derive(Default)]
+pub(super) struct CharUsage {
+    /// The bitmap of each cell as of the last update.
+    previous: Vec<[u8; Char::HEIGHT]>,
+    /// Index of each cell that may have changed since the last update.
+    changed: HashSet<usize>,
+    /// Whether every cell may have changed since the last update.
+    all_changed: bool,
+    /// Number of cells that use each bitmap.
+    counts: HashMap<[u8; Char::HEIGHT], usize>,
+    /// The canonical bitmap of each used bitmap, see [`Char::canonical_bitmap`].
+    /// Taken from the first cell found with the bitmap.
+    canonical: HashMap<[u8; Char::HEIGHT], [u8; Char::HEIGHT]>,
+    /// Number of used bitmaps that have each canonical bitmap.
+    canonical_counts: HashMap<[u8; Char::HEIGHT], usize>,
+}
+
+/// Copies start without counts, so e.g. images in the undo history don't keep them in memory.
+/// They are counted again when the copy is updated.
+impl Clone for CharUsage {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// The bitmaps that started or stopped being used in an update.
+pub(super) struct UsageChanges {
+    /// Bitmaps that weren't used before, in the order they appear in the image.
+    pub added: Vec<[u8; Char::HEIGHT]>,
+    /// Bitmaps that are no longer used.
+    pub removed: Vec<[u8; Char::HEIGHT]>,
+}
+
+impl CharUsage {
+    /// Number of unique bitmaps.
+    pub fn unique_count(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Record that the cell with the given index in `video` may have changed.
+    pub fn cell_changed(&mut self, index: usize) {
+        if !self.all_changed {
+            self.changed.insert(index);
+        }
+    }
+
+    /// Record that every cell may have changed, e.g. because the size changed.
+    pub fn all_changed(&mut self) {
+        self.all_changed = true;
+        self.changed.clear();
+    }
+
+    /// Whether all cells have to be counted again.
+    fn needs_recount(&self, video: &ImgVec<Char>) -> bool {
+        self.all_changed
+            || self.previous.len() != video.width() * video.height()
+            || self.counts.is_empty()
+    }
+
+    /// The cells that have changed since the last update, with their index and character.
+    fn changed_cells<'a>(
+        &'a self,
+        video: &'a ImgVec<Char>,
+    ) -> impl Iterator<Item = (usize, Char)> + 'a {
+        let width = video.width();
+        self.changed
+            .iter()
+            .map(move |&i| (i, video[(i % width, i / width)]))
+            .filter(move |(i, char)| char.bits != self.previous[*i])
+    }
+
+    /// Number of unique bitmaps in `video`, without updating the counts.
+    /// Only the cells that have changed since the last update are examined,
+    /// unless nothing has been counted yet or every cell may have changed.
+    pub fn unique_count_in(&self, video: &ImgVec<Char>) -> usize {
+        if self.needs_recount(video) {
+            return video
+                .pixels()
+                .map(|char| char.bits)
+                .collect::<HashSet<_>>()
+                .len();
+        }
+        // How much the number of cells using each bitmap has changed
+        let mut deltas: HashMap<[u8; Char::HEIGHT], isize> = HashMap::new();
+        for (i, char) in self.changed_cells(video) {
+            *deltas.entry(self.previous[i]).or_insert(0) -= 1;
+            *deltas.entry(char.bits).or_insert(0) += 1;
+        }
+        let mut count = self.counts.len();
+        for (bits, delta) in deltas {
+            let before = self.counts.get(&bits).copied().unwrap_or(0) as isize;
+            match (before > 0, before + delta > 0) {
+                (false, true) => count += 1,
+                (true, false) => count -= 1,
+                _ => {}
+            }
+        }
+        count
+    }
+
+    /// How many of the unique bitmaps have the same canonical bitmap as another one.
+    pub fn flipped_duplicates(&self) -> usize {
+        self.counts
+            .len()
+            .saturating_sub(self.canonical_counts.len())
+    }
+
+    /// Update the counts with the current content of the image.
+    /// Returns the bitmaps that were added or removed,
+    /// or None if everything had to be counted again, e.g. because the size changed.
+    pub fn update(&mut self, video: &ImgVec<Char>) -> Option<UsageChanges> {
+        if self.needs_recount(video) {
+            self.recount(video);
+            return None;
+        }
+        let mut changed: Vec<(usize, Char)> = self.changed_cells(video).collect();
+        // In image order, so the added bitmaps are too
+        changed.sort_unstable_by_key(|(i, _)| *i);
+        self.changed.clear();
+        // Remove the old bitmaps before adding the new ones,
+        // so a bitmap that just moved is not counted as added
+        let mut removed = Vec::new();
+        for &(i, _) in changed.iter() {
+            *self.counts.get_mut(&self.previous[i]).unwrap() -= 1;
+            removed.push(self.previous[i]);
+        }
+        let mut added = Vec::new();
+        for &(i, char) in changed.iter() {
+            self.previous[i] = char.bits;
+            let count = self.counts.entry(char.bits).or_insert(0);
+            *count += 1;
+            if !self.canonical.contains_key(&char.bits) {
+                added.push(char.bits);
+                self.add_canonical(&char);
+            }
+        }
+        removed.retain(|bits| self.counts.get(bits) == Some(&0));
+        removed.sort_unstable();
+        removed.dedup();
+        for bits in removed.iter() {
+            self.counts.remove(bits);
+            let canonical = self.canonical.remove(bits).unwrap();
+            let canonical_count = self.canonical_counts.get_mut(&canonical).unwrap();
+            *canonical_count -= 1;
+            if *canonical_count == 0 {
+                self.canonical_counts.remove(&canonical);
+            }
+        }
+        Some(UsageChanges { added, removed })
+    }
+
+    /// Count all cells from scratch.
+    fn recount(&mut self, video: &ImgVec<Char>) {
+        self.changed.clear();
+        self.all_changed = false;
+        self.previous = video.pixels().map(|char| char.bits).collect();
+        self.counts.clear();
+        self.canonical.clear();
+        self.canonical_counts.clear();
+        for char in video.pixels() {
+            *self.counts.entry(char.bits).or_insert(0) += 1;
+            if !self.canonical.contains_key(&char.bits) {
+                self.add_canonical(&char);
+            }
+        }
+    }
+
+    fn add_canonical(&mut self, char: &Char) {
+        let canonical = char.canonical_bitmap();
+        self.canonical.insert(char.bits, canonical);
+        *self.canonical_counts.entry(canonical).or_insert(0) += 1;
+    }
+}
@@ -1,13 +1,17 @@
 use super::{
-    char::Char, ColorFormat, DisallowedEdit, GlobalColors, PixelColor, Register, VicPalette,
+    cell_changes::{CellChanges, Revision},
+    char::Char,
+    char_usage::CharUsage,
+    ColorFormat, DisallowedEdit, GlobalColors, PasteMode, PixelColor, Register, VicPalette,
+    ViewSettings,
 };
 use crate::{
+    brush,
     cell_image::{CellCoordinates, CellImageSize},
     colors::TrueColor,
-    coords::{self, CellPos, CellRect, PixelPoint, SizeInCells, WithinBounds},
+    coords::{self, Anchor, CellPos, CellRect, PixelPoint, SizeInCells, WithinBounds},
     error::{DisallowedAction, Error},
-    image_operations,
-    ui::ViewSettings,
+    image_operations::{self, Dithering},
     update_area::UpdateArea,
 };
 use bimap::BiMap;
@@ -15,20 +19,42 @@ use bit_vec::BitVec;
 use image::{imageops::FilterType, GenericImage, GenericImageView, RgbaImage};
 use imgref::{ImgRef, ImgVec};
 use itertools::Itertools;
-use std::collections::HashMap;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct VicImage {
     pub(super) colors: GlobalColors,
 
+    /// The true colors to display the color indices with.
+    pub(super) palette: VicPalette,
+
     /// The character at each position.
     /// Size: columns x rows.
     pub(super) video: ImgVec<Char>,
 
-    /// Bitmap for each character
+    /// Bitmap for each character.
+    /// Only kept up to date when `stable_characters` is set, as it is otherwise not needed.
     bitmaps: BiMap<usize, [u8; 8]>,
+
+    /// Which bitmaps are used, as of the last `update`.
+    usage: CharUsage,
+
+    /// How many characters could be saved if characters could be flipped, as of the last `update`.
+    flip_savings: usize,
+
+    /// Keep the character numbers in `bitmaps` when the image changes,
+    /// instead of numbering the characters in the order they appear.
+    pub(super) stable_characters: bool,
+
+    /// Which cells have changed, for updating only those where the image is shown.
+    changes: CellChanges,
 }
 
+/// The characters of some cells, saved so they can be put back later.
+pub type SavedChars = Vec<(WithinBounds<CellPos>, Char)>;
+
 impl Default for VicImage {
     fn default() -> Self {
         VicImage::new(22, 23)
@@ -37,6 +63,8 @@ impl Default for VicImage {
 
 impl VicImage {
     pub const MAX_SIZE: SizeInCells = SizeInCells::new(10000, 10000);
+    /// How many unique characters the hardware can address.
+    pub const MAX_CHARACTERS: usize = 256;
 
     pub fn new(columns: usize, rows: usize) -> Self {
         let video = ImgVec::new(vec![Char::default(); columns * rows], columns, rows);
@@ -55,37 +83,62 @@ impl VicImage {
         video_colors: Vec<u8>,
         characters: HashMap<usize, [u8; Char::HEIGHT]>,
     ) -> Result<Self, Error> {
-        let raw_video: Vec<Char> = video_chars
+        let area = size.area() as usize;
+        if video_chars.len() != area {
+            return Err(Error::WrongDataLength(
+                "video characters",
+                video_chars.len(),
+                area,
+            ));
+        }
+        if video_colors.len() != area {
+            return Err(Error::WrongDataLength(
+                "video colors",
+                video_colors.len(),
+                area,
+            ));
+        }
+        let raw_video = video_chars
             .iter()
             .zip(video_colors)
-            .map(|(charnum, color)| {
-                let bits = *characters.get(charnum).unwrap_or(&Char::EMPTY_BITMAP);
-                Char {
+            .enumerate()
+            .map(|(position, (charnum, color))| {
+                let bits = *characters
+                    .get(charnum)
+                    .ok_or(Error::UndefinedCharacter(*charnum, position))?;
+                Ok(Char {
                     bits,
                     color: color & 7,
                     multicolor: (color & 8) == 8,
-                }
+                })
             })
-            // Add padding in case video_colors is too short
-            .chain(std::iter::repeat(Char::default()))
-            .take(size.area() as usize)
-            .collect();
-        assert_eq!(size.area() as usize, raw_video.len());
+            .collect::<Result<Vec<Char>, Error>>()?;
         let video = ImgVec::new(raw_video, size.width as usize, size.height as usize);
         let mut bitmaps = BiMap::new();
         bitmaps.extend(characters);
         Ok(Self {
             colors: global_colors,
+            palette: VicPalette::default(),
             video,
             bitmaps,
+            usage: CharUsage::default(),
+            flip_savings: 0,
+            stable_characters: false,
+            changes: CellChanges::new(size),
         })
     }
 
     pub fn with_content(video: ImgVec<Char>) -> Self {
+        let size = SizeInCells::new(video.width() as i32, video.height() as i32);
         Self {
             colors: Default::default(),
+            palette: VicPalette::default(),
             video,
             bitmaps: BiMap::new(),
+            usage: CharUsage::default(),
+            flip_savings: 0,
+            stable_characters: false,
+            changes: CellChanges::new(size),
         }
     }
 
@@ -93,7 +146,13 @@ impl VicImage {
         let columns = (source_image.width() as usize + Char::WIDTH - 1) / Char::WIDTH;
         let rows = (source_image.height() as usize + Char::HEIGHT - 1) / Char::HEIGHT;
         let mut image = VicImage::new(columns, rows);
-        image.paste_image(source_image, PixelPoint::zero(), ColorFormat::Multicolor);
+        image.paste_image(
+            source_image,
+            PixelPoint::zero(),
+            ColorFormat::Multicolor,
+            Dithering::None,
+            super::ALL_CHAR_COLORS_MASK,
+        );
         Ok(image)
     }
 
@@ -105,6 +164,24 @@ impl VicImage {
     /// Set the global colors.
     pub fn set_global_colors(&mut self, colors: GlobalColors) {
         self.colors = colors;
+        self.all_cells_changed();
+    }
+
+    /// Get the palette used for displaying the image.
+    pub fn palette(&self) -> &VicPalette {
+        &self.palette
+    }
+
+    /// Set the palette used for displaying the image.
+    /// Return true if the palette actually changed.
+    pub fn set_palette(&mut self, palette: VicPalette) -> bool {
+        if self.palette == palette {
+            false
+        } else {
+            self.palette = palette;
+            self.all_cells_changed();
+            true
+        }
     }
 
     /// Set one of the global colors.
@@ -115,12 +192,186 @@ impl VicImage {
             false
         } else {
             *v = value;
+            self.all_cells_changed();
             true
         }
     }
 
+    /// Change the size of the image without scaling its content.
+    /// The existing cells are placed according to `anchor`, and new cells get the default character.
+    /// The size is clamped to what is allowed in a saved file.
+    /// Return true if the size actually changed.
+    pub fn resize_canvas(&mut self, new_size: SizeInCells, anchor: Anchor) -> bool {
+        let new_size = SizeInCells::new(
+            new_size.width.clamp(1, Self::MAX_SIZE.width - 1),
+            new_size.height.clamp(1, Self::MAX_SIZE.height - 1),
+        );
+        let old_size = self.size_in_cells();
+        if new_size == old_size {
+            return false;
+        }
+        let offset = anchor.offset(old_size, new_size);
+        let mut video = ImgVec::new(
+            vec![Char::default(); new_size.area() as usize],
+            new_size.width as usize,
+            new_size.height as usize,
+        );
+        for (row, chars) in self.video.rows().enumerate() {
+            for (column, char) in chars.iter().enumerate() {
+                let p = CellPos::new(column as i32, row as i32) + offset;
+                if let Some(p) = coords::within_bounds(p, new_size) {
+                    video[p.as_tuple()] = *char;
+                }
+            }
+        }
+        self.video = video;
+        self.all_cells_changed();
+        true
+    }
+
+    /// Remove everything outside the given rectangle.
+    /// Return true if the size actually changed.
+    pub fn crop(&mut self, rect: &WithinBounds<CellRect>) -> bool {
+        if rect.size == self.size_in_cells() {
+            return false;
+        }
+        self.video = self.grab_cells(rect);
+        self.all_cells_changed();
+        true
+    }
+
+    /// Mirror the whole image horizontally.
+    pub fn flip_x(&mut self) {
+        brush::mirror_x(&mut self.video);
+        self.all_cells_changed();
+    }
+
+    /// Mirror the whole image vertically.
+    pub fn flip_y(&mut self) {
+        brush::mirror_y(&mut self.video);
+        self.all_cells_changed();
+    }
+
+    /// Move all cells `dx` columns and `dy` rows.
+    /// If `wrap` is true, cells that are moved outside the image come back on the other side,
+    /// otherwise they are lost, and the cells left behind get the default character.
+    /// Return true if anything was moved.
+    pub fn shift(&mut self, dx: i32, dy: i32, wrap: bool) -> bool {
+        if dx == 0 && dy == 0 {
+            return false;
+        }
+        let size = self.size_in_cells();
+        let mut video = ImgVec::new(
+            vec![Char::default(); size.area() as usize],
+            size.width as usize,
+            size.height as usize,
+        );
+        for (row, chars) in self.video.rows().enumerate() {
+            for (column, char) in chars.iter().enumerate() {
+                let mut p = CellPos::new(column as i32 + dx, row as i32 + dy);
+                if wrap {
+                    p = CellPos::new(p.x.rem_euclid(size.width), p.y.rem_euclid(size.height));
+                }
+                if let Some(p) = coords::within_bounds(p, size) {
+                    video[p.as_tuple()] = *char;
+                }
+            }
+        }
+        self.video = video;
+        self.all_cells_changed();
+        true
+    }
+
+    /// Rotate the whole image 90 degrees clockwise.
+    /// Multicolor pixels are not square, so this is only allowed if all cells are high-res.
+    pub fn rotate_cw(&mut self) -> Result<bool, Box<dyn DisallowedAction>> {
+        self.rotate(|column, row, _, height| (height - 1 - row, column), 1)
+    }
+
+    /// Rotate the whole image 90 degrees counterclockwise.
+    /// Multicolor pixels are not square, so this is only allowed if all cells are high-res.
+    pub fn rotate_ccw(&mut self) -> Result<bool, Box<dyn DisallowedAction>> {
+        self.rotate(|column, row, width, _| (row, width - 1 - column), 3)
+    }
+
+    /// Move every cell to the position given by `target_position`,
+    /// which gets the column, row, width, and height of the original image,
+    /// and rotate each character clockwise `char_rotations` times.
+    fn rotate<F>(
+        &mut self,
+        target_position: F,
+        char_rotations: usize,
+    ) -> Result<bool, Box<dyn DisallowedAction>>
+    where
+        F: Fn(usize, usize, usize, usize) -> (usize, usize),
+    {
+        if self.video.pixels().any(|char| char.is_multicolor()) {
+            return Err(Box::new(DisallowedEdit::RotateMulticolor));
+        }
+        let (width, height) = (self.video.width(), self.video.height());
+        let mut video = ImgVec::new(vec![Char::default(); width * height], height, width);
+        for (row, chars) in self.video.rows().enumerate() {
+            for (column, char) in chars.iter().enumerate() {
+                let mut char = *char;
+                for _ in 0..char_rotations {
+                    char.rotate_cw_bitmap();
+                }
+                video[target_position(column, row, width, height)] = char;
+            }
+        }
+        self.video = video;
+        self.all_cells_changed();
+        Ok(true)
+    }
+
     /// Paste a true color image into this image.
-    pub fn paste_image(&mut self, source: &RgbaImage, target: PixelPoint, format: ColorFormat) {
+    /// `dithering` is used when reducing the colors to the palette.
+    /// Only the character colors in the bit mask `allowed_colors` are used
+    /// (see [`super::char_colors_in_mask`]).
+    pub fn paste_image(
+        &mut self,
+        source: &RgbaImage,
+        target: PixelPoint,
+        format: ColorFormat,
+        dithering: Dithering,
+        allowed_colors: u8,
+    ) {
+        let converted = self.convert_image(source, target, format, dithering, allowed_colors);
+        for (r, row) in (converted.start_row..).zip(converted.rows) {
+            for (c, (char, _)) in (converted.start_column..).zip(row) {
+                self.video[(c as usize, r as usize)] = char;
+                self.cell_changed(CellPos::new(c, r));
+            }
+        }
+    }
+
+    /// Get the total quantization error when pasting a true color image with `paste_image`
+    /// using the current global colors. A lower error means a result closer to the original.
+    pub fn conversion_error(
+        &self,
+        source: &RgbaImage,
+        target: PixelPoint,
+        format: ColorFormat,
+        dithering: Dithering,
+        allowed_colors: u8,
+    ) -> f64 {
+        self.convert_image(source, target, format, dithering, allowed_colors)
+            .rows
+            .iter()
+            .flatten()
+            .map(|(_, error)| error)
+            .sum()
+    }
+
+    /// Convert the cells covered by a true color image to characters.
+    fn convert_image(
+        &self,
+        source: &RgbaImage,
+        target: PixelPoint,
+        format: ColorFormat,
+        dithering: Dithering,
+        allowed_colors: u8,
+    ) -> ConvertedCells {
         const CELL_W: i32 = Char::WIDTH as i32;
         const CELL_H: i32 = Char::HEIGHT as i32;
         let start_column = (target.x / CELL_W as i32).max(0);
@@ -131,47 +382,37 @@ impl VicImage {
             .min(self.size_in_cells().height as i32);
 
         let global_colors = &self.colors;
+        let palette = &self.palette;
+        let convert_row = |r: i32| {
+            (start_column..end_column)
+                .map(|c| {
+                    let left = (c * CELL_W) - target.x;
+                    let top = (r * CELL_H) - target.y;
+                    convert_cell(
+                        source,
+                        left,
+                        top,
+                        format,
+                        global_colors,
+                        palette,
+                        dithering,
+                        allowed_colors,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let rows = (start_row..end_row)
+            .into_par_iter()
+            .map(convert_row)
+            .collect::<Vec<_>>();
+        #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+        let rows = (start_row..end_row).map(convert_row).collect::<Vec<_>>();
 
-        for (r, c) in (start_row..end_row).cartesian_product(start_column..end_column) {
-            let left = (c * CELL_W) - target.x;
-            let top = (r * CELL_H) - target.y;
-            let right = left + CELL_W;
-            let bottom = top + CELL_H;
-            let clamped_left = i32::max(0, left);
-            let clamped_top = i32::max(0, top);
-            let clamped_right = i32::min(source.width() as i32, right);
-            let clamped_bottom = i32::min(source.height() as i32, bottom);
-
-            let mut char_image = RgbaImage::new(Char::WIDTH as u32, Char::HEIGHT as u32);
-            char_image
-                .copy_from(
-                    &source.view(
-                        clamped_left as u32,
-                        clamped_top as u32,
-                        (clamped_right - clamped_left) as u32,
-                        (clamped_bottom - clamped_top) as u32,
-                    ),
-                    (clamped_left - left) as u32,
-                    (clamped_top - top) as u32,
-                )
-                .unwrap();
-
-            self.video[(c as usize, r as usize)] = match format {
-                ColorFormat::HighRes => {
-                    let colors = optimized_image_highres(&char_image, global_colors);
-                    Char::highres_from_colors(colors.as_ref(), global_colors)
-                }
-                ColorFormat::Multicolor => {
-                    let half_width = image::imageops::resize(
-                        &char_image,
-                        Char::WIDTH as u32 / 2,
-                        Char::HEIGHT as u32,
-                        FilterType::Triangle,
-                    );
-                    let colors = optimized_image_multicolor(&half_width, global_colors);
-                    Char::multicolor_from_colors(colors.as_ref(), global_colors)
-                }
-            }
+        ConvertedCells {
+            start_column,
+            start_row,
+            rows,
         }
     }
 
@@ -185,16 +426,18 @@ impl VicImage {
     }
 
     pub fn true_color_from_paint_color(&self, c: &PixelColor) -> TrueColor {
-        VicPalette::color(self.color_index_from_paint_color(c))
+        self.palette.color(self.color_index_from_paint_color(c))
     }
 
     /// Paste characters into the image.
     /// `target_pos` is the top-left corner.
     /// The extents of the pasted chars may be outside the image (they are clipped).
+    /// `mode` decides how the pasted characters are combined with the existing ones.
     pub fn paste_chars(
         &mut self,
         target_pos: &CellPos,
         source: ImgRef<'_, Char>,
+        mode: PasteMode,
     ) -> Result<bool, Box<dyn DisallowedAction>> {
         let mut changed = false;
         let source_size = SizeInCells::new(source.width() as i32, source.height() as i32);
@@ -202,10 +445,27 @@ impl VicImage {
             (target_pos.y..target_pos.y + source_size.height as i32)
                 .cartesian_product(target_pos.x..target_pos.x + source_size.width as i32),
         ) {
+            if mode != PasteMode::Replace && char.bits == Char::EMPTY_BITMAP {
+                continue;
+            }
             let p = CellPos::new(c, r);
             if let Some(p) = coords::within_bounds(p, self.size_in_cells()) {
-                self.video[p.as_tuple()] = char;
-                changed = true;
+                let target = &mut self.video[p.as_tuple()];
+                let new_char = match mode {
+                    PasteMode::Merge if !char.multicolor && !target.multicolor => {
+                        let mut merged = char;
+                        for (bits, old_bits) in merged.bits.iter_mut().zip(target.bits) {
+                            *bits |= old_bits;
+                        }
+                        merged
+                    }
+                    _ => char,
+                };
+                if *target != new_char {
+                    *target = new_char;
+                    self.cell_changed(*p);
+                    changed = true;
+                }
             }
         }
         Ok(changed)
@@ -222,7 +482,10 @@ impl VicImage {
         let mut changed = false;
         for (cell, mask) in self.cells_and_pixels(target) {
             let char = &mut self.video[cell.as_tuple()];
-            changed |= char.mutate_pixels(&mask, &operation)?;
+            if char.mutate_pixels(&mask, &operation)? {
+                self.cell_changed(*cell);
+                changed = true;
+            }
         }
         Ok(changed)
     }
@@ -239,7 +502,10 @@ impl VicImage {
         let mask = BitVec::from_elem(Char::WIDTH * Char::HEIGHT, true);
         for cell in self.target_cells(target) {
             let char = &mut self.video[cell.as_tuple()];
-            changed |= char.mutate_pixels(&mask, &operation)?;
+            if char.mutate_pixels(&mask, &operation)? {
+                self.cell_changed(*cell);
+                changed = true;
+            }
         }
         Ok(changed)
     }
@@ -262,6 +528,33 @@ impl VicImage {
         self.apply_operation_to_cells(target, |_| color)
     }
 
+    /// Set every pixel in the image to one color, keeping the size of the image.
+    /// Clearing to the background color gives cells with an empty bitmap.
+    pub fn clear(&mut self, color: PixelColor) -> Result<bool, Box<dyn DisallowedAction>> {
+        let char = match color {
+            PixelColor::Background => Char::default(),
+            PixelColor::Border => Char::new([0b01010101; Char::HEIGHT], Char::default().color),
+            PixelColor::Aux => Char::new([0xff; Char::HEIGHT], Char::default().color),
+            PixelColor::CharColor(c) => {
+                if !super::ALLOWED_CHAR_COLORS.contains(&c) {
+                    return Err(Box::new(DisallowedEdit::DisallowedCharacterColor));
+                }
+                Char::new_highres([0xff; Char::HEIGHT], c)
+            }
+        };
+        let mut changed = false;
+        for cell in self.video.pixels_mut() {
+            if *cell != char {
+                *cell = char;
+                changed = true;
+            }
+        }
+        if changed {
+            self.all_cells_changed();
+        }
+        Ok(changed)
+    }
+
     /// Replace one color with another.
     pub fn replace_color(
         &mut self,
@@ -275,6 +568,102 @@ impl VicImage {
         )
     }
 
+    /// Replace one color with another in the whole image.
+    pub fn replace_color_everywhere(
+        &mut self,
+        to_replace: PixelColor,
+        replacement: PixelColor,
+    ) -> Result<bool, Box<dyn DisallowedAction>> {
+        let mask = BitVec::from_elem(Char::WIDTH * Char::HEIGHT, true);
+        // Before the loop, as an error may stop it after some cells have changed
+        self.all_cells_changed();
+        let mut changed = false;
+        for char in self.video.pixels_mut() {
+            changed |=
+                char.mutate_pixels(
+                    &mask,
+                    |old| {
+                        if old == to_replace {
+                            replacement
+                        } else {
+                            old
+                        }
+                    },
+                )?;
+        }
+        Ok(changed)
+    }
+
+    /// Fill the connected pixels that have the same color as the pixels in `seeds`.
+    pub fn flood_fill(
+        &mut self,
+        seeds: &UpdateArea,
+        color: PixelColor,
+    ) -> Result<bool, Box<dyn DisallowedAction>> {
+        let area = self.connected_pixels(seeds);
+        self.plot(&area, color)
+    }
+
+    /// Replace the colors of the pixels in `seeds` in the whole image.
+    pub fn global_fill(
+        &mut self,
+        seeds: &UpdateArea,
+        color: PixelColor,
+    ) -> Result<bool, Box<dyn DisallowedAction>> {
+        let to_replace: Vec<PixelColor> =
+            seeds.pixels().filter_map(|p| self.pixel_color(p)).collect();
+        let mut changed = false;
+        for old_color in to_replace {
+            changed |= self.replace_color_everywhere(old_color, color)?;
+        }
+        Ok(changed)
+    }
+
+    /// Get the color of a pixel, or None if it is outside the image.
+    pub fn pixel_color(&self, position: PixelPoint) -> Option<PixelColor> {
+        let (cell, cx, cy) = self.cell(position)?;
+        Some(self.video[cell.as_tuple()].pixel_color(cx as usize, cy as usize))
+    }
+
+    /// Find the pixels that can be reached from `seeds`
+    /// by only passing pixels of the same color as the seed.
+    pub fn connected_pixels(&self, seeds: &UpdateArea) -> UpdateArea {
+        self.connected_pixels_within(seeds, |_| true)
+    }
+
+    /// Like `connected_pixels`, but only passes pixels for which `within` returns true.
+    pub fn connected_pixels_within(
+        &self,
+        seeds: &UpdateArea,
+        within: impl Fn(PixelPoint) -> bool,
+    ) -> UpdateArea {
+        let (width, height) = self.size_in_pixels();
+        let mut visited = vec![false; width * height];
+        let mut result = Vec::new();
+        for seed in seeds.pixels().filter(|p| within(*p)) {
+            let color = match self.pixel_color(seed) {
+                Some(c) => c,
+                None => continue,
+            };
+            let mut stack = vec![seed];
+            while let Some(p) = stack.pop() {
+                let index = p.y as usize * width + p.x as usize;
+                if visited[index] {
+                    continue;
+                }
+                visited[index] = true;
+                result.push(p);
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let neighbor = PixelPoint::new(p.x + dx, p.y + dy);
+                    if self.pixel_color(neighbor) == Some(color) && within(neighbor) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+        UpdateArea::from_pixels(result)
+    }
+
     /// Swap two colors
     pub fn swap_colors(
         &mut self,
@@ -304,19 +693,108 @@ impl VicImage {
         }
         let mut changed = false;
         for cell in self.target_cells(target) {
-            let cell = &mut self.video[cell.as_tuple()];
-            changed = cell.set_color(color);
+            if self.video[cell.as_tuple()].set_color(color) {
+                self.cell_changed(*cell);
+                changed = true;
+            }
         }
         Ok(changed)
     }
 
+    /// Get the multicolor cells in a rectangle.
+    pub fn multicolor_cells(&self, rect: &WithinBounds<CellRect>) -> Vec<CellPos> {
+        (rect.min_y()..rect.max_y())
+            .cartesian_product(rect.min_x()..rect.max_x())
+            .filter(|(row, column)| self.video[(*column as usize, *row as usize)].is_multicolor())
+            .map(|(row, column)| CellPos::new(column, row))
+            .collect()
+    }
+
+    /// Get the cells that can't be shown on the hardware. See `Char::validate`.
+    pub fn invalid_cells(&self) -> Vec<CellPos> {
+        self.video
+            .rows()
+            .enumerate()
+            .flat_map(|(row, chars)| {
+                chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, char)| !char.validate(&self.colors))
+                    .map(move |(column, _)| CellPos::new(column as i32, row as i32))
+            })
+            .collect()
+    }
+
+    /// Convert the invalid cells in the area to cells the hardware can show,
+    /// keeping them as similar as possible to how they look.
+    /// Multicolor cells are converted to high-res if the multicolor registers are invalid.
+    pub fn fix_cells(&mut self, target: &UpdateArea) -> bool {
+        let size = self.size_in_cells();
+        let mut changed = false;
+        for cell in self.target_cells(target) {
+            let char = self.video[cell.as_tuple()];
+            if char.validate(&self.colors) {
+                continue;
+            }
+            let rect =
+                coords::clamp_rect_to_bounds(CellRect::new(*cell, SizeInCells::new(1, 1)), size);
+            let rendered = self.render_region(&rect, &ViewSettings::Normal);
+            let format = if char.multicolor && self.colors.are_valid(true) {
+                ColorFormat::Multicolor
+            } else {
+                ColorFormat::HighRes
+            };
+            let (fixed, _) = convert_cell(
+                &rendered,
+                0,
+                0,
+                format,
+                &self.colors,
+                &self.palette,
+                Dithering::None,
+                super::ALL_CHAR_COLORS_MASK,
+            );
+            if fixed != char {
+                self.video[cell.as_tuple()] = fixed;
+                self.cell_changed(*cell);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Swap the pixels in the area between background and foreground colors.
+    /// See `Char::invert_pixels`.
+    pub fn invert_colors(&mut self, target: &UpdateArea) -> bool {
+        let mut changed = false;
+        for (cell, mask) in self.cells_and_pixels(target) {
+            if self.video[cell.as_tuple()].invert_pixels(&mask) {
+                self.cell_changed(*cell);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Swap all pixels between background and foreground colors.
+    pub fn invert_all_colors(&mut self) {
+        let mask = BitVec::from_elem(Char::WIDTH * Char::HEIGHT, true);
+        for char in self.video.pixels_mut() {
+            char.invert_pixels(&mask);
+        }
+        self.all_cells_changed();
+    }
+
     pub fn make_high_res(
         &mut self,
         target: &UpdateArea,
     ) -> Result<bool, Box<dyn DisallowedAction>> {
         let mut changed = false;
         for cell in self.target_cells(target) {
-            changed |= self.video[cell.as_tuple()].make_high_res()?;
+            if self.video[cell.as_tuple()].make_high_res()? {
+                self.cell_changed(*cell);
+                changed = true;
+            }
         }
         Ok(changed)
     }
@@ -327,7 +805,10 @@ impl VicImage {
     ) -> Result<bool, Box<dyn DisallowedAction>> {
         let mut changed = false;
         for cell in self.target_cells(target) {
-            changed |= self.video[cell.as_tuple()].make_multicolor()?;
+            if self.video[cell.as_tuple()].make_multicolor()? {
+                self.cell_changed(*cell);
+                changed = true;
+            }
         }
         Ok(changed)
     }
@@ -344,7 +825,45 @@ impl VicImage {
 
     /// General information about the image
     pub fn image_info(&self) -> String {
-        format!("{} characters used", self.bitmaps.len())
+        let count = self.unique_char_count();
+        if count > Self::MAX_CHARACTERS {
+            format!(
+                "{} characters used (maximum is {})",
+                count,
+                Self::MAX_CHARACTERS
+            )
+        } else if self.flip_savings != 0 {
+            format!(
+                "{} characters used ({} are flipped versions of others)",
+                count, self.flip_savings
+            )
+        } else {
+            format!("{} characters used", count)
+        }
+    }
+
+    /// How many of the unique characters are mirrored or rotated versions of other characters.
+    /// That is how many characters could be saved if the hardware could flip characters.
+    pub fn count_flipped_duplicates(&self) -> usize {
+        let mut bitmaps = HashSet::new();
+        let mut canonical = HashSet::new();
+        for char in self.video.pixels() {
+            if bitmaps.insert(char.bits) {
+                canonical.insert(char.canonical_bitmap());
+            }
+        }
+        bitmaps.len().saturating_sub(canonical.len())
+    }
+
+    /// Number of unique characters in the image, as of the last call to `update`.
+    pub fn unique_char_count(&self) -> usize {
+        self.usage.unique_count()
+    }
+
+    /// Number of unique character bitmaps in the image as it is now, even if it has changed
+    /// since the last `update`. Only the cells that changed since then are examined.
+    pub fn current_unique_char_count(&self) -> usize {
+        self.usage.unique_count_in(&self.video)
     }
 
     /// Information about the given pixel in the image
@@ -369,6 +888,15 @@ impl VicImage {
         }
     }
 
+    /// Get the width in pixels of the paintable pixels at the given position:
+    /// 2 in multicolor cells, otherwise 1.
+    pub fn pixel_width_at(&self, position: PixelPoint) -> i32 {
+        match self.cell(position) {
+            Some((cell, _, _)) if self.video[cell.as_tuple()].is_multicolor() => 2,
+            _ => 1,
+        }
+    }
+
     /// Width of one pixel compared to its height.
     pub fn pixel_aspect_ratio(&self) -> f32 {
         // I measured the 176x184 pixels of the Vic-20 screen,
@@ -376,27 +904,130 @@ impl VicImage {
         1.654822
     }
 
+    /// Update the character numbering and statistics after the image has changed.
+    /// Only the cells that changed since the last update are examined.
     pub fn update(&mut self) {
-        self.bitmaps = self.map_characters();
+        let changes = self.usage.update(&self.video);
+        self.flip_savings = self.usage.flipped_duplicates();
+        if !self.stable_characters {
+            return;
+        }
+        let changes = match changes {
+            Some(changes) => changes,
+            None => {
+                self.bitmaps = self.map_characters();
+                return;
+            }
+        };
+        for bits in changes.removed {
+            self.bitmaps.remove_by_right(&bits);
+        }
+        // Like in `map_characters`, new characters get the lowest free numbers
+        let mut next_num = 0;
+        for bits in changes.added {
+            while self.bitmaps.contains_left(&next_num) {
+                next_num += 1;
+            }
+            self.bitmaps.insert(next_num, bits);
+        }
+    }
+
+    /// Whether character numbers are kept when the image changes.
+    pub fn stable_characters(&self) -> bool {
+        self.stable_characters
+    }
+
+    /// Set whether character numbers are kept when the image changes.
+    /// Return true if the setting actually changed.
+    pub fn set_stable_characters(&mut self, stable: bool) -> bool {
+        if self.stable_characters == stable {
+            false
+        } else {
+            if stable {
+                // Keep the numbers the characters have now
+                self.bitmaps = self.map_characters();
+            }
+            self.stable_characters = stable;
+            true
+        }
     }
 
     /// Generate a mapping between character bitmaps and character numbers.
+    /// Characters are numbered in the order they appear in the image,
+    /// unless stable characters are enabled, in which case characters keep the numbers
+    /// they had when the mapping was last updated, and new characters get the lowest free numbers.
+    /// There may then be unused numbers.
     pub fn map_characters(&self) -> BiMap<usize, [u8; 8]> {
         let mut map = BiMap::new();
+        if self.stable_characters {
+            for char in self.video.pixels() {
+                if let Some(&num) = self.bitmaps.get_by_right(&char.bits) {
+                    map.insert(num, char.bits);
+                }
+            }
+        }
+        let mut next_num = 0;
         for char in self.video.pixels() {
-            if map.get_by_right(&char.bits).is_some() {
-                // Existing bitmap
-            } else {
-                let num = map.len();
-                map.insert(num, char.bits);
+            if !map.contains_right(&char.bits) {
+                while map.contains_left(&next_num) {
+                    next_num += 1;
+                }
+                map.insert(next_num, char.bits);
             }
         }
         map
     }
 
+    /// How many character numbers a mapping uses, including any unused numbers.
+    fn character_number_count(map: &BiMap<usize, [u8; 8]>) -> usize {
+        map.left_values().max().map(|m| m + 1).unwrap_or(0)
+    }
+
+    /// Get the bitmaps of all characters, 8 bytes per character.
+    /// The characters are ordered by character number as given by [`Self::map_characters`],
+    /// so the bitmap for character number `n` starts at byte `n * 8`.
+    /// Unused character numbers get an empty bitmap.
+    pub fn to_charset_bytes(&self) -> Vec<u8> {
+        let map = self.map_characters();
+        (0..Self::character_number_count(&map))
+            .flat_map(|num| map.get_by_left(&num).copied().unwrap_or(Char::EMPTY_BITMAP))
+            .collect()
+    }
+
+    /// Get the screen RAM and color RAM for this image, row by row.
+    /// The screen RAM contains the character number for each cell,
+    /// matching the order of [`Self::to_charset_bytes`].
+    /// The color RAM contains the color and multicolor bit for each cell.
+    /// Fails if the image uses more characters than the screen RAM can address.
+    pub fn to_screen_and_color_ram(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let map = self.map_characters();
+        let count = Self::character_number_count(&map);
+        if count > Self::MAX_CHARACTERS {
+            return Err(Error::TooManyCharacters(count));
+        }
+        let screen = self
+            .video
+            .pixels()
+            .map(|char| *map.get_by_right(&char.bits).unwrap() as u8)
+            .collect();
+        let colors = self.video.pixels().map(|char| char.raw_nibble()).collect();
+        Ok((screen, colors))
+    }
+
+    /// The character number of each cell, with the numbering used by `map_characters`.
+    pub fn character_numbers(&self) -> ImgVec<usize> {
+        let map = self.map_characters();
+        let numbers = self
+            .video
+            .pixels()
+            .map(|char| *map.get_by_right(&char.bits).unwrap())
+            .collect();
+        ImgVec::new(numbers, self.video.width(), self.video.height())
+    }
+
     pub fn border(&self) -> TrueColor {
         let i = self.colors.border;
-        VicPalette::color(i)
+        self.palette.color(i)
     }
 
     /// Render true color pixels for this image.
@@ -404,23 +1035,111 @@ impl VicImage {
         self.render_with_settings(&ViewSettings::default())
     }
 
+    /// Render true color pixels for this image,
+    /// surrounded by `border_cells` cells of the border color on all sides.
+    pub fn render_with_border(&self, border_cells: u32) -> RgbaImage {
+        let image = self.render();
+        let border_x = border_cells * Char::WIDTH as u32;
+        let border_y = border_cells * Char::HEIGHT as u32;
+        let mut result = RgbaImage::from_pixel(
+            image.width() + border_x * 2,
+            image.height() + border_y * 2,
+            self.border().into(),
+        );
+        image::imageops::replace(&mut result, &image, border_x, border_y);
+        result
+    }
+
     pub fn render_with_settings(&self, settings: &ViewSettings) -> RgbaImage {
-        let (source_width, source_height) = self.size_in_pixels();
-        let mut image = RgbaImage::new(source_width as u32, source_height as u32);
-        for (row, chars) in self.video.rows().enumerate() {
-            for (column, char) in chars.iter().enumerate() {
-                let char_pixels = char.render(&self.colors, settings);
-                let left = column as u32 * Char::WIDTH as u32;
-                let top = row as u32 * Char::HEIGHT as u32;
-                for ((y, x), s) in ((0..Char::HEIGHT as u32)
-                    .cartesian_product(0..Char::WIDTH as u32))
+        let size = self.size_in_cells();
+        let rect = coords::clamp_rect_to_bounds(CellRect::new(CellPos::zero(), size), size);
+        self.render_region(&rect, settings)
+    }
+
+    /// Render the cells in a rectangle.
+    /// The returned image only contains that part of the image.
+    pub fn render_region(
+        &self,
+        rect: &WithinBounds<CellRect>,
+        settings: &ViewSettings,
+    ) -> RgbaImage {
+        let mut image = RgbaImage::new(
+            rect.width() as u32 * Char::WIDTH as u32,
+            rect.height() as u32 * Char::HEIGHT as u32,
+        );
+        if image.is_empty() {
+            return image;
+        }
+        // Each row of cells is rendered into its own horizontal band of the image
+        let band_size = image.width() as usize * Char::HEIGHT * 4;
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        image
+            .par_chunks_mut(band_size)
+            .enumerate()
+            .for_each(|(row, band)| self.render_band(rect, row as i32, settings, band));
+        #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+        image
+            .chunks_mut(band_size)
+            .enumerate()
+            .for_each(|(row, band)| self.render_band(rect, row as i32, settings, band));
+        image
+    }
+
+    /// Render one row of cells in a rectangle into `band`,
+    /// the RGBA bytes of the pixels of that row.
+    fn render_band(
+        &self,
+        rect: &WithinBounds<CellRect>,
+        row: i32,
+        settings: &ViewSettings,
+        band: &mut [u8],
+    ) {
+        let stride = rect.width() as usize * Char::WIDTH;
+        for column in 0..rect.width() {
+            let char = &self.video[(
+                (rect.min_x() + column) as usize,
+                (rect.min_y() + row) as usize,
+            )];
+            let char_pixels = char.render(&self.colors, &self.palette, settings);
+            let left = column as usize * Char::WIDTH;
+            for ((y, x), s) in (0..Char::HEIGHT)
+                .cartesian_product(0..Char::WIDTH)
                 .zip(char_pixels.iter())
-                {
-                    image.put_pixel(x + left, y + top, (*s).into());
-                }
+            {
+                let i = (y * stride + left + x) * 4;
+                let pixel: image::Rgba<u8> = (*s).into();
+                band[i..i + 4].copy_from_slice(&pixel.0);
             }
         }
-        image
+    }
+
+    /// Identifies the current state of the image. See [`VicImage::changed_since`].
+    pub fn revision(&self) -> Revision {
+        self.changes.revision()
+    }
+
+    /// Check whether any of the cells in a rectangle may have changed since `revision`.
+    /// Also true if `revision` is of another image, including a copy of this one.
+    pub fn changed_since(&self, revision: &Revision, rect: &WithinBounds<CellRect>) -> bool {
+        self.changes.changed_since(revision, rect)
+    }
+
+    /// Record that a cell has to be drawn and counted again.
+    fn cell_changed(&mut self, cell: CellPos) {
+        self.changes.cell_changed(cell);
+        self.usage
+            .cell_changed(cell.y as usize * self.video.width() + cell.x as usize);
+    }
+
+    /// Record that the whole image has to be drawn and counted again.
+    fn all_cells_changed(&mut self) {
+        self.changes.all_changed(self.size_in_cells());
+        self.usage.all_changed();
+    }
+
+    /// Get the character in a cell.
+    pub fn char_at(&self, cell: &WithinBounds<CellPos>) -> Char {
+        self.video[cell.as_tuple()]
     }
 
     /// Get a copy of the characters in a rectangular area.
@@ -438,6 +1157,47 @@ impl VicImage {
         ImgVec::new(chars, rect.width() as usize, rect.height() as usize)
     }
 
+    /// Save the characters in the cells that an area touches.
+    pub fn save_chars_in_area(&self, area: &UpdateArea) -> SavedChars {
+        self.target_cells(area)
+            .into_iter()
+            .map(|cell| (cell, self.video[cell.as_tuple()]))
+            .collect()
+    }
+
+    /// Save the characters in a rectangle of cells.
+    pub fn save_chars_in_rect(&self, rect: &WithinBounds<CellRect>) -> SavedChars {
+        (rect.min_y()..rect.max_y())
+            .cartesian_product(rect.min_x()..rect.max_x())
+            .map(|(row, column)| {
+                let cell = WithinBounds::assume_within_bounds(CellPos::new(column, row));
+                (cell, self.video[cell.as_tuple()])
+            })
+            .collect()
+    }
+
+    /// Save the characters in all cells.
+    pub fn save_all_chars(&self) -> SavedChars {
+        let size = self.size_in_cells();
+        self.save_chars_in_rect(&coords::clamp_rect_to_bounds(
+            CellRect::new(CellPos::zero(), size),
+            size,
+        ))
+    }
+
+    /// Forget the saved characters that are the same in this image.
+    pub fn retain_changed_chars(&self, saved: &mut SavedChars) {
+        saved.retain(|(cell, char)| self.video[cell.as_tuple()] != *char);
+    }
+
+    /// Put back characters saved when the image had the same size as now.
+    pub fn restore_chars(&mut self, saved: &[(WithinBounds<CellPos>, Char)]) {
+        for (cell, char) in saved {
+            self.video[cell.as_tuple()] = *char;
+            self.cell_changed(**cell);
+        }
+    }
+
     /// Get the character cells to update given an UpdateArea.
     /// Returns the columns and rows of the cells within this image's bounds.
     fn target_cells(&self, target: &UpdateArea) -> Vec<WithinBounds<CellPos>> {
@@ -477,26 +1237,117 @@ impl CellCoordinates for VicImage {
     const CELL_HEIGHT: usize = Char::HEIGHT;
 }
 
+/// The result of converting part of a true color image to characters.
+struct ConvertedCells {
+    start_column: i32,
+    start_row: i32,
+    /// The character and the quantization error for each converted cell, row by row.
+    rows: Vec<Vec<(Char, f64)>>,
+}
+
+/// Convert the part of a true color image that covers a cell to a character.
+/// `left` and `top` is the position of the cell relative to the source image.
+/// Returns the character and the quantization error.
+#[allow(clippy::too_many_arguments)]
+fn convert_cell(
+    source: &RgbaImage,
+    left: i32,
+    top: i32,
+    format: ColorFormat,
+    global_colors: &GlobalColors,
+    palette: &VicPalette,
+    dithering: Dithering,
+    allowed_colors: u8,
+) -> (Char, f64) {
+    let right = left + Char::WIDTH as i32;
+    let bottom = top + Char::HEIGHT as i32;
+    let clamped_left = i32::max(0, left);
+    let clamped_top = i32::max(0, top);
+    let clamped_right = i32::min(source.width() as i32, right);
+    let clamped_bottom = i32::min(source.height() as i32, bottom);
+
+    let mut char_image = RgbaImage::new(Char::WIDTH as u32, Char::HEIGHT as u32);
+    char_image
+        .copy_from(
+            &source.view(
+                clamped_left as u32,
+                clamped_top as u32,
+                (clamped_right - clamped_left) as u32,
+                (clamped_bottom - clamped_top) as u32,
+            ),
+            (clamped_left - left) as u32,
+            (clamped_top - top) as u32,
+        )
+        .unwrap();
+
+    match format {
+        ColorFormat::HighRes => {
+            let (colors, error) = optimized_image_highres(
+                &char_image,
+                global_colors,
+                palette,
+                dithering,
+                allowed_colors,
+            );
+            (
+                Char::highres_from_colors(colors.as_ref(), global_colors),
+                error,
+            )
+        }
+        ColorFormat::Multicolor => {
+            let half_width = image::imageops::resize(
+                &char_image,
+                Char::WIDTH as u32 / 2,
+                Char::HEIGHT as u32,
+                FilterType::Triangle,
+            );
+            let (colors, error) = optimized_image_multicolor(
+                &half_width,
+                global_colors,
+                palette,
+                dithering,
+                allowed_colors,
+            );
+            (
+                Char::multicolor_from_colors(colors.as_ref(), global_colors),
+                error,
+            )
+        }
+    }
+}
+
 /// Generates an optimized highres image using the given hardware palette colors.
-/// Tries different colors and finds the one that gives the least quantization error.
-/// Returns the resulting color numbers.
-pub fn optimized_image_highres(original: &RgbaImage, global_colors: &GlobalColors) -> ImgVec<u8> {
+/// Tries the character colors in the bit mask `allowed_colors`
+/// and finds the one that gives the least quantization error.
+/// Returns the resulting color numbers and the quantization error.
+pub fn optimized_image_highres(
+    original: &RgbaImage,
+    global_colors: &GlobalColors,
+    palette: &VicPalette,
+    dithering: Dithering,
+    allowed_colors: u8,
+) -> (ImgVec<u8>, f64) {
     let fixed_colors = [global_colors.background];
     image_operations::optimized_image(
         original,
         &fixed_colors,
-        super::ALLOWED_CHAR_COLORS,
-        VicPalette::all_colors(),
+        super::char_colors_in_mask(allowed_colors),
+        palette.all_colors(),
+        dithering,
     )
 }
 
 /// Generates an optimized multicolor image using the given hardware palette colors.
-/// Tries different colors and finds the one that gives the least quantization error.
-/// Returns the resulting color numbers.
+/// Tries the character colors in the bit mask `allowed_colors`
+/// and finds the one that gives the least quantization error.
+/// Returns the resulting color numbers and the quantization error.
 pub fn optimized_image_multicolor(
     original: &RgbaImage,
     global_colors: &GlobalColors,
-) -> ImgVec<u8> {
+    palette: &VicPalette,
+    dithering: Dithering,
+    allowed_colors: u8,
+) -> (ImgVec<u8>, f64) {
     let fixed_colors = [
         global_colors.background,
         global_colors.border,
@@ -505,7 +1356,452 @@ pub fn optimized_image_multicolor(
     image_operations::optimized_image(
         original,
         &fixed_colors,
-        super::ALLOWED_CHAR_COLORS,
-        VicPalette::all_colors(),
+        super::char_colors_in_mask(allowed_colors),
+        palette.all_colors(),
+        dithering,
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coords::PixelRect;
+
+    /// Change a cell in the first row, like the editing operations do.
+    fn set_char(image: &mut VicImage, column: i32, char: Char) {
+        image.video[(column as usize, 0)] = char;
+        image.cell_changed(CellPos::new(column, 0));
+    }
+
+    #[test]
+    fn fix_cells_makes_cells_valid() {
+        let hires = Char::new_highres([0x0f; 8], 1);
+        let multicolor = Char::new([0b00_10_10_00; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![hires, multicolor], 2, 1));
+        image.colors.border = 12;
+        assert_eq!(image.invalid_cells(), vec![CellPos::new(1, 0)]);
+        let area = UpdateArea::rectangle(PixelRect::from_points([
+            PixelPoint::zero(),
+            PixelPoint::new(16, 8),
+        ]));
+        assert!(image.fix_cells(&area));
+        assert!(image.invalid_cells().is_empty());
+        assert!(image.video[(0usize, 0usize)] == hires);
+        assert!(image.video[(1usize, 0usize)].bits == [0x3c; 8]);
+    }
+
+    #[test]
+    fn border_surrounds_image() {
+        let mut image = VicImage::new(2, 1);
+        image.colors.border = 3;
+        let rendered = image.render_with_border(1);
+        assert_eq!(rendered.dimensions(), (32, 24));
+        let border: image::Rgba<u8> = image.border().into();
+        assert_eq!(*rendered.get_pixel(0, 0), border);
+        assert_eq!(*rendered.get_pixel(31, 23), border);
+        assert_eq!(*rendered.get_pixel(8, 8), *image.render().get_pixel(0, 0));
+    }
+
+    #[test]
+    fn clear_keeps_size() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a; 6], 3, 2));
+        assert!(image.clear(PixelColor::Background).unwrap());
+        assert_eq!(image.size_in_cells(), SizeInCells::new(3, 2));
+        assert!(image.video.pixels().all(|c| c.bits == Char::EMPTY_BITMAP));
+        assert!(!image.clear(PixelColor::Background).unwrap());
+        assert!(image.clear(PixelColor::CharColor(5)).unwrap());
+        assert_eq!(
+            image.pixel_color(PixelPoint::new(23, 15)),
+            Some(PixelColor::CharColor(5))
+        );
+    }
+
+    #[test]
+    fn charset_bytes_contains_unique_chars() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x80; 8], 2);
+        let image = VicImage::with_content(ImgVec::new(vec![a, b, a, a], 2, 2));
+        let bytes = image.to_charset_bytes();
+        assert_eq!(bytes.len(), 2 * Char::HEIGHT);
+        assert_eq!(&bytes[..8], &[0x01; 8]);
+        assert_eq!(&bytes[8..], &[0x80; 8]);
+    }
+
+    #[test]
+    fn resize_canvas_centered() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a; 4], 2, 2));
+        assert!(image.resize_canvas(SizeInCells::new(4, 3), Anchor::Center));
+        assert_eq!(image.size_in_cells(), SizeInCells::new(4, 3));
+        let (screen, _) = image.to_screen_and_color_ram().unwrap();
+        assert_eq!(screen, vec![0, 1, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0]);
+        assert!(!image.resize_canvas(SizeInCells::new(4, 3), Anchor::Center));
+    }
+
+    #[test]
+    fn crop() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x80; 8], 2);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, a, a, a, b, b], 3, 2));
+        let rect = coords::rect_within_size(
+            CellRect::new(CellPos::new(1, 1), SizeInCells::new(2, 1)),
+            image.size_in_cells(),
+        )
+        .unwrap();
+        assert!(image.crop(&rect));
+        assert_eq!(image.size_in_cells(), SizeInCells::new(2, 1));
+        assert_eq!(image.to_charset_bytes(), vec![0x80; 8]);
+    }
+
+    fn bitmaps(image: &VicImage) -> Vec<[u8; 8]> {
+        image.video.pixels().map(|c| c.bits).collect()
+    }
+
+    #[test]
+    fn flip_x_twice() {
+        let a = Char::new_highres([0x01, 0x03, 0x07, 0x0f, 0, 0, 0, 0], 1);
+        let b = Char::new([0x1b, 0, 0, 0, 0, 0, 0, 0xe4], 2);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, a, a, a], 3, 2));
+        let original = bitmaps(&image);
+        image.flip_x();
+        assert_ne!(bitmaps(&image), original);
+        assert_eq!(
+            image.video[(2usize, 0usize)].bits,
+            [0x80, 0xc0, 0xe0, 0xf0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            image.video[(0usize, 0usize)].bits,
+            [0xe4, 0, 0, 0, 0, 0, 0, 0x1b]
+        );
+        image.flip_x();
+        assert_eq!(bitmaps(&image), original);
+    }
+
+    #[test]
+    fn flip_y_twice() {
+        let a = Char::new_highres([0x01, 0x03, 0x07, 0x0f, 0, 0, 0, 0], 1);
+        let b = Char::new([0x1b, 0, 0, 0, 0, 0, 0, 0xe4], 2);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, a, a, a], 2, 3));
+        let original = bitmaps(&image);
+        image.flip_y();
+        assert_ne!(bitmaps(&image), original);
+        image.flip_y();
+        assert_eq!(bitmaps(&image), original);
+    }
+
+    #[test]
+    fn rotate_cw() {
+        let a = Char::new_highres([0xf0, 0, 0, 0, 0, 0, 0, 0], 1);
+        let b = Char::new_highres([0x01; 8], 2);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, b, b, b], 3, 2));
+        image.rotate_cw().unwrap();
+        assert_eq!(image.size_in_cells(), SizeInCells::new(2, 3));
+        // The top-left cell ends up in the top-right corner
+        assert_eq!(
+            image.video[(1usize, 0usize)].bits,
+            [0x01, 0x01, 0x01, 0x01, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            image.video[(0usize, 0usize)].bits,
+            [0, 0, 0, 0, 0, 0, 0, 0xff]
+        );
+    }
+
+    #[test]
+    fn rotate_back_and_forth() {
+        let a = Char::new_highres([0x01, 0x03, 0x07, 0x0f, 0, 0, 0, 0x80], 1);
+        let b = Char::new_highres([0x55; 8], 2);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, b, a, a, a], 3, 2));
+        let original = bitmaps(&image);
+        image.rotate_cw().unwrap();
+        image.rotate_ccw().unwrap();
+        assert_eq!(bitmaps(&image), original);
+        for _ in 0..4 {
+            image.rotate_ccw().unwrap();
+        }
+        assert_eq!(image.size_in_cells(), SizeInCells::new(3, 2));
+        assert_eq!(bitmaps(&image), original);
+    }
+
+    #[test]
+    fn rotate_multicolor_is_disallowed() {
+        let mut image = VicImage::new(2, 2);
+        assert!(image.rotate_cw().is_err());
+    }
+
+    #[test]
+    fn shift_with_wrap() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x02; 8], 1);
+        let c = Char::new_highres([0x03; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, c], 3, 1));
+        assert!(image.shift(1, 0, true));
+        assert_eq!(bitmaps(&image), vec![[0x03; 8], [0x01; 8], [0x02; 8]]);
+        assert!(image.shift(-2, 5, true));
+        assert_eq!(bitmaps(&image), vec![[0x02; 8], [0x03; 8], [0x01; 8]]);
+    }
+
+    #[test]
+    fn shift_without_wrap() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x02; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, a, b], 2, 2));
+        assert!(image.shift(0, 1, false));
+        assert_eq!(bitmaps(&image), vec![[0; 8], [0; 8], [0x01; 8], [0x02; 8]]);
+    }
+
+    #[test]
+    fn paste_full_screen_image() {
+        // Every cell has the background color and one character color,
+        // so the image can be converted without any loss.
+        let (width, height) = (176, 184);
+        let palette = VicPalette::default();
+        let source = RgbaImage::from_fn(width, height, |x, y| {
+            let cell_number = x / 8 + y / 8 * (width / 8);
+            if (x + y) % 3 == 0 {
+                palette.color(0u8).into()
+            } else {
+                palette.color(cell_number as usize % 7 + 1).into()
+            }
+        });
+        let mut image = VicImage::new(width as usize / 8, height as usize / 8);
+        image.paste_image(
+            &source,
+            PixelPoint::new(0, 0),
+            ColorFormat::HighRes,
+            Dithering::None,
+            crate::vic::ALL_CHAR_COLORS_MASK,
+        );
+        assert!(image.render() == source);
+    }
+
+    #[test]
+    fn paste_transparent_skips_empty_cells() {
+        let background = Char::new_highres([0x0f; 8], 1);
+        let empty = Char::new_highres(Char::EMPTY_BITMAP, 2);
+        let full = Char::new_highres([0xf0; 8], 3);
+        let mut image = VicImage::with_content(ImgVec::new(vec![background; 2], 2, 1));
+        let brush = ImgVec::new(vec![empty, full], 2, 1);
+        assert!(image
+            .paste_chars(&CellPos::new(0, 0), brush.as_ref(), PasteMode::Transparent)
+            .unwrap());
+        assert!(image.video[(0usize, 0usize)] == background);
+        assert!(image.video[(1usize, 0usize)] == full);
+    }
+
+    #[test]
+    fn paste_merge_combines_highres_bitmaps() {
+        let background = Char::new_highres([0x0f; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![background], 1, 1));
+        let brush = ImgVec::new(vec![Char::new_highres([0xf0; 8], 3)], 1, 1);
+        image
+            .paste_chars(&CellPos::new(0, 0), brush.as_ref(), PasteMode::Merge)
+            .unwrap();
+        assert!(image.video[(0usize, 0usize)] == Char::new_highres([0xff; 8], 3));
+    }
+
+    /// Image with a checkerboard pattern of char color 1 and background pixels.
+    fn checkerboard() -> VicImage {
+        let char = Char::new_highres([0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55], 1);
+        VicImage::with_content(ImgVec::new(vec![char; 2], 2, 1))
+    }
+
+    #[test]
+    fn flood_fill_only_fills_connected_pixels() {
+        let mut image = checkerboard();
+        let seed = UpdateArea::from_pixel(PixelPoint::new(1, 0));
+        assert!(image.flood_fill(&seed, PixelColor::CharColor(1)).unwrap());
+        assert_eq!(image.video[(0usize, 0usize)].bits[0], 0xea);
+        assert_eq!(image.video[(0usize, 0usize)].bits[1], 0x55);
+        assert!(image.video[(1usize, 0usize)] == checkerboard().video[(1usize, 0usize)]);
+    }
+
+    #[test]
+    fn global_fill_replaces_color_everywhere() {
+        let mut image = checkerboard();
+        let seed = UpdateArea::from_pixel(PixelPoint::new(1, 0));
+        assert!(image.global_fill(&seed, PixelColor::CharColor(1)).unwrap());
+        let full = Char::new_highres([0xff; 8], 1);
+        assert!(image.video.pixels().all(|c| c == full));
+    }
+
+    #[test]
+    fn changes_are_recorded_for_the_touched_cells() {
+        let mut image = VicImage::new(4, 1);
+        let size = image.size_in_cells();
+        let cell = |column| {
+            coords::clamp_rect_to_bounds(
+                CellRect::new(CellPos::new(column, 0), SizeInCells::new(1, 1)),
+                size,
+            )
+        };
+        let before = image.revision();
+        image
+            .plot(
+                &UpdateArea::from_pixel(PixelPoint::new(17, 3)),
+                PixelColor::CharColor(1),
+            )
+            .unwrap();
+        assert!(image.changed_since(&before, &cell(2)));
+        assert!(!image.changed_since(&before, &cell(1)));
+
+        let before = image.revision();
+        image.set_global_color(Register::Border, 5);
+        assert!(image.changed_since(&before, &cell(1)));
+    }
+
+    #[test]
+    fn optimized_image_only_uses_allowed_colors() {
+        let palette = VicPalette::default();
+        let white = image::Rgba::from(palette.color(1u8));
+        let original = RgbaImage::from_pixel(8, 8, white);
+        let (colors, _) = optimized_image_highres(
+            &original,
+            &GlobalColors::default(),
+            &palette,
+            Dithering::None,
+            1 << 5,
+        );
+        assert!(colors.pixels().all(|c| c == 5));
+    }
+
+    #[test]
+    fn screen_and_color_ram() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new([0x80; 8], 2);
+        let image = VicImage::with_content(ImgVec::new(vec![a, b, a, a], 2, 2));
+        let (screen, colors) = image.to_screen_and_color_ram().unwrap();
+        assert_eq!(screen, vec![0, 1, 0, 0]);
+        assert_eq!(colors, vec![1, 10, 1, 1]);
+    }
+
+    #[test]
+    fn map_characters_counts_unique_bitmaps() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x02; 8], 1);
+        // Same bitmap as `a` but different color
+        let c = Char::new_highres([0x01; 8], 3);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, c, a], 2, 2));
+        assert_eq!(image.map_characters().len(), 2);
+        image.update();
+        assert_eq!(image.unique_char_count(), 2);
+    }
+
+    #[test]
+    fn current_unique_char_count_includes_changes_since_update() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x02; 8], 1);
+        let c = Char::new_highres([0x03; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, a], 3, 1));
+        assert_eq!(image.current_unique_char_count(), 2);
+        image.update();
+        // Replaces the only b and one of the a's
+        set_char(&mut image, 1, c);
+        set_char(&mut image, 0, c);
+        assert_eq!(image.current_unique_char_count(), 2);
+        set_char(&mut image, 2, b);
+        assert_eq!(image.current_unique_char_count(), 2);
+        assert_eq!(
+            image.current_unique_char_count(),
+            image.map_characters().len()
+        );
+        // The counts are not updated
+        assert_eq!(image.unique_char_count(), 2);
+    }
+
+    #[test]
+    fn stable_characters_keep_numbers() {
+        let a = Char::new_highres([0x01; 8], 1);
+        let b = Char::new_highres([0x02; 8], 1);
+        let c = Char::new_highres([0x03; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b], 2, 1));
+        image.set_stable_characters(true);
+        image.update();
+        // Without stable numbers, b would become number 0
+        set_char(&mut image, 0, b);
+        image.update();
+        assert_eq!(image.map_characters().get_by_right(&b.bits), Some(&1));
+        assert_eq!(image.to_charset_bytes().len(), 16);
+        // New characters get the lowest free number
+        set_char(&mut image, 0, c);
+        image.update();
+        assert_eq!(image.map_characters().get_by_right(&c.bits), Some(&0));
+        assert_eq!(image.map_characters().get_by_right(&b.bits), Some(&1));
+    }
+
+    #[test]
+    fn flipped_duplicates() {
+        let a = Char::new_highres([0x80, 0, 0, 0, 0, 0, 0, 0], 1);
+        let mirrored = Char::new_highres([0x01, 0, 0, 0, 0, 0, 0, 0], 1);
+        let rotated = Char::new_highres([0, 0, 0, 0, 0, 0, 0, 0x01], 1);
+        let other = Char::new_highres([0xff, 0, 0, 0, 0, 0, 0, 0], 1);
+        let image = VicImage::with_content(ImgVec::new(vec![a, mirrored, rotated, other, a], 5, 1));
+        assert_eq!(image.count_flipped_duplicates(), 2);
+    }
+
+    #[test]
+    fn incremental_update_matches_full_mapping() {
+        let a = Char::new_highres([0x80, 0, 0, 0, 0, 0, 0, 0], 1);
+        let b = Char::new_highres([0x01, 0, 0, 0, 0, 0, 0, 0], 1);
+        let c = Char::new_highres([0xff; 8], 1);
+        let d = Char::new_highres([0x0f; 8], 1);
+        let mut image = VicImage::with_content(ImgVec::new(vec![a, b, c, a], 4, 1));
+        image.set_stable_characters(true);
+        image.update();
+        for (column, char) in [(0, b), (3, d), (1, c), (0, a), (2, d), (3, a)] {
+            set_char(&mut image, column, char);
+            let expected = image.map_characters();
+            image.update();
+            assert_eq!(image.bitmaps, expected);
+            assert_eq!(image.unique_char_count(), expected.len());
+            assert_eq!(image.flip_savings, image.count_flipped_duplicates());
+        }
+    }
+
+    #[test]
+    fn parallel_render_matches_sequential() {
+        let chars = (0..12u8)
+            .map(|i| {
+                Char::new(
+                    [i, i.wrapping_mul(37), 0xa5, i ^ 0x3c, 0, 0xff, i, 1],
+                    i % 8,
+                )
+            })
+            .collect();
+        let image = VicImage::with_content(ImgVec::new(chars, 3, 4));
+        let rect = coords::clamp_rect_to_bounds(
+            CellRect::new(CellPos::new(1, 1), SizeInCells::new(2, 3)),
+            image.size_in_cells(),
+        );
+        let rendered = image.render_region(&rect, &ViewSettings::Normal);
+        let mut sequential = RgbaImage::new(rendered.width(), rendered.height());
+        let band_size = sequential.width() as usize * Char::HEIGHT * 4;
+        for (row, band) in sequential.chunks_mut(band_size).enumerate() {
+            image.render_band(&rect, row as i32, &ViewSettings::Normal, band);
+        }
+        assert!(rendered == sequential);
+        assert_eq!(
+            *rendered.get_pixel(8, 0),
+            image::Rgba::from(
+                image.video[(2usize, 1usize)].render(
+                    &image.colors,
+                    &image.palette,
+                    &ViewSettings::Normal
+                )[0]
+            )
+        );
+    }
+
+    #[test]
+    fn screen_ram_with_too_many_characters() {
+        let chars = (0..=VicImage::MAX_CHARACTERS)
+            .map(|i| Char::new_highres([i as u8, (i >> 8) as u8, 0, 0, 0, 0, 0, 0], 1))
+            .collect();
+        let image = VicImage::with_content(ImgVec::new(chars, VicImage::MAX_CHARACTERS + 1, 1));
+        assert!(matches!(
+            image.to_screen_and_color_ram(),
+            Err(Error::TooManyCharacters(257))
+        ));
+    }
+}
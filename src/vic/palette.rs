@@ -1,36 +1,235 @@
 //! Vic-20 palette.
 
-use crate::colors::TrueColor;
+use std::{convert::TryFrom, path::Path, sync::Arc};
 
-/// Functions for getting the true colors to display for different color indices.
-pub struct VicPalette;
+use serde::{Deserialize, Serialize};
+
+use crate::{colors::TrueColor, error::Error};
+
+/// Colors and names loaded from a file.
+/// Serialized in the same format as the JSON palette files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PaletteEntry>", into = "Vec<PaletteEntry>")]
+pub struct CustomPalette {
+    colors: [TrueColor; PALETTE_SIZE],
+    names: [String; PALETTE_SIZE],
+}
+
+impl CustomPalette {
+    /// Load a palette from a file. The format is determined from the file name extension:
+    /// "gpl" for GIMP palettes, "act" for Adobe Color Tables, and JSON otherwise.
+    /// The JSON format is a list of objects with "color" as "#rrggbb" and an optional "name".
+    /// The file must contain exactly 16 colors, except Adobe Color Tables without a color count,
+    /// which always have 256 entries of which the first 16 are used.
+    pub fn load(filename: &Path) -> Result<Self, Error> {
+        let extension = filename
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("gpl") => Self::from_gpl(&std::fs::read_to_string(filename)?),
+            Some("act") => Self::from_act(&std::fs::read(filename)?),
+            _ => Self::from_json(&std::fs::read_to_string(filename)?),
+        }
+    }
+
+    /// Create a palette from colors and optional names, which must be exactly 16.
+    /// Colors without names get the default names.
+    fn from_entries(entries: Vec<(TrueColor, Option<String>)>) -> Result<Self, Error> {
+        if entries.len() != PALETTE_SIZE {
+            return Err(Error::InvalidPalette(format!(
+                "{} colors found, expected {}",
+                entries.len(),
+                PALETTE_SIZE
+            )));
+        }
+        let mut colors = [TrueColor::default(); PALETTE_SIZE];
+        let mut names: [String; PALETTE_SIZE] = Default::default();
+        for (index, (color, name)) in entries.into_iter().enumerate() {
+            colors[index] = color;
+            names[index] = name.unwrap_or_else(|| NAMES[index].to_string());
+        }
+        Ok(Self { colors, names })
+    }
+
+    /// Parse a GIMP palette: a "GIMP Palette" header, optional "Name:" and "Columns:" lines,
+    /// then one color per line as red, green and blue from 0 to 255, followed by an optional name.
+    fn from_gpl(text: &str) -> Result<Self, Error> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err(Error::InvalidPalette(
+                "missing \"GIMP Palette\" header".to_string(),
+            ));
+        }
+        let entries = lines
+            .map(str::trim)
+            .filter(|line| {
+                !(line.is_empty()
+                    || line.starts_with('#')
+                    || line.starts_with("Name:")
+                    || line.starts_with("Columns:"))
+            })
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let mut component = || {
+                    fields
+                        .next()
+                        .and_then(|f| f.parse::<u8>().ok())
+                        .ok_or_else(|| Error::InvalidPalette(format!("invalid line: {}", line)))
+                };
+                let rgb = [component()?, component()?, component()?];
+                let name = fields.collect::<Vec<_>>().join(" ");
+                Ok((
+                    TrueColor::from(image::Rgba([rgb[0], rgb[1], rgb[2], 0xff])),
+                    Some(name).filter(|n| !n.is_empty()),
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Self::from_entries(entries)
+    }
+
+    /// Parse an Adobe Color Table: 256 red, green and blue bytes,
+    /// optionally followed by the number of colors used as a big endian 16 bit number.
+    fn from_act(data: &[u8]) -> Result<Self, Error> {
+        let count = match data.len() {
+            768 => PALETTE_SIZE,
+            772 => u16::from_be_bytes([data[768], data[769]]) as usize,
+            _ => {
+                return Err(Error::InvalidPalette(format!(
+                    "{} bytes long, expected 768 or 772",
+                    data.len()
+                )))
+            }
+        };
+        let entries = data
+            .chunks_exact(3)
+            .take(count)
+            .map(|c| (TrueColor::from(image::Rgba([c[0], c[1], c[2], 0xff])), None))
+            .collect();
+        Self::from_entries(entries)
+    }
+
+    fn from_json(text: &str) -> Result<Self, Error> {
+        Self::try_from(serde_json::from_str::<Vec<PaletteEntry>>(text)?)
+    }
+}
+
+/// A color in a JSON palette file.
+#[derive(Serialize, Deserialize)]
+struct PaletteEntry {
+    /// The color as "#rrggbb".
+    color: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl TryFrom<Vec<PaletteEntry>> for CustomPalette {
+    type Error = Error;
+
+    fn try_from(entries: Vec<PaletteEntry>) -> Result<Self, Error> {
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let hex = entry.color.trim_start_matches('#');
+                match u32::from_str_radix(hex, 16) {
+                    Ok(rgb) if hex.len() == 6 => Ok((TrueColor::from_u32(rgb), entry.name)),
+                    _ => Err(Error::InvalidPalette(format!(
+                        "invalid color: {}",
+                        entry.color
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Self::from_entries(entries)
+    }
+}
+
+impl From<CustomPalette> for Vec<PaletteEntry> {
+    fn from(palette: CustomPalette) -> Self {
+        palette
+            .colors
+            .iter()
+            .zip(palette.names)
+            .map(|(color, name)| PaletteEntry {
+                color: format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b()),
+                name: Some(name),
+            })
+            .collect()
+    }
+}
+
+/// A set of true colors to display for the Vic-20 color indices.
+/// The hardware has no exact RGB values, so there are several interpretations to choose from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VicPalette {
+    /// Colors measured from a real machine on a TV.
+    Measured,
+    /// The "Colodore" palette for the VIC.
+    Colodore,
+    /// Brighter, more saturated colors.
+    Saturated,
+    /// Colors loaded by the user from a file.
+    /// They are saved with the document.
+    Custom(Arc<CustomPalette>),
+}
+
+impl Default for VicPalette {
+    fn default() -> Self {
+        VicPalette::Measured
+    }
+}
 
 impl VicPalette {
+    /// All built-in palettes, in the order to show them in the user interface.
+    pub const ALL: [VicPalette; 3] = [
+        VicPalette::Measured,
+        VicPalette::Colodore,
+        VicPalette::Saturated,
+    ];
+
     /// Get the color to display for a given palette index.
-    pub fn color<T>(index: T) -> TrueColor
+    pub fn color<T>(&self, index: T) -> TrueColor
     where
         T: Into<usize>,
     {
-        COLORS[index.into()]
+        self.all_colors()[index.into()]
     }
 
     /// Get the name of a color from the palette.
     /// `index` must be in the range `0..PALETTE_SIZE`.
-    pub fn name<T>(index: T) -> &'static str
+    pub fn name<T>(&self, index: T) -> &str
     where
         T: Into<usize>,
     {
-        NAMES[index.into()]
+        match self {
+            VicPalette::Custom(custom) => &custom.names[index.into()],
+            _ => NAMES[index.into()],
+        }
     }
 
-    pub fn all_colors() -> &'static [TrueColor] {
-        &COLORS
+    pub fn all_colors(&self) -> &[TrueColor] {
+        match self {
+            VicPalette::Measured => &MEASURED_COLORS,
+            VicPalette::Colodore => &COLODORE_COLORS,
+            VicPalette::Saturated => &SATURATED_COLORS,
+            VicPalette::Custom(custom) => &custom.colors,
+        }
+    }
+
+    /// The name of the palette to show in the user interface.
+    pub fn title(&self) -> &'static str {
+        match self {
+            VicPalette::Measured => "Measured",
+            VicPalette::Colodore => "Colodore",
+            VicPalette::Saturated => "Saturated",
+            VicPalette::Custom(_) => "Custom",
+        }
     }
 }
 
 const PALETTE_SIZE: usize = 16;
 
-const COLORS: [TrueColor; PALETTE_SIZE] = [
+const MEASURED_COLORS: [TrueColor; PALETTE_SIZE] = [
     //                      0xRRGGBB
     TrueColor::from_u32(0x000000), // Black
     TrueColor::from_u32(0xffffff), // White
@@ -50,6 +249,46 @@ const COLORS: [TrueColor; PALETTE_SIZE] = [
     TrueColor::from_u32(0xffffc9), // Light Yellow
 ];
 
+const COLODORE_COLORS: [TrueColor; PALETTE_SIZE] = [
+    //                      0xRRGGBB
+    TrueColor::from_u32(0x000000), // Black
+    TrueColor::from_u32(0xffffff), // White
+    TrueColor::from_u32(0x96282e), // Red
+    TrueColor::from_u32(0x5bd6ce), // Cyan
+    TrueColor::from_u32(0x9f2dad), // Purple
+    TrueColor::from_u32(0x41b936), // Green
+    TrueColor::from_u32(0x2724c4), // Blue
+    TrueColor::from_u32(0xeff347), // Yellow
+    TrueColor::from_u32(0x9f4815), // Orange
+    TrueColor::from_u32(0xe49b69), // Light Orange
+    TrueColor::from_u32(0xe49ca9), // Pink
+    TrueColor::from_u32(0x87fcf3), // Light Cyan
+    TrueColor::from_u32(0xe266ff), // Light Purple
+    TrueColor::from_u32(0x84ff78), // Light Green
+    TrueColor::from_u32(0x6e61ff), // Light Blue
+    TrueColor::from_u32(0xffff8e), // Light Yellow
+];
+
+const SATURATED_COLORS: [TrueColor; PALETTE_SIZE] = [
+    //                      0xRRGGBB
+    TrueColor::from_u32(0x000000), // Black
+    TrueColor::from_u32(0xffffff), // White
+    TrueColor::from_u32(0xb00000), // Red
+    TrueColor::from_u32(0x00ffff), // Cyan
+    TrueColor::from_u32(0xb000c0), // Purple
+    TrueColor::from_u32(0x00d000), // Green
+    TrueColor::from_u32(0x0000e0), // Blue
+    TrueColor::from_u32(0xffff00), // Yellow
+    TrueColor::from_u32(0xd06000), // Orange
+    TrueColor::from_u32(0xffa040), // Light Orange
+    TrueColor::from_u32(0xff8090), // Pink
+    TrueColor::from_u32(0x80ffff), // Light Cyan
+    TrueColor::from_u32(0xff60ff), // Light Purple
+    TrueColor::from_u32(0x80ff80), // Light Green
+    TrueColor::from_u32(0x8080ff), // Light Blue
+    TrueColor::from_u32(0xffff80), // Light Yellow
+];
+
 const NAMES: [&str; PALETTE_SIZE] = [
     "Black",
     "White",
@@ -68,3 +307,55 @@ const NAMES: [&str; PALETTE_SIZE] = [
     "Light Blue",
     "Light Yellow",
 ];
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{CustomPalette, VicPalette, PALETTE_SIZE};
+    use crate::error::Error;
+
+    #[test]
+    fn gpl_palette() {
+        let mut text = "GIMP Palette\nName: Test\n# comment\n".to_string();
+        for i in 0..PALETTE_SIZE {
+            text.push_str(&format!("{} 0 255\tColor {}\n", i, i));
+        }
+        let palette = CustomPalette::from_gpl(&text).unwrap();
+        assert_eq!(palette.colors[3].r(), 3);
+        assert_eq!(palette.colors[3].b(), 255);
+        assert_eq!(palette.names[3], "Color 3");
+
+        text.push_str("1 2 3\n");
+        assert!(matches!(
+            CustomPalette::from_gpl(&text),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+
+    #[test]
+    fn act_palette() {
+        let mut data = vec![0u8; 772];
+        data[3 * 15] = 0x80;
+        data[769] = PALETTE_SIZE as u8;
+        let palette = CustomPalette::from_act(&data).unwrap();
+        assert_eq!(palette.colors[15].r(), 0x80);
+        assert_eq!(palette.names[15], "Light Yellow");
+
+        data[769] = 17;
+        assert!(CustomPalette::from_act(&data).is_err());
+    }
+
+    #[test]
+    fn custom_palette_is_serialized_with_its_colors() {
+        let mut data = vec![0u8; 768];
+        data[3 * 2..3 * 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+        let palette = VicPalette::Custom(Arc::new(CustomPalette::from_act(&data).unwrap()));
+        let json = serde_json::to_string(&palette).unwrap();
+        assert!(json.contains("#123456"));
+        let loaded: VicPalette = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, palette);
+        assert_eq!(loaded.color(2u8).g(), 0x34);
+        assert_eq!(loaded.name(2u8), "Red");
+    }
+}
@@ -3,6 +3,8 @@
 use serde::{Deserialize, Serialize};
 use std::ops::{Index, IndexMut};
 
+use super::PixelColor;
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub enum Register {
     Background,
@@ -10,7 +12,7 @@ pub enum Register {
     Aux,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GlobalColors {
     pub background: u8,
     pub border: u8,
@@ -26,6 +28,16 @@ impl Default for GlobalColors {
         }
     }
 }
+impl GlobalColors {
+    /// Check whether the registers used by a cell are within their allowed ranges.
+    /// High-res cells only use the background color.
+    pub fn are_valid(&self, multicolor: bool) -> bool {
+        let valid = |color: PixelColor, value: u8| color.selectable_colors().any(|c| c == value);
+        valid(PixelColor::Background, self.background)
+            && (!multicolor
+                || valid(PixelColor::Border, self.border) && valid(PixelColor::Aux, self.aux))
+    }
+}
 impl Index<Register> for GlobalColors {
     type Output = u8;
     fn index(&self, index: Register) -> &Self::Output {
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 
+use imgref::ImgVec;
 use serde::{Deserialize, Serialize};
 
 use crate::{cell_image::CellImageSize, coords::SizeInCells, error::Error};
 
-use super::{Char, GlobalColors, VicImage};
+use super::{Char, GlobalColors, VicImage, VicPalette};
 
 /// Image for serialization to or deserialization from file.
 #[derive(Serialize, Deserialize)]
@@ -24,6 +25,14 @@ struct VicImageFile {
 
     /// Bitmap for each character as hex string
     characters: Vec<Option<String>>,
+
+    /// The palette to display the image with. Not present in old files.
+    #[serde(default)]
+    palette: VicPalette,
+
+    /// Keep the character numbers in `characters` when the image is edited.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    stable_characters: bool,
 }
 
 /// Supports deserializing GlobalColors from an array of three integers, used in old files.
@@ -77,6 +86,8 @@ impl VicImageFile {
             video_chars,
             video_colors,
             characters,
+            palette: image.palette.clone(),
+            stable_characters: image.stable_characters,
         };
         assert!(instance.verify().is_ok());
         instance
@@ -94,13 +105,16 @@ impl VicImageFile {
                 Ok((num, bits))
             })
             .collect::<Result<HashMap<usize, [u8; Char::HEIGHT]>, Error>>()?;
-        VicImage::from_data(
+        let mut image = VicImage::from_data(
             SizeInCells::new(self.columns as i32, self.rows as i32),
             self.colors.into(),
             self.video_chars,
             self.video_colors,
             characters,
-        )
+        )?;
+        image.palette = self.palette;
+        image.stable_characters = self.stable_characters;
+        Ok(image)
     }
 
     pub fn verify(&self) -> Result<(), Error> {
@@ -112,12 +126,73 @@ impl VicImageFile {
             Err(Error::InvalidSize(self.columns, self.rows))
         } else if self.characters.is_empty() {
             Err(Error::NoCharacters)
+        } else if self.video_chars.len() != self.columns * self.rows {
+            Err(Error::WrongDataLength(
+                "video-chars",
+                self.video_chars.len(),
+                self.columns * self.rows,
+            ))
+        } else if self.video_colors.len() != self.columns * self.rows {
+            Err(Error::WrongDataLength(
+                "video-colors",
+                self.video_colors.len(),
+                self.columns * self.rows,
+            ))
         } else {
             Ok(())
         }
     }
 }
 
+/// Character brush for serialization to or deserialization from file.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BrushFile {
+    columns: usize,
+    rows: usize,
+
+    /// The color and multicolor bit at each position.
+    /// Size: columns x rows.
+    video_colors: Vec<u8>,
+
+    /// Bitmap at each position as hex string.
+    /// Size: columns x rows.
+    bitmaps: Vec<String>,
+}
+
+impl BrushFile {
+    pub fn from_brush(brush: &ImgVec<Char>) -> Self {
+        Self {
+            columns: brush.width(),
+            rows: brush.height(),
+            video_colors: brush.pixels().map(|char| char.raw_nibble()).collect(),
+            bitmaps: brush.pixels().map(|char| hex::encode(char.bits)).collect(),
+        }
+    }
+
+    pub fn into_brush(self) -> Result<ImgVec<Char>, Error> {
+        let size = self.columns * self.rows;
+        if size == 0 || self.video_colors.len() != size || self.bitmaps.len() != size {
+            return Err(Error::InvalidSize(self.columns, self.rows));
+        }
+        let chars = self
+            .bitmaps
+            .iter()
+            .zip(self.video_colors)
+            .map(|(bits_string, color)| {
+                let mut bits = Char::EMPTY_BITMAP;
+                hex::decode_to_slice(bits_string, &mut bits)?;
+                Ok(Char {
+                    bits,
+                    color: color & 7,
+                    multicolor: (color & 8) == 8,
+                })
+            })
+            .collect::<Result<Vec<Char>, Error>>()?;
+        Ok(ImgVec::new(chars, self.columns, self.rows))
+    }
+}
+
 impl Serialize for VicImage {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -140,3 +215,75 @@ impl<'de> Deserialize<'de> for VicImage {
             .map_err(serde::de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use imgref::ImgVec;
+
+    use super::BrushFile;
+    use crate::vic::{Char, VicImage};
+
+    /// Load an image from JSON with a 2 by 1 image, with the given fields.
+    fn load_image(
+        video_chars: &str,
+        video_colors: &str,
+        characters: &str,
+    ) -> Result<VicImage, serde_json::Error> {
+        serde_json::from_str(&format!(
+            r#"{{"columns": 2, "rows": 1, "colors": [0, 1, 2],
+                "video-chars": {}, "video-colors": {}, "characters": {}}}"#,
+            video_chars, video_colors, characters
+        ))
+    }
+
+    #[test]
+    fn valid_image_is_loaded() {
+        assert!(load_image(
+            "[0, 1]",
+            "[1, 9]",
+            r#"["0000000000000000", "ffffffffffffffff"]"#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn image_with_wrong_data_length_is_rejected() {
+        let characters = r#"["0000000000000000"]"#;
+        let error = load_image("[0]", "[1, 1]", characters).err().unwrap();
+        assert!(error.to_string().contains("video-chars"));
+        let error = load_image("[0, 0]", "[1, 1, 1]", characters).err().unwrap();
+        assert!(error.to_string().contains("video-colors"));
+    }
+
+    #[test]
+    fn image_with_undefined_character_is_rejected() {
+        let error = load_image("[0, 1]", "[1, 1]", r#"["0000000000000000", null]"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Character 1"));
+        let error = load_image("[0, 5]", "[1, 1]", r#"["0000000000000000"]"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Character 5"));
+    }
+
+    #[test]
+    fn brush_round_trip() {
+        let a = Char::new_highres([0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80], 7);
+        let b = Char::new([0x1b; 8], 3);
+        let brush = ImgVec::new(vec![a, b, b, a, a, b], 3, 2);
+        let json = serde_json::to_string(&BrushFile::from_brush(&brush)).unwrap();
+        let file: BrushFile = serde_json::from_str(&json).unwrap();
+        let loaded = file.into_brush().unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (3, 2));
+        assert!(loaded.pixels().eq(brush.pixels()));
+    }
+
+    #[test]
+    fn brush_with_wrong_size_is_rejected() {
+        let brush = ImgVec::new(vec![Char::default(); 2], 2, 1);
+        let mut file = BrushFile::from_brush(&brush);
+        file.rows = 2;
+        assert!(file.into_brush().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! How to display an image.
+
+use crate::colors::TrueColor;
+
+/// How to display the colors of an image.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViewSettings {
+    Normal,
+    Raw,
+}
+impl Default for ViewSettings {
+    fn default() -> Self {
+        ViewSettings::Normal
+    }
+}
+impl ViewSettings {
+    /// Get the colors to use when displaying in raw mode.
+    pub fn raw_colors() -> (TrueColor, TrueColor, TrueColor, TrueColor) {
+        (
+            Self::raw_multicolor_background(),
+            Self::raw_multicolor_border(),
+            Self::raw_multicolor_aux(),
+            Self::raw_multicolor_char_color(),
+        )
+    }
+
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_highres_background() -> TrueColor {
+        TrueColor::from_u32(0x555555)
+    }
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_hires_char_color() -> TrueColor {
+        TrueColor::from_u32(0xeeeeee)
+    }
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_multicolor_background() -> TrueColor {
+        TrueColor::from_u32(0x000000)
+    }
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_multicolor_border() -> TrueColor {
+        TrueColor::from_u32(0x0044ff)
+    }
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_multicolor_aux() -> TrueColor {
+        TrueColor::from_u32(0xff0000)
+    }
+    /// Get color to use when displaying in raw mode.
+    pub fn raw_multicolor_char_color() -> TrueColor {
+        TrueColor::from_u32(0xffffff)
+    }
+}
@@ -0,0 +1,264 @@
+//! System functions for running in a web browser.
+//!
+//! There is no file system, so files the user opens are read into memory.
+//! Reading is asynchronous, so `open_file_dialog` doesn't return the file.
+//! The content is instead fetched later with `take_opened_files`.
+//! Documents are saved by downloading them,
+//! and copies of unsaved documents are kept in the browser's local storage.
+
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use eframe::wasm_bindgen::{self, closure::Closure, JsCast};
+use js_sys::{Array, Uint8Array};
+use web_sys::{Blob, FileReader, HtmlAnchorElement, HtmlInputElement, Storage, Url};
+
+use crate::{
+    error::Error,
+    storage,
+    system::{OpenFileOptions, SaveFileOptions, SystemFunctions},
+};
+
+/// Prefix of the keys in local storage for copies of unsaved documents.
+/// It is followed by the document's index number, a colon, and the document's name,
+/// as documents may have the same name.
+const RECOVERY_KEY_PREFIX: &str = "pixel_pen_recovery:";
+
+/// Convert a JavaScript exception to an error.
+fn js_error(context: &str, e: wasm_bindgen::JsValue) -> Error {
+    Error::DialogError(format!("{}: {:?}", context, e))
+}
+
+fn local_storage() -> Result<Storage, Error> {
+    web_sys::window()
+        .ok_or_else(|| Error::DialogError("No window".to_string()))?
+        .local_storage()
+        .map_err(|e| js_error("No local storage", e))?
+        .ok_or_else(|| Error::DialogError("No local storage".to_string()))
+}
+
+/// The keys of the copies of unsaved documents in local storage.
+fn recovery_keys(storage: &Storage) -> Result<Vec<String>, Error> {
+    let length = storage
+        .length()
+        .map_err(|e| js_error("Failed to read local storage", e))?;
+    Ok((0..length)
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter(|key| key.starts_with(RECOVERY_KEY_PREFIX))
+        .collect())
+}
+
+/// Files that have been opened, as name and content,
+/// and how many of them are still being read.
+#[derive(Default)]
+struct OpenedFiles {
+    files: Vec<(String, Vec<u8>)>,
+    pending: usize,
+}
+
+#[derive(Default)]
+pub struct WebSystemFunctions {
+    opened: Rc<RefCell<OpenedFiles>>,
+}
+
+impl WebSystemFunctions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The value of the `accept` attribute of a file input element for the given options.
+fn accept_attribute(options: &OpenFileOptions<'_>) -> String {
+    let mut extensions = Vec::new();
+    if options.include_native {
+        extensions.extend([
+            storage::NATIVE_EXTENSION,
+            storage::COMPRESSED_EXTENSION,
+            "flf",
+            storage::PACKED_EXTENSION,
+        ]);
+    }
+    if options.include_images {
+        extensions.extend(["png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff"]);
+    }
+    extensions
+        .iter()
+        .map(|e| format!(".{}", e))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Start reading a file the user has picked. When done, it is added to `opened`.
+fn read_file(file: web_sys::File, opened: Rc<RefCell<OpenedFiles>>) -> Result<(), Error> {
+    let reader = FileReader::new().map_err(|e| Error::DialogError(format!("{:?}", e)))?;
+    let name = file.name();
+    let onload = {
+        let reader = reader.clone();
+        let opened = opened.clone();
+        Closure::once_into_js(move || {
+            let mut opened = opened.borrow_mut();
+            opened.pending -= 1;
+            // On error, there is no result
+            if let Some(result) = reader.result().ok().filter(|r| !r.is_null()) {
+                opened.files.push((name, Uint8Array::new(&result).to_vec()));
+            }
+        })
+    };
+    reader.set_onload(Some(onload.unchecked_ref()));
+    reader.set_onerror(Some(onload.unchecked_ref()));
+    reader
+        .read_as_array_buffer(&file)
+        .map_err(|e| Error::DialogError(format!("Could not read file: {:?}", e)))?;
+    // Only now, as `onload` isn't called if reading never started
+    opened.borrow_mut().pending += 1;
+    Ok(())
+}
+
+/// Show a message in a dialog box.
+fn alert(message: &str) {
+    if let Some(window) = web_sys::window() {
+        let _ = window.alert_with_message(message);
+    }
+}
+
+impl SystemFunctions for WebSystemFunctions {
+    fn has_open_file_dialog(&self) -> bool {
+        true
+    }
+
+    fn has_save_file_dialog(&self) -> bool {
+        false
+    }
+
+    /// Show the browser's file picker. Always returns `None`,
+    /// as the files are read asynchronously and returned by `take_opened_files`.
+    fn open_file_dialog(&mut self, options: OpenFileOptions<'_>) -> Result<Option<PathBuf>, Error> {
+        if !options.include_native {
+            return Err(Error::DialogError(
+                "Only opening documents is supported in the browser".to_string(),
+            ));
+        }
+        let document = web_sys::window()
+            .and_then(|w| w.document())
+            .ok_or_else(|| Error::DialogError("No document".to_string()))?;
+        let input: HtmlInputElement = document
+            .create_element("input")
+            .map_err(|e| Error::DialogError(format!("{:?}", e)))?
+            .unchecked_into();
+        input.set_type("file");
+        input.set_multiple(true);
+        input.set_accept(&accept_attribute(&options));
+        let onchange = {
+            let input = input.clone();
+            let opened = self.opened.clone();
+            Closure::once_into_js(move || {
+                if let Some(files) = input.files() {
+                    for file in (0..files.length()).filter_map(|i| files.get(i)) {
+                        if let Err(e) = read_file(file, opened.clone()) {
+                            alert(&e.to_string());
+                        }
+                    }
+                }
+            })
+        };
+        input.set_onchange(Some(onchange.unchecked_ref()));
+        input.click();
+        Ok(None)
+    }
+
+    fn save_file_dialog(
+        &mut self,
+        _options: SaveFileOptions<'_>,
+    ) -> Result<Option<PathBuf>, Error> {
+        Err(Error::DialogError(
+            "Saving files is not supported in the browser".to_string(),
+        ))
+    }
+
+    fn has_download(&self) -> bool {
+        true
+    }
+
+    fn download(&mut self, filename: &str, data: &[u8]) -> Result<(), Error> {
+        let document = web_sys::window()
+            .and_then(|w| w.document())
+            .ok_or_else(|| Error::DialogError("No document".to_string()))?;
+        let parts = Array::of1(&Uint8Array::from(data));
+        let blob = Blob::new_with_u8_array_sequence(&parts)
+            .map_err(|e| js_error("Could not create download", e))?;
+        let url = Url::create_object_url_with_blob(&blob)
+            .map_err(|e| js_error("Could not create download", e))?;
+        let anchor: HtmlAnchorElement = document
+            .create_element("a")
+            .map_err(|e| js_error("Could not create download", e))?
+            .unchecked_into();
+        anchor.set_href(&url);
+        anchor.set_download(filename);
+        anchor.click();
+        Url::revoke_object_url(&url).map_err(|e| js_error("Could not create download", e))
+    }
+
+    fn has_recovery_storage(&self) -> bool {
+        local_storage().is_ok()
+    }
+
+    fn store_recovery_copies(&mut self, copies: &[(u32, String, Vec<u8>)]) -> Result<(), Error> {
+        let storage = local_storage()?;
+        let old_keys = recovery_keys(&storage)?;
+        // Write the new copies before removing the old ones,
+        // so the old ones are kept if the storage is full
+        let mut new_keys = Vec::new();
+        for (index, name, data) in copies {
+            // The native format is JSON, so it can be stored as text
+            let text = String::from_utf8_lossy(data);
+            let key = format!("{}{}:{}", RECOVERY_KEY_PREFIX, index, name);
+            storage
+                .set_item(&key, &text)
+                .map_err(|e| js_error("Failed to write local storage", e))?;
+            new_keys.push(key);
+        }
+        for key in old_keys.iter().filter(|key| !new_keys.contains(*key)) {
+            storage
+                .remove_item(key)
+                .map_err(|e| js_error("Failed to write local storage", e))?;
+        }
+        Ok(())
+    }
+
+    fn take_recovery_copies(&mut self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let storage = local_storage()?;
+        let mut copies = Vec::new();
+        for key in recovery_keys(&storage)? {
+            if let Ok(Some(text)) = storage.get_item(&key) {
+                // Remove the prefix and the document's index number
+                let name = key[RECOVERY_KEY_PREFIX.len()..]
+                    .split_once(':')
+                    .map_or("", |(_, name)| name)
+                    .to_string();
+                copies.push((name, text.into_bytes()));
+            }
+            storage
+                .remove_item(&key)
+                .map_err(|e| js_error("Failed to write local storage", e))?;
+        }
+        Ok(copies)
+    }
+
+    fn show_error(&self, message: &str) {
+        alert(message);
+    }
+
+    fn request_confirmation(&self, prompt: &str) -> Result<bool, Error> {
+        web_sys::window()
+            .ok_or_else(|| Error::DialogError("No window".to_string()))?
+            .confirm_with_message(prompt)
+            .map_err(|e| Error::DialogError(format!("{:?}", e)))
+    }
+
+    fn take_opened_files(&mut self) -> Vec<(String, Vec<u8>)> {
+        std::mem::take(&mut self.opened.borrow_mut().files)
+    }
+
+    fn is_reading_files(&self) -> bool {
+        self.opened.borrow().pending != 0
+    }
+}